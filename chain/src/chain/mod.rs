@@ -12,6 +12,7 @@ use std::ops::BitOr;
 
 use crate::{
     resource::{Buffer, Image, Resource},
+    subresource::Subresource,
     Id,
 };
 
@@ -95,5 +96,5 @@ where
 /// Type alias for map of chains by id for buffers.
 pub(crate) type BufferChains = HashMap<Id, Chain<Buffer>>;
 
-/// Type alias for map of chains by id for images.
-pub(crate) type ImageChains = HashMap<Id, Chain<Image>>;
+/// Type alias for map of chains by subresource range for images.
+pub(crate) type ImageChains = HashMap<Subresource, Chain<Image>>;
@@ -9,9 +9,56 @@ use crate::{
     node::{Node, State},
     resource::{Buffer, Image, Resource},
     schedule::{Queue, QueueId, Schedule, Submission, SubmissionId},
+    scheduler::{Candidate, Greedy, QueueCandidate, Scheduler},
+    subresource::{self, Subresource},
     Id,
 };
 
+/// Error collecting chains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectError {
+    /// `Scheduler` selected candidate or queue that doesn't exist.
+    InvalidSelection {
+        /// Index of the candidate selected.
+        candidate: usize,
+        /// Index of the queue selected.
+        queue: usize,
+        /// Number of candidates.
+        candidates: usize,
+    },
+
+    /// Node accesses overlapping subresource ranges of an image.
+    OverlappingRanges {
+        /// Id of the node.
+        node: usize,
+        /// Id of the image.
+        image: Id,
+    },
+}
+
+impl std::fmt::Display for CollectError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CollectError::InvalidSelection {
+                candidate,
+                queue,
+                candidates,
+            } => write!(
+                fmt,
+                "Scheduler selected queue {} of candidate {} out of {} candidates",
+                queue, candidate, candidates
+            ),
+            CollectError::OverlappingRanges { node, image } => write!(
+                fmt,
+                "Node {} accesses overlapping subresource ranges of image {:?}",
+                node, image
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CollectError {}
+
 /// Placeholder for synchronization type.
 #[derive(Clone, Copy, Debug)]
 pub struct Unsynchronized;
@@ -29,10 +76,13 @@ pub struct Chains {
     pub images: ImageChains,
 }
 
-#[derive(PartialEq, PartialOrd, Eq, Ord)]
-struct Fitness {
-    transfers: usize,
-    wait_factor: usize,
+impl Chains {
+    /// Get chains of all tracked subresource ranges of the image.
+    pub fn image_chains(&self, id: Id) -> impl Iterator<Item = (&Subresource, &Chain<Image>)> {
+        self.images
+            .iter()
+            .filter(move |(subresource, _)| subresource.id == id)
+    }
 }
 
 struct ResolvedNode {
@@ -40,6 +90,7 @@ struct ResolvedNode {
     family: rendy_core::hal::queue::QueueFamilyId,
     queues: Range<usize>,
     rev_deps: Vec<usize>,
+    critical_path: usize,
     buffers: Vec<(usize, State<Buffer>)>,
     images: Vec<(usize, State<Image>)>,
 }
@@ -51,6 +102,7 @@ impl Default for ResolvedNode {
             family: rendy_core::hal::queue::QueueFamilyId(0),
             queues: 0..0,
             rev_deps: Vec::new(),
+            critical_path: 0,
             buffers: Vec::new(),
             images: Vec::new(),
         }
@@ -59,9 +111,10 @@ impl Default for ResolvedNode {
 
 struct ResolvedNodeSet {
     nodes: Vec<ResolvedNode>,
+    ids: Vec<usize>,
     queues: Vec<QueueId>,
     buffers: Vec<Id>,
-    images: Vec<Id>,
+    images: Vec<Subresource>,
 }
 
 struct ChainData<R: Resource> {
@@ -87,13 +140,30 @@ struct QueueData {
 }
 
 /// Calculate automatic `Chains` for nodes.
-/// This function tries to find the most appropriate schedule for nodes execution.
-pub fn collect<Q>(nodes: Vec<Node>, max_queues: Q) -> Chains
+/// This function tries to find the most appropriate schedule for nodes execution
+/// using `Greedy` scheduler.
+/// Fails if a node accesses overlapping subresource ranges of an image.
+pub fn collect<Q>(nodes: Vec<Node>, max_queues: Q) -> Result<Chains, CollectError>
+where
+    Q: Fn(rendy_core::hal::queue::QueueFamilyId) -> usize,
+{
+    collect_with(nodes, max_queues, &mut Greedy)
+}
+
+/// Calculate `Chains` for nodes scheduled by the specified `Scheduler`.
+/// Fails if scheduler selects candidate or queue that doesn't exist
+/// or if a node accesses overlapping subresource ranges of an image.
+pub fn collect_with<Q, S>(
+    nodes: Vec<Node>,
+    max_queues: Q,
+    scheduler: &mut S,
+) -> Result<Chains, CollectError>
 where
     Q: Fn(rendy_core::hal::queue::QueueFamilyId) -> usize,
+    S: Scheduler + ?Sized,
 {
     // Resolve nodes into a form faster to work with.
-    let (nodes, mut unscheduled_nodes) = resolve_nodes(nodes, max_queues);
+    let (nodes, mut unscheduled_nodes) = resolve_nodes(nodes, max_queues)?;
     let mut ready_nodes = Vec::new();
 
     // Chains.
@@ -136,16 +206,24 @@ where
         }
     } else {
         while !ready_nodes.is_empty() {
-            // Among ready nodes find best fit.
-            let (fitness, qid, index) = ready_nodes
+            // Let scheduler pick among ready nodes.
+            let candidates: Vec<_> = ready_nodes
                 .iter()
-                .enumerate()
-                .map(|(index, &node)| {
-                    let (fitness, qid) = fitness(node, &mut images, &mut buffers, &mut schedule);
-                    (fitness, qid, index)
-                })
-                .min()
-                .unwrap();
+                .map(|&node| candidate(node, &nodes, &images, &buffers, &schedule))
+                .collect();
+            let (index, queue) = scheduler.select(&candidates);
+            let candidate = match candidates.get(index) {
+                Some(candidate) if queue < candidate.queues.len() => candidate,
+                _ => {
+                    return Err(CollectError::InvalidSelection {
+                        candidate: index,
+                        queue,
+                        candidates: candidates.len(),
+                    })
+                }
+            };
+            let wait_factor = candidate.wait_factor(&candidate.queues[queue]);
+            let qid = ready_nodes[index].queues.start + queue;
 
             let node = ready_nodes.swap_remove(index);
             schedule_node(
@@ -154,7 +232,7 @@ where
                 &nodes,
                 node,
                 qid,
-                fitness.wait_factor,
+                wait_factor,
                 scheduled,
                 &mut schedule,
                 &mut images,
@@ -165,11 +243,11 @@ where
     }
     assert_eq!(scheduled, nodes.nodes.len(), "Dependency loop found!");
 
-    Chains {
+    Ok(Chains {
         schedule: reify_schedule(schedule),
         buffers: reify_chain(&nodes.buffers, buffers),
         images: reify_chain(&nodes.images, images),
-    }
+    })
 }
 
 fn fill<T: Default>(num: usize) -> Vec<T> {
@@ -180,11 +258,11 @@ fn fill<T: Default>(num: usize) -> Vec<T> {
     vec
 }
 
-struct LookupBuilder<I: Hash + Eq + Copy> {
+struct LookupBuilder<I: Hash + Eq + Clone> {
     forward: HashMap<I, usize>,
     backward: Vec<I>,
 }
-impl<I: Hash + Eq + Copy> LookupBuilder<I> {
+impl<I: Hash + Eq + Clone> LookupBuilder<I> {
     fn new() -> LookupBuilder<I> {
         LookupBuilder {
             forward: HashMap::default(),
@@ -197,14 +275,17 @@ impl<I: Hash + Eq + Copy> LookupBuilder<I> {
             id_num
         } else {
             let id_num = self.backward.len();
-            self.backward.push(id);
+            self.backward.push(id.clone());
             self.forward.insert(id, id_num);
             id_num
         }
     }
 }
 
-fn resolve_nodes<Q>(nodes: Vec<Node>, max_queues: Q) -> (ResolvedNodeSet, Vec<usize>)
+fn resolve_nodes<Q>(
+    nodes: Vec<Node>,
+    max_queues: Q,
+) -> Result<(ResolvedNodeSet, Vec<usize>), CollectError>
 where
     Q: Fn(rendy_core::hal::queue::QueueFamilyId) -> usize,
 {
@@ -220,6 +301,25 @@ where
     let s = RandomState::new();
     let mut family_full = HashMap::with_hasher(s);
 
+    // Split image subresource ranges accessed by nodes into disjoint ones.
+    let mut image_ranges: HashMap<Id, Vec<_>> = HashMap::new();
+    for subresource in nodes.iter().flat_map(|node| node.images.keys()) {
+        image_ranges
+            .entry(subresource.id)
+            .or_default()
+            .push(&subresource.range);
+    }
+    let image_ranges: HashMap<Id, Vec<Subresource>> = image_ranges
+        .into_iter()
+        .map(|(id, ranges)| {
+            let ranges = subresource::split(ranges)
+                .into_iter()
+                .map(|range| Subresource::new(id, range))
+                .collect();
+            (id, ranges)
+        })
+        .collect();
+
     for node in nodes {
         let family = node.family;
         if !family_full.contains_key(&family) {
@@ -254,28 +354,76 @@ where
             .into_iter()
             .map(|(k, v)| (buffers.forward(k), v))
             .collect();
-        reified_nodes[id].images = node
+        let mut node_images: Vec<(usize, State<Image>)> = node
             .images
             .into_iter()
+            .flat_map(|(k, v)| {
+                image_ranges[&k.id]
+                    .iter()
+                    .filter(move |split| k.contains(split))
+                    .map(move |split| (split.clone(), v))
+            })
             .map(|(k, v)| (images.forward(k), v))
             .collect();
+        node_images.sort_by_key(|&(k, _)| k);
+        if let Some(pair) = node_images.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(CollectError::OverlappingRanges {
+                node: node.id,
+                image: images.backward[pair[0].0].id,
+            });
+        }
+        reified_nodes[id].images = node_images;
     }
 
-    (
+    compute_critical_paths(&mut reified_nodes, &unscheduled_nodes);
+
+    Ok((
         ResolvedNodeSet {
             nodes: reified_nodes,
+            ids: node_ids.backward,
             queues: queues.backward,
             buffers: buffers.backward,
             images: images.backward,
         },
         unscheduled_nodes,
-    )
+    ))
 }
 
-fn reify_chain<R: Resource>(ids: &[Id], vec: Vec<ChainData<R>>) -> HashMap<Id, Chain<R>> {
+/// Count nodes on the longest dependency path starting from each node.
+/// Nodes in dependency loops are left with zero.
+fn compute_critical_paths(nodes: &mut [ResolvedNode], unscheduled_nodes: &[usize]) {
+    // Topological order.
+    let mut unscheduled = unscheduled_nodes.to_vec();
+    let mut order: Vec<usize> = (0..nodes.len())
+        .filter(|&id| unscheduled[id] == 0)
+        .collect();
+    let mut index = 0;
+    while index < order.len() {
+        for &rev_dep in &nodes[order[index]].rev_deps {
+            unscheduled[rev_dep] -= 1;
+            if unscheduled[rev_dep] == 0 {
+                order.push(rev_dep);
+            }
+        }
+        index += 1;
+    }
+
+    for &id in order.iter().rev() {
+        let critical_path = nodes[id]
+            .rev_deps
+            .iter()
+            .map(|&rev_dep| nodes[rev_dep].critical_path)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        nodes[id].critical_path = critical_path;
+    }
+}
+
+fn reify_chain<R: Resource>(ids: &[R::Key], vec: Vec<ChainData<R>>) -> HashMap<R::Key, Chain<R>> {
     let mut map = HashMap::with_capacity_and_hasher(vec.len(), Default::default());
-    for (chain, &i) in vec.into_iter().zip(ids) {
-        map.insert(i, chain.chain);
+    for (chain, i) in vec.into_iter().zip(ids) {
+        map.insert(i.clone(), chain.chain);
     }
     map
 }
@@ -288,14 +436,15 @@ fn reify_schedule(vec: Vec<QueueData>) -> Schedule<Unsynchronized> {
     schedule
 }
 
-fn fitness(
+fn candidate(
     node: &ResolvedNode,
-    images: &mut Vec<ChainData<Image>>,
-    buffers: &mut Vec<ChainData<Buffer>>,
-    schedule: &mut Vec<QueueData>,
-) -> (Fitness, usize) {
+    nodes: &ResolvedNodeSet,
+    images: &[ChainData<Image>],
+    buffers: &[ChainData<Buffer>],
+    schedule: &[QueueData],
+) -> Candidate {
     let mut transfers = 0;
-    let mut wait_factor_from_chains = 0;
+    let mut chains_wait_factor = 0;
 
     // Collect minimal waits required and resource transfers count.
     for &(id, _) in &node.buffers {
@@ -306,7 +455,7 @@ fn fitness(
         {
             transfers += 1;
         }
-        wait_factor_from_chains = max(wait_factor_from_chains, chain.last_link_wait_factor);
+        chains_wait_factor = max(chains_wait_factor, chain.last_link_wait_factor);
     }
     for &(id, _) in &node.images {
         let chain = &images[id];
@@ -316,23 +465,25 @@ fn fitness(
         {
             transfers += 1;
         }
-        wait_factor_from_chains = max(wait_factor_from_chains, chain.last_link_wait_factor);
+        chains_wait_factor = max(chains_wait_factor, chain.last_link_wait_factor);
     }
 
-    // Find best queue for node.
-    let (wait_factor_from_queue, queue) = node
-        .queues
-        .clone()
-        .map(|index| (schedule[index].wait_factor, index))
-        .min()
-        .unwrap();
-    (
-        Fitness {
-            transfers,
-            wait_factor: max(wait_factor_from_chains, wait_factor_from_queue),
-        },
-        queue,
-    )
+    Candidate {
+        node: nodes.ids[node.id],
+        family: node.family,
+        transfers,
+        chains_wait_factor,
+        critical_path: node.critical_path,
+        queues: node
+            .queues
+            .clone()
+            .map(|index| QueueCandidate {
+                id: nodes.queues[index],
+                wait_factor: schedule[index].wait_factor,
+                order: index,
+            })
+            .collect(),
+    }
 }
 
 fn schedule_node<'a>(
@@ -367,7 +518,7 @@ fn schedule_node<'a>(
     }
     for &(id, state) in &node.images {
         add_to_chain(
-            nodes.images[id],
+            nodes.images[id].clone(),
             node.family,
             &mut images[id],
             sid,
@@ -386,13 +537,13 @@ fn schedule_node<'a>(
 }
 
 fn add_to_chain<R, S>(
-    id: Id,
+    id: R::Key,
     family: rendy_core::hal::queue::QueueFamilyId,
     chain_data: &mut ChainData<R>,
     sid: SubmissionId,
     submission: &mut Submission<S>,
     state: State<R>,
-    set_link: impl FnOnce(&mut Submission<S>, Id, usize),
+    set_link: impl FnOnce(&mut Submission<S>, R::Key, usize),
 ) where
     R: Resource,
 {
@@ -0,0 +1,382 @@
+//! This module exports synchronized schedule to the Graphviz DOT format.
+//!
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Write};
+
+use crate::{
+    resource::Resource,
+    schedule::{Schedule, Submission, SubmissionId},
+    subresource::Subresource,
+    sync::{Barriers, SyncData},
+    Id,
+};
+
+impl<S, W> Schedule<SyncData<S, W>>
+where
+    S: Debug + PartialEq<W>,
+    W: Debug,
+{
+    /// Describe the schedule as Graphviz DOT document.
+    ///
+    /// Submissions are grouped into clusters per family and queue
+    /// and connected in the order they are submitted to the queue.
+    /// Each submission lists resource links it belongs to and barriers it must record.
+    /// Semaphores are shown as edges from signaling submission to waiting one,
+    /// and resource links as dashed edges between submissions of consecutive links.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        self.write_dot(&mut dot)
+            .expect("Writing to `String` never fails");
+        dot
+    }
+
+    fn write_dot(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "digraph schedule {{")?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        let mut families: Vec<_> = self.iter().collect();
+        families.sort_by_key(|family| family.id().0);
+
+        let mut submissions = Vec::new();
+
+        for family in families {
+            let fid = family.id().0;
+            writeln!(out)?;
+            writeln!(out, "    subgraph cluster_family_{} {{", fid)?;
+            writeln!(out, "        label=\"family {}\";", fid)?;
+
+            for queue in family.iter() {
+                let qid = queue.id();
+                writeln!(out)?;
+                writeln!(
+                    out,
+                    "        subgraph cluster_queue_{}_{} {{",
+                    fid,
+                    qid.index()
+                )?;
+                writeln!(out, "            label=\"queue {}\";", qid.index())?;
+
+                for submission in queue.iter() {
+                    writeln!(
+                        out,
+                        "            {} [label=\"{}\"];",
+                        dot_id(submission.id()),
+                        submission_label(submission)
+                    )?;
+                    submissions.push(submission);
+                }
+
+                for (prev, next) in queue.iter().zip(queue.iter().skip(1)) {
+                    writeln!(
+                        out,
+                        "            {} -> {} [style=bold];",
+                        dot_id(prev.id()),
+                        dot_id(next.id())
+                    )?;
+                }
+
+                writeln!(out, "        }}")?;
+            }
+
+            writeln!(out, "    }}")?;
+        }
+
+        writeln!(out)?;
+        for signal in &submissions {
+            for semaphore in &signal.sync().signal {
+                let waits = submissions.iter().flat_map(|submission| {
+                    submission
+                        .sync()
+                        .wait
+                        .iter()
+                        .filter(|wait| semaphore.semaphore() == wait.semaphore())
+                        .map(move |wait| (submission, wait))
+                });
+                for (submission, wait) in waits {
+                    writeln!(
+                        out,
+                        "    {} -> {} [color=red, label=\"{}\"];",
+                        dot_id(signal.id()),
+                        dot_id(submission.id()),
+                        escape(format!(
+                            "semaphore {:?}\nwait {:?}",
+                            semaphore.semaphore(),
+                            wait.stage()
+                        ))
+                    )?;
+                }
+            }
+        }
+
+        write_link_edges(out, &submissions, |s| &s.buffer_links)?;
+        write_link_edges(out, &submissions, |s| &s.image_links)?;
+
+        writeln!(out, "}}")
+    }
+}
+
+fn dot_id(sid: SubmissionId) -> String {
+    format!(
+        "s_{}_{}_{}",
+        sid.family().0,
+        sid.queue().index(),
+        sid.index()
+    )
+}
+
+/// Escape text for double-quoted DOT label, making every line left-justified.
+fn escape(text: String) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\l"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push_str("\\l");
+    escaped
+}
+
+fn submission_label<S, W>(submission: &Submission<SyncData<S, W>>) -> String {
+    let mut label = format!(
+        "node {}\nsubmission {}",
+        submission.node(),
+        submission.id().index()
+    );
+    if submission.submit_order() != !0 {
+        label += &format!(", order {}", submission.submit_order());
+    }
+
+    for (id, link) in sorted(&submission.buffer_links) {
+        label += &format!("\n{}: link {}", id.name(), link);
+    }
+    for (subresource, link) in sorted(&submission.image_links) {
+        label += &format!("\n{}: link {}", subresource.name(), link);
+    }
+
+    let sync = submission.sync();
+    barriers_label(&mut label, "acquire", &sync.acquire.buffers);
+    barriers_label(&mut label, "acquire", &sync.acquire.images);
+    barriers_label(&mut label, "release", &sync.release.buffers);
+    barriers_label(&mut label, "release", &sync.release.images);
+
+    escape(label)
+}
+
+fn barriers_label<R>(label: &mut String, side: &str, barriers: &Barriers<R>)
+where
+    R: Resource,
+    R::Key: Name,
+{
+    for (key, barrier) in sorted(barriers) {
+        let (start, end) = (&barrier.states.start, &barrier.states.end);
+        *label += &format!(
+            "\n{} {}: {:?} {:?} {:?} -> {:?} {:?} {:?}",
+            side,
+            key.name(),
+            start.0,
+            start.1,
+            start.2,
+            end.0,
+            end.1,
+            end.2,
+        );
+        if let Some(families) = &barrier.families {
+            *label += &format!(" (family {} -> {})", families.start.0, families.end.0);
+        }
+    }
+}
+
+/// Connect submissions of consecutive links of each resource.
+fn write_link_edges<S, W, K>(
+    out: &mut String,
+    submissions: &[&Submission<SyncData<S, W>>],
+    links: impl Fn(&Submission<SyncData<S, W>>) -> &HashMap<K, usize>,
+) -> std::fmt::Result
+where
+    K: Name + Ord + Clone,
+{
+    let mut chains: BTreeMap<K, BTreeMap<usize, Vec<SubmissionId>>> = BTreeMap::new();
+    for submission in submissions {
+        for (key, &link) in links(submission) {
+            chains
+                .entry(key.clone())
+                .or_default()
+                .entry(link)
+                .or_default()
+                .push(submission.id());
+        }
+    }
+
+    for (key, links) in chains {
+        let links: Vec<_> = links.into_iter().collect();
+        for pair in links.windows(2) {
+            let ((prev_link, prev), (next_link, next)) = (&pair[0], &pair[1]);
+            for &prev in prev {
+                for &next in next {
+                    writeln!(
+                        out,
+                        "    {} -> {} [style=dashed, label=\"{}\"];",
+                        dot_id(prev),
+                        dot_id(next),
+                        escape(format!(
+                            "{}: link {} -> {}",
+                            key.name(),
+                            prev_link,
+                            next_link
+                        ))
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn sorted<K: Ord, T>(map: &HashMap<K, T>) -> Vec<(&K, &T)> {
+    let mut sorted: Vec<_> = map.iter().collect();
+    sorted.sort_by_key(|&(key, _)| key);
+    sorted
+}
+
+/// Name of the resource in labels.
+trait Name {
+    fn name(&self) -> String;
+}
+
+impl Name for Id {
+    fn name(&self) -> String {
+        format!("buffer {}", self.0)
+    }
+}
+
+impl Name for Subresource {
+    fn name(&self) -> String {
+        format!(
+            "image {} {:?} levels {:?} layers {:?}",
+            self.id.0, self.range.aspects, self.range.levels, self.range.layers
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        collect, sync, BufferState, Id, ImageState, Node, Schedule, Subresource, SyncData,
+    };
+    use rendy_core::hal::{buffer, format, image, pso::PipelineStage, queue::QueueFamilyId};
+
+    fn image() -> Subresource {
+        Subresource::new(
+            Id(0),
+            image::SubresourceRange {
+                aspects: format::Aspects::COLOR,
+                levels: 0..1,
+                layers: 0..1,
+            },
+        )
+    }
+
+    fn nodes() -> Vec<Node> {
+        let write = ImageState {
+            access: image::Access::COLOR_ATTACHMENT_WRITE,
+            layout: image::Layout::ColorAttachmentOptimal,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            usage: image::Usage::COLOR_ATTACHMENT,
+        };
+        let storage_image = ImageState {
+            access: image::Access::SHADER_WRITE,
+            layout: image::Layout::General,
+            stages: PipelineStage::COMPUTE_SHADER,
+            usage: image::Usage::STORAGE,
+        };
+        let storage = BufferState {
+            access: buffer::Access::SHADER_WRITE,
+            layout: (),
+            stages: PipelineStage::COMPUTE_SHADER,
+            usage: buffer::Usage::STORAGE,
+        };
+
+        vec![
+            Node {
+                id: 0,
+                family: QueueFamilyId(0),
+                dependencies: vec![],
+                buffers: Default::default(),
+                images: vec![(image(), write)].into_iter().collect(),
+            },
+            Node {
+                id: 1,
+                family: QueueFamilyId(1),
+                dependencies: vec![0],
+                buffers: vec![(Id(0), storage)].into_iter().collect(),
+                images: vec![(image(), storage_image)].into_iter().collect(),
+            },
+        ]
+    }
+
+    fn schedule() -> Schedule<SyncData<usize, usize>> {
+        schedule_of(nodes())
+    }
+
+    fn schedule_of(nodes: Vec<Node>) -> Schedule<SyncData<usize, usize>> {
+        let chains = collect(nodes, |_| 1).unwrap();
+        let mut semaphores = 0..;
+        let mut schedule = sync(&chains, || {
+            let id = semaphores.next().unwrap();
+            (id, id)
+        });
+        schedule.build_order();
+        schedule
+    }
+
+    #[test]
+    fn clusters_and_edges() {
+        let dot = schedule().to_dot();
+        assert!(dot.starts_with("digraph schedule {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("subgraph cluster_family_0 {"));
+        assert!(dot.contains("subgraph cluster_family_1 {"));
+        assert!(dot.contains("subgraph cluster_queue_1_0 {"));
+        assert!(
+            dot.contains("s_0_0_0 [label=\"node 0\\lsubmission 0, order 0\\limage 0 COLOR levels 0..1 layers 0..1: link 0\\l")
+        );
+        assert!(dot.contains("s_1_0_0 [label=\"node 1\\lsubmission 0, order 1\\lbuffer 0: link 0\\limage 0 COLOR levels 0..1 layers 0..1: link 1\\l"));
+        assert!(dot.contains("s_0_0_0 -> s_1_0_0 [color=red, label=\"semaphore "));
+        assert!(dot.contains("\\lwait COMPUTE_SHADER\\l\"];"));
+        assert!(dot.contains("s_1_0_0 -> s_0_0_0 [color=red, label=\"semaphore "));
+        assert!(
+            dot.contains("s_0_0_0 -> s_1_0_0 [style=dashed, label=\"image 0 COLOR levels 0..1 layers 0..1: link 0 -> 1\\l\"];")
+        );
+        assert!(dot.contains("(family 0 -> 1)"));
+        assert!(dot.contains("ColorAttachmentOptimal"));
+    }
+
+    #[test]
+    fn deterministic() {
+        // Enough resources for hash map iteration order to differ between builds.
+        let nodes = || {
+            let mut nodes = nodes();
+            let state = nodes[1].buffers[&Id(0)];
+            for node in &mut nodes {
+                node.buffers.extend((0..32).map(|id| (Id(id), state)));
+            }
+            nodes
+        };
+
+        // Each build gets its own hash map seeds.
+        assert_eq!(schedule_of(nodes()).to_dot(), schedule_of(nodes()).to_dot());
+    }
+
+    #[test]
+    fn escape_label() {
+        assert_eq!(
+            super::escape("say \"a\\b\"\nnext".to_string()),
+            "say \\\"a\\\\b\\\"\\lnext\\l"
+        );
+    }
+}
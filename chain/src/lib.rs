@@ -18,16 +18,23 @@ pub struct Id(pub usize);
 
 mod chain;
 mod collect;
+mod dot;
 mod node;
 mod resource;
 mod schedule;
+mod scheduler;
+mod subresource;
 mod sync;
+mod validate;
 
 pub use crate::{
     chain::{Chain, Link, LinkNode},
-    collect::{collect, Chains, Unsynchronized},
+    collect::{collect, collect_with, Chains, CollectError, Unsynchronized},
     node::{BufferState, ImageState, Node, State},
     resource::{AccessFlags, Buffer, Image, Resource, UsageFlags},
     schedule::{Family, Queue, QueueId, Schedule, Submission, SubmissionId},
+    scheduler::{Candidate, CriticalPath, DedicatedQueue, Greedy, QueueCandidate, Scheduler},
+    subresource::Subresource,
     sync::{sync, Barrier, Barriers, BufferBarriers, Guard, ImageBarriers, Signal, SyncData, Wait},
+    validate::{validate, Hazard, ResourceId, ValidationError},
 };
@@ -2,6 +2,7 @@ use std::collections::hash_map::{HashMap, Iter as HashMapIter};
 
 use crate::{
     resource::{Buffer, Image, Resource},
+    subresource::Subresource,
     Id,
 };
 
@@ -43,8 +44,9 @@ pub struct Node {
     /// Buffer category ids and required state.
     pub buffers: HashMap<Id, State<Buffer>>,
 
-    /// Image category ids and required state.
-    pub images: HashMap<Id, State<Image>>,
+    /// Image subresource ranges and required state.
+    /// Ranges of the same image accessed by the node must not overlap.
+    pub images: HashMap<Subresource, State<Image>>,
 }
 
 impl Node {
@@ -64,7 +66,7 @@ impl Node {
     }
 
     /// Get iterator to image states this node accesses.
-    pub fn images(&self) -> HashMapIter<'_, Id, State<Image>> {
+    pub fn images(&self) -> HashMapIter<'_, Subresource, State<Image>> {
         self.images.iter()
     }
 }
@@ -1,8 +1,11 @@
 use std::{
     fmt::Debug,
+    hash::Hash,
     ops::{BitOr, BitOrAssign},
 };
 
+use crate::{subresource::Subresource, Id};
+
 /// Trait to abstract of specific access flags.
 pub trait AccessFlags: Copy + Debug + BitOr<Output = Self> + BitOrAssign + 'static {
     /// Get flags value with no flags set.
@@ -12,6 +15,9 @@ pub trait AccessFlags: Copy + Debug + BitOr<Output = Self> + BitOrAssign + 'stat
     ///
     /// Basically this checks if all flags are known read flags.
     fn exclusive(&self) -> bool;
+
+    /// Check if all flags of `other` are set.
+    fn contains(&self, other: Self) -> bool;
 }
 
 impl AccessFlags for rendy_core::hal::buffer::Access {
@@ -26,6 +32,11 @@ impl AccessFlags for rendy_core::hal::buffer::Access {
             Self::SHADER_WRITE | Self::TRANSFER_WRITE | Self::HOST_WRITE | Self::MEMORY_WRITE,
         )
     }
+
+    #[inline]
+    fn contains(&self, other: Self) -> bool {
+        Self::contains(self, other)
+    }
 }
 
 impl AccessFlags for rendy_core::hal::image::Access {
@@ -45,6 +56,11 @@ impl AccessFlags for rendy_core::hal::image::Access {
                 | Self::MEMORY_WRITE,
         )
     }
+
+    #[inline]
+    fn contains(&self, other: Self) -> bool {
+        Self::contains(self, other)
+    }
 }
 
 /// Trait to abstract of specific usage flags.
@@ -62,7 +78,10 @@ pub trait Resource: 'static {
     type Usage: UsageFlags;
 
     /// Layout type for the resource.
-    type Layout: Copy + Debug + 'static;
+    type Layout: Copy + Debug + PartialEq + 'static;
+
+    /// Key of the part of the resource tracked by separate chain.
+    type Key: Clone + Debug + Eq + Hash + Ord + 'static;
 
     /// Empty usage.
     fn no_usage() -> Self::Usage;
@@ -79,6 +98,7 @@ impl Resource for Buffer {
     type Access = rendy_core::hal::buffer::Access;
     type Usage = rendy_core::hal::buffer::Usage;
     type Layout = ();
+    type Key = Id;
 
     fn no_usage() -> Self::Usage {
         rendy_core::hal::buffer::Usage::empty()
@@ -98,6 +118,8 @@ impl Resource for Image {
 
     type Layout = rendy_core::hal::image::Layout;
 
+    type Key = Subresource;
+
     fn no_usage() -> Self::Usage {
         rendy_core::hal::image::Usage::empty()
     }
@@ -1,5 +1,5 @@
 use super::queue::QueueId;
-use crate::Id;
+use crate::{subresource::Subresource, Id};
 use std::collections::HashMap;
 
 /// Submission id.
@@ -39,8 +39,8 @@ impl SubmissionId {
 pub struct Submission<S> {
     node: usize,
     id: SubmissionId,
-    buffer_links: HashMap<Id, usize>,
-    image_links: HashMap<Id, usize>,
+    pub(crate) buffer_links: HashMap<Id, usize>,
+    pub(crate) image_links: HashMap<Subresource, usize>,
     wait_factor: usize,
     submit_order: usize,
     sync: S,
@@ -82,14 +82,27 @@ impl<S> Submission<S> {
         assert!(self.buffer_links.insert(id, link).is_none());
     }
 
-    /// Get link index for resource by id.
-    pub fn image_link_index(&self, id: Id) -> usize {
-        self.image_links[&id]
+    /// Get link index for image subresource range.
+    pub fn image_link_index(&self, subresource: &Subresource) -> usize {
+        self.image_links[subresource]
+    }
+
+    /// Get subresource ranges of the image accessed by the submission
+    /// with their link indices, sorted by range.
+    pub fn image_subresources(&self, id: Id) -> Vec<(&Subresource, usize)> {
+        let mut subresources: Vec<_> = self
+            .image_links
+            .iter()
+            .filter(|(subresource, _)| subresource.id == id)
+            .map(|(subresource, &link)| (subresource, link))
+            .collect();
+        subresources.sort();
+        subresources
     }
 
     /// Set link index for given chain.
-    pub fn set_image_link(&mut self, id: Id, link: usize) {
-        assert!(self.image_links.insert(id, link).is_none());
+    pub fn set_image_link(&mut self, subresource: Subresource, link: usize) {
+        assert!(self.image_links.insert(subresource, link).is_none());
     }
 
     /// Create new submission with specified pass.
@@ -0,0 +1,388 @@
+//! This module defines strategies `collect_with` uses to pick nodes and queues.
+
+use std::cmp::{max, Reverse};
+
+use crate::schedule::QueueId;
+
+/// Queue node can be scheduled to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueCandidate {
+    /// Id of the queue.
+    pub id: QueueId,
+
+    /// Wait factor of the queue.
+    /// Node scheduled to this queue will wait at least for this many submissions.
+    pub wait_factor: usize,
+
+    /// Position of the queue among queues of all families.
+    /// Schedulers use it to break ties between nodes of different families.
+    pub order: usize,
+}
+
+/// Node ready to be scheduled.
+/// All its dependencies are already scheduled.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// Id of the node.
+    pub node: usize,
+
+    /// Family of the node.
+    pub family: rendy_core::hal::queue::QueueFamilyId,
+
+    /// Number of resources that will be transferred to the node's family from another one.
+    pub transfers: usize,
+
+    /// Wait factor required by resources node uses.
+    pub chains_wait_factor: usize,
+
+    /// Number of nodes on the longest dependency path starting from this node, including itself.
+    pub critical_path: usize,
+
+    /// Queues of the node's family.
+    pub queues: Vec<QueueCandidate>,
+}
+
+impl Candidate {
+    /// Wait factor of the node if scheduled to the queue.
+    pub fn wait_factor(&self, queue: &QueueCandidate) -> usize {
+        max(self.chains_wait_factor, queue.wait_factor)
+    }
+
+    /// Least loaded queue of the node's family, the one with minimal wait factor.
+    /// Returns index in `queues` and wait factor of the node if scheduled to that queue.
+    pub fn best_queue(&self) -> (usize, usize) {
+        self.queues
+            .iter()
+            .enumerate()
+            .map(|(index, queue)| (queue.wait_factor, index))
+            .min()
+            .map(|(_, index)| (index, self.wait_factor(&self.queues[index])))
+            .expect("Family has at least one queue")
+    }
+}
+
+/// Strategy to schedule nodes.
+///
+/// `collect_with` repeatedly asks scheduler to select one of the ready nodes
+/// and the queue to put it to until all nodes are scheduled.
+///
+/// With single queue nodes are scheduled in dependency order
+/// and scheduler is not consulted.
+pub trait Scheduler: std::fmt::Debug {
+    /// Select next node to schedule.
+    /// Returns index in `candidates` and index in `Candidate::queues`.
+    ///
+    /// `candidates` is never empty.
+    fn select(&mut self, candidates: &[Candidate]) -> (usize, usize);
+}
+
+/// Default scheduler.
+///
+/// Selects node that requires least resource transfers between families
+/// and then least waiting, and puts it to the queue it waits least on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Greedy;
+
+impl Scheduler for Greedy {
+    fn select(&mut self, candidates: &[Candidate]) -> (usize, usize) {
+        candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let (queue, wait_factor) = candidate.best_queue();
+                (
+                    (candidate.transfers, wait_factor),
+                    candidate.queues[queue].order,
+                    queue,
+                    index,
+                )
+            })
+            .min()
+            .map(|(_, _, queue, index)| (index, queue))
+            .unwrap()
+    }
+}
+
+/// Scheduler that selects node on the longest dependency path first.
+///
+/// Nodes with equal critical path are selected as by `Greedy`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CriticalPath;
+
+impl Scheduler for CriticalPath {
+    fn select(&mut self, candidates: &[Candidate]) -> (usize, usize) {
+        candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let (queue, wait_factor) = candidate.best_queue();
+                (
+                    (
+                        Reverse(candidate.critical_path),
+                        candidate.transfers,
+                        wait_factor,
+                    ),
+                    candidate.queues[queue].order,
+                    queue,
+                    index,
+                )
+            })
+            .min()
+            .map(|(_, _, queue, index)| (index, queue))
+            .unwrap()
+    }
+}
+
+/// Scheduler that keeps listed nodes on a dedicated queue.
+///
+/// Listed nodes are put to the last queue of their family,
+/// e.g. to run async compute alongside graphics work.
+/// Other nodes are put to the least loaded of the remaining queues.
+/// If family has single queue all its nodes share it.
+///
+/// Nodes are selected as by `Greedy`.
+#[derive(Clone, Debug, Default)]
+pub struct DedicatedQueue {
+    nodes: Vec<usize>,
+}
+
+impl DedicatedQueue {
+    /// Create scheduler that keeps nodes with specified ids on a dedicated queue.
+    pub fn new(nodes: impl IntoIterator<Item = usize>) -> Self {
+        DedicatedQueue {
+            nodes: nodes.into_iter().collect(),
+        }
+    }
+}
+
+impl Scheduler for DedicatedQueue {
+    fn select(&mut self, candidates: &[Candidate]) -> (usize, usize) {
+        let index = Greedy.select(candidates).0;
+        let queues = &candidates[index].queues;
+        let queue = if self.nodes.contains(&candidates[index].node) {
+            queues.len() - 1
+        } else {
+            queues[..queues.len().max(2) - 1]
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, queue)| queue.wait_factor)
+                .map(|(queue, _)| queue)
+                .unwrap()
+        };
+        (index, queue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Candidate, CriticalPath, DedicatedQueue, Greedy, Scheduler};
+    use crate::{collect, collect_with, BufferState, Chains, CollectError, Id, Node, QueueId};
+    use rendy_core::hal::{buffer, pso::PipelineStage, queue::QueueFamilyId};
+
+    fn node(id: usize, dependencies: Vec<usize>) -> Node {
+        Node {
+            id,
+            family: QueueFamilyId(0),
+            dependencies,
+            buffers: Default::default(),
+            images: Default::default(),
+        }
+    }
+
+    /// Independent node `0` and chain `1 -> 2 -> 3`.
+    fn nodes() -> Vec<Node> {
+        vec![
+            node(0, vec![]),
+            node(1, vec![]),
+            node(2, vec![1]),
+            node(3, vec![2]),
+        ]
+    }
+
+    /// Nodes of two families randomly accessing few buffers.
+    fn random_nodes(mut seed: u32) -> Vec<Node> {
+        let mut next = move |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % n
+        };
+        (0..16)
+            .map(|id| {
+                let mut dependencies: Vec<usize> = if id == 0 {
+                    vec![]
+                } else {
+                    (0..next(3)).map(|_| next(id as u32) as usize).collect()
+                };
+                dependencies.sort();
+                dependencies.dedup();
+                let buffers = (0..next(3) + 1)
+                    .map(|_| {
+                        let access = if next(2) == 0 {
+                            buffer::Access::SHADER_WRITE
+                        } else {
+                            buffer::Access::SHADER_READ
+                        };
+                        let state = BufferState {
+                            access,
+                            layout: (),
+                            stages: PipelineStage::COMPUTE_SHADER,
+                            usage: buffer::Usage::STORAGE,
+                        };
+                        (Id(next(6) as usize), state)
+                    })
+                    .collect();
+                Node {
+                    id,
+                    family: QueueFamilyId(next(2) as usize),
+                    dependencies,
+                    buffers,
+                    images: Default::default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Nodes in submission order with families and queues they are scheduled to.
+    fn family_order(chains: &Chains) -> Vec<(usize, usize, usize)> {
+        let mut order: Vec<_> = chains
+            .schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| {
+                queue.iter().map(move |submission| {
+                    (
+                        submission.submit_order(),
+                        submission.node(),
+                        queue.id().family().0,
+                        queue.id().index(),
+                    )
+                })
+            })
+            .collect();
+        order.sort();
+        order
+            .into_iter()
+            .map(|(_, node, family, queue)| (node, family, queue))
+            .collect()
+    }
+
+    /// Nodes in submission order with queues they are scheduled to.
+    fn order(chains: &Chains) -> Vec<(usize, usize)> {
+        let mut order: Vec<_> = chains
+            .schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| {
+                queue.iter().map(move |submission| {
+                    (
+                        submission.submit_order(),
+                        submission.node(),
+                        queue.id().index(),
+                    )
+                })
+            })
+            .collect();
+        order.sort();
+        order
+            .into_iter()
+            .map(|(_, node, queue)| (node, queue))
+            .collect()
+    }
+
+    /// Selects queue that doesn't exist.
+    #[derive(Debug)]
+    struct OutOfRange;
+
+    impl Scheduler for OutOfRange {
+        fn select(&mut self, candidates: &[Candidate]) -> (usize, usize) {
+            (0, candidates[0].queues.len())
+        }
+    }
+
+    #[test]
+    fn greedy_is_default() {
+        let default = collect(nodes(), |_| 2).unwrap();
+        let greedy = collect_with(nodes(), |_| 2, &mut Greedy).unwrap();
+        assert_eq!(order(&default), order(&greedy));
+        assert_eq!(order(&greedy)[0], (0, 0));
+    }
+
+    #[test]
+    fn greedy_matches_original_order() {
+        // Schedules produced by the scheduling algorithm before `Scheduler` was introduced.
+        #[rustfmt::skip]
+        let expected = [
+            [
+                (0, 1, 0), (3, 1, 1), (1, 0, 0), (9, 0, 1), (11, 1, 0), (4, 0, 0), (7, 0, 1),
+                (15, 0, 0), (2, 0, 1), (13, 0, 0), (5, 0, 0), (8, 0, 1), (12, 1, 1), (6, 1, 0),
+                (14, 1, 1), (10, 1, 0),
+            ],
+            [
+                (0, 1, 0), (5, 1, 1), (10, 1, 0), (15, 1, 1), (1, 1, 0), (3, 1, 1), (12, 1, 0),
+                (11, 1, 0), (2, 0, 0), (14, 0, 1), (8, 0, 0), (7, 0, 1), (4, 0, 0), (6, 1, 1),
+                (9, 1, 0), (13, 0, 1),
+            ],
+            [
+                (0, 1, 0), (7, 1, 1), (12, 1, 0), (5, 1, 1), (8, 0, 0), (14, 0, 1), (15, 0, 0),
+                (1, 0, 0), (2, 1, 0), (3, 0, 1), (6, 0, 0), (10, 1, 1), (9, 1, 0), (4, 1, 1),
+                (11, 1, 0), (13, 1, 0),
+            ],
+            [
+                (0, 0, 0), (7, 0, 1), (4, 1, 0), (2, 0, 0), (8, 0, 1), (14, 1, 1), (11, 1, 0),
+                (12, 0, 0), (1, 1, 1), (3, 0, 1), (6, 1, 0), (10, 1, 1), (9, 1, 0), (13, 1, 1),
+                (5, 1, 0), (15, 0, 0),
+            ],
+        ];
+
+        for (seed, expected) in expected.iter().enumerate() {
+            let chains = collect(random_nodes(seed as u32), |_| 2).unwrap();
+            assert_eq!(family_order(&chains), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn invalid_selection() {
+        match collect_with(nodes(), |_| 2, &mut OutOfRange) {
+            Err(CollectError::InvalidSelection {
+                candidate: 0,
+                queue: 2,
+                ..
+            }) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn critical_path_first() {
+        let chains = collect_with(nodes(), |_| 2, &mut CriticalPath).unwrap();
+        let order = order(&chains);
+        assert_eq!(order[0].0, 1);
+        assert_eq!(order.len(), 4);
+    }
+
+    #[test]
+    fn dedicated_queue() {
+        let chains = collect_with(nodes(), |_| 2, &mut DedicatedQueue::new(vec![0])).unwrap();
+        for (node, queue) in order(&chains) {
+            assert_eq!(queue, if node == 0 { 1 } else { 0 });
+        }
+        let queue = chains
+            .schedule
+            .queue(QueueId::new(QueueFamilyId(0), 1))
+            .unwrap();
+        assert_eq!(queue.len(), 1);
+
+        // Other nodes are balanced between remaining queues.
+        let chains = collect_with(nodes(), |_| 3, &mut DedicatedQueue::new(vec![3])).unwrap();
+        let scheduled = order(&chains);
+        assert_eq!(
+            scheduled.iter().filter(|&&(_, queue)| queue == 2).count(),
+            1
+        );
+        assert!(scheduled.contains(&(0, 0)));
+        assert!(scheduled.contains(&(1, 1)));
+
+        // Family with single queue has nothing to dedicate.
+        let chains = collect_with(nodes(), |_| 1, &mut DedicatedQueue::new(vec![0])).unwrap();
+        assert!(order(&chains).iter().all(|&(_, queue)| queue == 0));
+    }
+}
@@ -0,0 +1,340 @@
+//! This module defines subresource ranges of images tracked by separate chains.
+
+use std::cmp::Ordering;
+
+use rendy_core::hal::{
+    format::Aspects,
+    image::{Layer, Level, SubresourceRange},
+};
+
+use crate::Id;
+
+/// Subresource range of the image.
+/// Nodes access images by subresource ranges.
+/// Each range is split into disjoint ones that are tracked by separate chains,
+/// so that nodes accessing different subresources of the same image are not serialized.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subresource {
+    /// Id of the image.
+    pub id: Id,
+
+    /// Aspects, mip levels and array layers.
+    pub range: SubresourceRange,
+}
+
+impl Subresource {
+    /// Create new subresource of the image.
+    pub fn new(id: Id, range: SubresourceRange) -> Self {
+        Subresource { id, range }
+    }
+
+    /// Check if the subresource range contains the other one.
+    pub fn contains(&self, other: &Self) -> bool {
+        self.id == other.id && contains(&self.range, &other.range)
+    }
+
+    fn sort_key(&self) -> (Id, u8, Level, Level, Layer, Layer) {
+        (
+            self.id,
+            self.range.aspects.bits(),
+            self.range.levels.start,
+            self.range.levels.end,
+            self.range.layers.start,
+            self.range.layers.end,
+        )
+    }
+}
+
+impl PartialOrd for Subresource {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Subresource {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+/// Check if `outer` range contains `inner` range.
+pub(crate) fn contains(outer: &SubresourceRange, inner: &SubresourceRange) -> bool {
+    outer.aspects.contains(inner.aspects)
+        && outer.levels.start <= inner.levels.start
+        && inner.levels.end <= outer.levels.end
+        && outer.layers.start <= inner.layers.start
+        && inner.layers.end <= outer.layers.end
+}
+
+/// Split ranges of one image into disjoint ones.
+/// Every input range is union of some of the output ranges,
+/// and every output range is contained in at least one input range.
+///
+/// Ranges are split along boundaries of all input ranges,
+/// so result can have more ranges than strictly necessary.
+pub(crate) fn split<'a>(
+    ranges: impl IntoIterator<Item = &'a SubresourceRange>,
+) -> Vec<SubresourceRange> {
+    let ranges: Vec<_> = ranges
+        .into_iter()
+        .filter(|range| {
+            !range.aspects.is_empty()
+                && range.levels.start < range.levels.end
+                && range.layers.start < range.layers.end
+        })
+        .collect();
+
+    let mut levels: Vec<Level> = ranges
+        .iter()
+        .flat_map(|range| vec![range.levels.start, range.levels.end])
+        .collect();
+    levels.sort();
+    levels.dedup();
+
+    let mut layers: Vec<Layer> = ranges
+        .iter()
+        .flat_map(|range| vec![range.layers.start, range.layers.end])
+        .collect();
+    layers.sort();
+    layers.dedup();
+
+    // Aspects always used together are never split.
+    let mut aspects: Vec<(Vec<usize>, Aspects)> = Vec::new();
+    for bit in 0..8 {
+        let aspect = Aspects::from_bits_truncate(1 << bit);
+        if aspect.is_empty() {
+            continue;
+        }
+        let users: Vec<usize> = ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.aspects.contains(aspect))
+            .map(|(index, _)| index)
+            .collect();
+        if users.is_empty() {
+            continue;
+        }
+        match aspects.iter_mut().find(|(other, _)| *other == users) {
+            Some((_, aspects)) => *aspects |= aspect,
+            None => aspects.push((users, aspect)),
+        }
+    }
+
+    let mut result = Vec::new();
+    for &(_, aspects) in &aspects {
+        for levels in levels.windows(2) {
+            for layers in layers.windows(2) {
+                let range = SubresourceRange {
+                    aspects,
+                    levels: levels[0]..levels[1],
+                    layers: layers[0]..layers[1],
+                };
+                if ranges.iter().any(|outer| contains(outer, &range)) {
+                    result.push(range);
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{contains, split, Subresource};
+    use crate::{collect, sync, CollectError, Id, ImageState, Node};
+    use rendy_core::hal::{
+        format::Aspects,
+        image::{Access, Layout, SubresourceRange, Usage},
+        pso::PipelineStage,
+        queue::QueueFamilyId,
+    };
+
+    fn range(
+        aspects: Aspects,
+        levels: std::ops::Range<u8>,
+        layers: std::ops::Range<u16>,
+    ) -> SubresourceRange {
+        SubresourceRange {
+            aspects,
+            levels,
+            layers,
+        }
+    }
+
+    #[test]
+    fn same_ranges_are_not_split() {
+        let whole = range(Aspects::COLOR, 0..4, 0..1);
+        assert_eq!(split(&[whole.clone(), whole.clone()]), vec![whole]);
+    }
+
+    #[test]
+    fn mip_levels() {
+        let whole = range(Aspects::COLOR, 0..3, 0..1);
+        let split = split(&[
+            range(Aspects::COLOR, 0..1, 0..1),
+            range(Aspects::COLOR, 1..2, 0..1),
+            whole.clone(),
+        ]);
+        assert_eq!(
+            split,
+            vec![
+                range(Aspects::COLOR, 0..1, 0..1),
+                range(Aspects::COLOR, 1..2, 0..1),
+                range(Aspects::COLOR, 2..3, 0..1),
+            ]
+        );
+        assert!(split.iter().all(|atom| contains(&whole, atom)));
+    }
+
+    #[test]
+    fn layers_and_aspects() {
+        let depth_stencil = Aspects::DEPTH | Aspects::STENCIL;
+        let split = split(&[
+            range(depth_stencil, 0..1, 0..4),
+            range(Aspects::DEPTH, 0..1, 2..3),
+        ]);
+        assert_eq!(
+            split,
+            vec![
+                range(Aspects::DEPTH, 0..1, 0..2),
+                range(Aspects::DEPTH, 0..1, 2..3),
+                range(Aspects::DEPTH, 0..1, 3..4),
+                range(Aspects::STENCIL, 0..1, 0..2),
+                range(Aspects::STENCIL, 0..1, 2..3),
+                range(Aspects::STENCIL, 0..1, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_ranges_are_ignored() {
+        assert!(split(&[range(Aspects::COLOR, 1..1, 0..1)]).is_empty());
+    }
+
+    fn state(access: Access, layout: Layout, usage: Usage) -> ImageState {
+        ImageState {
+            access,
+            layout,
+            stages: PipelineStage::TRANSFER,
+            usage,
+        }
+    }
+
+    #[test]
+    fn mip_chain_barriers() {
+        let level = |level| Subresource::new(Id(0), range(Aspects::COLOR, level..level + 1, 0..1));
+        let write = state(
+            Access::TRANSFER_WRITE,
+            Layout::TransferDstOptimal,
+            Usage::TRANSFER_DST,
+        );
+        let read = state(
+            Access::TRANSFER_READ,
+            Layout::TransferSrcOptimal,
+            Usage::TRANSFER_SRC,
+        );
+        let node = |id, dependencies, images: Vec<(Subresource, ImageState)>| Node {
+            id,
+            family: QueueFamilyId(0),
+            dependencies,
+            buffers: Default::default(),
+            images: images.into_iter().collect(),
+        };
+
+        // Write level 0, blit level 0 to level 1, read whole image.
+        let nodes = vec![
+            node(0, vec![], vec![(level(0), write)]),
+            node(1, vec![0], vec![(level(0), read), (level(1), write)]),
+            node(
+                2,
+                vec![1],
+                vec![(
+                    Subresource::new(Id(0), range(Aspects::COLOR, 0..2, 0..1)),
+                    read,
+                )],
+            ),
+        ];
+
+        let chains = collect(nodes, |_| 1).unwrap();
+        assert_eq!(chains.images.len(), 2);
+        assert_eq!(chains.images[&level(0)].links().len(), 2);
+        assert_eq!(chains.images[&level(1)].links().len(), 2);
+
+        let schedule = sync(&chains, || ((), ()));
+        let submission = |node| {
+            schedule
+                .iter()
+                .flat_map(|family| family.iter())
+                .flat_map(|queue| queue.iter())
+                .find(|submission| submission.node() == node)
+                .unwrap()
+        };
+
+        // Level 0 becomes readable after the first node.
+        let release = &submission(0).sync().release.images;
+        assert_eq!(release.len(), 1);
+        assert_eq!(release[&level(0)].states.end.0, Access::TRANSFER_READ,);
+
+        // Level 1 is written by the second node while level 0 is read.
+        let release = &submission(1).sync().release.images;
+        assert_eq!(release.len(), 1);
+        assert_eq!(
+            release[&level(1)].states,
+            (
+                Access::TRANSFER_WRITE,
+                Layout::TransferDstOptimal,
+                PipelineStage::TRANSFER
+            )
+                ..(
+                    Access::TRANSFER_READ,
+                    Layout::TransferSrcOptimal,
+                    PipelineStage::TRANSFER
+                ),
+        );
+        assert_eq!(submission(2).image_links.len(), 2);
+        assert_eq!(
+            submission(2).image_subresources(Id(0)),
+            vec![(&level(0), 1), (&level(1), 1)]
+        );
+        assert_eq!(
+            submission(1).image_subresources(Id(0)),
+            vec![(&level(0), 1), (&level(1), 0)]
+        );
+        assert!(submission(1).image_subresources(Id(1)).is_empty());
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let write = state(
+            Access::TRANSFER_WRITE,
+            Layout::TransferDstOptimal,
+            Usage::TRANSFER_DST,
+        );
+        let node = Node {
+            id: 3,
+            family: QueueFamilyId(0),
+            dependencies: Vec::new(),
+            buffers: Default::default(),
+            images: vec![
+                (
+                    Subresource::new(Id(1), range(Aspects::COLOR, 0..2, 0..1)),
+                    write,
+                ),
+                (
+                    Subresource::new(Id(1), range(Aspects::COLOR, 1..3, 0..1)),
+                    write,
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        assert_eq!(
+            collect(vec![node], |_| 1).unwrap_err(),
+            CollectError::OverlappingRanges {
+                node: 3,
+                image: Id(1),
+            }
+        );
+    }
+}
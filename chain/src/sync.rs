@@ -138,13 +138,13 @@ where
     }
 }
 
-/// Map of barriers by resource id.
-pub type Barriers<R> = HashMap<Id, Barrier<R>>;
+/// Map of barriers by resource key.
+pub type Barriers<R> = HashMap<<R as Resource>::Key, Barrier<R>>;
 
 /// Map of barriers by buffer id.
 pub type BufferBarriers = Barriers<Buffer>;
 
-/// Map of barriers by image id.
+/// Map of barriers by image subresource range.
 pub type ImageBarriers = Barriers<Image>;
 
 /// Synchronization for submission at one side.
@@ -266,12 +266,21 @@ where
     let ref buffers = chains.buffers;
     let ref images = chains.images;
 
+    // Semaphores generated for different chains must be distinguishable.
+    let mut uids = (0..).map(Id);
+
     let mut sync = SyncTemp(HashMap::default());
-    for (&id, chain) in buffers {
-        sync_chain(id, chain, schedule, &mut sync);
+    for (id, chain) in buffers {
+        sync_chain(id, uids.next().unwrap(), chain, schedule, &mut sync);
     }
-    for (&id, chain) in images {
-        sync_chain(id, chain, schedule, &mut sync);
+    for (subresource, chain) in images {
+        sync_chain(
+            subresource,
+            uids.next().unwrap(),
+            chain,
+            schedule,
+            &mut sync,
+        );
     }
     if schedule.queue_count() > 1 {
         optimize(schedule, &mut sync);
@@ -281,7 +290,11 @@ where
     let mut signals: HashMap<Semaphore, Option<S>> = HashMap::default();
     let mut waits: HashMap<Semaphore, Option<W>> = HashMap::default();
 
-    for queue in schedule.iter().flat_map(|family| family.iter()) {
+    // Visit families in stable order so that semaphores are generated deterministically.
+    let mut families: Vec<_> = schedule.iter().collect();
+    families.sort_by_key(|family| family.id().0);
+
+    for queue in families.into_iter().flat_map(|family| family.iter()) {
         let mut new_queue = Queue::new(queue.id());
         for submission in queue.iter() {
             let sync = if let Some(sync) = sync.0.remove(&submission.id()) {
@@ -368,12 +381,15 @@ fn generate_semaphore_pair<R: Resource>(
     }
 }
 
-fn sync_chain<R, S>(id: Id, chain: &Chain<R>, schedule: &Schedule<S>, sync: &mut SyncTemp)
-where
+fn sync_chain<R, S>(
+    id: &R::Key,
+    uid: Id,
+    chain: &Chain<R>,
+    schedule: &Schedule<S>,
+    sync: &mut SyncTemp,
+) where
     R: Resource,
 {
-    let uid = id.into();
-
     let pairs = chain
         .links()
         .windows(2)
@@ -396,7 +412,7 @@ where
                 sync.get_sync(signal_sid)
                     .release
                     .pick::<R>()
-                    .insert(id, Barrier::new(prev_link.state()..link.state()));
+                    .insert(id.clone(), Barrier::new(prev_link.state()..link.state()));
 
                 // Generate semaphores between queues in the previous link and the current one.
                 for (queue_id, queue) in link.queues() {
@@ -416,7 +432,7 @@ where
                 sync.get_sync(wait_sid)
                     .acquire
                     .pick()
-                    .insert(id, Barrier::new(prev_link.state()..link.state()));
+                    .insert(id.clone(), Barrier::new(prev_link.state()..link.state()));
 
                 if !link.access().exclusive() {
                     unimplemented!("This case is unimplemented");
@@ -435,7 +451,7 @@ where
 
             // Generate barriers to transfer the resource to another family.
             sync.get_sync(signal_sid).release.pick::<R>().insert(
-                id,
+                id.clone(),
                 Barrier::release(
                     signal_sid.family()..wait_sid.family(),
                     (prev_link.access(), prev_link.layout())..,
@@ -443,7 +459,7 @@ where
                 ),
             );
             sync.get_sync(wait_sid).acquire.pick::<R>().insert(
-                id,
+                id.clone(),
                 Barrier::acquire(
                     signal_sid.family()..wait_sid.family(),
                     prev_link.layout()..,
@@ -0,0 +1,830 @@
+//! This module checks that synchronization found by `sync` is sufficient.
+//!
+//! Execution of two consecutive frames is simulated,
+//! so that hazards between the end of one frame and the beginning of the next one are found too.
+//!
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{
+    chain::Chain,
+    collect::Chains,
+    resource::{AccessFlags, Buffer, Image, Resource},
+    schedule::{Schedule, Submission, SubmissionId},
+    subresource::Subresource,
+    sync::{Barrier, Barriers, Guard, SyncData},
+    Id,
+};
+
+/// Kind of hazard between two accesses of the same resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hazard {
+    /// Second access reads what first access writes.
+    ReadAfterWrite,
+
+    /// Second access overwrites what first access reads.
+    WriteAfterRead,
+
+    /// Both accesses write.
+    WriteAfterWrite,
+}
+
+/// Resource or part of it validation error refers to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceId {
+    /// Buffer with the id.
+    Buffer(Id),
+
+    /// Subresource range of the image.
+    Image(Subresource),
+}
+
+/// Synchronization error found by `validate`.
+/// Nodes are referred to by `Node::id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// Accesses are not ordered by queue submission order or semaphores.
+    Unordered {
+        /// Accessed resource.
+        resource: ResourceId,
+        /// Hazard between accesses.
+        hazard: Hazard,
+        /// Node that accesses resource first.
+        first: usize,
+        /// Node that accesses resource second.
+        second: usize,
+    },
+
+    /// Accesses are ordered but there is no pipeline barrier between them.
+    MissingBarrier {
+        /// Accessed resource.
+        resource: ResourceId,
+        /// Hazard between accesses.
+        hazard: Hazard,
+        /// Node that accesses resource first.
+        first: usize,
+        /// Node that accesses resource second.
+        second: usize,
+    },
+
+    /// Barrier between accesses doesn't cover access flags or stages of one of them.
+    InsufficientBarrier {
+        /// Accessed resource.
+        resource: ResourceId,
+        /// Node that accesses resource first.
+        first: usize,
+        /// Node that accesses resource second.
+        second: usize,
+    },
+
+    /// Node uses image in layout different from the one synchronization assumes.
+    LayoutMismatch {
+        /// Accessed image subresource range.
+        resource: Subresource,
+        /// Node that accesses the image.
+        node: usize,
+        /// Layout synchronization assumes.
+        expected: rendy_core::hal::image::Layout,
+        /// Layout node uses.
+        actual: rendy_core::hal::image::Layout,
+    },
+
+    /// Resource is accessed on another queue family without ownership transfer.
+    MissingOwnershipTransfer {
+        /// Accessed resource.
+        resource: ResourceId,
+        /// Node that accesses resource first.
+        first: usize,
+        /// Node that accesses resource second.
+        second: usize,
+        /// Families between which ownership must be transferred.
+        families: Range<rendy_core::hal::queue::QueueFamilyId>,
+    },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::Unordered {
+                resource,
+                hazard,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "{:?} hazard on {:?}: node {} is not ordered before node {}",
+                hazard, resource, first, second
+            ),
+            ValidationError::MissingBarrier {
+                resource,
+                hazard,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "{:?} hazard on {:?}: no barrier between node {} and node {}",
+                hazard, resource, first, second
+            ),
+            ValidationError::InsufficientBarrier {
+                resource,
+                first,
+                second,
+            } => write!(
+                fmt,
+                "Barrier on {:?} between node {} and node {} doesn't cover their accesses",
+                resource, first, second
+            ),
+            ValidationError::LayoutMismatch {
+                resource,
+                node,
+                expected,
+                actual,
+            } => write!(
+                fmt,
+                "Layout mismatch on {:?}: node {} uses {:?} while {:?} is expected",
+                resource, node, actual, expected
+            ),
+            ValidationError::MissingOwnershipTransfer {
+                resource,
+                first,
+                second,
+                families,
+            } => write!(
+                fmt,
+                "Missing ownership transfer of {:?} from family {:?} (node {}) to family {:?} (node {})",
+                resource, families.start, first, families.end, second
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Check that synchronization of the schedule prevents all hazards
+/// between nodes accessing the same resources,
+/// transitions images to layouts nodes use
+/// and transfers ownership of resources between queue families.
+///
+/// `schedule` must be made by `sync` from the `chains`.
+pub fn validate<S, W>(
+    chains: &Chains,
+    schedule: &Schedule<SyncData<S, W>>,
+) -> Result<(), Vec<ValidationError>>
+where
+    S: PartialEq<W>,
+{
+    let order = Order::new(schedule);
+    let mut errors = Vec::new();
+
+    let mut buffers: Vec<_> = chains.buffers.iter().collect();
+    buffers.sort_by_key(|&(id, _)| id);
+    for (id, chain) in buffers {
+        validate_chain(id, chain, schedule, &order, &mut errors);
+    }
+
+    let mut images: Vec<_> = chains.images.iter().collect();
+    images.sort_by_key(|&(subresource, _)| subresource);
+    for (subresource, chain) in images {
+        validate_chain(subresource, chain, schedule, &order, &mut errors);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Resource types validation knows how to report.
+trait Validated: Resource + Sized {
+    fn resource_id(key: &Self::Key) -> ResourceId;
+
+    fn links<S>(submission: &Submission<S>) -> &HashMap<Self::Key, usize>;
+
+    fn barriers(guard: &Guard) -> &Barriers<Self>;
+
+    fn layout_mismatch(
+        key: &Self::Key,
+        node: usize,
+        expected: Self::Layout,
+        actual: Self::Layout,
+    ) -> ValidationError;
+}
+
+impl Validated for Buffer {
+    fn resource_id(key: &Id) -> ResourceId {
+        ResourceId::Buffer(*key)
+    }
+
+    fn links<S>(submission: &Submission<S>) -> &HashMap<Id, usize> {
+        &submission.buffer_links
+    }
+
+    fn barriers(guard: &Guard) -> &Barriers<Self> {
+        &guard.buffers
+    }
+
+    fn layout_mismatch(_: &Id, _: usize, _: (), _: ()) -> ValidationError {
+        unreachable!("Buffers have no layouts")
+    }
+}
+
+impl Validated for Image {
+    fn resource_id(key: &Subresource) -> ResourceId {
+        ResourceId::Image(key.clone())
+    }
+
+    fn links<S>(submission: &Submission<S>) -> &HashMap<Subresource, usize> {
+        &submission.image_links
+    }
+
+    fn barriers(guard: &Guard) -> &Barriers<Self> {
+        &guard.images
+    }
+
+    fn layout_mismatch(
+        key: &Subresource,
+        node: usize,
+        expected: rendy_core::hal::image::Layout,
+        actual: rendy_core::hal::image::Layout,
+    ) -> ValidationError {
+        ValidationError::LayoutMismatch {
+            resource: key.clone(),
+            node,
+            expected,
+            actual,
+        }
+    }
+}
+
+/// Submission in the first or the second frame.
+type Point = (SubmissionId, usize);
+
+/// Happens-before relation between submissions of two consecutive frames.
+struct Order {
+    sids: Vec<SubmissionId>,
+    index: HashMap<SubmissionId, usize>,
+    reach: Vec<Vec<bool>>,
+}
+
+impl Order {
+    fn new<S, W>(schedule: &Schedule<SyncData<S, W>>) -> Self
+    where
+        S: PartialEq<W>,
+    {
+        let submissions: Vec<_> = schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .flat_map(|queue| queue.iter())
+            .collect();
+        let sids: Vec<_> = submissions
+            .iter()
+            .map(|submission| submission.id())
+            .collect();
+        let index: HashMap<_, _> = sids
+            .iter()
+            .enumerate()
+            .map(|(index, &sid)| (sid, index))
+            .collect();
+        let count = sids.len();
+        let node = |sid: SubmissionId, frame: usize| index[&sid] + frame * count;
+
+        let mut edges = vec![Vec::new(); count * 2];
+
+        // Submissions are executed in order they are submitted to the queue.
+        for queue in schedule.iter().flat_map(|family| family.iter()) {
+            let queue: Vec<_> = queue.iter().map(|submission| submission.id()).collect();
+            for pair in queue.windows(2) {
+                for frame in 0..2 {
+                    edges[node(pair[0], frame)].push(node(pair[1], frame));
+                }
+            }
+            if let (Some(&first), Some(&last)) = (queue.first(), queue.last()) {
+                edges[node(last, 0)].push(node(first, 1));
+            }
+        }
+
+        // Semaphores signaled by later submission are waited in the next frame.
+        for signal in &submissions {
+            for semaphore in &signal.sync().signal {
+                for wait in &submissions {
+                    if !wait
+                        .sync()
+                        .wait
+                        .iter()
+                        .any(|wait| semaphore.semaphore() == wait.semaphore())
+                    {
+                        continue;
+                    }
+                    if signal.submit_order() < wait.submit_order() {
+                        for frame in 0..2 {
+                            edges[node(signal.id(), frame)].push(node(wait.id(), frame));
+                        }
+                    } else {
+                        edges[node(signal.id(), 0)].push(node(wait.id(), 1));
+                    }
+                }
+            }
+        }
+
+        let reach = (0..count * 2)
+            .map(|start| {
+                let mut reached = vec![false; count * 2];
+                let mut stack = edges[start].clone();
+                while let Some(next) = stack.pop() {
+                    if !reached[next] {
+                        reached[next] = true;
+                        stack.extend_from_slice(&edges[next]);
+                    }
+                }
+                reached
+            })
+            .collect();
+
+        Order { sids, index, reach }
+    }
+
+    fn node(&self, (sid, frame): Point) -> usize {
+        self.index[&sid] + frame * self.sids.len()
+    }
+
+    /// Check if the first point happens before the second one.
+    fn before(&self, first: Point, second: Point) -> bool {
+        self.reach[self.node(first)][self.node(second)]
+    }
+
+    /// Check if the first point is the second one or happens before it.
+    fn not_after(&self, first: Point, second: Point) -> bool {
+        first == second || self.before(first, second)
+    }
+
+    /// Iterate over all points of both frames.
+    fn points<'a>(&'a self) -> impl Iterator<Item = Point> + 'a {
+        (0..2).flat_map(move |frame| self.sids.iter().map(move |&sid| (sid, frame)))
+    }
+}
+
+fn validate_chain<R, S, W>(
+    key: &R::Key,
+    chain: &Chain<R>,
+    schedule: &Schedule<SyncData<S, W>>,
+    order: &Order,
+    errors: &mut Vec<ValidationError>,
+) where
+    R: Validated,
+{
+    let links = chain.links();
+    if links.is_empty() {
+        return;
+    }
+
+    let mut members = vec![Vec::new(); links.len()];
+    for &sid in &order.sids {
+        if let Some(&link) = R::links(&schedule[sid]).get(key) {
+            members[link].push(sid);
+        }
+    }
+
+    // Last link is followed by the first one in the next frame.
+    let pairs = (1..links.len())
+        .map(|index| (index - 1, index, 0))
+        .chain(Some((links.len() - 1, 0, 1)));
+
+    for (prev, next, frame) in pairs {
+        for &first in &members[prev] {
+            for &second in &members[next] {
+                validate_pair(
+                    key,
+                    (&links[prev], (first, 0)),
+                    (&links[next], (second, frame)),
+                    schedule,
+                    order,
+                    errors,
+                );
+            }
+        }
+    }
+}
+
+fn validate_pair<R, S, W>(
+    key: &R::Key,
+    (first_link, first): (&crate::chain::Link<R>, Point),
+    (second_link, second): (&crate::chain::Link<R>, Point),
+    schedule: &Schedule<SyncData<S, W>>,
+    order: &Order,
+    errors: &mut Vec<ValidationError>,
+) where
+    R: Validated,
+{
+    let first_state = first_link.submission_state(first.0);
+    let second_state = second_link.submission_state(second.0);
+    let first_node = schedule[first.0].node();
+    let second_node = schedule[second.0].node();
+
+    let barrier = |point: Point, release: bool| find_barrier(schedule, key, point, release);
+    let first_layout = (first_node, first_state.layout);
+    let second_layout = (second_node, second_state.layout);
+    let covers = |barrier: &Barrier<R>, stages: bool| {
+        let (start, end) = (&barrier.states.start, &barrier.states.end);
+        start.0.contains(first_state.access)
+            && end.0.contains(second_state.access)
+            && (!stages
+                || start.2.contains(first_state.stages) && end.2.contains(second_state.stages))
+    };
+    let insufficient = || ValidationError::InsufficientBarrier {
+        resource: R::resource_id(key),
+        first: first_node,
+        second: second_node,
+    };
+
+    if first_link.family() != second_link.family() {
+        let families = first_link.family()..second_link.family();
+        let is_transfer = |barrier: &&Barrier<R>| barrier.families.as_ref() == Some(&families);
+
+        let transfer = order
+            .points()
+            .filter(|&release| order.not_after(first, release))
+            .filter_map(|release| {
+                barrier(release, true)
+                    .filter(is_transfer)
+                    .map(|b| (release, b))
+            })
+            .flat_map(|(release, release_barrier)| {
+                order
+                    .points()
+                    .filter(move |&acquire| {
+                        order.before(release, acquire) && order.not_after(acquire, second)
+                    })
+                    .filter_map(move |acquire| barrier(acquire, false).filter(is_transfer))
+                    .map(move |acquire_barrier| (release_barrier, acquire_barrier))
+            })
+            .next();
+
+        match transfer {
+            Some((release, acquire)) => {
+                let barrier = Barrier::<R> {
+                    families: None,
+                    states: release.states.start..acquire.states.end,
+                };
+                // Semaphore between the transfer barriers orders the stages.
+                if !covers(&barrier, false) {
+                    errors.push(insufficient());
+                }
+                check_layouts(key, &barrier, first_layout, second_layout, errors)
+            }
+            None => errors.push(ValidationError::MissingOwnershipTransfer {
+                resource: R::resource_id(key),
+                first: first_node,
+                second: second_node,
+                families,
+            }),
+        }
+        return;
+    }
+
+    let hazard = match (
+        first_state.access.exclusive(),
+        second_state.access.exclusive(),
+    ) {
+        (true, true) => Some(Hazard::WriteAfterWrite),
+        (true, false) => Some(Hazard::ReadAfterWrite),
+        (false, true) => Some(Hazard::WriteAfterRead),
+        (false, false) => None,
+    };
+
+    if hazard.is_none() && first_state.layout == second_state.layout {
+        return;
+    }
+
+    let found = order
+        .points()
+        .filter_map(|point| {
+            let release = barrier(point, true)
+                .filter(|_| order.not_after(first, point) && order.before(point, second));
+            let acquire = barrier(point, false)
+                .filter(|_| order.before(first, point) && order.not_after(point, second));
+            release.or(acquire)
+        })
+        .next();
+
+    match (found, hazard) {
+        (Some(barrier), _) => {
+            if !covers(barrier, true) {
+                errors.push(insufficient());
+            }
+            check_layouts(key, barrier, first_layout, second_layout, errors)
+        }
+        (None, Some(hazard)) if order.before(first, second) => {
+            errors.push(ValidationError::MissingBarrier {
+                resource: R::resource_id(key),
+                hazard,
+                first: first_node,
+                second: second_node,
+            })
+        }
+        (None, Some(hazard)) => errors.push(ValidationError::Unordered {
+            resource: R::resource_id(key),
+            hazard,
+            first: first_node,
+            second: second_node,
+        }),
+        (None, None) => errors.push(R::layout_mismatch(
+            key,
+            second_node,
+            first_state.layout,
+            second_state.layout,
+        )),
+    }
+}
+
+fn find_barrier<'a, R, S, W>(
+    schedule: &'a Schedule<SyncData<S, W>>,
+    key: &R::Key,
+    (sid, _): Point,
+    release: bool,
+) -> Option<&'a Barrier<R>>
+where
+    R: Validated,
+{
+    let sync = schedule[sid].sync();
+    let guard = if release {
+        &sync.release
+    } else {
+        &sync.acquire
+    };
+    R::barriers(guard).get(key)
+}
+
+/// Check that barrier transitions resource from layout first node uses to layout second node uses.
+fn check_layouts<R>(
+    key: &R::Key,
+    barrier: &Barrier<R>,
+    (first_node, first_layout): (usize, R::Layout),
+    (second_node, second_layout): (usize, R::Layout),
+    errors: &mut Vec<ValidationError>,
+) where
+    R: Validated,
+{
+    if barrier.states.start.1 != first_layout {
+        errors.push(R::layout_mismatch(
+            key,
+            first_node,
+            barrier.states.start.1,
+            first_layout,
+        ));
+    }
+    if barrier.states.end.1 != second_layout {
+        errors.push(R::layout_mismatch(
+            key,
+            second_node,
+            barrier.states.end.1,
+            second_layout,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Hazard, ResourceId, ValidationError};
+    use crate::{
+        collect, collect_with, sync, validate, BufferState, Candidate, Chains, Id, ImageState,
+        Node, Queue, Schedule, Scheduler, Subresource, SyncData,
+    };
+    use rendy_core::hal::{
+        buffer, format::Aspects, image, pso::PipelineStage, queue::QueueFamilyId,
+    };
+
+    type TestSchedule = Schedule<SyncData<usize, usize>>;
+
+    fn schedule(chains: &Chains) -> TestSchedule {
+        let mut semaphores = 0..;
+        let mut schedule = sync(chains, || {
+            let id = semaphores.next().unwrap();
+            (id, id)
+        });
+        schedule.build_order();
+        schedule
+    }
+
+    /// Modify synchronization of the node's submission.
+    fn modify(
+        schedule: &mut TestSchedule,
+        node: usize,
+        f: impl FnOnce(&mut SyncData<usize, usize>),
+    ) {
+        let queue = schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .find(|queue| queue.iter().any(|submission| submission.node() == node))
+            .unwrap();
+        let mut modified = Queue::new(queue.id());
+        let mut f = Some(f);
+        for submission in queue.iter() {
+            let mut sync = submission.sync().clone();
+            if submission.node() == node {
+                f.take().unwrap()(&mut sync);
+            }
+            modified.add_submission_checked(submission.set_sync(sync));
+        }
+        schedule.set_queue(modified);
+    }
+
+    fn level(level: u8) -> Subresource {
+        Subresource::new(
+            Id(0),
+            image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: level..level + 1,
+                layers: 0..1,
+            },
+        )
+    }
+
+    fn image_state(access: image::Access, layout: image::Layout) -> ImageState {
+        ImageState {
+            access,
+            layout,
+            stages: PipelineStage::TRANSFER,
+            usage: image::Usage::TRANSFER_SRC | image::Usage::TRANSFER_DST,
+        }
+    }
+
+    fn buffer_state(access: buffer::Access) -> BufferState {
+        BufferState {
+            access,
+            layout: (),
+            stages: PipelineStage::COMPUTE_SHADER,
+            usage: buffer::Usage::STORAGE,
+        }
+    }
+
+    fn node(id: usize, family: usize, dependencies: Vec<usize>) -> Node {
+        Node {
+            id,
+            family: QueueFamilyId(family),
+            dependencies,
+            buffers: Default::default(),
+            images: Default::default(),
+        }
+    }
+
+    /// Write level 0 then blit level 0 to level 1.
+    fn mip_chain() -> Chains {
+        let write = image_state(
+            image::Access::TRANSFER_WRITE,
+            image::Layout::TransferDstOptimal,
+        );
+        let read = image_state(
+            image::Access::TRANSFER_READ,
+            image::Layout::TransferSrcOptimal,
+        );
+        let mut first = node(0, 0, vec![]);
+        first.images.insert(level(0), write);
+        let mut second = node(1, 0, vec![0]);
+        second.images.insert(level(0), read);
+        second.images.insert(level(1), write);
+        collect(vec![first, second], |_| 1).unwrap()
+    }
+
+    /// Write buffer on family 0 then write it on family 1.
+    fn transfer() -> Chains {
+        let write = buffer_state(buffer::Access::SHADER_WRITE);
+        let mut first = node(0, 0, vec![]);
+        first.buffers.insert(Id(0), write);
+        let mut second = node(1, 1, vec![0]);
+        second.buffers.insert(Id(0), write);
+        collect(vec![first, second], |_| 1).unwrap()
+    }
+
+    /// Puts each node to the queue with index equal to node id.
+    #[derive(Debug)]
+    struct QueuePerNode;
+
+    impl Scheduler for QueuePerNode {
+        fn select(&mut self, candidates: &[Candidate]) -> (usize, usize) {
+            (0, candidates[0].node)
+        }
+    }
+
+    /// Write buffer on one queue then read it on another queue of the same family.
+    fn two_queues() -> Chains {
+        let mut first = node(0, 0, vec![]);
+        first
+            .buffers
+            .insert(Id(0), buffer_state(buffer::Access::SHADER_WRITE));
+        let mut second = node(1, 0, vec![0]);
+        second
+            .buffers
+            .insert(Id(0), buffer_state(buffer::Access::SHADER_READ));
+        collect_with(vec![first, second], |_| 2, &mut QueuePerNode).unwrap()
+    }
+
+    #[test]
+    fn synchronized_schedules_are_valid() {
+        for chains in &[mip_chain(), transfer(), two_queues()] {
+            assert_eq!(validate(chains, &schedule(chains)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn missing_barrier() {
+        let chains = mip_chain();
+        let mut schedule = schedule(&chains);
+        modify(&mut schedule, 0, |sync| {
+            sync.release.images.clear();
+        });
+        let errors = validate(&chains, &schedule).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ValidationError::MissingBarrier {
+                resource: ResourceId::Image(level(0)),
+                hazard: Hazard::ReadAfterWrite,
+                first: 0,
+                second: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn unordered() {
+        let chains = two_queues();
+        let mut schedule = schedule(&chains);
+        modify(&mut schedule, 1, |sync| {
+            sync.wait.clear();
+        });
+        let errors = validate(&chains, &schedule).unwrap_err();
+        assert!(errors.contains(&ValidationError::Unordered {
+            resource: ResourceId::Buffer(Id(0)),
+            hazard: Hazard::ReadAfterWrite,
+            first: 0,
+            second: 1,
+        }));
+    }
+
+    #[test]
+    fn layout_mismatch() {
+        let chains = mip_chain();
+        let mut schedule = schedule(&chains);
+        modify(&mut schedule, 0, |sync| {
+            let barrier = sync.release.images.get_mut(&level(0)).unwrap();
+            barrier.states.end.1 = image::Layout::General;
+        });
+        let errors = validate(&chains, &schedule).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ValidationError::LayoutMismatch {
+                resource: level(0),
+                node: 1,
+                expected: image::Layout::General,
+                actual: image::Layout::TransferSrcOptimal,
+            }]
+        );
+    }
+
+    #[test]
+    fn insufficient_barrier() {
+        let chains = mip_chain();
+        let error = ValidationError::InsufficientBarrier {
+            resource: ResourceId::Image(level(0)),
+            first: 0,
+            second: 1,
+        };
+
+        // Barrier doesn't make the write available.
+        let mut access = schedule(&chains);
+        modify(&mut access, 0, |sync| {
+            let barrier = sync.release.images.get_mut(&level(0)).unwrap();
+            barrier.states.start.0 = image::Access::empty();
+        });
+        assert_eq!(validate(&chains, &access), Err(vec![error.clone()]));
+
+        // Barrier doesn't block the stage reading the image.
+        let mut stages = schedule(&chains);
+        modify(&mut stages, 0, |sync| {
+            let barrier = sync.release.images.get_mut(&level(0)).unwrap();
+            barrier.states.end.2 = PipelineStage::BOTTOM_OF_PIPE;
+        });
+        assert_eq!(validate(&chains, &stages), Err(vec![error]));
+    }
+
+    #[test]
+    fn missing_ownership_transfer() {
+        let chains = transfer();
+        let mut schedule = schedule(&chains);
+        modify(&mut schedule, 1, |sync| {
+            sync.acquire.buffers.get_mut(&Id(0)).unwrap().families = None;
+        });
+        let errors = validate(&chains, &schedule).unwrap_err();
+        assert!(
+            errors.contains(&ValidationError::MissingOwnershipTransfer {
+                resource: ResourceId::Buffer(Id(0)),
+                first: 0,
+                second: 1,
+                families: QueueFamilyId(0)..QueueFamilyId(1),
+            }),
+            "{:?}",
+            errors
+        );
+    }
+}
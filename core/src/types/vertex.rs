@@ -138,6 +138,45 @@ impl AsAttribute for TexCoord {
     const FORMAT: Format = Format::Rg32Sfloat;
 }
 
+/// Type for joint indices attribute of skinned vertex.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Joints(pub [u16; 4]);
+impl<T> From<T> for Joints
+where
+    T: Into<[u16; 4]>,
+{
+    fn from(from: T) -> Self {
+        Joints(from.into())
+    }
+}
+
+impl AsAttribute for Joints {
+    const NAME: &'static str = "joints";
+    const FORMAT: Format = Format::Rgba16Uint;
+}
+
+/// Type for joint weights attribute of skinned vertex.
+/// Weights should add up to 1.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Weights(pub [f32; 4]);
+impl<T> From<T> for Weights
+where
+    T: Into<[f32; 4]>,
+{
+    fn from(from: T) -> Self {
+        Weights(from.into())
+    }
+}
+
+impl AsAttribute for Weights {
+    const NAME: &'static str = "weights";
+    const FORMAT: Format = Format::Rgba32Sfloat;
+}
+
 /// Vertex format contains information to initialize graphics pipeline
 /// Attributes must be sorted by offset.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -75,7 +75,7 @@ unsafe fn allocate_from_pool<B: Backend>(
     allocation: &mut SmallVec<[B::DescriptorSet; 1]>,
 ) -> Result<(), OutOfMemory> {
     let sets_were = allocation.len();
-    raw.allocate_set(layout)
+    raw.allocate((0..count).map(|_| layout), allocation)
         .map_err(|err| match err {
             AllocationError::Host => OutOfMemory::Host,
             AllocationError::Device => OutOfMemory::Device,
@@ -3,7 +3,7 @@ use std::cmp::min;
 use crate::{
     command::FamilyId,
     core::DeviceId,
-    memory::{DynamicConfig, HeapsConfig, LinearConfig},
+    memory::{DynamicConfig, HeapBudget, HeapsConfig, LinearConfig, PoolConfig},
 };
 
 /// Factory initialization config.
@@ -119,8 +119,10 @@ unsafe impl QueuesConfigure for SavedQueueConfig {
 /// Method [`configure`] receives memory properties and
 /// emits iterator memory types together with configurations for allocators and
 /// iterator over heaps sizes.
+/// Method [`budgets`] may set soft limits on the memory allocated from heaps.
 ///
 /// [`configure`]: trait.HeapsConfigure.html#tymethod.configure
+/// [`budgets`]: trait.HeapsConfigure.html#method.budgets
 pub unsafe trait HeapsConfigure {
     /// Iterator over memory types.
     type Types: IntoIterator<Item = (rendy_core::hal::memory::Properties, u32, HeapsConfig)>;
@@ -133,12 +135,25 @@ pub unsafe trait HeapsConfigure {
         &self,
         properties: &rendy_core::hal::adapter::MemoryProperties,
     ) -> (Self::Types, Self::Heaps);
+
+    /// Budgets for heaps, in the same order as heaps.
+    /// Heaps without budget can be omitted from the end.
+    fn budgets(
+        &self,
+        _properties: &rendy_core::hal::adapter::MemoryProperties,
+    ) -> Vec<Option<HeapBudget>> {
+        Vec::new()
+    }
 }
 
 /// Basic heaps config.
 /// It uses some arbitrary values that can be considered sane default
 /// for today (year 2019) hardware and software.
 ///
+/// Shards are not configured, as each of them holds its own partially used chunks.
+/// Set `HeapsConfig::shards` in custom configuration
+/// if many threads allocate small blocks concurrently.
+///
 /// If default allocators configuration is suboptimal for the particular use case
 /// a custom [`HeapsConfigure`] implementation can be used instead.
 ///
@@ -185,6 +200,9 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
                             (properties.memory_heaps[mt.heap_index] / 128).next_power_of_two(),
                         ),
                     }),
+                    tlsf: None,
+                    shards: None,
+                    pool: Some(PoolConfig { chunk_size: _1mb }),
                 };
 
                 (mt.properties, mt.heap_index as u32, config)
@@ -205,6 +223,8 @@ unsafe impl HeapsConfigure for BasicHeapsConfigure {
 pub struct SavedHeapsConfig {
     types: Vec<(rendy_core::hal::memory::Properties, u32, HeapsConfig)>,
     heaps: Vec<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    budgets: Vec<Option<HeapBudget>>,
 }
 
 unsafe impl HeapsConfigure for SavedHeapsConfig {
@@ -217,6 +237,13 @@ unsafe impl HeapsConfigure for SavedHeapsConfig {
     ) -> (Self::Types, Self::Heaps) {
         (self.types.clone(), self.heaps.clone())
     }
+
+    fn budgets(
+        &self,
+        _properties: &rendy_core::hal::adapter::MemoryProperties,
+    ) -> Vec<Option<HeapBudget>> {
+        self.budgets.clone()
+    }
 }
 
 /// Devices configuration.
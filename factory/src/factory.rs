@@ -7,9 +7,13 @@ use {
         config::{Config, DevicesConfigure, HeapsConfigure, QueuesConfigure},
         core::{rendy_with_slow_safety_checks, Device, DeviceId, Instance, InstanceId},
         descriptor::DescriptorAllocator,
-        memory::{self, Heaps, MemoryUsage, TotalMemoryUtilization, Write},
+        memory::{
+            self, Block, BudgetEvent, DefragmentationConfig, FaultPolicy, HeapBudget, Heaps,
+            HeapsError, MemoryBlock, MemoryFallback, MemoryReport, MemoryTypePolicy, MemoryUsage,
+            TotalMemoryUtilization, Write,
+        },
         resource::*,
-        upload::{BufferState, ImageState, ImageStateOrLayout, Uploader},
+        upload::{BufferState, ImageState, ImageStateOrLayout, Readback, Uploader},
         wsi::{Surface, SwapchainError, Target},
     },
     rendy_core::{
@@ -32,6 +36,14 @@ use {
     thread_profiler::profile_scope,
 };
 
+/// Relocatable resource registered in the factory.
+#[derive(Debug)]
+struct RelocatableEntry<T, S> {
+    resource: WeakRelocatable<T>,
+    memory_usage: Box<dyn MemoryUsage + Send + Sync>,
+    state: S,
+}
+
 #[derive(Debug)]
 struct ResourceHub<B: Backend> {
     buffers: ResourceTracker<Buffer<B>>,
@@ -41,6 +53,8 @@ struct ResourceHub<B: Backend> {
     sets: ResourceTracker<DescriptorSet<B>>,
     samplers: ResourceTracker<Sampler<B>>,
     samplers_cache: parking_lot::RwLock<SamplerCache<B>>,
+    relocatable_buffers: parking_lot::Mutex<Vec<RelocatableEntry<Buffer<B>, BufferState>>>,
+    relocatable_images: parking_lot::Mutex<Vec<RelocatableEntry<Image<B>, ImageState>>>,
 }
 
 impl<B> Default for ResourceHub<B>
@@ -56,6 +70,8 @@ where
             sets: ResourceTracker::default(),
             samplers: ResourceTracker::default(),
             samplers_cache: parking_lot::RwLock::new(SamplerCache::default()),
+            relocatable_buffers: parking_lot::Mutex::new(Vec::new()),
+            relocatable_images: parking_lot::Mutex::new(Vec::new()),
         }
     }
 }
@@ -67,7 +83,7 @@ where
     unsafe fn cleanup(
         &mut self,
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &Heaps<B>,
         allocator: &mut DescriptorAllocator<B>,
         next: Epochs,
         complete: Epochs,
@@ -88,7 +104,7 @@ where
     unsafe fn dispose(
         mut self,
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &Heaps<B>,
         allocator: &mut DescriptorAllocator<B>,
     ) {
         drop(self.samplers_cache);
@@ -110,6 +126,14 @@ pub enum UploadError {
     Map(MapError),
     /// Failed to upload the data.
     Upload(OutOfMemory),
+    /// Resource was last used on a queue other than the one performing the transfer.
+    /// Synchronizing resources across queues is not supported.
+    CrossQueue,
+    /// Resource was not created with usage required for the transfer.
+    Usage,
+    /// Requested region is empty, lies outside of the resource
+    /// or refers to aspects resource doesn't have.
+    Region,
 }
 
 impl std::fmt::Display for UploadError {
@@ -118,6 +142,9 @@ impl std::fmt::Display for UploadError {
             UploadError::Create(err) => write!(fmt, "Upload failed: {:?}", err),
             UploadError::Map(err) => write!(fmt, "Upload failed: {:?}", err),
             UploadError::Upload(err) => write!(fmt, "Upload failed: {:?}", err),
+            UploadError::CrossQueue => write!(fmt, "Can't sync resources across queues"),
+            UploadError::Usage => write!(fmt, "Resource lacks transfer usage"),
+            UploadError::Region => write!(fmt, "Invalid resource region"),
         }
     }
 }
@@ -128,10 +155,99 @@ impl std::error::Error for UploadError {
             UploadError::Create(err) => Some(err),
             UploadError::Map(err) => Some(err),
             UploadError::Upload(err) => Some(err),
+            UploadError::CrossQueue | UploadError::Usage | UploadError::Region => None,
         }
     }
 }
 
+/// Failure relocating a buffer or an image during defragmentation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DefragmentationError {
+    /// Failed to create relocated buffer.
+    CreateBuffer(BufferCreationError),
+    /// Failed to create relocated image.
+    CreateImage(ImageCreationError),
+    /// Failed to record the copy.
+    Copy(OutOfMemory),
+}
+
+impl std::fmt::Display for DefragmentationError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DefragmentationError::CreateBuffer(err) => {
+                write!(fmt, "Defragmentation failed: {:?}", err)
+            }
+            DefragmentationError::CreateImage(err) => {
+                write!(fmt, "Defragmentation failed: {:?}", err)
+            }
+            DefragmentationError::Copy(err) => write!(fmt, "Defragmentation failed: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for DefragmentationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DefragmentationError::CreateBuffer(err) => Some(err),
+            DefragmentationError::CreateImage(err) => Some(err),
+            DefragmentationError::Copy(err) => Some(err),
+        }
+    }
+}
+
+/// Defragmentation pass interrupted by failed relocation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DefragmentationFailure {
+    /// Resources relocated before the failure.
+    /// No chunks are released as the rest of the selected resources are left in place.
+    pub partial: Defragmentation,
+
+    /// Error that interrupted the pass.
+    pub error: DefragmentationError,
+}
+
+impl std::fmt::Display for DefragmentationFailure {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} after relocating {} buffers and {} images",
+            self.error, self.partial.buffers, self.partial.images
+        )
+    }
+}
+
+impl std::error::Error for DefragmentationFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Outcome of the defragmentation pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Defragmentation {
+    /// Number of relocated buffers.
+    pub buffers: usize,
+
+    /// Number of relocated images.
+    pub images: usize,
+
+    /// Total size of memory blocks of relocated resources.
+    pub bytes: u64,
+
+    /// Number of chunks that will be released once replaced resources are destroyed.
+    pub chunks: usize,
+
+    /// Total size of the chunks that will be released.
+    pub released: u64,
+}
+
+/// Relocatable resource selected for relocation.
+#[derive(Clone, Copy, Debug)]
+enum Relocation {
+    Buffer(usize),
+    Image(usize),
+}
+
 #[derive(Debug)]
 enum InstanceOrId<B: Backend> {
     Instance(Instance<B>),
@@ -162,7 +278,7 @@ where
 #[derive(Debug)]
 pub struct Factory<B: Backend> {
     descriptor_allocator: ManuallyDrop<parking_lot::Mutex<DescriptorAllocator<B>>>,
-    heaps: ManuallyDrop<parking_lot::Mutex<Heaps<B>>>,
+    heaps: ManuallyDrop<parking_lot::RwLock<Heaps<B>>>,
     resources: ManuallyDrop<ResourceHub<B>>,
     epochs: Vec<parking_lot::RwLock<Vec<u64>>>,
     uploader: Uploader<B>,
@@ -249,7 +365,7 @@ where
     ) -> Result<Buffer<B>, BufferCreationError> {
         profile_scope!("create_relevant_buffer");
 
-        unsafe { Buffer::create(&self.device, &mut self.heaps.lock(), info, memory_usage) }
+        unsafe { Buffer::create(&self.device, &self.heaps.read(), info, memory_usage) }
     }
 
     /// Destroy buffer.
@@ -263,7 +379,7 @@ where
     ///
     /// [`create_buffer`]: #method.create_buffer
     pub unsafe fn destroy_relevant_buffer(&self, buffer: Buffer<B>) {
-        buffer.dispose(&self.device, &mut self.heaps.lock());
+        buffer.dispose(&self.device, &self.heaps.read());
     }
 
     /// Creates a buffer with the specified properties.
@@ -280,6 +396,29 @@ where
         Ok(self.resources.buffers.escape(buffer))
     }
 
+    /// Creates a buffer without memory bound to it.
+    /// Returns the buffer and its memory requirements.
+    ///
+    /// Memory allocated with [`allocate_memory`] can be bound to the buffer using [`Buffer::bind`].
+    /// Then the buffer can be wrapped with [`escape_buffer`].
+    ///
+    /// [`allocate_memory`]: #method.allocate_memory
+    /// [`escape_buffer`]: #method.escape_buffer
+    /// [`Buffer::bind`]: ../rendy_resource/struct.Buffer.html#method.bind
+    pub fn create_relevant_unbound_buffer(
+        &self,
+        info: BufferInfo,
+    ) -> Result<(Buffer<B>, rendy_core::hal::memory::Requirements), BufferCreationError> {
+        profile_scope!("create_relevant_unbound_buffer");
+
+        unsafe { Buffer::create_unbound(&self.device, info) }
+    }
+
+    /// Wrap relevant buffer into value that can be dropped.
+    pub fn escape_buffer(&self, buffer: Buffer<B>) -> Escape<Buffer<B>> {
+        self.resources.buffers.escape(buffer)
+    }
+
     /// Creates an image with the specified properties.
     ///
     /// This function returns relevant value, that is, the value cannot be dropped.
@@ -293,7 +432,7 @@ where
     ) -> Result<Image<B>, ImageCreationError> {
         profile_scope!("create_relevant_image");
 
-        unsafe { Image::create(&self.device, &mut self.heaps.lock(), info, memory_usage) }
+        unsafe { Image::create(&self.device, &self.heaps.read(), info, memory_usage) }
     }
 
     /// Destroy image.
@@ -307,7 +446,7 @@ where
     ///
     /// [`create_image`]: #method.create_image
     pub unsafe fn destroy_relevant_image(&self, image: Image<B>) {
-        image.dispose(&self.device, &mut self.heaps.lock());
+        image.dispose(&self.device, &self.heaps.read());
     }
 
     /// Creates an image with the specified properties.
@@ -324,6 +463,241 @@ where
         Ok(self.resources.images.escape(image))
     }
 
+    /// Creates an image without memory bound to it.
+    /// Returns the image and its memory requirements.
+    ///
+    /// Memory allocated with [`allocate_memory`] can be bound to the image using [`Image::bind`].
+    /// Then the image can be wrapped with [`escape_image`].
+    ///
+    /// [`allocate_memory`]: #method.allocate_memory
+    /// [`escape_image`]: #method.escape_image
+    /// [`Image::bind`]: ../rendy_resource/struct.Image.html#method.bind
+    pub fn create_relevant_unbound_image(
+        &self,
+        info: ImageInfo,
+    ) -> Result<(Image<B>, rendy_core::hal::memory::Requirements), ImageCreationError> {
+        profile_scope!("create_relevant_unbound_image");
+
+        unsafe { Image::create_unbound(&self.device, info) }
+    }
+
+    /// Wrap relevant image into value that can be dropped.
+    pub fn escape_image(&self, image: Image<B>) -> Escape<Image<B>> {
+        self.resources.images.escape(image)
+    }
+
+    /// Allocate memory block directly from the heaps.
+    /// Block must be freed with [`free_memory`].
+    ///
+    /// [`free_memory`]: #method.free_memory
+    pub fn allocate_memory(
+        &self,
+        mask: u32,
+        memory_usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        profile_scope!("allocate_memory");
+
+        self.heaps
+            .read()
+            .allocate(&self.device, mask, memory_usage, size, align)
+    }
+
+    /// Free memory block allocated with [`allocate_memory`].
+    ///
+    /// # Safety
+    ///
+    /// Resources bound to the block must not be used after this call.
+    ///
+    /// [`allocate_memory`]: #method.allocate_memory
+    pub unsafe fn free_memory(&self, block: MemoryBlock<B>) {
+        self.heaps.read().free(&self.device, block);
+    }
+
+    /// Creates a buffer that can be relocated by [`defragment`].
+    ///
+    /// Buffer is created with `TRANSFER_SRC` and `TRANSFER_DST` usage in addition to requested.
+    /// `state` is the state buffer is left in between uses.
+    /// Relocation copies buffer content assuming it is in that state.
+    ///
+    /// [`defragment`]: #method.defragment
+    pub fn create_relocatable_buffer(
+        &self,
+        mut info: BufferInfo,
+        memory_usage: impl MemoryUsage + Send + Sync + 'static,
+        state: BufferState,
+    ) -> Result<Relocatable<Buffer<B>>, BufferCreationError> {
+        info.usage |= buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST;
+        let buffer = self.create_buffer(info, &memory_usage)?;
+        let relocatable = Relocatable::new(buffer.into());
+        self.resources
+            .relocatable_buffers
+            .lock()
+            .push(RelocatableEntry {
+                resource: relocatable.downgrade(),
+                memory_usage: Box::new(memory_usage),
+                state,
+            });
+        Ok(relocatable)
+    }
+
+    /// Creates an image that can be relocated by [`defragment`].
+    ///
+    /// Image is created with `TRANSFER_SRC` and `TRANSFER_DST` usage in addition to requested.
+    /// `state` is the state image is left in between uses.
+    /// Relocation copies all levels and layers of the image assuming it is in that state.
+    ///
+    /// [`defragment`]: #method.defragment
+    pub fn create_relocatable_image(
+        &self,
+        mut info: ImageInfo,
+        memory_usage: impl MemoryUsage + Send + Sync + 'static,
+        state: ImageState,
+    ) -> Result<Relocatable<Image<B>>, ImageCreationError> {
+        info.usage |= image::Usage::TRANSFER_SRC | image::Usage::TRANSFER_DST;
+        let image = self.create_image(info, &memory_usage)?;
+        let relocatable = Relocatable::new(image.into());
+        self.resources
+            .relocatable_images
+            .lock()
+            .push(RelocatableEntry {
+                resource: relocatable.downgrade(),
+                memory_usage: Box::new(memory_usage),
+                state,
+            });
+        Ok(relocatable)
+    }
+
+    /// Move relocatable buffers and images out of sparse chunks of dynamic allocators,
+    /// so that those chunks can be released.
+    ///
+    /// Each selected resource is replaced with a copy bound to newly allocated memory.
+    /// Copy operation will actually be submitted to the graphics device queue
+    /// upon next [`flush_uploads`] or [`maintain`] call to this `Factory`.
+    /// Replaced resources are destroyed as usual once copies are complete
+    /// and all handles to them are dropped.
+    ///
+    /// If relocation of a resource fails, resources relocated so far stay relocated
+    /// and are reported in the returned [`DefragmentationFailure`].
+    /// The rest of the selected resources are left in place
+    /// and selected chunks are used for new allocations again.
+    ///
+    /// # Safety
+    ///
+    /// Relocatable resources must be in states specified on creation when copies are executed.
+    /// Replaced resources must not be written after copies are submitted.
+    ///
+    /// [`flush_uploads`]: #method.flush_uploads
+    /// [`maintain`]: #method.maintain
+    /// [`DefragmentationFailure`]: struct.DefragmentationFailure.html
+    pub unsafe fn defragment(
+        &self,
+        config: DefragmentationConfig,
+    ) -> Result<Defragmentation, DefragmentationFailure> {
+        profile_scope!("defragment");
+
+        let mut buffer_entries = self.resources.relocatable_buffers.lock();
+        let mut image_entries = self.resources.relocatable_images.lock();
+        buffer_entries.retain(|entry| entry.resource.upgrade().is_some());
+        image_entries.retain(|entry| entry.resource.upgrade().is_some());
+
+        let buffers: Vec<_> = buffer_entries
+            .iter()
+            .filter_map(|entry| {
+                let relocatable = entry.resource.upgrade()?;
+                let buffer = relocatable.get();
+                Some((entry, relocatable, buffer))
+            })
+            .collect();
+
+        let images: Vec<_> = image_entries
+            .iter()
+            .filter_map(|entry| {
+                let relocatable = entry.resource.upgrade()?;
+                let image = relocatable.get();
+                Some((entry, relocatable, image))
+            })
+            .collect();
+
+        let plan = self.heaps.write().plan_defragmentation(
+            buffers
+                .iter()
+                .enumerate()
+                .filter_map(|(index, (_, _, buffer))| {
+                    Some((Relocation::Buffer(index), buffer.block()?))
+                })
+                .chain(
+                    images
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, (_, _, image))| {
+                            Some((Relocation::Image(index), image.block()?))
+                        }),
+                ),
+            config,
+        );
+
+        log::debug!(
+            "Relocate {} resources ({} bytes) to release {} chunks ({} bytes)",
+            plan.moves.len(),
+            plan.bytes,
+            plan.chunks,
+            plan.released
+        );
+
+        let mut defragmentation = Defragmentation::default();
+
+        let mut relocate = |relocation| -> Result<(), DefragmentationError> {
+            match relocation {
+                Relocation::Buffer(index) => {
+                    let (entry, relocatable, buffer) = &buffers[index];
+                    let relocated: Handle<Buffer<B>> = self
+                        .create_buffer(*buffer.info(), &entry.memory_usage)
+                        .map_err(DefragmentationError::CreateBuffer)?
+                        .into();
+                    self.uploader
+                        .relocate_buffer(&self.device, buffer.clone(), &relocated, entry.state)
+                        .map_err(DefragmentationError::Copy)?;
+                    relocatable.relocate(relocated);
+                    defragmentation.buffers += 1;
+                    defragmentation.bytes += buffer.block().map_or(0, Block::size);
+                }
+                Relocation::Image(index) => {
+                    let (entry, relocatable, image) = &images[index];
+                    let relocated: Handle<Image<B>> = self
+                        .create_image(*image.info(), &entry.memory_usage)
+                        .map_err(DefragmentationError::CreateImage)?
+                        .into();
+                    self.uploader
+                        .relocate_image(&self.device, image.clone(), relocated.clone(), entry.state)
+                        .map_err(DefragmentationError::Copy)?;
+                    relocatable.relocate(relocated);
+                    defragmentation.images += 1;
+                    defragmentation.bytes += image.block().map_or(0, Block::size);
+                }
+            }
+            Ok(())
+        };
+
+        if let Err(error) = plan.moves.iter().cloned().try_for_each(&mut relocate) {
+            // Blocks left in place keep selected chunks from being released.
+            // Chunks can't be freed yet as `buffers` and `images` hold their blocks.
+            self.heaps.write().cancel_defragmentation(&plan);
+            return Err(DefragmentationFailure {
+                partial: defragmentation,
+                error,
+            });
+        }
+
+        debug_assert_eq!(defragmentation.bytes, plan.bytes);
+        Ok(Defragmentation {
+            chunks: plan.chunks,
+            released: plan.released,
+            ..defragmentation
+        })
+    }
+
     /// Fetch image format details for a particular `ImageInfo`.
     pub fn image_format_properties(&self, info: ImageInfo) -> Option<FormatProperties> {
         self.physical().image_format_properties(
@@ -627,12 +1001,7 @@ where
         assert!(image_layers.level <= image.info().levels);
 
         let content_size = content.len() as u64 * std::mem::size_of::<T>() as u64;
-        let format_desc = image.format().surface_desc();
-        let texels_count = (image_extent.width / format_desc.dim.0 as u32) as u64
-            * (image_extent.height / format_desc.dim.1 as u32) as u64
-            * image_extent.depth as u64
-            * (image_layers.layers.end - image_layers.layers.start) as u64;
-        let total_bytes = (format_desc.bits as u64 / 8) * texels_count;
+        let (_, _, total_bytes) = image_region_pitches(image.format(), image_extent, &image_layers);
         assert_eq!(
             total_bytes, content_size,
             "Size of must match size of the image region"
@@ -667,6 +1036,155 @@ where
             .map_err(UploadError::Upload)
     }
 
+    /// Download buffer range content from the device.
+    ///
+    /// Download operation will actually be submitted to the graphics device queue
+    /// upon next [`flush_uploads`] or [`maintain`] call to this `Factory`, and
+    /// is guaranteed to take place after all previous operations that have been
+    /// submitted to the same graphics queue on this `Factory` since last
+    /// [`flush_uploads`] or [`maintain`] call.
+    ///
+    /// Content can be read from returned [`Readback`] once operation is complete.
+    ///
+    /// Returns [`UploadError::Usage`] if buffer wasn't created with `TRANSFER_SRC` usage
+    /// and [`UploadError::Region`] if `range` is empty or exceeds the buffer.
+    ///
+    /// # Safety
+    ///
+    /// If buffer is used by device then `last` state must match the last usage state of the buffer
+    /// before downloading happen.
+    /// `next` must match buffer usage state in the next device operation that uses the buffer.
+    ///
+    /// [`flush_uploads`]: #method.flush_uploads
+    /// [`maintain`]: #method.maintain
+    /// [`Readback`]: struct.Readback.html
+    pub unsafe fn download_buffer(
+        &self,
+        buffer: &Buffer<B>,
+        range: std::ops::Range<u64>,
+        last: Option<BufferState>,
+        next: BufferState,
+    ) -> Result<Readback<B>, UploadError> {
+        if !buffer.info().usage.contains(buffer::Usage::TRANSFER_SRC) {
+            return Err(UploadError::Usage);
+        }
+        if range.start >= range.end || range.end > buffer.size() {
+            return Err(UploadError::Region);
+        }
+
+        let size = range.end - range.start;
+        let staging: Handle<Buffer<B>> = self
+            .create_buffer(
+                BufferInfo {
+                    size,
+                    usage: buffer::Usage::TRANSFER_DST,
+                },
+                memory::Download,
+            )
+            .map_err(UploadError::Create)?
+            .into();
+
+        let batch = self.uploader.download_buffer(
+            &self.device,
+            buffer,
+            range.start,
+            staging.clone(),
+            last,
+            next,
+        )?;
+
+        Ok(Readback::new(
+            staging,
+            next.queue.family.index,
+            batch,
+            size,
+            size,
+        ))
+    }
+
+    /// Download image layers content from the device.
+    ///
+    /// Download operation will actually be submitted to the graphics device queue
+    /// upon next [`flush_uploads`] or [`maintain`] call to this `Factory`, and
+    /// is guaranteed to take place after all previous operations that have been
+    /// submitted to the same graphics queue on this `Factory` since last
+    /// [`flush_uploads`] or [`maintain`] call.
+    ///
+    /// Content can be read from returned [`Readback`] once operation is complete.
+    /// Texels are tightly packed in the same order `upload_image` expects them.
+    ///
+    /// Returns [`UploadError::Usage`] if image wasn't created with `TRANSFER_SRC` usage
+    /// and [`UploadError::Region`] if `image_layers` doesn't match aspects of the image format
+    /// or refers to layers or level the image doesn't have.
+    ///
+    /// # Safety
+    ///
+    /// Image must be created by this `Factory`.
+    /// If image is used by device then `last` state must match the last usage state of the image
+    /// before downloading happen.
+    /// `next` must match image usage state in the next device operation that uses the image.
+    ///
+    /// [`flush_uploads`]: #method.flush_uploads
+    /// [`maintain`]: #method.maintain
+    /// [`Readback`]: struct.Readback.html
+    pub unsafe fn download_image(
+        &self,
+        image: Handle<Image<B>>,
+        image_layers: SubresourceLayers,
+        image_offset: image::Offset,
+        image_extent: Extent,
+        last: impl Into<ImageStateOrLayout>,
+        next: ImageState,
+    ) -> Result<Readback<B>, UploadError> {
+        if !image.info().usage.contains(image::Usage::TRANSFER_SRC) {
+            return Err(UploadError::Usage);
+        }
+        if image.format().surface_desc().aspects != image_layers.aspects
+            || image_layers.layers.start > image_layers.layers.end
+            || image_layers.layers.end > image.kind().num_layers()
+            || image_layers.level >= image.info().levels
+        {
+            return Err(UploadError::Region);
+        }
+
+        let (row_pitch, slice_pitch, total_bytes) =
+            image_region_pitches(image.format(), image_extent, &image_layers);
+
+        let staging: Handle<Buffer<B>> = self
+            .create_buffer(
+                BufferInfo {
+                    size: total_bytes,
+                    usage: buffer::Usage::TRANSFER_DST,
+                },
+                memory::Download,
+            )
+            .map_err(UploadError::Create)?
+            .into();
+
+        let batch = self.uploader.download_image(
+            &self.device,
+            image,
+            image_layers,
+            image_offset,
+            image_extent,
+            staging.clone(),
+            last.into(),
+            next,
+        )?;
+
+        Ok(Readback::new(
+            staging,
+            next.queue.family.index,
+            batch,
+            row_pitch,
+            slice_pitch,
+        ))
+    }
+
+    pub(crate) fn uploader(&self) -> &Uploader<B> {
+        &self.uploader
+    }
+
     /// Get blitter instance
     pub fn blitter(&self) -> &Blitter<B> {
         &self.blitter
@@ -1145,8 +1663,50 @@ where
     }
 
     /// Query memory utilization.
+    ///
+    /// Includes live bytes per allocation tag.
+    /// Resources can be tagged by creating them with `Tagged` memory usage.
     pub fn memory_utilization(&self) -> TotalMemoryUtilization {
-        self.heaps.lock().utilization()
+        self.heaps.read().utilization()
+    }
+
+    /// Query live memory blocks by allocation tag, memory type and size.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.heaps.read().report()
+    }
+
+    /// Set soft limit on the memory allocated from the heap.
+    /// Resource creation that would exceed the budget fails
+    /// with `HeapsError::BudgetExceeded`.
+    pub fn set_memory_budget(&self, heap_index: usize, budget: Option<HeapBudget>) {
+        self.heaps.write().set_budget(heap_index, budget);
+    }
+
+    /// Set policy to select memory types for resources.
+    pub fn set_memory_type_policy(&self, policy: impl MemoryTypePolicy + 'static) {
+        self.heaps.write().set_memory_type_policy(policy);
+    }
+
+    /// Get allocations served from memory types other than preferred by the policy.
+    pub fn memory_fallbacks(&self) -> Vec<MemoryFallback> {
+        self.heaps.read().fallbacks()
+    }
+
+    /// Set policy to fail memory allocations on purpose.
+    /// Resource creation fails as if device is out of memory.
+    ///
+    /// This is intended for testing of out-of-memory error handling.
+    pub fn set_memory_fault_policy(&self, policy: Option<FaultPolicy>) {
+        self.heaps.write().set_fault_policy(policy);
+    }
+
+    /// Set callback to invoke when heap usage crosses one of budget thresholds.
+    ///
+    /// Callback is invoked while memory heaps are locked,
+    /// so it must not create or destroy resources through this `Factory`.
+    /// Instead it should schedule eviction to run later.
+    pub fn set_memory_budget_callback(&self, callback: impl FnMut(BudgetEvent) + Send + 'static) {
+        self.heaps.write().set_budget_callback(callback);
     }
 
     /// Get Factory's instance id.
@@ -1155,6 +1715,24 @@ where
     }
 }
 
+/// Get row pitch, slice pitch and total size in bytes
+/// of the tightly packed image region.
+fn image_region_pitches(
+    format: format::Format,
+    extent: Extent,
+    layers: &SubresourceLayers,
+) -> (u64, u64, u64) {
+    let format_desc = format.surface_desc();
+    let texel_bytes = format_desc.bits as u64 / 8;
+    // Partially covered blocks of compressed formats are copied whole.
+    let blocks = |texels: u32, dim: u8| ((texels + dim as u32 - 1) / dim as u32) as u64;
+    let row_pitch = blocks(extent.width, format_desc.dim.0) * texel_bytes;
+    let slice_pitch = blocks(extent.height, format_desc.dim.1) * row_pitch;
+    let total_bytes =
+        slice_pitch * extent.depth as u64 * (layers.layers.end - layers.layers.start) as u64;
+    (row_pitch, slice_pitch, total_bytes)
+}
+
 impl<B> std::ops::Deref for Factory<B>
 where
     B: Backend,
@@ -1271,22 +1849,28 @@ where
 
     let device = Device::from_raw(device, device_id);
 
-    let (types, heaps) = config
-        .heaps
-        .configure(&adapter.physical_device.memory_properties());
+    let memory_properties = adapter.physical_device.memory_properties();
+    let (types, heaps) = config.heaps.configure(&memory_properties);
+    let budgets = config.heaps.budgets(&memory_properties);
     let heaps = heaps.into_iter().collect::<SmallVec<[_; 16]>>();
     let types = types.into_iter().collect::<SmallVec<[_; 32]>>();
 
     log::debug!("Heaps: {:#?}\nTypes: {:#?}", heaps, types);
 
-    let heaps = unsafe {
+    let limits = adapter.physical_device.limits();
+    let mut heaps = unsafe {
         Heaps::new(
             types,
             heaps,
-            adapter.physical_device.limits().non_coherent_atom_size as u64,
+            limits.non_coherent_atom_size as u64,
+            limits.buffer_image_granularity,
         )
     };
 
+    for (index, budget) in budgets.into_iter().enumerate() {
+        heaps.set_budget(index, budget);
+    }
+
     let epochs = families
         .as_slice()
         .iter()
@@ -1297,7 +1881,7 @@ where
         descriptor_allocator: ManuallyDrop::new(
             parking_lot::Mutex::new(DescriptorAllocator::new()),
         ),
-        heaps: ManuallyDrop::new(parking_lot::Mutex::new(heaps)),
+        heaps: ManuallyDrop::new(parking_lot::RwLock::new(heaps)),
         resources: ManuallyDrop::new(ResourceHub::default()),
         uploader: unsafe { Uploader::new(&device, &families) }
             .map_err(rendy_core::hal::device::CreationError::OutOfMemory)?,
@@ -1312,3 +1896,445 @@ where
 
     Ok((factory, families))
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{
+            image_region_pitches, init_with_instance, Defragmentation, DefragmentationError,
+            DefragmentationFailure, Factory, UploadError,
+        },
+        crate::{
+            command::{Families, QueueId, Submission},
+            config::Config,
+            core::Instance,
+            memory::{self, FaultPolicy, HeapsError},
+            resource::{BufferInfo, CreationError, Handle, Image, ImageInfo},
+            upload::{BufferState, DownloadError, ImageState},
+        },
+        rendy_core::hal::{buffer, device::OutOfMemory, format, image},
+        rendy_fake::{FakeBackend, FakeInstance},
+    };
+
+    fn fake_factory() -> (Factory<FakeBackend>, Families<FakeBackend>) {
+        let config: Config = Default::default();
+        init_with_instance(Instance::new(FakeInstance), &config).unwrap()
+    }
+
+    fn out_of_memory() -> HeapsError {
+        HeapsError::AllocationError(OutOfMemory::Device.into())
+    }
+
+    /// Complete few submissions so that resources dropped before are destroyed.
+    fn cleanup(factory: &mut Factory<FakeBackend>, families: &mut Families<FakeBackend>) {
+        for _ in 0..3 {
+            let mut fence = factory.create_fence(false).unwrap();
+            unsafe {
+                families
+                    .family_by_index_mut(0)
+                    .queue_mut(0)
+                    .submit(Some(Submission::new()), Some(&mut fence));
+            }
+            factory.wait_for_fence(&mut fence, !0).unwrap();
+            factory.destroy_fence(fence);
+            factory.cleanup(families);
+        }
+    }
+
+    #[test]
+    fn allocation_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let info = BufferInfo {
+            size: 1024,
+            usage: buffer::Usage::VERTEX | buffer::Usage::TRANSFER_DST,
+        };
+
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        match factory.create_buffer(info, memory::Data) {
+            Err(CreationError::Allocate(err)) => {
+                assert_eq!(err, out_of_memory())
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(factory.device().stats().buffers, 0);
+
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        let image = factory.create_image(
+            ImageInfo {
+                kind: image::Kind::D2(16, 16, 1, 1),
+                levels: 1,
+                format: format::Format::Rgba8Unorm,
+                tiling: image::Tiling::Optimal,
+                view_caps: image::ViewCapabilities::empty(),
+                usage: image::Usage::SAMPLED,
+            },
+            memory::Data,
+        );
+        match image {
+            Err(CreationError::Allocate(err)) => {
+                assert_eq!(err, out_of_memory())
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(factory.device().stats().images, 0);
+
+        // Staging buffer allocation fails, target buffer stays intact.
+        factory.set_memory_fault_policy(None);
+        let buffer = factory.create_relevant_buffer(info, memory::Data).unwrap();
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        let result = unsafe {
+            factory.upload_buffer(
+                &buffer,
+                0,
+                &[0u32; 256],
+                None,
+                BufferState::new(families.family_by_index(0).queue(0).id()),
+            )
+        };
+        match result {
+            Err(UploadError::Create(CreationError::Allocate(err))) => {
+                assert_eq!(err, out_of_memory())
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(factory.device().stats().buffers, 1);
+
+        factory.set_memory_fault_policy(None);
+        unsafe { factory.destroy_relevant_buffer(buffer) };
+        factory.maintain(&mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn dropped_readback() {
+        let (mut factory, mut families) = fake_factory();
+        let buffer = factory
+            .create_relevant_buffer(
+                BufferInfo {
+                    size: 1024,
+                    usage: buffer::Usage::TRANSFER_SRC,
+                },
+                memory::Data,
+            )
+            .unwrap();
+
+        let readback = unsafe {
+            factory.download_buffer(
+                &buffer,
+                0..256,
+                None,
+                BufferState::new(families.family_by_index(0).queue(0).id()),
+            )
+        }
+        .unwrap();
+        assert_eq!(factory.device().stats().buffers, 2);
+
+        // Staging buffer is not destroyed before download is submitted and complete.
+        drop(readback);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().buffers, 2);
+
+        factory.flush_uploads(&mut families);
+        factory.wait_idle().unwrap();
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().buffers, 1);
+
+        unsafe { factory.destroy_relevant_buffer(buffer) };
+        assert_eq!(factory.device().stats().buffers, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn readback() {
+        let (mut factory, mut families) = fake_factory();
+        let queue = families.family_by_index(0).queue(0).id();
+        let mut buffer = factory
+            .create_relevant_buffer(
+                BufferInfo {
+                    size: 1024,
+                    usage: buffer::Usage::TRANSFER_SRC,
+                },
+                memory::Upload,
+            )
+            .unwrap();
+        let content: Vec<u8> = (0..1024).map(|i| i as u8).collect();
+        unsafe { factory.upload_visible_buffer(&mut buffer, 0, &content) }.unwrap();
+
+        let mut readback =
+            unsafe { factory.download_buffer(&buffer, 256..768, None, BufferState::new(queue)) }
+                .unwrap();
+        assert_eq!(readback.row_pitch(), 512);
+        assert_eq!(readback.slice_pitch(), 512);
+
+        // Download can't be waited for before it is submitted.
+        assert_eq!(readback.wait(&factory), Err(DownloadError::NotFlushed));
+        factory.flush_uploads(&mut families);
+        assert_eq!(readback.wait(&factory).unwrap(), &content[256..768]);
+        drop(readback);
+
+        // Buffer last used on another queue can't be downloaded.
+        let other = QueueId {
+            index: 1,
+            family: queue.family,
+        };
+        let result = unsafe {
+            factory.download_buffer(
+                &buffer,
+                0..256,
+                Some(BufferState::new(other)),
+                BufferState::new(queue),
+            )
+        };
+        assert_eq!(result.err(), Some(UploadError::CrossQueue));
+
+        // Invalid ranges are rejected.
+        for range in vec![256..256, 768..1025] {
+            let result =
+                unsafe { factory.download_buffer(&buffer, range, None, BufferState::new(queue)) };
+            assert_eq!(result.err(), Some(UploadError::Region));
+        }
+
+        // Image region is read tightly packed.
+        let image: Handle<Image<_>> = factory
+            .create_image(
+                ImageInfo {
+                    kind: image::Kind::D2(8, 4, 3, 1),
+                    levels: 1,
+                    format: format::Format::Rgba8Unorm,
+                    tiling: image::Tiling::Optimal,
+                    view_caps: image::ViewCapabilities::empty(),
+                    usage: image::Usage::TRANSFER_SRC,
+                },
+                memory::Data,
+            )
+            .unwrap()
+            .into();
+        let mut readback = unsafe {
+            factory.download_image(
+                image.clone(),
+                image::SubresourceLayers {
+                    aspects: format::Aspects::COLOR,
+                    level: 0,
+                    layers: 1..3,
+                },
+                image::Offset { x: 2, y: 0, z: 0 },
+                image::Extent {
+                    width: 6,
+                    height: 4,
+                    depth: 1,
+                },
+                image::Layout::Undefined,
+                ImageState::new(queue, image::Layout::TransferSrcOptimal),
+            )
+        }
+        .unwrap();
+        assert_eq!(readback.row_pitch(), 6 * 4);
+        assert_eq!(readback.slice_pitch(), 6 * 4 * 4);
+        factory.flush_uploads(&mut families);
+        assert_eq!(readback.wait(&factory).unwrap().len(), 6 * 4 * 4 * 2);
+        drop(readback);
+
+        // Layers, level and aspects must exist in the image.
+        let invalid = vec![
+            (format::Aspects::DEPTH, 0, 0..1),
+            (format::Aspects::COLOR, 0, 2..4),
+            (format::Aspects::COLOR, 1, 0..1),
+        ];
+        for (aspects, level, layers) in invalid {
+            let result = unsafe {
+                factory.download_image(
+                    image.clone(),
+                    image::SubresourceLayers {
+                        aspects,
+                        level,
+                        layers,
+                    },
+                    image::Offset::ZERO,
+                    image::Extent {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    },
+                    image::Layout::Undefined,
+                    ImageState::new(queue, image::Layout::TransferSrcOptimal),
+                )
+            };
+            assert_eq!(result.err(), Some(UploadError::Region));
+        }
+        drop(image);
+
+        // Resources must be created with transfer source usage.
+        let upload_only = factory
+            .create_relevant_buffer(
+                BufferInfo {
+                    size: 16,
+                    usage: buffer::Usage::TRANSFER_DST,
+                },
+                memory::Data,
+            )
+            .unwrap();
+        let result =
+            unsafe { factory.download_buffer(&upload_only, 0..16, None, BufferState::new(queue)) };
+        assert_eq!(result.err(), Some(UploadError::Usage));
+        unsafe { factory.destroy_relevant_buffer(upload_only) };
+
+        unsafe { factory.destroy_relevant_buffer(buffer) };
+        factory.wait_idle().unwrap();
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn region_pitches() {
+        let layers = |layers| image::SubresourceLayers {
+            aspects: format::Aspects::COLOR,
+            level: 0,
+            layers,
+        };
+        let extent = |width, height, depth| image::Extent {
+            width,
+            height,
+            depth,
+        };
+
+        assert_eq!(
+            image_region_pitches(format::Format::Rgba8Unorm, extent(5, 3, 1), &layers(0..1)),
+            (20, 60, 60)
+        );
+        assert_eq!(
+            image_region_pitches(format::Format::R16Uint, extent(4, 4, 2), &layers(1..4)),
+            (8, 32, 192)
+        );
+
+        // Blocks of compressed formats partially covered by the region are counted whole.
+        assert_eq!(
+            image_region_pitches(format::Format::Bc1RgbUnorm, extent(2, 2, 1), &layers(0..1)),
+            (8, 8, 8)
+        );
+        assert_eq!(
+            image_region_pitches(format::Format::Bc1RgbUnorm, extent(6, 10, 1), &layers(0..2)),
+            (16, 48, 96)
+        );
+    }
+
+    #[test]
+    fn defragmentation_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let state = BufferState::new(families.family_by_index(0).queue(0).id());
+        let info = BufferInfo {
+            size: 512 * 1024,
+            usage: buffer::Usage::VERTEX,
+        };
+
+        // Second chunk of 8 blocks is left with one buffer.
+        let mut buffers: Vec<_> = (0..16)
+            .map(|_| {
+                factory
+                    .create_relocatable_buffer(info, memory::Data, state)
+                    .unwrap()
+            })
+            .collect();
+        buffers.truncate(9);
+        cleanup(&mut factory, &mut families);
+        let allocations = factory.device().stats().allocations;
+
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        match unsafe { factory.defragment(Default::default()) } {
+            Err(DefragmentationFailure {
+                partial,
+                error: DefragmentationError::CreateBuffer(CreationError::Allocate(err)),
+            }) => {
+                assert_eq!(err, out_of_memory());
+                assert_eq!(partial, Defragmentation::default());
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        factory.set_memory_fault_policy(None);
+        assert_eq!(buffers[8].version(), 0);
+
+        // Sparse chunk is used for new buffers again.
+        let buffer = factory.create_relevant_buffer(info, memory::Data).unwrap();
+        assert_eq!(factory.device().stats().allocations, allocations);
+        unsafe { factory.destroy_relevant_buffer(buffer) };
+
+        let defragmentation = unsafe { factory.defragment(Default::default()) }.unwrap();
+        assert_eq!(defragmentation.buffers, 1);
+        assert_eq!(defragmentation.chunks, 1);
+        assert_eq!(buffers[8].version(), 1);
+
+        // Buffer is moved to the new chunk and sparse one is released once copy is complete.
+        let frees = factory.device().stats().frees;
+        factory.flush_uploads(&mut families);
+        factory.wait_idle().unwrap();
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.allocations, allocations + 1);
+        assert_eq!(stats.frees, frees + 1);
+
+        drop(buffers);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().buffers, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn defragmentation_partial_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let state = BufferState::new(families.family_by_index(0).queue(0).id());
+        let info = BufferInfo {
+            size: 512 * 1024,
+            usage: buffer::Usage::VERTEX,
+        };
+
+        // Second chunk of 8 blocks is left with two buffers.
+        let mut buffers: Vec<_> = (0..16)
+            .map(|_| {
+                factory
+                    .create_relocatable_buffer(info, memory::Data, state)
+                    .unwrap()
+            })
+            .collect();
+        buffers.truncate(10);
+        cleanup(&mut factory, &mut families);
+
+        // Second relocation fails, first one is reported.
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(2)));
+        match unsafe { factory.defragment(Default::default()) } {
+            Err(DefragmentationFailure {
+                partial,
+                error: DefragmentationError::CreateBuffer(CreationError::Allocate(err)),
+            }) => {
+                assert_eq!(err, out_of_memory());
+                assert_eq!(
+                    partial,
+                    Defragmentation {
+                        buffers: 1,
+                        images: 0,
+                        bytes: 512 * 1024,
+                        chunks: 0,
+                        released: 0,
+                    }
+                );
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        factory.set_memory_fault_policy(None);
+        let versions: Vec<_> = buffers[8..].iter().map(|buffer| buffer.version()).collect();
+        versions.iter().for_each(|&version| assert!(version <= 1));
+        assert_eq!(versions.iter().sum::<u64>(), 1);
+
+        // Relocated buffer is copied as usual.
+        factory.flush_uploads(&mut families);
+        factory.wait_idle().unwrap();
+        drop(buffers);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().buffers, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+}
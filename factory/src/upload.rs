@@ -2,13 +2,15 @@ use {
     crate::{
         barriers::Barriers,
         command::{
-            CommandBuffer, CommandPool, Families, Family, IndividualReset, InitialState, OneShot,
-            PendingOnceState, PrimaryLevel, QueueId, RecordingState, Submission, Transfer,
+            CommandBuffer, CommandPool, Encoder, Families, Family, IndividualReset, InitialState,
+            OneShot, PendingOnceState, PrimaryLevel, QueueId, RecordingState, Submission, Transfer,
         },
         core::Device,
+        factory::{Factory, UploadError},
+        memory::Read as _,
         resource::{Buffer, Escape, Handle, Image},
     },
-    rendy_core::hal::device::{Device as _, OutOfMemory},
+    rendy_core::hal::device::{Device as _, MapError, OomOrDeviceLost, OutOfMemory},
     std::{collections::VecDeque, iter::once},
 };
 
@@ -120,6 +122,168 @@ impl From<rendy_core::hal::image::Layout> for ImageStateOrLayout {
     }
 }
 
+/// Error reading content downloaded from the device.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DownloadError {
+    /// Failed to wait for download completion.
+    Wait(OomOrDeviceLost),
+    /// Failed to map the staging buffer.
+    Map(MapError),
+    /// Download wasn't flushed yet, so it can't be waited for.
+    NotFlushed,
+}
+
+impl std::fmt::Display for DownloadError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Wait(err) => write!(fmt, "Download failed: {:?}", err),
+            DownloadError::Map(err) => write!(fmt, "Download failed: {:?}", err),
+            DownloadError::NotFlushed => write!(fmt, "Download must be flushed before waiting"),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Wait(err) => Some(err),
+            DownloadError::Map(err) => Some(err),
+            DownloadError::NotFlushed => None,
+        }
+    }
+}
+
+/// Pending download of buffer or image content from the device.
+///
+/// Download operation is submitted along with uploads
+/// upon next [`flush_uploads`] or [`maintain`] call to the `Factory`.
+/// After that content can be read with [`poll`] or [`wait`].
+///
+/// Image content is tightly packed.
+/// Rows are [`row_pitch`] bytes apart and depth slices or layers are [`slice_pitch`] bytes apart.
+///
+/// [`flush_uploads`]: struct.Factory.html#method.flush_uploads
+/// [`maintain`]: struct.Factory.html#method.maintain
+/// [`poll`]: #method.poll
+/// [`wait`]: #method.wait
+/// [`row_pitch`]: #method.row_pitch
+/// [`slice_pitch`]: #method.slice_pitch
+#[derive(Debug)]
+pub struct Readback<B: rendy_core::hal::Backend> {
+    staging: Handle<Buffer<B>>,
+    family: usize,
+    batch: u64,
+    row_pitch: u64,
+    slice_pitch: u64,
+}
+
+impl<B> Readback<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    pub(crate) fn new(
+        staging: Handle<Buffer<B>>,
+        family: usize,
+        batch: u64,
+        row_pitch: u64,
+        slice_pitch: u64,
+    ) -> Self {
+        Readback {
+            staging,
+            family,
+            batch,
+            row_pitch,
+            slice_pitch,
+        }
+    }
+
+    /// Get size of downloaded content in bytes.
+    pub fn size(&self) -> u64 {
+        self.staging.size()
+    }
+
+    /// Get number of bytes between rows of downloaded image.
+    /// For buffers this is size of the content.
+    pub fn row_pitch(&self) -> u64 {
+        self.row_pitch
+    }
+
+    /// Get number of bytes between depth slices or layers of downloaded image.
+    /// For buffers this is size of the content.
+    pub fn slice_pitch(&self) -> u64 {
+        self.slice_pitch
+    }
+
+    /// Check if download is complete.
+    /// Returns `false` if download wasn't flushed yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factory` is not the one that created this `Readback`.
+    pub fn is_complete(&self, factory: &Factory<B>) -> Result<bool, DownloadError> {
+        self.staging.assert_device_owner(factory.device());
+        unsafe {
+            factory
+                .uploader()
+                .is_complete(factory.device(), self.family, self.batch)
+                .map(|complete| complete.unwrap_or(false))
+                .map_err(DownloadError::Wait)
+        }
+    }
+
+    /// Read downloaded content if download is complete.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factory` is not the one that created this `Readback`.
+    pub fn poll(&mut self, factory: &Factory<B>) -> Result<Option<Vec<u8>>, DownloadError> {
+        if self.is_complete(factory)? {
+            self.read(factory.device()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Wait for download completion and read downloaded content.
+    /// Returns [`NotFlushed`] if download wasn't flushed with [`flush_uploads`] or [`maintain`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factory` is not the one that created this `Readback`.
+    ///
+    /// [`NotFlushed`]: enum.DownloadError.html#variant.NotFlushed
+    /// [`flush_uploads`]: struct.Factory.html#method.flush_uploads
+    /// [`maintain`]: struct.Factory.html#method.maintain
+    pub fn wait(&mut self, factory: &Factory<B>) -> Result<Vec<u8>, DownloadError> {
+        self.staging.assert_device_owner(factory.device());
+        let flushed = unsafe {
+            factory
+                .uploader()
+                .wait(factory.device(), self.family, self.batch)
+                .map_err(DownloadError::Wait)?
+        };
+        if !flushed {
+            return Err(DownloadError::NotFlushed);
+        }
+        self.read(factory.device())
+    }
+
+    fn read(&mut self, device: &Device<B>) -> Result<Vec<u8>, DownloadError> {
+        let size = self.staging.size();
+        let staging = Handle::get_mut(&mut self.staging)
+            .expect("Uploader releases staging buffer once download is complete");
+        let mut mapped = staging.map(device, 0..size).map_err(DownloadError::Map)?;
+
+        // Device finished writing to the staging buffer.
+        unsafe {
+            mapped
+                .read::<u8>(device, 0..size)
+                .map(|content| content.slice().to_vec())
+                .map_err(DownloadError::Map)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Uploader<B: rendy_core::hal::Backend> {
     family_uploads: Vec<Option<parking_lot::Mutex<FamilyUploads<B>>>>,
@@ -155,6 +319,7 @@ where
                     rendy_core::hal::buffer::Access::TRANSFER_WRITE,
                     rendy_core::hal::image::Access::TRANSFER_WRITE,
                 ),
+                batches: 0,
             }));
         }
 
@@ -368,6 +533,347 @@ where
         Ok(())
     }
 
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `buffer` and `staging` must belong to the `device`.
+    ///
+    pub(crate) unsafe fn download_buffer(
+        &self,
+        device: &Device<B>,
+        buffer: &Buffer<B>,
+        offset: u64,
+        staging: Handle<Buffer<B>>,
+        last: Option<BufferState>,
+        next: BufferState,
+    ) -> Result<u64, UploadError> {
+        if let Some(last) = last {
+            if last.queue != next.queue {
+                return Err(UploadError::CrossQueue);
+            }
+        }
+
+        let mut family_uploads = self.family_uploads[next.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        family_uploads.barriers.add_buffer(
+            last.map_or(rendy_core::hal::pso::PipelineStage::empty(), |l| l.stage),
+            last.map_or(rendy_core::hal::buffer::Access::empty(), |l| l.access),
+            next.stage,
+            next.access,
+        );
+
+        // Make staging buffer content visible to the host.
+        family_uploads.barriers.add_buffer(
+            rendy_core::hal::pso::PipelineStage::empty(),
+            rendy_core::hal::buffer::Access::empty(),
+            rendy_core::hal::pso::PipelineStage::HOST,
+            rendy_core::hal::buffer::Access::HOST_READ,
+        );
+
+        let next_upload = family_uploads
+            .next_upload(device, next.queue.index)
+            .map_err(UploadError::Upload)?;
+        let mut encoder = next_upload.command_buffer.encoder();
+        transfer_read_barrier(&mut encoder);
+        encoder.copy_buffer(
+            buffer.raw(),
+            staging.raw(),
+            Some(rendy_core::hal::command::BufferCopy {
+                src: offset,
+                dst: 0,
+                size: staging.size(),
+            }),
+        );
+
+        next_upload.download_buffers.push(staging);
+        Ok(next_upload.batch)
+    }
+
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `image` and `staging` must belong to the `device`.
+    ///
+    pub(crate) unsafe fn download_image(
+        &self,
+        device: &Device<B>,
+        image: Handle<Image<B>>,
+        image_layers: rendy_core::hal::image::SubresourceLayers,
+        image_offset: rendy_core::hal::image::Offset,
+        image_extent: rendy_core::hal::image::Extent,
+        staging: Handle<Buffer<B>>,
+        last: ImageStateOrLayout,
+        next: ImageState,
+    ) -> Result<u64, UploadError> {
+        use rendy_core::hal::image::{Access, Layout};
+
+        if let ImageStateOrLayout::State(last) = last {
+            if last.queue != next.queue {
+                return Err(UploadError::CrossQueue);
+            }
+        }
+
+        let mut family_uploads = self.family_uploads[next.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        let image_range = rendy_core::hal::image::SubresourceRange {
+            aspects: image_layers.aspects,
+            levels: image_layers.level..image_layers.level + 1,
+            layers: image_layers.layers.clone(),
+        };
+
+        let (last_stage, mut last_access, last_layout) = match last {
+            ImageStateOrLayout::State(last) => (last.stage, last.access, last.layout),
+            ImageStateOrLayout::Layout(last_layout) => (
+                rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
+                Access::empty(),
+                last_layout,
+            ),
+        };
+
+        let target_layout = match (last_layout, next.layout) {
+            (Layout::TransferSrcOptimal, _) => Layout::TransferSrcOptimal,
+            (_, Layout::General) => Layout::General,
+            (Layout::General, _) => Layout::General,
+            _ => Layout::TransferSrcOptimal,
+        };
+
+        if last_layout == Layout::Undefined || last_layout == target_layout {
+            last_access = Access::empty();
+        }
+
+        family_uploads.barriers.add_image(
+            image.clone(),
+            image_range,
+            last_stage,
+            last_access,
+            last_layout,
+            target_layout,
+            next.stage,
+            next.access,
+            next.layout,
+        );
+
+        // Make staging buffer content visible to the host.
+        family_uploads.barriers.add_buffer(
+            rendy_core::hal::pso::PipelineStage::empty(),
+            rendy_core::hal::buffer::Access::empty(),
+            rendy_core::hal::pso::PipelineStage::HOST,
+            rendy_core::hal::buffer::Access::HOST_READ,
+        );
+
+        let next_upload = family_uploads
+            .next_upload(device, next.queue.index)
+            .map_err(UploadError::Upload)?;
+        let mut encoder = next_upload.command_buffer.encoder();
+        transfer_read_barrier(&mut encoder);
+        encoder.copy_image_to_buffer(
+            image.raw(),
+            target_layout,
+            staging.raw(),
+            Some(rendy_core::hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers,
+                image_offset,
+                image_extent,
+            }),
+        );
+
+        next_upload.download_buffers.push(staging);
+        Ok(next_upload.batch)
+    }
+
+    /// Copy whole buffer content to the relocated copy.
+    /// Replaced buffer is kept alive until copy is complete.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `buffer` and `relocated` must belong to the `device`.
+    /// `buffer` must be in the `state` when copy is executed.
+    ///
+    pub(crate) unsafe fn relocate_buffer(
+        &self,
+        device: &Device<B>,
+        buffer: Handle<Buffer<B>>,
+        relocated: &Buffer<B>,
+        state: BufferState,
+    ) -> Result<(), OutOfMemory> {
+        let mut family_uploads = self.family_uploads[state.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        family_uploads
+            .barriers
+            .add_buffer(state.stage, state.access, state.stage, state.access);
+
+        let next_upload = family_uploads.next_upload(device, state.queue.index)?;
+        let mut encoder = next_upload.command_buffer.encoder();
+        transfer_read_barrier(&mut encoder);
+        encoder.copy_buffer(
+            buffer.raw(),
+            relocated.raw(),
+            Some(rendy_core::hal::command::BufferCopy {
+                src: 0,
+                dst: 0,
+                size: buffer.size(),
+            }),
+        );
+
+        next_upload.retired_buffers.push(buffer);
+        Ok(())
+    }
+
+    /// Copy all levels and layers of the image to the relocated copy.
+    /// Replaced image is kept alive until copy is complete.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    /// `image` and `relocated` must belong to the `device`.
+    /// `image` must be in the `state` when copy is executed.
+    ///
+    pub(crate) unsafe fn relocate_image(
+        &self,
+        device: &Device<B>,
+        image: Handle<Image<B>>,
+        relocated: Handle<Image<B>>,
+        state: ImageState,
+    ) -> Result<(), OutOfMemory> {
+        use rendy_core::hal::image::{Access, Layout};
+
+        let mut family_uploads = self.family_uploads[state.queue.family.index]
+            .as_ref()
+            .unwrap()
+            .lock();
+
+        let aspects = image.format().surface_desc().aspects;
+        let image_range = rendy_core::hal::image::SubresourceRange {
+            aspects,
+            levels: 0..image.levels(),
+            layers: 0..image.layers(),
+        };
+
+        let (src_layout, dst_layout) = match state.layout {
+            Layout::General => (Layout::General, Layout::General),
+            _ => (Layout::TransferSrcOptimal, Layout::TransferDstOptimal),
+        };
+
+        family_uploads.barriers.add_image(
+            image.clone(),
+            image_range.clone(),
+            state.stage,
+            state.access,
+            state.layout,
+            src_layout,
+            state.stage,
+            state.access,
+            state.layout,
+        );
+
+        family_uploads.barriers.add_image(
+            relocated.clone(),
+            image_range,
+            rendy_core::hal::pso::PipelineStage::TOP_OF_PIPE,
+            Access::empty(),
+            Layout::Undefined,
+            dst_layout,
+            state.stage,
+            state.access,
+            state.layout,
+        );
+
+        let next_upload = family_uploads.next_upload(device, state.queue.index)?;
+        let mut encoder = next_upload.command_buffer.encoder();
+        transfer_read_barrier(&mut encoder);
+        encoder.copy_image(
+            image.raw(),
+            src_layout,
+            relocated.raw(),
+            dst_layout,
+            (0..image.levels()).map(|level| {
+                let layers = rendy_core::hal::image::SubresourceLayers {
+                    aspects,
+                    level,
+                    layers: 0..image.layers(),
+                };
+                rendy_core::hal::command::ImageCopy {
+                    src_subresource: layers.clone(),
+                    src_offset: rendy_core::hal::image::Offset::ZERO,
+                    dst_subresource: layers,
+                    dst_offset: rendy_core::hal::image::Offset::ZERO,
+                    extent: image.kind().level_extent(level),
+                }
+            }),
+        );
+
+        next_upload.retired_images.push(image);
+        Ok(())
+    }
+
+    /// Check if batch of uploads submitted to the family is complete.
+    /// Returns `None` if batch wasn't flushed yet.
+    /// Staging buffers of downloads in complete batch are released.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    ///
+    pub(crate) unsafe fn is_complete(
+        &self,
+        device: &Device<B>,
+        family: usize,
+        batch: u64,
+    ) -> Result<Option<bool>, OomOrDeviceLost> {
+        let mut family_uploads = self.family_uploads[family].as_ref().unwrap().lock();
+        if family_uploads.is_next(batch) {
+            return Ok(None);
+        }
+        match family_uploads.pending.iter_mut().find(|p| p.batch == batch) {
+            Some(pending) => {
+                let complete = device.get_fence_status(&pending.fence)?;
+                if complete {
+                    pending.download_buffers.clear();
+                }
+                Ok(Some(complete))
+            }
+            None => Ok(Some(true)),
+        }
+    }
+
+    /// Wait for batch of uploads submitted to the family to complete.
+    /// Returns `false` if batch wasn't flushed yet.
+    /// Staging buffers of downloads in the batch are released.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same that was used to create this `Uploader`.
+    ///
+    pub(crate) unsafe fn wait(
+        &self,
+        device: &Device<B>,
+        family: usize,
+        batch: u64,
+    ) -> Result<bool, OomOrDeviceLost> {
+        let mut family_uploads = self.family_uploads[family].as_ref().unwrap().lock();
+        if family_uploads.is_next(batch) {
+            return Ok(false);
+        }
+        if let Some(pending) = family_uploads.pending.iter_mut().find(|p| p.batch == batch) {
+            device.wait_for_fence(&pending.fence, !0)?;
+            pending.download_buffers.clear();
+        }
+        Ok(true)
+    }
+
     /// Cleanup pending updates.
     ///
     /// # Safety
@@ -409,6 +915,29 @@ where
     }
 }
 
+/// Make transfer writes recorded earlier in the batch visible to the following copy.
+/// Batch barriers make resources available only for transfer writes.
+unsafe fn transfer_read_barrier<B, C, L>(encoder: &mut Encoder<'_, B, C, L>)
+where
+    B: rendy_core::hal::Backend,
+{
+    encoder.pipeline_barrier(
+        rendy_core::hal::pso::PipelineStage::TRANSFER
+            ..rendy_core::hal::pso::PipelineStage::TRANSFER,
+        rendy_core::hal::memory::Dependencies::empty(),
+        vec![
+            rendy_core::hal::memory::Barrier::AllBuffers(
+                rendy_core::hal::buffer::Access::TRANSFER_WRITE
+                    ..rendy_core::hal::buffer::Access::TRANSFER_READ,
+            ),
+            rendy_core::hal::memory::Barrier::AllImages(
+                rendy_core::hal::image::Access::TRANSFER_WRITE
+                    ..rendy_core::hal::image::Access::TRANSFER_READ,
+            ),
+        ],
+    );
+}
+
 #[derive(Debug)]
 pub(crate) struct FamilyUploads<B: rendy_core::hal::Backend> {
     pool: CommandPool<B, Transfer, IndividualReset>,
@@ -418,6 +947,7 @@ pub(crate) struct FamilyUploads<B: rendy_core::hal::Backend> {
     pending: VecDeque<PendingUploads<B>>,
     fences: Vec<B::Fence>,
     barriers: Barriers<B>,
+    batches: u64,
 }
 
 #[derive(Debug)]
@@ -425,7 +955,11 @@ pub(crate) struct PendingUploads<B: rendy_core::hal::Backend> {
     barrier_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     command_buffer: CommandBuffer<B, Transfer, PendingOnceState, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
+    download_buffers: Vec<Handle<Buffer<B>>>,
+    retired_buffers: Vec<Handle<Buffer<B>>>,
+    retired_images: Vec<Handle<Image<B>>>,
     fence: B::Fence,
+    batch: u64,
 }
 
 #[derive(Debug)]
@@ -435,7 +969,11 @@ struct NextUploads<B: rendy_core::hal::Backend> {
     command_buffer:
         CommandBuffer<B, Transfer, RecordingState<OneShot>, PrimaryLevel, IndividualReset>,
     staging_buffers: Vec<Escape<Buffer<B>>>,
+    download_buffers: Vec<Handle<Buffer<B>>>,
+    retired_buffers: Vec<Handle<Buffer<B>>>,
+    retired_images: Vec<Handle<Image<B>>>,
     fence: B::Fence,
+    batch: u64,
 }
 
 impl<B> FamilyUploads<B>
@@ -467,7 +1005,11 @@ where
                 barrier_buffer,
                 command_buffer,
                 staging_buffers: next.staging_buffers,
+                download_buffers: next.download_buffers,
+                retired_buffers: next.retired_buffers,
+                retired_images: next.retired_images,
                 fence: next.fence,
+                batch: next.batch,
             });
         }
     }
@@ -482,6 +1024,7 @@ where
         }
 
         let pool = &mut self.pool;
+        let batches = &mut self.batches;
 
         match &mut self.next[queue] {
             Some(next) => Ok(next),
@@ -498,14 +1041,23 @@ where
                     barrier_buffer: buf_a.begin(OneShot, ()),
                     command_buffer: buf_b.begin(OneShot, ()),
                     staging_buffers: Vec::new(),
+                    download_buffers: Vec::new(),
+                    retired_buffers: Vec::new(),
+                    retired_images: Vec::new(),
                     fence,
+                    batch: *batches,
                 });
+                *batches += 1;
 
                 Ok(slot.as_mut().unwrap())
             }
         }
     }
 
+    fn is_next(&self, batch: u64) -> bool {
+        self.next.iter().flatten().any(|next| next.batch == batch)
+    }
+
     /// Cleanup pending updates.
     ///
     /// # Safety
@@ -523,6 +1075,11 @@ where
                     panic!("Device lost error is not handled yet");
                 }
                 Ok(true) => {
+                    log::trace!(
+                        "Release {} relocated buffers and {} relocated images",
+                        pending.retired_buffers.len(),
+                        pending.retired_images.len()
+                    );
                     device
                         .reset_fence(&pending.fence)
                         .expect("Can always reset signalled fence");
@@ -0,0 +1,1514 @@
+//! Fake backend that runs without GPU.
+//! Device memory is allocated from host memory when mapped.
+//! Buffer copies are performed when recorded.
+//! Other objects have no backing and other recorded commands are not executed.
+//! Submissions complete when device or queue is waited for.
+//!
+//! This crate is intended for tests and tools, not for rendering.
+
+#![warn(
+    missing_debug_implementations,
+    missing_copy_implementations,
+    missing_docs,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use {
+    gfx_hal::{
+        adapter::{self, Adapter, AdapterInfo, DeviceType, Gpu, MemoryProperties, PhysicalDevice},
+        buffer, command,
+        command::{
+            AttachmentClear, BufferCopy, BufferImageCopy, ClearValue, CommandBufferFlags,
+            CommandBufferInheritanceInfo, DescriptorSetOffset, ImageBlit, ImageCopy, ImageResolve,
+            SubpassContents,
+        },
+        device::{
+            self, AllocationError, BindError, Device, DeviceLost, MapError, OomOrDeviceLost,
+            OutOfMemory, ShaderError,
+        },
+        format,
+        image::{self, Filter, Layout, SubresourceRange},
+        memory::{Barrier, Dependencies, Properties, Requirements, Segment},
+        pass,
+        pool::{CommandPool, CommandPoolCreateFlags},
+        pso::{self, DescriptorPool, DescriptorPoolCreateFlags},
+        query,
+        queue::{
+            CommandQueue, QueueFamily, QueueFamilyId, QueueGroup, QueuePriority, QueueType,
+            Submission,
+        },
+        window::{
+            self, AcquireError, PresentError, PresentationSurface, Suboptimal, Surface,
+            SurfaceCapabilities, SwapImageIndex, Swapchain, SwapchainConfig,
+        },
+        Backend, DrawCount, Features, Hints, IndexCount, Instance, InstanceCount, Limits,
+        MemoryTypeId, UnsupportedBackend, VertexCount, VertexOffset, WorkGroupCount,
+    },
+    std::{
+        borrow::Borrow,
+        ops::Range,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+    },
+};
+
+/// Alignment of buffers and images created by fake device.
+const ALIGNMENT: u64 = 256;
+
+/// Backend with fake device.
+/// Used to run allocators and factory without GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FakeBackend {}
+
+/// Placeholder for objects fake backend can't create.
+#[derive(Clone, Copy, Debug)]
+pub enum Unsupported {}
+
+impl Backend for FakeBackend {
+    type Instance = FakeInstance;
+    type PhysicalDevice = FakePhysicalDevice;
+    type Device = FakeDevice;
+
+    type Surface = Unsupported;
+    type Swapchain = Unsupported;
+
+    type QueueFamily = FakeQueueFamily;
+    type CommandQueue = FakeQueue;
+    type CommandBuffer = FakeCommandBuffer;
+
+    type ShaderModule = FakeObject;
+    type RenderPass = FakeObject;
+    type Framebuffer = FakeObject;
+
+    type Memory = FakeMemory;
+    type CommandPool = FakeCommandPool;
+
+    type Buffer = FakeBuffer;
+    type BufferView = FakeObject;
+    type Image = FakeImage;
+    type ImageView = FakeObject;
+    type Sampler = FakeObject;
+
+    type ComputePipeline = FakeObject;
+    type GraphicsPipeline = FakeObject;
+    type PipelineCache = FakeObject;
+    type PipelineLayout = FakeObject;
+    type DescriptorPool = FakeDescriptorPool;
+    type DescriptorSet = FakeObject;
+    type DescriptorSetLayout = FakeObject;
+
+    type Fence = FakeFence;
+    type Semaphore = FakeObject;
+    type Event = FakeObject;
+    type QueryPool = FakeObject;
+}
+
+/// Object of the fake backend without any state.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakeObject;
+
+/// Memory object of the fake device.
+#[derive(Debug)]
+pub struct FakeMemory {
+    memory_type: MemoryTypeId,
+    size: u64,
+    data: HostMemory,
+}
+
+impl FakeMemory {
+    /// Get memory type this memory was allocated from.
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.memory_type
+    }
+
+    /// Get size of the memory object.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Buffer of the fake device.
+#[derive(Debug)]
+pub struct FakeBuffer {
+    size: u64,
+    bound: Option<(HostMemory, u64, u64)>,
+}
+
+impl FakeBuffer {
+    /// Get host memory bound to the buffer, allocating it if memory wasn't mapped yet.
+    fn host_memory(&self) -> (&HostMemory, usize) {
+        let (data, offset, size) = self.bound.as_ref().expect("Buffer must be bound to memory");
+        let mut bytes = data.lock().unwrap();
+        if bytes.is_empty() {
+            bytes.resize(*size as usize, 0);
+        }
+        (data, *offset as usize)
+    }
+}
+
+/// Image of the fake device.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakeImage {
+    size: u64,
+}
+
+/// Fence of the fake device.
+/// Submitted fence becomes signaled when device or queue is waited for.
+#[derive(Debug)]
+pub struct FakeFence {
+    signaled: Arc<AtomicBool>,
+}
+
+/// Host memory backing memory object of the fake device.
+type HostMemory = Arc<Mutex<Vec<u8>>>;
+
+/// Fences submitted but not signaled yet.
+type Pending = Arc<Mutex<Vec<Arc<AtomicBool>>>>;
+
+/// Signal all submitted fences.
+fn complete(pending: &Pending) {
+    for fence in pending.lock().unwrap().drain(..) {
+        fence.store(true, Ordering::Release);
+    }
+}
+
+/// Memory and object statistics of the fake device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FakeDeviceStats {
+    /// Number of memory objects allocated.
+    pub allocations: usize,
+
+    /// Number of memory objects freed.
+    pub frees: usize,
+
+    /// Total size of memory objects currently allocated.
+    pub allocated: u64,
+
+    /// Highest total size of memory objects allocated at once.
+    pub peak: u64,
+
+    /// Total size of mapped ranges flushed.
+    pub flushed: u64,
+
+    /// Total size of mapped ranges invalidated.
+    pub invalidated: u64,
+
+    /// Number of buffers currently alive.
+    pub buffers: usize,
+
+    /// Number of images currently alive.
+    pub images: usize,
+
+    /// Number of pipeline layouts currently alive.
+    pub pipeline_layouts: usize,
+
+    /// Number of compute pipelines currently alive.
+    pub compute_pipelines: usize,
+
+    /// Number of times command buffers were begun.
+    pub recordings: usize,
+}
+
+/// Command recorded by command buffer of the fake device.
+/// Only commands inspected by tests are recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FakeCommand {
+    /// Compute pipeline was bound.
+    BindComputePipeline,
+
+    /// Descriptor sets were bound to compute pipeline.
+    BindComputeDescriptorSets {
+        /// Index of the first set.
+        first_set: usize,
+
+        /// Number of sets bound.
+        sets: usize,
+    },
+
+    /// Compute work was dispatched.
+    Dispatch(WorkGroupCount),
+}
+
+/// Statistics shared by device and objects that update them.
+type Stats = Arc<Mutex<FakeDeviceStats>>;
+
+/// Commands of submitted command buffers.
+type Submitted = Arc<Mutex<Vec<FakeCommand>>>;
+
+/// Get total size of memory segments, checking that they are in bounds.
+fn segments_size<'a, I>(ranges: I) -> u64
+where
+    I: IntoIterator,
+    I::Item: Borrow<(&'a FakeMemory, Segment)>,
+{
+    ranges
+        .into_iter()
+        .map(|range| {
+            let (memory, segment) = range.borrow();
+            let size = segment.size.unwrap_or(memory.size - segment.offset);
+            assert!(
+                segment.offset + size <= memory.size,
+                "Segment is out of memory bounds"
+            );
+            size
+        })
+        .sum()
+}
+
+/// Get size of the image with all mip levels and layers.
+fn image_size(kind: image::Kind, levels: image::Level, format: format::Format) -> u64 {
+    let bytes = u64::from(format.surface_desc().bits / 8);
+    let extent = kind.extent();
+    let texels: u64 = (0..levels)
+        .map(|level| {
+            let extent = extent.at_level(level);
+            u64::from(extent.width) * u64::from(extent.height) * u64::from(extent.depth)
+        })
+        .sum();
+    texels * u64::from(kind.num_layers()) * u64::from(kind.num_samples()) * bytes
+}
+
+/// Check that memory range is in bounds of the memory object.
+fn bind(memory: &FakeMemory, offset: u64, size: u64) -> Result<(), BindError> {
+    if offset & (ALIGNMENT - 1) != 0 || offset + size > memory.size {
+        return Err(BindError::OutOfBounds);
+    }
+    Ok(())
+}
+
+/// Device of the fake backend.
+#[derive(Debug, Default)]
+pub struct FakeDevice {
+    stats: Stats,
+    pending: Pending,
+    submitted: Submitted,
+    out_of_memory: AtomicBool,
+}
+
+impl FakeDevice {
+    /// Create fake device.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get memory statistics.
+    pub fn stats(&self) -> FakeDeviceStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Take commands of command buffers submitted to queues of the device since last call.
+    pub fn take_submitted(&self) -> Vec<FakeCommand> {
+        std::mem::take(&mut *self.submitted.lock().unwrap())
+    }
+
+    /// Make creation of objects other than memory, fence resets and waiting for idle device
+    /// fail with `OutOfMemory::Device`.
+    /// Memory allocation failures are injected with `Heaps::set_fault_policy`.
+    pub fn set_out_of_memory(&self, out_of_memory: bool) {
+        self.out_of_memory.store(out_of_memory, Ordering::Relaxed);
+    }
+
+    fn check_memory(&self) -> Result<(), OutOfMemory> {
+        if self.out_of_memory.load(Ordering::Relaxed) {
+            Err(OutOfMemory::Device)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Device<FakeBackend> for FakeDevice {
+    unsafe fn allocate_memory(
+        &self,
+        memory_type: MemoryTypeId,
+        size: u64,
+    ) -> Result<FakeMemory, AllocationError> {
+        let mut stats = self.stats.lock().unwrap();
+        stats.allocations += 1;
+        stats.allocated += size;
+        stats.peak = stats.peak.max(stats.allocated);
+        Ok(FakeMemory {
+            memory_type,
+            size,
+            data: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    unsafe fn free_memory(&self, memory: FakeMemory) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.frees += 1;
+        stats.allocated -= memory.size;
+    }
+
+    unsafe fn map_memory(
+        &self,
+        memory: &FakeMemory,
+        segment: Segment,
+    ) -> Result<*mut u8, MapError> {
+        let end = segment
+            .size
+            .map_or(memory.size, |size| segment.offset + size);
+        if end > memory.size {
+            return Err(MapError::OutOfBounds);
+        }
+
+        // Host memory is allocated on first mapping and lives as long as memory object.
+        let mut data = memory.data.lock().unwrap();
+        if data.is_empty() {
+            data.resize(memory.size as usize, 0);
+        }
+        Ok(data.as_mut_ptr().add(segment.offset as usize))
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a FakeMemory, Segment)>,
+    {
+        let size = segments_size(ranges);
+        self.stats.lock().unwrap().flushed += size;
+        Ok(())
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a FakeMemory, Segment)>,
+    {
+        let size = segments_size(ranges);
+        self.stats.lock().unwrap().invalidated += size;
+        Ok(())
+    }
+
+    unsafe fn unmap_memory(&self, _memory: &FakeMemory) {}
+
+    unsafe fn create_command_pool(
+        &self,
+        _family: QueueFamilyId,
+        _create_flags: CommandPoolCreateFlags,
+    ) -> Result<FakeCommandPool, OutOfMemory> {
+        self.check_memory()?;
+        Ok(FakeCommandPool {
+            stats: self.stats.clone(),
+        })
+    }
+
+    unsafe fn destroy_command_pool(&self, _pool: FakeCommandPool) {}
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        _attachments: IA,
+        _subpasses: IS,
+        _dependencies: ID,
+    ) -> Result<FakeObject, OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_render_pass(&self, _rp: FakeObject) {}
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        _set_layouts: IS,
+        _push_constant: IR,
+    ) -> Result<FakeObject, OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<FakeObject>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        self.check_memory()?;
+        self.stats.lock().unwrap().pipeline_layouts += 1;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, _layout: FakeObject) {
+        self.stats.lock().unwrap().pipeline_layouts -= 1;
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        _data: Option<&[u8]>,
+    ) -> Result<FakeObject, OutOfMemory> {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn get_pipeline_cache_data(&self, _cache: &FakeObject) -> Result<Vec<u8>, OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    unsafe fn merge_pipeline_caches<I>(
+        &self,
+        _target: &FakeObject,
+        _sources: I,
+    ) -> Result<(), OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<FakeObject>,
+    {
+        Ok(())
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _cache: FakeObject) {}
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        _desc: &pso::GraphicsPipelineDesc<'a, FakeBackend>,
+        _cache: Option<&FakeObject>,
+    ) -> Result<FakeObject, pso::CreationError> {
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_graphics_pipeline(&self, _pipeline: FakeObject) {}
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        _desc: &pso::ComputePipelineDesc<'a, FakeBackend>,
+        _cache: Option<&FakeObject>,
+    ) -> Result<FakeObject, pso::CreationError> {
+        self.stats.lock().unwrap().compute_pipelines += 1;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, _pipeline: FakeObject) {
+        self.stats.lock().unwrap().compute_pipelines -= 1;
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _pass: &FakeObject,
+        _attachments: I,
+        _extent: image::Extent,
+    ) -> Result<FakeObject, OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<FakeObject>,
+    {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_framebuffer(&self, _buf: FakeObject) {}
+
+    unsafe fn create_shader_module(&self, _spirv_data: &[u32]) -> Result<FakeObject, ShaderError> {
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_shader_module(&self, _shader: FakeObject) {}
+
+    unsafe fn create_buffer(
+        &self,
+        size: u64,
+        _usage: buffer::Usage,
+    ) -> Result<FakeBuffer, buffer::CreationError> {
+        self.check_memory()
+            .map_err(buffer::CreationError::OutOfMemory)?;
+        self.stats.lock().unwrap().buffers += 1;
+        Ok(FakeBuffer { size, bound: None })
+    }
+
+    unsafe fn get_buffer_requirements(&self, buf: &FakeBuffer) -> Requirements {
+        Requirements {
+            size: buf.size,
+            alignment: ALIGNMENT,
+            type_mask: !0,
+        }
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        memory: &FakeMemory,
+        offset: u64,
+        buf: &mut FakeBuffer,
+    ) -> Result<(), BindError> {
+        bind(memory, offset, buf.size)?;
+        buf.bound = Some((memory.data.clone(), offset, memory.size));
+        Ok(())
+    }
+
+    unsafe fn destroy_buffer(&self, _buffer: FakeBuffer) {
+        self.stats.lock().unwrap().buffers -= 1;
+    }
+
+    unsafe fn create_buffer_view(
+        &self,
+        _buf: &FakeBuffer,
+        _fmt: Option<format::Format>,
+        _range: buffer::SubRange,
+    ) -> Result<FakeObject, buffer::ViewCreationError> {
+        self.check_memory()
+            .map_err(buffer::ViewCreationError::OutOfMemory)?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_buffer_view(&self, _view: FakeObject) {}
+
+    unsafe fn create_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        _tiling: image::Tiling,
+        _usage: image::Usage,
+        _view_caps: image::ViewCapabilities,
+    ) -> Result<FakeImage, image::CreationError> {
+        self.check_memory()
+            .map_err(image::CreationError::OutOfMemory)?;
+        self.stats.lock().unwrap().images += 1;
+        Ok(FakeImage {
+            size: image_size(kind, mip_levels, format),
+        })
+    }
+
+    unsafe fn get_image_requirements(&self, image: &FakeImage) -> Requirements {
+        Requirements {
+            size: image.size,
+            alignment: ALIGNMENT,
+            type_mask: !0,
+        }
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &FakeImage,
+        _subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        image::SubresourceFootprint {
+            slice: 0..image.size,
+            row_pitch: 0,
+            array_pitch: 0,
+            depth_pitch: 0,
+        }
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        memory: &FakeMemory,
+        offset: u64,
+        image: &mut FakeImage,
+    ) -> Result<(), BindError> {
+        bind(memory, offset, image.size)
+    }
+
+    unsafe fn destroy_image(&self, _image: FakeImage) {
+        self.stats.lock().unwrap().images -= 1;
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        _image: &FakeImage,
+        _view_kind: image::ViewKind,
+        _format: format::Format,
+        _swizzle: format::Swizzle,
+        _range: SubresourceRange,
+    ) -> Result<FakeObject, image::ViewCreationError> {
+        self.check_memory()
+            .map_err(image::ViewCreationError::OutOfMemory)?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_image_view(&self, _view: FakeObject) {}
+
+    unsafe fn create_sampler(
+        &self,
+        _desc: &image::SamplerDesc,
+    ) -> Result<FakeObject, AllocationError> {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_sampler(&self, _sampler: FakeObject) {}
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        _max_sets: usize,
+        _descriptor_ranges: I,
+        _flags: DescriptorPoolCreateFlags,
+    ) -> Result<FakeDescriptorPool, OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        self.check_memory()?;
+        Ok(FakeDescriptorPool)
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, _pool: FakeDescriptorPool) {}
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        _bindings: I,
+        _immutable_samplers: J,
+    ) -> Result<FakeObject, OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<FakeObject>,
+    {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _layout: FakeObject) {}
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, _write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, FakeBackend, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, FakeBackend>>,
+    {
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, _copy_iter: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, FakeBackend>>,
+    {
+    }
+
+    fn create_semaphore(&self) -> Result<FakeObject, OutOfMemory> {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_semaphore(&self, _semaphore: FakeObject) {}
+
+    fn create_fence(&self, signaled: bool) -> Result<FakeFence, OutOfMemory> {
+        self.check_memory()?;
+        Ok(FakeFence {
+            signaled: Arc::new(AtomicBool::new(signaled)),
+        })
+    }
+
+    unsafe fn reset_fence(&self, fence: &FakeFence) -> Result<(), OutOfMemory> {
+        self.check_memory()?;
+        fence.signaled.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    unsafe fn wait_for_fence(
+        &self,
+        fence: &FakeFence,
+        _timeout_ns: u64,
+    ) -> Result<bool, OomOrDeviceLost> {
+        complete(&self.pending);
+        Ok(fence.signaled.load(Ordering::Acquire))
+    }
+
+    unsafe fn get_fence_status(&self, fence: &FakeFence) -> Result<bool, DeviceLost> {
+        Ok(fence.signaled.load(Ordering::Acquire))
+    }
+
+    unsafe fn destroy_fence(&self, _fence: FakeFence) {}
+
+    fn create_event(&self) -> Result<FakeObject, OutOfMemory> {
+        self.check_memory()?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_event(&self, _event: FakeObject) {}
+
+    unsafe fn get_event_status(&self, _event: &FakeObject) -> Result<bool, OomOrDeviceLost> {
+        Ok(false)
+    }
+
+    unsafe fn set_event(&self, _event: &FakeObject) -> Result<(), OutOfMemory> {
+        Ok(())
+    }
+
+    unsafe fn reset_event(&self, _event: &FakeObject) -> Result<(), OutOfMemory> {
+        Ok(())
+    }
+
+    unsafe fn create_query_pool(
+        &self,
+        _ty: query::Type,
+        _count: query::Id,
+    ) -> Result<FakeObject, query::CreationError> {
+        self.check_memory()
+            .map_err(query::CreationError::OutOfMemory)?;
+        Ok(FakeObject)
+    }
+
+    unsafe fn destroy_query_pool(&self, _pool: FakeObject) {}
+
+    unsafe fn get_query_pool_results(
+        &self,
+        _pool: &FakeObject,
+        _queries: Range<query::Id>,
+        _data: &mut [u8],
+        _stride: buffer::Offset,
+        _flags: query::ResultFlags,
+    ) -> Result<bool, OomOrDeviceLost> {
+        Ok(false)
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut Unsupported,
+        _config: SwapchainConfig,
+        _old_swapchain: Option<Unsupported>,
+    ) -> Result<(Unsupported, Vec<FakeImage>), window::CreationError> {
+        match *surface {}
+    }
+
+    unsafe fn destroy_swapchain(&self, swapchain: Unsupported) {
+        match swapchain {}
+    }
+
+    fn wait_idle(&self) -> Result<(), OutOfMemory> {
+        self.check_memory()?;
+        complete(&self.pending);
+        Ok(())
+    }
+
+    unsafe fn set_image_name(&self, _image: &mut FakeImage, _name: &str) {}
+
+    unsafe fn set_buffer_name(&self, _buffer: &mut FakeBuffer, _name: &str) {}
+
+    unsafe fn set_command_buffer_name(&self, _command_buffer: &mut FakeCommandBuffer, _name: &str) {
+    }
+
+    unsafe fn set_semaphore_name(&self, _semaphore: &mut FakeObject, _name: &str) {}
+
+    unsafe fn set_fence_name(&self, _fence: &mut FakeFence, _name: &str) {}
+
+    unsafe fn set_framebuffer_name(&self, _framebuffer: &mut FakeObject, _name: &str) {}
+
+    unsafe fn set_render_pass_name(&self, _render_pass: &mut FakeObject, _name: &str) {}
+
+    unsafe fn set_descriptor_set_name(&self, _descriptor_set: &mut FakeObject, _name: &str) {}
+
+    unsafe fn set_descriptor_set_layout_name(
+        &self,
+        _descriptor_set_layout: &mut FakeObject,
+        _name: &str,
+    ) {
+    }
+}
+
+/// Command buffer of the fake device.
+/// Only some commands are recorded, see `FakeCommand`.
+#[derive(Debug)]
+pub struct FakeCommandBuffer {
+    commands: Vec<FakeCommand>,
+    stats: Stats,
+}
+
+impl command::CommandBuffer<FakeBackend> for FakeCommandBuffer {
+    unsafe fn begin(
+        &mut self,
+        _flags: CommandBufferFlags,
+        _inheritance_info: CommandBufferInheritanceInfo<FakeBackend>,
+    ) {
+        self.commands.clear();
+        self.stats.lock().unwrap().recordings += 1;
+    }
+
+    unsafe fn finish(&mut self) {}
+
+    unsafe fn reset(&mut self, _release_resources: bool) {
+        self.commands.clear();
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        _stages: Range<pso::PipelineStage>,
+        _dependencies: Dependencies,
+        _barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, FakeBackend>>,
+    {
+    }
+
+    unsafe fn fill_buffer(&mut self, _buffer: &FakeBuffer, _range: buffer::SubRange, _data: u32) {}
+
+    unsafe fn update_buffer(
+        &mut self,
+        _buffer: &FakeBuffer,
+        _offset: buffer::Offset,
+        _data: &[u8],
+    ) {
+    }
+
+    unsafe fn clear_image<T>(
+        &mut self,
+        _image: &FakeImage,
+        _layout: Layout,
+        _value: ClearValue,
+        _subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<SubresourceRange>,
+    {
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, _clears: T, _rects: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        _src: &FakeImage,
+        _src_layout: Layout,
+        _dst: &FakeImage,
+        _dst_layout: Layout,
+        _regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ImageResolve>,
+    {
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        _src: &FakeImage,
+        _src_layout: Layout,
+        _dst: &FakeImage,
+        _dst_layout: Layout,
+        _filter: Filter,
+        _regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ImageBlit>,
+    {
+    }
+
+    unsafe fn bind_index_buffer(&mut self, _view: buffer::IndexBufferView<FakeBackend>) {}
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, _first_binding: pso::BufferIndex, _buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::SubRange)>,
+        T: Borrow<FakeBuffer>,
+    {
+    }
+
+    unsafe fn set_viewports<T>(&mut self, _first_viewport: u32, _viewports: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+    }
+
+    unsafe fn set_scissors<T>(&mut self, _first_scissor: u32, _rects: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+    }
+
+    unsafe fn set_stencil_reference(&mut self, _faces: pso::Face, _value: pso::StencilValue) {}
+
+    unsafe fn set_stencil_read_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {}
+
+    unsafe fn set_stencil_write_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {}
+
+    unsafe fn set_blend_constants(&mut self, _color: pso::ColorValue) {}
+
+    unsafe fn set_depth_bounds(&mut self, _bounds: Range<f32>) {}
+
+    unsafe fn set_line_width(&mut self, _width: f32) {}
+
+    unsafe fn set_depth_bias(&mut self, _depth_bias: pso::DepthBias) {}
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        _render_pass: &FakeObject,
+        _framebuffer: &FakeObject,
+        _render_area: pso::Rect,
+        _clear_values: T,
+        _first_subpass: SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ClearValue>,
+    {
+    }
+
+    unsafe fn next_subpass(&mut self, _contents: SubpassContents) {}
+
+    unsafe fn end_render_pass(&mut self) {}
+
+    unsafe fn bind_graphics_pipeline(&mut self, _pipeline: &FakeObject) {}
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(
+        &mut self,
+        _layout: &FakeObject,
+        _first_set: usize,
+        _sets: I,
+        _offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<FakeObject>,
+        J: IntoIterator,
+        J::Item: Borrow<DescriptorSetOffset>,
+    {
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, _pipeline: &FakeObject) {
+        self.commands.push(FakeCommand::BindComputePipeline);
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(
+        &mut self,
+        _layout: &FakeObject,
+        first_set: usize,
+        sets: I,
+        _offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<FakeObject>,
+        J: IntoIterator,
+        J::Item: Borrow<DescriptorSetOffset>,
+    {
+        self.commands.push(FakeCommand::BindComputeDescriptorSets {
+            first_set,
+            sets: sets.into_iter().count(),
+        });
+    }
+
+    unsafe fn dispatch(&mut self, count: WorkGroupCount) {
+        self.commands.push(FakeCommand::Dispatch(count));
+    }
+
+    unsafe fn dispatch_indirect(&mut self, _buffer: &FakeBuffer, _offset: buffer::Offset) {}
+
+    unsafe fn copy_buffer<T>(&mut self, src: &FakeBuffer, dst: &FakeBuffer, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<BufferCopy>,
+    {
+        let (src_data, src_offset) = src.host_memory();
+        let (dst_data, dst_offset) = dst.host_memory();
+        for region in regions {
+            let region = region.borrow();
+            assert!(region.src + region.size <= src.size && region.dst + region.size <= dst.size);
+            let src_start = src_offset + region.src as usize;
+            let dst_start = dst_offset + region.dst as usize;
+            let size = region.size as usize;
+            if Arc::ptr_eq(src_data, dst_data) {
+                let mut data = dst_data.lock().unwrap();
+                data.copy_within(src_start..src_start + size, dst_start);
+            } else {
+                let src_data = src_data.lock().unwrap();
+                let mut dst_data = dst_data.lock().unwrap();
+                dst_data[dst_start..dst_start + size]
+                    .copy_from_slice(&src_data[src_start..src_start + size]);
+            }
+        }
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        _src: &FakeImage,
+        _src_layout: Layout,
+        _dst: &FakeImage,
+        _dst_layout: Layout,
+        _regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ImageCopy>,
+    {
+    }
+
+    unsafe fn copy_buffer_to_image<T>(
+        &mut self,
+        _src: &FakeBuffer,
+        _dst: &FakeImage,
+        _dst_layout: Layout,
+        _regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<BufferImageCopy>,
+    {
+    }
+
+    unsafe fn copy_image_to_buffer<T>(
+        &mut self,
+        _src: &FakeImage,
+        _src_layout: Layout,
+        _dst: &FakeBuffer,
+        _regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<BufferImageCopy>,
+    {
+    }
+
+    unsafe fn draw(&mut self, _vertices: Range<VertexCount>, _instances: Range<InstanceCount>) {}
+
+    unsafe fn draw_indexed(
+        &mut self,
+        _indices: Range<IndexCount>,
+        _base_vertex: VertexOffset,
+        _instances: Range<InstanceCount>,
+    ) {
+    }
+
+    unsafe fn draw_indirect(
+        &mut self,
+        _buffer: &FakeBuffer,
+        _offset: buffer::Offset,
+        _draw_count: DrawCount,
+        _stride: u32,
+    ) {
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        _buffer: &FakeBuffer,
+        _offset: buffer::Offset,
+        _draw_count: DrawCount,
+        _stride: u32,
+    ) {
+    }
+
+    unsafe fn set_event(&mut self, _event: &FakeObject, _stages: pso::PipelineStage) {}
+
+    unsafe fn reset_event(&mut self, _event: &FakeObject, _stages: pso::PipelineStage) {}
+
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        _events: I,
+        _stages: Range<pso::PipelineStage>,
+        _barriers: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<FakeObject>,
+        J: IntoIterator,
+        J::Item: Borrow<Barrier<'a, FakeBackend>>,
+    {
+    }
+
+    unsafe fn begin_query(
+        &mut self,
+        _query: query::Query<FakeBackend>,
+        _flags: query::ControlFlags,
+    ) {
+    }
+
+    unsafe fn end_query(&mut self, _query: query::Query<FakeBackend>) {}
+
+    unsafe fn reset_query_pool(&mut self, _pool: &FakeObject, _queries: Range<query::Id>) {}
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        _pool: &FakeObject,
+        _queries: Range<query::Id>,
+        _buffer: &FakeBuffer,
+        _offset: buffer::Offset,
+        _stride: buffer::Offset,
+        _flags: query::ResultFlags,
+    ) {
+    }
+
+    unsafe fn write_timestamp(
+        &mut self,
+        _stage: pso::PipelineStage,
+        _query: query::Query<FakeBackend>,
+    ) {
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        _layout: &FakeObject,
+        _stages: pso::ShaderStageFlags,
+        _offset: u32,
+        _constants: &[u32],
+    ) {
+    }
+
+    unsafe fn push_compute_constants(
+        &mut self,
+        _layout: &FakeObject,
+        _offset: u32,
+        _constants: &[u32],
+    ) {
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, _cmd_buffers: I)
+    where
+        T: 'a + Borrow<FakeCommandBuffer>,
+        I: IntoIterator<Item = &'a T>,
+    {
+    }
+
+    unsafe fn insert_debug_marker(&mut self, _name: &str, _color: u32) {}
+
+    unsafe fn begin_debug_marker(&mut self, _name: &str, _color: u32) {}
+
+    unsafe fn end_debug_marker(&mut self) {}
+}
+
+/// Physical device of the fake backend.
+/// Has device local heap and host visible heap.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakePhysicalDevice;
+
+impl PhysicalDevice<FakeBackend> for FakePhysicalDevice {
+    unsafe fn open(
+        &self,
+        families: &[(&FakeQueueFamily, &[QueuePriority])],
+        _requested_features: Features,
+    ) -> Result<Gpu<FakeBackend>, device::CreationError> {
+        let device = FakeDevice::new();
+        let queue_groups = families
+            .iter()
+            .map(|&(family, priorities)| {
+                let mut group = QueueGroup::new(family.id());
+                for _ in priorities {
+                    group.add_queue(FakeQueue {
+                        pending: device.pending.clone(),
+                        submitted: device.submitted.clone(),
+                    });
+                }
+                group
+            })
+            .collect();
+
+        Ok(Gpu {
+            device,
+            queue_groups,
+        })
+    }
+
+    fn format_properties(&self, _format: Option<format::Format>) -> format::Properties {
+        format::Properties {
+            linear_tiling: format::ImageFeature::all(),
+            optimal_tiling: format::ImageFeature::all(),
+            buffer_features: format::BufferFeature::all(),
+        }
+    }
+
+    fn image_format_properties(
+        &self,
+        _format: format::Format,
+        _dimensions: u8,
+        _tiling: image::Tiling,
+        _usage: image::Usage,
+        _view_caps: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        Some(image::FormatProperties {
+            max_extent: image::Extent {
+                width: 4096,
+                height: 4096,
+                depth: 256,
+            },
+            max_levels: 13,
+            max_layers: 256,
+            sample_count_mask: 1,
+            max_resource_size: 1 << 30,
+        })
+    }
+
+    fn memory_properties(&self) -> MemoryProperties {
+        MemoryProperties {
+            memory_types: vec![
+                adapter::MemoryType {
+                    properties: Properties::DEVICE_LOCAL,
+                    heap_index: 0,
+                },
+                adapter::MemoryType {
+                    properties: Properties::CPU_VISIBLE | Properties::COHERENT,
+                    heap_index: 1,
+                },
+                adapter::MemoryType {
+                    properties: Properties::CPU_VISIBLE
+                        | Properties::COHERENT
+                        | Properties::CPU_CACHED,
+                    heap_index: 1,
+                },
+            ],
+            memory_heaps: vec![1 << 30, 1 << 28],
+        }
+    }
+
+    fn features(&self) -> Features {
+        Features::empty()
+    }
+
+    fn hints(&self) -> Hints {
+        Hints::empty()
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_image_1d_size: 4096,
+            max_image_2d_size: 4096,
+            max_image_3d_size: 256,
+            max_image_cube_size: 4096,
+            max_image_array_layers: 256,
+            max_framebuffer_layers: 256,
+            max_framebuffer_extent: image::Extent {
+                width: 4096,
+                height: 4096,
+                depth: 1,
+            },
+            max_bound_descriptor_sets: 8,
+            max_memory_allocation_count: 4096,
+            buffer_image_granularity: 1,
+            min_texel_buffer_offset_alignment: ALIGNMENT,
+            min_uniform_buffer_offset_alignment: ALIGNMENT,
+            min_storage_buffer_offset_alignment: ALIGNMENT,
+            optimal_buffer_copy_offset_alignment: 4,
+            optimal_buffer_copy_pitch_alignment: 4,
+            non_coherent_atom_size: 64,
+            framebuffer_color_sample_counts: 1,
+            framebuffer_depth_sample_counts: 1,
+            framebuffer_stencil_sample_counts: 1,
+            max_color_attachments: 8,
+            ..Limits::default()
+        }
+    }
+}
+
+impl Surface<FakeBackend> for Unsupported {
+    fn supports_queue_family(&self, _family: &FakeQueueFamily) -> bool {
+        match *self {}
+    }
+
+    fn capabilities(&self, _physical_device: &FakePhysicalDevice) -> SurfaceCapabilities {
+        match *self {}
+    }
+
+    fn supported_formats(
+        &self,
+        _physical_device: &FakePhysicalDevice,
+    ) -> Option<Vec<format::Format>> {
+        match *self {}
+    }
+}
+
+impl Borrow<FakeImage> for Unsupported {
+    fn borrow(&self) -> &FakeImage {
+        match *self {}
+    }
+}
+
+impl Borrow<FakeObject> for Unsupported {
+    fn borrow(&self) -> &FakeObject {
+        match *self {}
+    }
+}
+
+impl PresentationSurface<FakeBackend> for Unsupported {
+    type SwapchainImage = Unsupported;
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        _device: &FakeDevice,
+        _config: SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        match *self {}
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, _device: &FakeDevice) {
+        match *self {}
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        _timeout_ns: u64,
+    ) -> Result<(Unsupported, Option<Suboptimal>), AcquireError> {
+        match *self {}
+    }
+}
+
+impl Swapchain<FakeBackend> for Unsupported {
+    unsafe fn acquire_image(
+        &mut self,
+        _timeout_ns: u64,
+        _semaphore: Option<&FakeObject>,
+        _fence: Option<&FakeFence>,
+    ) -> Result<(SwapImageIndex, Option<Suboptimal>), AcquireError> {
+        match *self {}
+    }
+}
+
+/// Queue of the fake device.
+/// Submitted fences are signaled when device or queue is waited for.
+#[derive(Debug)]
+pub struct FakeQueue {
+    pending: Pending,
+    submitted: Submitted,
+}
+
+impl CommandQueue<FakeBackend> for FakeQueue {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        submission: Submission<Ic, Iw, Is>,
+        fence: Option<&FakeFence>,
+    ) where
+        T: 'a + Borrow<FakeCommandBuffer>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<FakeObject>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        let mut submitted = self.submitted.lock().unwrap();
+        for command_buffer in submission.command_buffers {
+            submitted.extend(command_buffer.borrow().commands.iter().copied());
+        }
+        if let Some(fence) = fence {
+            self.pending.lock().unwrap().push(fence.signaled.clone());
+        }
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        swapchains: Is,
+        _wait_semaphores: Iw,
+    ) -> Result<Option<Suboptimal>, PresentError>
+    where
+        Self: Sized,
+        W: 'a + Borrow<Unsupported>,
+        Is: IntoIterator<Item = (&'a W, SwapImageIndex)>,
+        S: 'a + Borrow<FakeObject>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        match swapchains.into_iter().next() {
+            Some((swapchain, _)) => match *swapchain.borrow() {},
+            None => Ok(None),
+        }
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        surface: &mut Unsupported,
+        _image: Unsupported,
+        _wait_semaphore: Option<&FakeObject>,
+    ) -> Result<Option<Suboptimal>, PresentError> {
+        match *surface {}
+    }
+
+    fn wait_idle(&self) -> Result<(), OutOfMemory> {
+        complete(&self.pending);
+        Ok(())
+    }
+}
+
+/// Queue family of the fake backend.
+/// Supports all operations.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakeQueueFamily;
+
+impl QueueFamily for FakeQueueFamily {
+    fn queue_type(&self) -> QueueType {
+        QueueType::General
+    }
+
+    fn max_queues(&self) -> usize {
+        4
+    }
+
+    fn id(&self) -> QueueFamilyId {
+        QueueFamilyId(0)
+    }
+}
+
+/// Command pool of the fake device.
+#[derive(Debug)]
+pub struct FakeCommandPool {
+    stats: Stats,
+}
+
+impl CommandPool<FakeBackend> for FakeCommandPool {
+    unsafe fn reset(&mut self, _release_resources: bool) {}
+
+    unsafe fn allocate_one(&mut self, _level: command::Level) -> FakeCommandBuffer {
+        FakeCommandBuffer {
+            commands: Vec::new(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    unsafe fn free<I>(&mut self, _buffers: I)
+    where
+        I: IntoIterator<Item = FakeCommandBuffer>,
+    {
+    }
+}
+
+/// Descriptor pool of the fake device.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakeDescriptorPool;
+
+impl DescriptorPool<FakeBackend> for FakeDescriptorPool {
+    unsafe fn allocate_set(
+        &mut self,
+        _layout: &FakeObject,
+    ) -> Result<FakeObject, pso::AllocationError> {
+        Ok(FakeObject)
+    }
+
+    unsafe fn free_sets<I>(&mut self, _descriptor_sets: I)
+    where
+        I: IntoIterator<Item = FakeObject>,
+    {
+    }
+
+    unsafe fn reset(&mut self) {}
+}
+
+/// Instance of the fake backend.
+/// Enumerates single adapter with `FakePhysicalDevice`.
+#[derive(Debug)]
+#[allow(missing_copy_implementations)]
+pub struct FakeInstance;
+
+impl Instance<FakeBackend> for FakeInstance {
+    fn create(_name: &str, _version: u32) -> Result<Self, UnsupportedBackend> {
+        Ok(FakeInstance)
+    }
+
+    fn enumerate_adapters(&self) -> Vec<Adapter<FakeBackend>> {
+        vec![Adapter {
+            info: AdapterInfo {
+                name: "Fake device".into(),
+                vendor: 0,
+                device: 0,
+                device_type: DeviceType::Other,
+            },
+            physical_device: FakePhysicalDevice,
+            queue_families: vec![FakeQueueFamily],
+        }]
+    }
+
+    unsafe fn create_surface(
+        &self,
+        _: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Unsupported, window::InitError> {
+        Err(window::InitError::UnsupportedWindowHandle)
+    }
+
+    unsafe fn destroy_surface(&self, surface: Unsupported) {
+        match surface {}
+    }
+}
@@ -65,6 +65,12 @@ impl FramesRange {
     pub fn next(&self) -> Frame {
         Frame { index: self.next }
     }
+
+    /// Get upper bound of complete frames.
+    /// All frames with index less than result of this function are complete.
+    pub fn complete_upper_bound(&self) -> u64 {
+        self.complete_upper_bound
+    }
 }
 
 /// Timeline of frames, complete, pending and next.
@@ -4,18 +4,22 @@ use {
         command::{Families, FamilyId, QueueId},
         core::{device_owned, DeviceId},
         factory::Factory,
-        frame::{Fences, Frame, Frames},
-        memory::Data,
+        frame::{Fences, Frame, Frames, FramesRange},
+        memory::{Data, MemoryBlock},
         node::{
             BufferBarrier, DynNode, ImageBarrier, NodeBuffer, NodeBuildError, NodeBuilder,
             NodeImage,
         },
         resource::{
-            Buffer, BufferCreationError, BufferInfo, Handle, Image, ImageCreationError, ImageInfo,
+            Buffer, BufferCreationError, BufferInfo, CreationError, Escape, Handle, Image,
+            ImageCreationError, ImageInfo,
         },
         BufferId, ImageId, NodeId,
     },
-    rendy_core::hal::{queue::QueueFamilyId, Backend},
+    rendy_core::hal::{
+        device::OutOfMemory, pso::PipelineStage, queue::QueueFamilyId, window::Extent2D, Backend,
+    },
+    std::ops::Range,
     thread_profiler::profile_scope,
 };
 
@@ -48,9 +52,16 @@ pub enum GraphBuildError {
     /// Failed to create an image.
     Image(ImageCreationError),
     /// Failed to create a semaphore.
-    Semaphore(rendy_core::hal::device::OutOfMemory),
+    Semaphore(OutOfMemory),
+    /// Failed to wait for the device to become idle.
+    WaitIdle(OutOfMemory),
     /// Failed to build a node.
     Node(NodeBuildError),
+    /// Failed to schedule nodes.
+    Schedule(chain::CollectError),
+    /// Synchronization generated for the graph is invalid.
+    /// Checked only when debug assertions are enabled.
+    Validation(Vec<chain::ValidationError>),
 }
 
 impl std::fmt::Display for GraphBuildError {
@@ -71,11 +82,26 @@ impl std::fmt::Display for GraphBuildError {
                 "Failed to build graph because of failure to create a semaphore: {:?}",
                 err
             ),
+            GraphBuildError::WaitIdle(err) => write!(
+                fmt,
+                "Failed to resize graph because of failure to wait for device idle: {:?}",
+                err
+            ),
             GraphBuildError::Node(err) => write!(
                 fmt,
                 "Failed to build graph because of failure to build a node: {:?}",
                 err
             ),
+            GraphBuildError::Schedule(err) => write!(
+                fmt,
+                "Failed to build graph because of failure to schedule nodes: {:?}",
+                err
+            ),
+            GraphBuildError::Validation(errors) => write!(
+                fmt,
+                "Failed to build graph because synchronization is invalid: {:?}",
+                errors
+            ),
         }
     }
 }
@@ -86,11 +112,43 @@ impl std::error::Error for GraphBuildError {
             GraphBuildError::Buffer(err) => Some(err),
             GraphBuildError::Image(err) => Some(err),
             GraphBuildError::Semaphore(err) => Some(err),
+            GraphBuildError::WaitIdle(err) => Some(err),
             GraphBuildError::Node(err) => Some(err),
+            GraphBuildError::Schedule(err) => Some(err),
+            GraphBuildError::Validation(errors) => match errors.first() {
+                Some(err) => Some(err),
+                None => None,
+            },
         }
     }
 }
 
+/// Report about memory shared between transient resources with disjoint lifetimes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AliasingReport {
+    /// Number of buffers bound to shared memory.
+    pub buffers: usize,
+
+    /// Number of images bound to shared memory.
+    pub images: usize,
+
+    /// Number of memory blocks shared by those resources.
+    pub blocks: usize,
+
+    /// Bytes those resources would occupy with dedicated allocations.
+    pub requested: u64,
+
+    /// Bytes actually allocated for those resources.
+    pub allocated: u64,
+}
+
+impl AliasingReport {
+    /// Get number of bytes saved by aliasing.
+    pub fn saved(&self) -> u64 {
+        self.requested - self.allocated
+    }
+}
+
 /// Graphics context contains all transient resources managed by graph.
 #[derive(Debug)]
 pub struct GraphContext<B: Backend> {
@@ -101,40 +159,109 @@ pub struct GraphContext<B: Backend> {
             Option<rendy_core::hal::command::ClearValue>,
         )>,
     >,
+    resizable: Vec<bool>,
+    aliased_buffers: Vec<Option<PipelineStage>>,
+    aliased_images: Vec<Option<PipelineStage>>,
+    memory: Vec<MemoryBlock<B>>,
+    aliasing: AliasingReport,
+    extent: Option<Extent2D>,
     /// Number of potential frames in flight
     pub frames_in_flight: u32,
 }
 
+/// Submissions of the single queue that use a transient resource.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Lifetime {
+    queue: chain::QueueId,
+    submissions: Range<usize>,
+    stages: PipelineStage,
+}
+
+/// Transient resource waiting for memory shared with other resources.
+#[derive(Debug)]
+enum UnboundResource<B: Backend> {
+    Buffer(Escape<Buffer<B>>),
+    Image(
+        Escape<Image<B>>,
+        Option<rendy_core::hal::command::ClearValue>,
+    ),
+}
+
+/// Transient resource with its memory requirements and lifetime.
+#[derive(Debug)]
+struct Unbound<B: Backend> {
+    index: usize,
+    resource: UnboundResource<B>,
+    reqs: rendy_core::hal::memory::Requirements,
+    lifetime: Lifetime,
+}
+
+/// Memory block shared by resources with disjoint lifetimes on the same queue.
+#[derive(Debug)]
+struct AliasSlot {
+    queue: chain::QueueId,
+    mask: u32,
+    size: u64,
+    align: u64,
+    stages: PipelineStage,
+    lifetimes: Vec<Range<usize>>,
+    resources: Vec<usize>,
+}
+
+impl AliasSlot {
+    fn fits(&self, mask: u32, lifetime: &Lifetime) -> bool {
+        self.queue == lifetime.queue
+            && self.mask & mask != 0
+            && self
+                .lifetimes
+                .iter()
+                .all(|l| l.end <= lifetime.submissions.start || lifetime.submissions.end <= l.start)
+    }
+}
+
 impl<B: Backend> GraphContext<B> {
     fn alloc<'a>(
         factory: &Factory<B>,
         chains: &chain::Chains,
         buffers: impl IntoIterator<Item = &'a BufferInfo>,
         images: impl IntoIterator<Item = &'a (ImageInfo, Option<rendy_core::hal::command::ClearValue>)>,
+        resizable: Vec<bool>,
         frames_in_flight: u32,
+        aliasing: bool,
     ) -> Result<Self, GraphBuildError> {
         profile_scope!("alloc");
 
         log::trace!("Allocate buffers");
+        let mut unbound = Vec::new();
         let buffers: Vec<Option<Handle<Buffer<B>>>> = buffers
             .into_iter()
             .enumerate()
             .map(|(index, info)| {
-                chains
-                    .buffers
-                    .get(&chain::Id(index))
-                    .map(|buffer| {
-                        factory
-                            .create_buffer(
-                                BufferInfo {
-                                    usage: buffer.usage(),
-                                    ..info.clone()
-                                },
-                                Data,
-                            )
-                            .map(|buffer| Some(buffer.into()))
-                    })
-                    .unwrap_or(Ok(None))
+                let buffer = match chains.buffers.get(&chain::Id(index)) {
+                    Some(buffer) => buffer,
+                    None => return Ok(None),
+                };
+
+                let info = BufferInfo {
+                    usage: buffer.usage(),
+                    ..info.clone()
+                };
+
+                match resource_lifetime(Some(buffer)).filter(|_| aliasing) {
+                    Some(lifetime) => {
+                        let (buffer, reqs) = factory.create_relevant_unbound_buffer(info)?;
+                        unbound.push(Unbound {
+                            index,
+                            resource: UnboundResource::Buffer(factory.escape_buffer(buffer)),
+                            reqs,
+                            lifetime,
+                        });
+                        Ok(None)
+                    }
+                    None => factory
+                        .create_buffer(info, Data)
+                        .map(|buffer| Some(buffer.into())),
+                }
             })
             .collect::<Result<_, _>>()
             .map_err(GraphBuildError::Buffer)?;
@@ -144,30 +271,149 @@ impl<B: Backend> GraphContext<B> {
             .into_iter()
             .enumerate()
             .map(|(index, (info, clear))| {
-                chains
-                    .images
-                    .get(&chain::Id(index))
-                    .map(|image| {
-                        factory
-                            .create_image(
-                                ImageInfo {
-                                    usage: image.usage(),
-                                    ..info.clone()
-                                },
-                                Data,
-                            )
-                            .map(|image| Some((image.into(), *clear)))
-                    })
-                    .unwrap_or(Ok(None))
+                let image: Vec<_> = chains
+                    .image_chains(chain::Id(index))
+                    .map(|(_, chain)| chain)
+                    .collect();
+                if image.is_empty() {
+                    return Ok(None);
+                }
+
+                let info = ImageInfo {
+                    usage: image
+                        .iter()
+                        .fold(rendy_core::hal::image::Usage::empty(), |usage, chain| {
+                            usage | chain.usage()
+                        }),
+                    ..info.clone()
+                };
+
+                match resource_lifetime(image).filter(|_| aliasing && !resizable[index]) {
+                    Some(lifetime) => {
+                        let (image, reqs) = factory.create_relevant_unbound_image(info)?;
+                        unbound.push(Unbound {
+                            index,
+                            resource: UnboundResource::Image(factory.escape_image(image), *clear),
+                            reqs,
+                            lifetime,
+                        });
+                        Ok(None)
+                    }
+                    None => factory
+                        .create_image(info, Data)
+                        .map(|image| Some((image.into(), *clear))),
+                }
             })
             .collect::<Result<_, _>>()
             .map_err(GraphBuildError::Image)?;
 
-        Ok(Self {
+        let (memory, aliasing) = alias_resources(factory, &mut unbound)?;
+        if aliasing.blocks > 0 {
+            log::debug!("Resources aliasing: {:#?}", aliasing);
+        }
+
+        let mut ctx = Self {
+            aliased_buffers: vec![None; buffers.len()],
+            aliased_images: vec![None; images.len()],
             buffers,
             images,
+            resizable,
+            memory,
+            aliasing,
+            extent: None,
             frames_in_flight,
-        })
+        };
+
+        for unbound in unbound {
+            match unbound.resource {
+                UnboundResource::Buffer(buffer) => {
+                    ctx.buffers[unbound.index] = Some(buffer.into());
+                    ctx.aliased_buffers[unbound.index] = Some(unbound.lifetime.stages);
+                }
+                UnboundResource::Image(image, clear) => {
+                    ctx.images[unbound.index] = Some((image.into(), clear));
+                    ctx.aliased_images[unbound.index] = Some(unbound.lifetime.stages);
+                }
+            }
+        }
+
+        Ok(ctx)
+    }
+
+    /// Get report about memory shared by transient resources.
+    /// Resources are aliased only if graph was built with aliasing enabled.
+    pub fn aliasing_report(&self) -> AliasingReport {
+        self.aliasing
+    }
+
+    /// Get extent requested by the last `Graph::resize` call.
+    /// Returns `None` if graph was never resized.
+    pub fn extent(&self) -> Option<Extent2D> {
+        self.extent
+    }
+
+    /// Check if transient buffer shares memory with other resources.
+    pub fn is_buffer_aliased(&self, id: BufferId) -> bool {
+        matches!(self.aliased_buffers.get(id.0), Some(Some(_)))
+    }
+
+    /// Check if transient image shares memory with other resources.
+    pub fn is_image_aliased(&self, id: ImageId) -> bool {
+        matches!(self.aliased_images.get(id.0), Some(Some(_)))
+    }
+
+    /// Recreate resizable images with new extent.
+    /// Returns ids of recreated images.
+    fn resize(
+        &mut self,
+        factory: &Factory<B>,
+        extent: Extent2D,
+    ) -> Result<Vec<ImageId>, GraphBuildError> {
+        profile_scope!("resize");
+
+        self.extent = Some(extent);
+
+        let mut resized = Vec::new();
+        for (index, (image, &resizable)) in self.images.iter_mut().zip(&self.resizable).enumerate()
+        {
+            let (image, _) = match image {
+                Some(image) if resizable => image,
+                _ => continue,
+            };
+
+            let kind = resized_kind(image.kind(), extent);
+            if kind == image.kind() {
+                continue;
+            }
+
+            log::trace!(
+                "Resize image {} from {:?} to {:?}",
+                index,
+                image.kind(),
+                kind
+            );
+            *image = factory
+                .create_image(
+                    ImageInfo {
+                        kind,
+                        ..*image.info()
+                    },
+                    Data,
+                )
+                .map_err(GraphBuildError::Image)?
+                .into();
+            resized.push(ImageId(index));
+        }
+
+        Ok(resized)
+    }
+
+    unsafe fn dispose(self, factory: &Factory<B>) {
+        drop(self.buffers);
+        drop(self.images);
+        for block in self.memory {
+            factory.free_memory(block);
+        }
     }
 
     /// Get reference to transient image by id.
@@ -202,7 +448,14 @@ where
 {
     /// Perform graph execution.
     /// Run every node of the graph and submit resulting command buffers to the queues.
-    pub fn run(&mut self, factory: &mut Factory<B>, families: &mut Families<B>, aux: &T) {
+    ///
+    /// If error is returned nothing is submitted and the frame can be run again.
+    pub fn run(
+        &mut self,
+        factory: &mut Factory<B>,
+        families: &mut Families<B>,
+        aux: &T,
+    ) -> Result<(), OutOfMemory> {
         profile_scope!("run");
 
         self.assert_device_owner(factory.device());
@@ -210,13 +463,41 @@ where
         if self.frames.next().index() >= self.inflight as _ {
             let wait = Frame::with_index(self.frames.next().index() - self.inflight as u64);
             let ref mut self_fences = self.fences;
+            let mut reset = Ok(());
             self.frames.wait_complete(wait, factory, |mut fences| {
-                factory.reset_fences(&mut fences).unwrap();
-                self_fences.push(fences);
+                match factory.reset_fences(&mut fences) {
+                    Ok(()) => self_fences.push(fences),
+                    Err(err) => {
+                        // Fences that failed to reset can't be reused.
+                        fences
+                            .into_iter()
+                            .for_each(|fence| factory.destroy_fence(fence));
+                        reset = Err(err);
+                    }
+                }
             });
+            reset?;
         }
 
+        // Fence is signaled by the last submission on each queue.
+        // All fences are created before submitting anything.
+        let fences_required = self
+            .schedule
+            .iter()
+            .flat_map(|family| family.iter())
+            .filter(|queue| queue.len() > 0)
+            .count();
         let mut fences = self.fences.pop().unwrap_or_else(Fences::<B>::default);
+        while fences.len() < fences_required {
+            match factory.create_fence(false) {
+                Ok(fence) => fences.push(fence),
+                Err(err) => {
+                    self.fences.push(fences);
+                    return Err(err);
+                }
+            }
+        }
+
         let mut fences_used = 0;
         let ref semaphores = self.semaphores;
 
@@ -237,9 +518,6 @@ where
 
             let last_in_queue = sid.index() + 1 == self.schedule.queue(qid).unwrap().len();
             let fence = if last_in_queue {
-                if fences_used >= fences.len() {
-                    fences.push(factory.create_fence(false).unwrap());
-                }
                 fences_used += 1;
                 Some(&mut fences[fences_used - 1])
             } else {
@@ -286,9 +564,16 @@ where
 
         fences.truncate(fences_used);
         self.frames.advance(fences);
+        Ok(())
+    }
+
+    /// Get range of frames of the graph.
+    /// Next frame is the one recorded by the next `run` call.
+    pub fn frames(&self) -> FramesRange {
+        self.frames.range()
     }
 
-    /// Get queue that will exeute given node.
+    /// Get queue that will execute given node.
     pub fn node_queue(&self, node: NodeId) -> QueueId {
         let (f, i) = self.nodes[node.0].queue;
         QueueId {
@@ -318,12 +603,70 @@ where
             for semaphore in self.semaphores {
                 factory.destroy_semaphore(semaphore);
             }
+
+            self.ctx.dispose(factory);
         }
         drop(self.device);
         drop(self.schedule);
         drop(self.fences);
         drop(self.inflight);
-        drop(self.ctx);
+    }
+
+    /// Get report about memory shared by transient images.
+    pub fn aliasing_report(&self) -> AliasingReport {
+        self.ctx.aliasing_report()
+    }
+
+    /// Describe scheduled graph in Graphviz DOT format.
+    ///
+    /// Shows submissions per family and queue, semaphores between them
+    /// and barriers recorded for resources.
+    pub fn describe(&self) -> String {
+        self.schedule.to_dot()
+    }
+
+    /// Resize the graph to the new extent without rebuilding it.
+    ///
+    /// Recreates images created with [`GraphBuilder::create_resizable_image`]
+    /// and lets every node recreate resources that depend on them or on the surface extent.
+    /// Pipelines and other resources that don't depend on extent are kept.
+    ///
+    /// Swapchains are recreated with the extent clamped to the range supported by the surface.
+    /// Surfaces that report fixed current extent still override it.
+    ///
+    /// If error is returned the graph can only be disposed.
+    ///
+    /// [`GraphBuilder::create_resizable_image`]: struct.GraphBuilder.html#method.create_resizable_image
+    pub fn resize(
+        &mut self,
+        factory: &mut Factory<B>,
+        families: &mut Families<B>,
+        extent: Extent2D,
+    ) -> Result<(), GraphBuildError> {
+        profile_scope!("resize");
+
+        self.assert_device_owner(factory.device());
+
+        factory.wait_idle().map_err(GraphBuildError::WaitIdle)?;
+
+        let images = self.ctx.resize(factory, extent)?;
+        log::debug!("Images resized to {:?}: {:?}", extent, images);
+
+        for GraphNode { node, queue } in &mut self.nodes {
+            unsafe {
+                // Device is idle.
+                node.resize(
+                    &self.ctx,
+                    factory,
+                    families.family_by_index_mut(queue.0),
+                    queue.1,
+                    &images,
+                )
+            }
+            .map_err(GraphBuildError::Node)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -332,7 +675,10 @@ pub struct GraphBuilder<B: Backend, T: ?Sized> {
     nodes: Vec<Box<dyn NodeBuilder<B, T>>>,
     buffers: Vec<BufferInfo>,
     images: Vec<(ImageInfo, Option<rendy_core::hal::command::ClearValue>)>,
+    resizable: Vec<bool>,
     frames_in_flight: u32,
+    aliasing: bool,
+    scheduler: Box<dyn chain::Scheduler>,
 }
 
 impl<B, T> Default for GraphBuilder<B, T>
@@ -345,7 +691,10 @@ where
             nodes: Vec::default(),
             buffers: Vec::default(),
             images: Vec::default(),
+            resizable: Vec::default(),
             frames_in_flight: u32::default(),
+            aliasing: false,
+            scheduler: Box::new(chain::Greedy),
         }
     }
 }
@@ -360,7 +709,10 @@ where
             .field("nodes", &self.nodes)
             .field("buffers", &self.buffers)
             .field("images", &self.images)
+            .field("resizable", &self.resizable)
             .field("frames_in_flight", &self.frames_in_flight)
+            .field("aliasing", &self.aliasing)
+            .field("scheduler", &self.scheduler)
             .finish()
     }
 }
@@ -376,7 +728,10 @@ where
             nodes: Vec::new(),
             buffers: Vec::new(),
             images: Vec::new(),
+            resizable: Vec::new(),
             frames_in_flight: 3,
+            aliasing: false,
+            scheduler: Box::new(chain::Greedy),
         }
     }
 
@@ -412,9 +767,29 @@ where
             },
             clear,
         ));
+        self.resizable.push(false);
         ImageId(self.images.len() - 1)
     }
 
+    /// Create new image owned by graph that follows extent of the graph.
+    ///
+    /// Image is recreated with new width and height by [`Graph::resize`].
+    /// Number of layers, depth and samples are preserved.
+    /// Resizable images never share memory with other images.
+    ///
+    /// [`Graph::resize`]: struct.Graph.html#method.resize
+    pub fn create_resizable_image(
+        &mut self,
+        kind: rendy_core::hal::image::Kind,
+        levels: rendy_core::hal::image::Level,
+        format: rendy_core::hal::format::Format,
+        clear: Option<rendy_core::hal::command::ClearValue>,
+    ) -> ImageId {
+        let id = self.create_image(kind, levels, format, clear);
+        self.resizable[id.0] = true;
+        id
+    }
+
     /// Add node to the graph.
     pub fn add_node<N: NodeBuilder<B, T> + 'static>(&mut self, builder: N) -> NodeId {
         self.add_dyn_node(Box::new(builder))
@@ -432,6 +807,25 @@ where
         self
     }
 
+    /// Allow transient buffers and images with disjoint lifetimes to share memory.
+    ///
+    /// Only resources used by single queue are aliased.
+    /// Content of transient resources is never preserved between frames,
+    /// so aliasing doesn't change graph semantics.
+    /// First use of an aliased resource waits for all uses of the memory it shares.
+    pub fn with_aliasing(mut self, aliasing: bool) -> Self {
+        self.aliasing = aliasing;
+        self
+    }
+
+    /// Choose strategy to distribute nodes among queues.
+    ///
+    /// `chain::Greedy` is used by default.
+    pub fn with_scheduler(mut self, scheduler: impl chain::Scheduler + 'static) -> Self {
+        self.scheduler = Box::new(scheduler);
+        self
+    }
+
     /// Build `Graph`.
     ///
     /// # Parameters
@@ -457,23 +851,19 @@ where
             self.nodes
                 .iter()
                 .enumerate()
-                .map(|(i, b)| make_chain_node(&**b, i, factory, families))
+                .map(|(i, b)| make_chain_node(&**b, i, factory, families, &self.images))
                 .collect()
         };
 
-        let chains = chain::collect(chain_nodes, |id| {
-            families.family_by_index(id.0).as_slice().len()
-        });
+        let mut scheduler = self.scheduler;
+        let chains = chain::collect_with(
+            chain_nodes,
+            |id| families.family_by_index(id.0).as_slice().len(),
+            &mut *scheduler,
+        )
+        .map_err(GraphBuildError::Schedule)?;
         log::trace!("Scheduled nodes execution {:#?}", chains);
 
-        let mut ctx = GraphContext::alloc(
-            factory,
-            &chains,
-            &self.buffers,
-            &self.images,
-            self.frames_in_flight,
-        )?;
-
         log::trace!("Synchronize");
 
         let mut semaphores = 0..;
@@ -484,6 +874,26 @@ where
         schedule.build_order();
         log::trace!("Schedule: {:#?}", schedule);
 
+        if cfg!(debug_assertions) {
+            profile_scope!("validate");
+            if let Err(errors) = chain::validate(&chains, &schedule) {
+                for error in &errors {
+                    log::error!("{}", error);
+                }
+                return Err(GraphBuildError::Validation(errors));
+            }
+        }
+
+        let ctx = GraphContext::alloc(
+            factory,
+            &chains,
+            &self.buffers,
+            &self.images,
+            self.resizable,
+            self.frames_in_flight,
+            self.aliasing,
+        )?;
+
         log::trace!("Build nodes");
         let mut built_nodes: Vec<_> = (0..self.nodes.len()).map(|_| None).collect();
         let mut node_descs: Vec<_> = self.nodes.into_iter().map(Some).collect();
@@ -499,8 +909,8 @@ where
                         log::trace!("For submission {:#?}", submission.id());
                         let builder = node_descs[submission.node()].take().unwrap();
                         log::trace!("Build node {:#?}", builder);
-                        let node = build_node(
-                            &mut ctx,
+                        let node = match build_node(
+                            &ctx,
                             builder,
                             factory,
                             families.family_by_index_mut(family.id().0),
@@ -508,8 +918,22 @@ where
                             aux,
                             &chains,
                             &submission,
-                        )
-                        .map_err(GraphBuildError::Node)?;
+                        ) {
+                            Ok(node) => node,
+                            Err(err) => {
+                                unsafe {
+                                    // Nodes were never run.
+                                    dispose_unfinished(
+                                        factory,
+                                        aux,
+                                        built_nodes.into_iter().flatten().map(|(node, _)| node),
+                                        Vec::new(),
+                                        ctx,
+                                    );
+                                }
+                                return Err(GraphBuildError::Node(err));
+                            }
+                        };
                         log::debug!("Node built: {:#?}", node);
                         built_nodes[submission.node()] = Some((node, submission.id().queue()));
                     }
@@ -518,10 +942,25 @@ where
         }
 
         log::debug!("Create {} semaphores", semaphores.start);
-        let semaphores = (0..semaphores.start)
-            .map(|_| factory.create_semaphore())
-            .collect::<Result<_, _>>()
-            .map_err(GraphBuildError::Semaphore)?;
+        let mut created = Vec::with_capacity(semaphores.start);
+        for _ in 0..semaphores.start {
+            match factory.create_semaphore() {
+                Ok(semaphore) => created.push(semaphore),
+                Err(err) => {
+                    unsafe {
+                        // Nodes were never run.
+                        dispose_unfinished(
+                            factory,
+                            aux,
+                            built_nodes.into_iter().flatten().map(|(node, _)| node),
+                            created,
+                            ctx,
+                        );
+                    }
+                    return Err(GraphBuildError::Semaphore(err));
+                }
+            }
+        }
 
         Ok(Graph {
             device: factory.device().id(),
@@ -535,7 +974,7 @@ where
                 })
                 .collect(),
             schedule,
-            semaphores,
+            semaphores: created,
             inflight: self.frames_in_flight,
             frames: Frames::new(),
             fences: Vec::new(),
@@ -565,7 +1004,7 @@ fn build_node<'a, B: Backend, T: ?Sized>(
             let buffer = ctx
                 .get_buffer(id)
                 .expect("Buffer referenced from at least one node must be instantiated");
-            NodeBuffer {
+            let mut node_buffer = NodeBuffer {
                 id,
                 range: 0..buffer.size(),
                 acquire: sync.acquire.buffers.get(&chain_id).map(
@@ -582,7 +1021,24 @@ fn build_node<'a, B: Backend, T: ?Sized>(
                         families: families.clone(),
                     },
                 ),
+            };
+
+            let chain = &chains.buffers[&chain_id];
+            let link = submission.buffer_link_index(chain_id);
+            if let Some(stages) =
+                alias_stages(ctx.aliased_buffers[id.0], chain, link, submission.id())
+            {
+                // Memory may be used by another resource earlier on the same queue.
+                let state = chain.links()[link].submission_state(submission.id());
+                let acquire = node_buffer.acquire.get_or_insert(BufferBarrier {
+                    states: state.access..state.access,
+                    stages: state.stages..state.stages,
+                    families: None,
+                });
+                acquire.states.start |= rendy_core::hal::buffer::Access::MEMORY_WRITE;
+                acquire.stages.start |= stages;
             }
+            node_buffer
         })
         .collect();
 
@@ -590,27 +1046,29 @@ fn build_node<'a, B: Backend, T: ?Sized>(
     image_ids.sort();
     image_ids.dedup();
 
+    // Image ranges accessed by other nodes are tracked separately,
+    // so one `NodeImage` is created per tracked range.
     let images: Vec<_> = image_ids
         .into_iter()
-        .map(|id| {
-            let chain_id = chain::Id(id.0);
-            let sync = submission.sync();
-            let link = submission.image_link_index(chain_id);
-            let (image, clear) = ctx
+        .flat_map(|id| {
+            let (_, clear) = ctx
                 .get_image_with_clear(id)
                 .expect("Image referenced from at least one node must be instantiated");
-            NodeImage {
+            submission
+                .image_subresources(chain::Id(id.0))
+                .into_iter()
+                .map(move |(subresource, link)| (id, clear, subresource, link))
+        })
+        .map(|(id, clear, subresource, link)| {
+            let sync = submission.sync();
+            let mut node_image = NodeImage {
                 id,
-                range: rendy_core::hal::image::SubresourceRange {
-                    aspects: image.format().surface_desc().aspects,
-                    levels: 0..image.levels(),
-                    layers: 0..image.layers(),
-                },
-                layout: chains.images[&chain_id].links()[link]
+                range: subresource.range.clone(),
+                layout: chains.images[subresource].links()[link]
                     .submission_state(submission.id())
                     .layout,
                 clear: if link == 0 { clear } else { None },
-                acquire: sync.acquire.images.get(&chain_id).map(
+                acquire: sync.acquire.images.get(subresource).map(
                     |chain::Barrier { states, families }| ImageBarrier {
                         states: (
                             states.start.0,
@@ -624,24 +1082,53 @@ fn build_node<'a, B: Backend, T: ?Sized>(
                         families: families.clone(),
                     },
                 ),
-                release: sync.release.images.get(&chain_id).map(
+                release: sync.release.images.get(subresource).map(
                     |chain::Barrier { states, families }| ImageBarrier {
                         states: (states.start.0, states.start.1)..(states.end.0, states.end.1),
                         stages: states.start.2..states.end.2,
                         families: families.clone(),
                     },
                 ),
+            };
+
+            let chain = &chains.images[subresource];
+            if let Some(stages) =
+                alias_stages(ctx.aliased_images[id.0], chain, link, submission.id())
+            {
+                // Memory may be used by another resource earlier on the same queue.
+                let state = chain.links()[link].submission_state(submission.id());
+                let acquire = node_image.acquire.get_or_insert(ImageBarrier {
+                    states: (state.access, rendy_core::hal::image::Layout::Undefined)
+                        ..(state.access, state.layout),
+                    stages: state.stages..state.stages,
+                    families: None,
+                });
+                acquire.states.start.0 |= rendy_core::hal::image::Access::MEMORY_WRITE;
+                acquire.stages.start |= stages;
             }
+            node_image
         })
         .collect();
     builder.build(ctx, factory, family, queue, aux, buffers, images)
 }
 
+/// Get stages that first use of an aliased resource must wait for.
+/// Returns `None` if resource isn't aliased or submission isn't its first use on the queue.
+fn alias_stages<R: chain::Resource>(
+    aliased: Option<PipelineStage>,
+    chain: &chain::Chain<R>,
+    link: usize,
+    sid: chain::SubmissionId,
+) -> Option<PipelineStage> {
+    aliased.filter(|_| link == 0 && chain.links()[0].queue(sid.queue()).first == sid.index())
+}
+
 fn make_chain_node<B, T>(
     builder: &dyn NodeBuilder<B, T>,
     id: usize,
     factory: &mut Factory<B>,
     families: &Families<B>,
+    image_infos: &[(ImageInfo, Option<rendy_core::hal::command::ClearValue>)],
 ) -> chain::Node
 where
     B: Backend,
@@ -671,7 +1158,12 @@ where
             .into_iter()
             .map(|(id, access)| {
                 (
-                    chain::Id(id.0),
+                    chain::Subresource::new(
+                        chain::Id(id.0),
+                        access
+                            .range
+                            .unwrap_or_else(|| whole_range(&image_infos[id.0].0)),
+                    ),
                     chain::ImageState {
                         access: access.access,
                         stages: access.stages,
@@ -683,3 +1175,874 @@ where
             .collect(),
     }
 }
+
+/// Get subresource range that covers the whole image.
+fn whole_range(info: &ImageInfo) -> rendy_core::hal::image::SubresourceRange {
+    rendy_core::hal::image::SubresourceRange {
+        aspects: info.format.surface_desc().aspects,
+        levels: 0..info.levels,
+        layers: 0..info.kind.num_layers(),
+    }
+}
+
+/// Get kind of the image with width and height replaced by the extent.
+fn resized_kind(
+    kind: rendy_core::hal::image::Kind,
+    extent: Extent2D,
+) -> rendy_core::hal::image::Kind {
+    use rendy_core::hal::image::Kind;
+    match kind {
+        Kind::D1(_, layers) => Kind::D1(extent.width, layers),
+        Kind::D2(_, _, layers, samples) => Kind::D2(extent.width, extent.height, layers, samples),
+        Kind::D3(_, _, depth) => Kind::D3(extent.width, extent.height, depth),
+    }
+}
+
+/// Clamp extent to the range of extents supported by the surface.
+pub(crate) fn clamped_extent(
+    extent: Extent2D,
+    capabilities: &rendy_core::hal::window::SurfaceCapabilities,
+) -> Extent2D {
+    let (min, max) = (capabilities.extents.start(), capabilities.extents.end());
+    Extent2D {
+        width: extent.width.max(min.width).min(max.width),
+        height: extent.height.max(min.height).min(max.height),
+    }
+}
+
+/// Find queue, range of submissions and stages in which resource is used.
+/// Returns `None` if resource is used by more than one queue.
+fn resource_lifetime<'a, R: chain::Resource + 'a>(
+    chains: impl IntoIterator<Item = &'a chain::Chain<R>>,
+) -> Option<Lifetime> {
+    let mut lifetime: Option<Lifetime> = None;
+    for link in chains.into_iter().flat_map(|chain| chain.links()) {
+        for (qid, queue) in link.queues() {
+            lifetime = match lifetime {
+                None => Some(Lifetime {
+                    queue: qid,
+                    submissions: queue.first..queue.last + 1,
+                    stages: queue.stages,
+                }),
+                Some(ref lifetime) if lifetime.queue != qid => return None,
+                Some(lifetime) => Some(Lifetime {
+                    queue: qid,
+                    submissions: lifetime.submissions.start.min(queue.first)
+                        ..lifetime.submissions.end.max(queue.last + 1),
+                    stages: lifetime.stages | queue.stages,
+                }),
+            };
+        }
+    }
+    lifetime
+}
+
+/// Pack resources into slots so that resources sharing a slot are never used simultaneously.
+/// Larger resources are placed first.
+fn pack_slots(resources: &[(rendy_core::hal::memory::Requirements, Lifetime)]) -> Vec<AliasSlot> {
+    let mut order: Vec<_> = (0..resources.len()).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(resources[index].0.size));
+
+    let mut slots: Vec<AliasSlot> = Vec::new();
+    for index in order {
+        let (reqs, ref lifetime) = resources[index];
+        let mask = reqs.type_mask as u32;
+        match slots.iter_mut().find(|slot| slot.fits(mask, lifetime)) {
+            Some(slot) => {
+                slot.mask &= mask;
+                slot.size = slot.size.max(reqs.size);
+                slot.align = slot.align.max(reqs.alignment);
+                slot.stages |= lifetime.stages;
+                slot.lifetimes.push(lifetime.submissions.clone());
+                slot.resources.push(index);
+            }
+            None => slots.push(AliasSlot {
+                queue: lifetime.queue,
+                mask,
+                size: reqs.size,
+                align: reqs.alignment,
+                stages: lifetime.stages,
+                lifetimes: vec![lifetime.submissions.clone()],
+                resources: vec![index],
+            }),
+        }
+    }
+    slots
+}
+
+/// Pack resources into memory blocks so that resources sharing a block are never used simultaneously.
+/// Binds all resources and returns allocated blocks.
+///
+/// Lifetime stages of every resource are replaced with stages of all resources sharing its block,
+/// so that first use of the resource can wait for them.
+fn alias_resources<B: Backend>(
+    factory: &Factory<B>,
+    unbound: &mut [Unbound<B>],
+) -> Result<(Vec<MemoryBlock<B>>, AliasingReport), GraphBuildError> {
+    profile_scope!("alias_resources");
+
+    let slots = pack_slots(
+        &unbound
+            .iter()
+            .map(|unbound| (unbound.reqs, unbound.lifetime.clone()))
+            .collect::<Vec<_>>(),
+    );
+
+    let mut memory = Vec::with_capacity(slots.len());
+    for slot in &slots {
+        let result = match factory.allocate_memory(slot.mask, Data, slot.size, slot.align) {
+            Ok(block) => match bind_slot(factory, unbound, slot, &block) {
+                Ok(()) => Ok(block),
+                Err(err) => {
+                    unsafe { factory.free_memory(block) };
+                    Err(err)
+                }
+            },
+            Err(err) => Err(match unbound[slot.resources[0]].resource {
+                UnboundResource::Buffer(_) => GraphBuildError::Buffer(CreationError::Allocate(err)),
+                UnboundResource::Image(..) => GraphBuildError::Image(CreationError::Allocate(err)),
+            }),
+        };
+
+        match result {
+            Ok(block) => memory.push(block),
+            Err(err) => {
+                for block in memory {
+                    unsafe { factory.free_memory(block) };
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    let report = AliasingReport {
+        buffers: unbound
+            .iter()
+            .filter(|unbound| match unbound.resource {
+                UnboundResource::Buffer(_) => true,
+                UnboundResource::Image(..) => false,
+            })
+            .count(),
+        images: unbound
+            .iter()
+            .filter(|unbound| match unbound.resource {
+                UnboundResource::Buffer(_) => false,
+                UnboundResource::Image(..) => true,
+            })
+            .count(),
+        blocks: slots.len(),
+        requested: unbound.iter().map(|unbound| unbound.reqs.size).sum(),
+        allocated: slots.iter().map(|slot| slot.size).sum(),
+    };
+
+    Ok((memory, report))
+}
+
+/// Bind resources of the slot to the block and make them wait for each other.
+fn bind_slot<B: Backend>(
+    factory: &Factory<B>,
+    unbound: &mut [Unbound<B>],
+    slot: &AliasSlot,
+    block: &MemoryBlock<B>,
+) -> Result<(), GraphBuildError> {
+    for &index in &slot.resources {
+        let unbound = &mut unbound[index];
+        unbound.lifetime.stages = slot.stages;
+        match &mut unbound.resource {
+            UnboundResource::Buffer(buffer) => unsafe {
+                buffer
+                    .bind(factory.device(), block, 0)
+                    .map_err(GraphBuildError::Buffer)?
+            },
+            UnboundResource::Image(image, _) => unsafe {
+                image
+                    .bind(factory.device(), block, 0)
+                    .map_err(GraphBuildError::Image)?
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Dispose of nodes, semaphores and transient resources of the graph that failed to build.
+///
+/// # Safety
+///
+/// Nodes must never have been run.
+unsafe fn dispose_unfinished<B: Backend, T: ?Sized>(
+    factory: &mut Factory<B>,
+    aux: &T,
+    nodes: impl IntoIterator<Item = Box<dyn DynNode<B, T>>>,
+    semaphores: Vec<B::Semaphore>,
+    ctx: GraphContext<B>,
+) {
+    for node in nodes {
+        node.dispose(factory, aux);
+    }
+    for semaphore in semaphores {
+        factory.destroy_semaphore(semaphore);
+    }
+    ctx.dispose(factory);
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            command::{Family, Graphics, Submission, Submit},
+            factory::{init_with_instance, Config},
+            memory::FaultPolicy,
+            node::{BufferAccess, ImageAccess, Node, NodeDesc, NodeSubmittable},
+        },
+        rendy_core::{
+            hal::{
+                buffer,
+                format::{self, Format},
+                image,
+            },
+            Instance,
+        },
+        rendy_fake::{FakeBackend, FakeInstance},
+        std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    /// Node that records nothing.
+    /// Number of live nodes is counted in auxiliary data.
+    #[derive(Debug, Default)]
+    struct TestDesc {
+        buffers: Vec<BufferAccess>,
+        images: Vec<ImageAccess>,
+        /// Fail to build the node.
+        fail: bool,
+        /// Buffers passed to the node when it is built.
+        built_buffers: Arc<Mutex<Vec<NodeBuffer>>>,
+        /// Images passed to the node when it is built.
+        built: Arc<Mutex<Vec<NodeImage>>>,
+        /// Images passed to the node when it is resized.
+        resized: Arc<Mutex<Vec<ImageId>>>,
+    }
+
+    #[derive(Debug)]
+    struct TestNode {
+        resized: Arc<Mutex<Vec<ImageId>>>,
+    }
+
+    impl<'a> NodeSubmittable<'a, FakeBackend> for TestNode {
+        type Submittable = Submit<FakeBackend>;
+        type Submittables = Option<Submit<FakeBackend>>;
+    }
+
+    impl Node<FakeBackend, AtomicUsize> for TestNode {
+        type Capability = Graphics;
+
+        fn run<'a>(
+            &'a mut self,
+            _ctx: &GraphContext<FakeBackend>,
+            _factory: &Factory<FakeBackend>,
+            _aux: &AtomicUsize,
+            _frames: &'a Frames<FakeBackend>,
+        ) -> Option<Submit<FakeBackend>> {
+            None
+        }
+
+        unsafe fn resize(
+            &mut self,
+            _ctx: &GraphContext<FakeBackend>,
+            _factory: &mut Factory<FakeBackend>,
+            images: &[ImageId],
+        ) -> Result<(), NodeBuildError> {
+            *self.resized.lock().unwrap() = images.to_vec();
+            Ok(())
+        }
+
+        unsafe fn dispose(self, _factory: &mut Factory<FakeBackend>, aux: &AtomicUsize) {
+            aux.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    impl NodeDesc<FakeBackend, AtomicUsize> for TestDesc {
+        type Node = TestNode;
+
+        fn buffers(&self) -> Vec<BufferAccess> {
+            self.buffers.clone()
+        }
+
+        fn images(&self) -> Vec<ImageAccess> {
+            self.images.clone()
+        }
+
+        fn build<'a>(
+            self,
+            _ctx: &GraphContext<FakeBackend>,
+            _factory: &mut Factory<FakeBackend>,
+            _family: &mut Family<FakeBackend>,
+            _queue: usize,
+            aux: &AtomicUsize,
+            buffers: Vec<NodeBuffer>,
+            images: Vec<NodeImage>,
+        ) -> Result<TestNode, NodeBuildError> {
+            if self.fail {
+                return Err(NodeBuildError::OutOfMemory(OutOfMemory::Device));
+            }
+            *self.built_buffers.lock().unwrap() = buffers;
+            *self.built.lock().unwrap() = images;
+            aux.fetch_add(1, Ordering::Relaxed);
+            Ok(TestNode {
+                resized: self.resized,
+            })
+        }
+    }
+
+    fn write_buffer() -> BufferAccess {
+        BufferAccess {
+            access: buffer::Access::SHADER_WRITE,
+            usage: buffer::Usage::STORAGE,
+            stages: PipelineStage::COMPUTE_SHADER,
+        }
+    }
+
+    fn write_image(range: Option<image::SubresourceRange>) -> ImageAccess {
+        ImageAccess {
+            access: image::Access::COLOR_ATTACHMENT_WRITE,
+            usage: image::Usage::COLOR_ATTACHMENT,
+            layout: image::Layout::ColorAttachmentOptimal,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            range,
+        }
+    }
+
+    fn read_image(range: Option<image::SubresourceRange>) -> ImageAccess {
+        ImageAccess {
+            access: image::Access::SHADER_READ,
+            usage: image::Usage::SAMPLED,
+            layout: image::Layout::ShaderReadOnlyOptimal,
+            stages: PipelineStage::FRAGMENT_SHADER,
+            range,
+        }
+    }
+
+    fn fake_factory() -> (Factory<FakeBackend>, Families<FakeBackend>) {
+        let config: Config = Default::default();
+        init_with_instance(Instance::new(FakeInstance), &config).unwrap()
+    }
+
+    /// Complete submitted work and free dropped resources.
+    /// Dropped resources are freed once a fence submitted after the drop is waited for.
+    fn cleanup(factory: &mut Factory<FakeBackend>, families: &mut Families<FakeBackend>) {
+        factory.maintain(families);
+        for _ in 0..2 {
+            let mut fence = factory.create_fence(false).unwrap();
+            unsafe {
+                families
+                    .family_by_index_mut(0)
+                    .queue_mut(0)
+                    .submit(Some(Submission::new()), Some(&mut fence));
+            }
+            factory.wait_for_fence(&mut fence, !0).unwrap();
+            factory.destroy_fence(fence);
+            factory.cleanup(families);
+        }
+    }
+
+    /// Two nodes writing an image each, so images have disjoint lifetimes.
+    /// First node also writes a buffer.
+    fn two_nodes(aliasing: bool) -> GraphBuilder<FakeBackend, AtomicUsize> {
+        let mut builder = GraphBuilder::new().with_aliasing(aliasing);
+        let buffer = builder.create_buffer(1024);
+        let kind = image::Kind::D2(64, 64, 1, 1);
+        let first = builder.create_image(kind, 1, Format::Rgba8Unorm, None);
+        let second = builder.create_image(kind, 1, Format::Rgba8Unorm, None);
+        let node = builder.add_node(
+            TestDesc {
+                buffers: vec![write_buffer()],
+                images: vec![write_image(None)],
+                ..Default::default()
+            }
+            .builder()
+            .with_buffer(buffer)
+            .with_image(first),
+        );
+        builder.add_node(
+            TestDesc {
+                images: vec![write_image(None)],
+                ..Default::default()
+            }
+            .builder()
+            .with_image(second)
+            .with_dependency(node),
+        );
+        builder
+    }
+
+    #[test]
+    fn transient_allocation_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+
+        // Without aliasing the buffer and each image are allocated separately.
+        // With aliasing images share one memory block
+        // and the buffer used together with the first image gets another one.
+        for &(aliasing, images) in &[
+            (false, &[false, true, true][..]),
+            (true, &[true, false][..]),
+        ] {
+            for (n, &image) in (1..).zip(images) {
+                factory.set_memory_fault_policy(Some(FaultPolicy::Nth(n)));
+                match two_nodes(aliasing).build(&mut factory, &mut families, &live) {
+                    Err(GraphBuildError::Buffer(CreationError::Allocate(_))) if !image => {}
+                    Err(GraphBuildError::Image(CreationError::Allocate(_))) if image => {}
+                    other => panic!("Allocation {} must fail, got {:?}", n, other),
+                }
+            }
+        }
+        factory.set_memory_fault_policy(None);
+
+        cleanup(&mut factory, &mut families);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn aliasing() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+
+        let mut builder = GraphBuilder::new().with_aliasing(true);
+        let buffer = builder.create_buffer(1024);
+        let kind = image::Kind::D2(64, 64, 1, 1);
+        let first = builder.create_image(kind, 1, Format::Rgba8Unorm, None);
+        let second = builder.create_image(kind, 1, Format::Rgba8Unorm, None);
+        let first_desc = TestDesc {
+            images: vec![write_image(None)],
+            ..Default::default()
+        };
+        let second_desc = TestDesc {
+            buffers: vec![write_buffer()],
+            images: vec![write_image(None)],
+            ..Default::default()
+        };
+        let buffers = second_desc.built_buffers.clone();
+        let images = second_desc.built.clone();
+        let node = builder.add_node(first_desc.builder().with_image(first));
+        builder.add_node(
+            second_desc
+                .builder()
+                .with_buffer(buffer)
+                .with_image(second)
+                .with_dependency(node),
+        );
+        let graph = builder.build(&mut factory, &mut families, &live).unwrap();
+
+        // Images share one block, buffer used together with the second image gets another one.
+        let report = graph.aliasing_report();
+        assert_eq!(report.buffers, 1);
+        assert_eq!(report.images, 2);
+        assert_eq!(report.blocks, 2);
+        assert_eq!(report.saved(), 64 * 64 * 4);
+        assert!(graph.ctx.is_buffer_aliased(buffer));
+        assert!(graph.ctx.is_image_aliased(first));
+        assert!(graph.ctx.is_image_aliased(second));
+
+        // Second image waits for the first one before it is written.
+        let images = images.lock().unwrap().clone();
+        assert_eq!(images.len(), 1);
+        let acquire = images[0].acquire.as_ref().unwrap();
+        assert!(acquire.states.start.0.contains(image::Access::MEMORY_WRITE));
+        assert_eq!(acquire.states.start.1, image::Layout::Undefined);
+        assert_eq!(
+            acquire.states.end,
+            (
+                image::Access::COLOR_ATTACHMENT_WRITE,
+                image::Layout::ColorAttachmentOptimal
+            )
+        );
+        assert!(acquire
+            .stages
+            .start
+            .contains(PipelineStage::COLOR_ATTACHMENT_OUTPUT));
+
+        // Buffer waits for its own use in the previous frame.
+        let buffers = buffers.lock().unwrap().clone();
+        assert_eq!(buffers.len(), 1);
+        let acquire = buffers[0].acquire.as_ref().unwrap();
+        assert!(acquire.states.start.contains(buffer::Access::MEMORY_WRITE));
+        assert!(acquire.states.end.contains(buffer::Access::SHADER_WRITE));
+        assert!(acquire.stages.start.contains(PipelineStage::COMPUTE_SHADER));
+
+        graph.dispose(&mut factory, &live);
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn node_build_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+
+        for &aliasing in &[false, true] {
+            let mut builder = two_nodes(aliasing);
+            let image = builder.create_image(image::Kind::D1(64, 1), 1, Format::Rgba8Unorm, None);
+            builder.add_node(
+                TestDesc {
+                    images: vec![write_image(None)],
+                    fail: true,
+                    ..Default::default()
+                }
+                .builder()
+                .with_image(image)
+                .with_dependency(NodeId(1)),
+            );
+            match builder.build(&mut factory, &mut families, &live) {
+                Err(GraphBuildError::Node(NodeBuildError::OutOfMemory(OutOfMemory::Device))) => {}
+                other => panic!("Node building must fail, got {:?}", other),
+            }
+
+            // Nodes built before the failure are disposed.
+            assert_eq!(live.load(Ordering::Relaxed), 0);
+        }
+
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    fn requirements(size: u64, type_mask: u64) -> rendy_core::hal::memory::Requirements {
+        rendy_core::hal::memory::Requirements {
+            size,
+            alignment: size / 4,
+            type_mask,
+        }
+    }
+
+    fn lifetime(queue: usize, submissions: Range<usize>) -> Lifetime {
+        Lifetime {
+            queue: chain::QueueId::new(QueueFamilyId(0), queue),
+            submissions,
+            stages: PipelineStage::TRANSFER,
+        }
+    }
+
+    #[test]
+    fn slot_fits() {
+        let slot = AliasSlot {
+            queue: chain::QueueId::new(QueueFamilyId(0), 0),
+            mask: 0b011,
+            size: 256,
+            align: 64,
+            stages: PipelineStage::TRANSFER,
+            lifetimes: vec![0..2, 4..5],
+            resources: vec![0, 1],
+        };
+        assert!(slot.fits(0b001, &lifetime(0, 2..4)));
+        assert!(slot.fits(0b110, &lifetime(0, 5..8)));
+        assert!(!slot.fits(0b001, &lifetime(0, 1..3)));
+        assert!(!slot.fits(0b001, &lifetime(0, 3..6)));
+        assert!(!slot.fits(0b001, &lifetime(1, 2..4)));
+        assert!(!slot.fits(0b100, &lifetime(0, 2..4)));
+    }
+
+    #[test]
+    fn slot_packing() {
+        let slots = pack_slots(&[
+            (requirements(256, 0b11), lifetime(0, 0..1)),
+            // Overlaps with the first resource.
+            (requirements(512, 0b11), lifetime(0, 0..2)),
+            // Follows both resources above.
+            (requirements(1024, 0b01), lifetime(0, 2..3)),
+            // Used by another queue.
+            (requirements(128, 0b11), lifetime(1, 1..2)),
+            // Incompatible with every slot above.
+            (requirements(64, 0b100), lifetime(0, 3..4)),
+        ]);
+
+        let packed: Vec<_> = slots
+            .iter()
+            .map(|slot| (slot.resources.clone(), slot.mask, slot.size, slot.align))
+            .collect();
+        assert_eq!(
+            packed,
+            vec![
+                (vec![2, 1], 0b01, 1024, 256),
+                (vec![0], 0b11, 256, 64),
+                (vec![3], 0b11, 128, 32),
+                (vec![4], 0b100, 64, 16),
+            ]
+        );
+        assert_eq!(slots[0].lifetimes, vec![2..3, 0..2]);
+    }
+
+    #[test]
+    fn run_failure() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+        let mut graph = two_nodes(true)
+            .with_frames_in_flight(2)
+            .build(&mut factory, &mut families, &live)
+            .unwrap();
+        assert_eq!(live.load(Ordering::Relaxed), 2);
+        assert_eq!(graph.aliasing_report().blocks, 2);
+
+        // Fence for the first frame can't be created.
+        factory.device().set_out_of_memory(true);
+        assert_eq!(
+            graph.run(&mut factory, &mut families, &live),
+            Err(OutOfMemory::Device)
+        );
+        factory.device().set_out_of_memory(false);
+        for _ in 0..2 {
+            graph.run(&mut factory, &mut families, &live).unwrap();
+        }
+
+        // Fence of the first frame can't be reset.
+        factory.device().set_out_of_memory(true);
+        assert_eq!(
+            graph.run(&mut factory, &mut families, &live),
+            Err(OutOfMemory::Device)
+        );
+        factory.device().set_out_of_memory(false);
+        graph.run(&mut factory, &mut families, &live).unwrap();
+
+        graph.dispose(&mut factory, &live);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.buffers, 0);
+        assert_eq!(stats.images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+
+    #[test]
+    fn mip_level_ranges() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+        let level = |level| {
+            Some(image::SubresourceRange {
+                aspects: format::Aspects::COLOR,
+                levels: level..level + 1,
+                layers: 0..1,
+            })
+        };
+
+        // First node writes level 0, second node reads it to write level 1.
+        let mut builder = GraphBuilder::new();
+        let image =
+            builder.create_image(image::Kind::D2(64, 64, 1, 1), 2, Format::Rgba8Unorm, None);
+        let first = TestDesc {
+            images: vec![write_image(level(0))],
+            ..Default::default()
+        };
+        let first_built = first.built.clone();
+        let second = TestDesc {
+            images: vec![read_image(level(0)), write_image(level(1))],
+            ..Default::default()
+        };
+        let second_built = second.built.clone();
+        let node = builder.add_node(first.builder().with_image(image));
+        builder.add_node(
+            second
+                .builder()
+                .with_image(image)
+                .with_image(image)
+                .with_dependency(node),
+        );
+        let graph = builder.build(&mut factory, &mut families, &live).unwrap();
+
+        // Level 0 is transitioned for reading after it is written.
+        let images = first_built.lock().unwrap().clone();
+        assert_eq!(images.len(), 1);
+        assert_eq!(Some(images[0].range.clone()), level(0));
+        assert_eq!(images[0].layout, image::Layout::ColorAttachmentOptimal);
+        let release = images[0].release.as_ref().unwrap();
+        assert_eq!(
+            release.states.start.1..release.states.end.1,
+            image::Layout::ColorAttachmentOptimal..image::Layout::ShaderReadOnlyOptimal
+        );
+
+        // Second node gets one image per level, in its own layout.
+        let images = second_built.lock().unwrap().clone();
+        assert_eq!(images.len(), 2);
+        assert_eq!(Some(images[0].range.clone()), level(0));
+        assert_eq!(images[0].layout, image::Layout::ShaderReadOnlyOptimal);
+        assert_eq!(Some(images[1].range.clone()), level(1));
+        assert_eq!(images[1].layout, image::Layout::ColorAttachmentOptimal);
+
+        graph.dispose(&mut factory, &live);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+
+        let mut builder = GraphBuilder::new();
+        let image =
+            builder.create_image(image::Kind::D2(64, 64, 1, 1), 2, Format::Rgba8Unorm, None);
+        builder.add_node(
+            TestDesc {
+                images: vec![
+                    read_image(None),
+                    write_image(Some(image::SubresourceRange {
+                        aspects: format::Aspects::COLOR,
+                        levels: 1..2,
+                        layers: 0..1,
+                    })),
+                ],
+                ..Default::default()
+            }
+            .builder()
+            .with_image(image)
+            .with_image(image),
+        );
+
+        match builder.build(&mut factory, &mut families, &live) {
+            Err(GraphBuildError::Schedule(chain::CollectError::OverlappingRanges {
+                node: 0,
+                image: chain::Id(0),
+            })) => {}
+            other => panic!("Overlapping ranges must be reported, got {:?}", other),
+        }
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn resized_kinds() {
+        let extent = Extent2D {
+            width: 128,
+            height: 32,
+        };
+        assert_eq!(
+            resized_kind(image::Kind::D1(64, 4), extent),
+            image::Kind::D1(128, 4)
+        );
+        assert_eq!(
+            resized_kind(image::Kind::D2(64, 64, 2, 4), extent),
+            image::Kind::D2(128, 32, 2, 4)
+        );
+        assert_eq!(
+            resized_kind(image::Kind::D3(64, 64, 8), extent),
+            image::Kind::D3(128, 32, 8)
+        );
+    }
+
+    #[test]
+    fn clamped_extents() {
+        use rendy_core::hal::window::{CompositeAlphaMode, PresentMode, SurfaceCapabilities};
+        let extent = |width, height| Extent2D { width, height };
+        let capabilities = SurfaceCapabilities {
+            image_count: 1..=3,
+            current_extent: None,
+            extents: extent(16, 16)..=extent(256, 128),
+            max_image_layers: 1,
+            usage: image::Usage::COLOR_ATTACHMENT,
+            present_modes: PresentMode::FIFO,
+            composite_alpha_modes: CompositeAlphaMode::OPAQUE,
+        };
+        assert_eq!(
+            clamped_extent(extent(128, 32), &capabilities),
+            extent(128, 32)
+        );
+        assert_eq!(
+            clamped_extent(extent(512, 8), &capabilities),
+            extent(256, 16)
+        );
+        assert_eq!(
+            clamped_extent(extent(0, 1024), &capabilities),
+            extent(16, 128)
+        );
+    }
+
+    #[test]
+    fn resize() {
+        let (mut factory, mut families) = fake_factory();
+        let live = AtomicUsize::new(0);
+
+        let mut builder = GraphBuilder::new();
+        let kind = image::Kind::D2(64, 64, 1, 1);
+        let fixed = builder.create_image(kind, 1, Format::Rgba8Unorm, None);
+        let resizable = builder.create_resizable_image(kind, 1, Format::Rgba8Unorm, None);
+        let desc = TestDesc {
+            images: vec![write_image(None), write_image(None)],
+            ..Default::default()
+        };
+        let resized = desc.resized.clone();
+        builder.add_node(desc.builder().with_image(fixed).with_image(resizable));
+        let mut graph = builder.build(&mut factory, &mut families, &live).unwrap();
+        assert!(!graph.ctx.is_image_aliased(resizable));
+        let before = graph.ctx.get_image(resizable).unwrap().clone();
+
+        // Only resizable image is recreated and node is notified about it.
+        let extent = Extent2D {
+            width: 128,
+            height: 32,
+        };
+        assert_eq!(graph.ctx.extent(), None);
+        graph.resize(&mut factory, &mut families, extent).unwrap();
+        assert_eq!(graph.ctx.extent(), Some(extent));
+        assert_eq!(*resized.lock().unwrap(), vec![resizable]);
+        assert_eq!(
+            graph.ctx.get_image(resizable).unwrap().kind(),
+            image::Kind::D2(128, 32, 1, 1)
+        );
+        assert_eq!(graph.ctx.get_image(fixed).unwrap().kind(), kind);
+
+        // Replaced image is destroyed once it is not used.
+        drop(before);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().images, 2);
+
+        // Nothing is recreated for the same extent.
+        graph.resize(&mut factory, &mut families, extent).unwrap();
+        assert!(resized.lock().unwrap().is_empty());
+
+        // Errors are returned and image is kept.
+        factory.device().set_out_of_memory(true);
+        match graph.resize(
+            &mut factory,
+            &mut families,
+            Extent2D {
+                width: 16,
+                height: 16,
+            },
+        ) {
+            Err(GraphBuildError::WaitIdle(OutOfMemory::Device)) => {}
+            other => panic!("Waiting for idle device must fail, got {:?}", other),
+        }
+        factory.device().set_out_of_memory(false);
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        match graph.resize(
+            &mut factory,
+            &mut families,
+            Extent2D {
+                width: 16,
+                height: 16,
+            },
+        ) {
+            Err(GraphBuildError::Image(CreationError::Allocate(_))) => {}
+            other => panic!("Image allocation must fail, got {:?}", other),
+        }
+        factory.set_memory_fault_policy(None);
+        assert_eq!(
+            graph.ctx.get_image(resizable).unwrap().kind(),
+            image::Kind::D2(128, 32, 1, 1)
+        );
+
+        graph.dispose(&mut factory, &live);
+        assert_eq!(live.load(Ordering::Relaxed), 0);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().images, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+}
@@ -0,0 +1,708 @@
+//!
+//! Simple compute node.
+//! Builds compute pipeline, descriptor sets and command buffers
+//! for a single dispatch described by `SimpleComputePipelineDesc`.
+//!
+
+use {
+    crate::{
+        command::{
+            CommandPool, Compute, Encoder, Family, IndividualReset, MultiShot, NoSimultaneousUse,
+            PrimaryLevel, QueueId, Submit,
+        },
+        factory::Factory,
+        frame::{
+            cirque::{CirqueRef, CommandCirque},
+            Frames,
+        },
+        graph::GraphContext,
+        node::{
+            gfx_acquire_barriers, gfx_release_barriers, render::PrepareResult, BufferAccess,
+            DescBuilder, ImageAccess, Node, NodeBuffer, NodeBuildError, NodeDesc, NodeImage,
+            NodeSubmittable,
+        },
+        resource::{DescriptorSet, DescriptorSetLayout, Escape, Handle},
+        ImageId,
+    },
+    rendy_core::hal::{device::Device as _, Backend},
+};
+
+pub use crate::core::types::{Layout, SetLayout};
+
+/// Descriptor for simple compute pipeline implementation.
+pub trait SimpleComputePipelineDesc<B: Backend, T: ?Sized>: std::fmt::Debug {
+    /// Simple compute pipeline implementation
+    type Pipeline: SimpleComputePipeline<B, T>;
+
+    /// Make simple compute node builder.
+    fn builder(self) -> DescBuilder<B, T, SimpleComputeNodeDesc<Self>>
+    where
+        Self: Sized + 'static,
+    {
+        SimpleComputeNodeDesc { inner: self }.builder()
+    }
+
+    /// Get set or buffer resources the node uses.
+    fn buffers(&self) -> Vec<BufferAccess> {
+        Vec::new()
+    }
+
+    /// Get set or image resources the node uses.
+    fn images(&self) -> Vec<ImageAccess> {
+        Vec::new()
+    }
+
+    /// Layout for compute pipeline.
+    /// One descriptor set is allocated for each set layout.
+    fn layout(&self) -> Layout {
+        Layout {
+            sets: Vec::new(),
+            push_constants: Vec::new(),
+        }
+    }
+
+    /// Load shader set.
+    /// Shader set must contain compute shader.
+    fn load_shader_set(&self, factory: &mut Factory<B>, aux: &T) -> rendy_shader::ShaderSet<B>;
+
+    /// Build pipeline instance.
+    ///
+    /// `sets` are allocated with layouts from `set_layouts`
+    /// and bound to the pipeline in the same order.
+    /// Implementation is expected to write descriptors for `buffers` and `images` into them.
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+        set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        sets: &[Escape<DescriptorSet<B>>],
+    ) -> Result<Self::Pipeline, rendy_core::hal::pso::CreationError>;
+}
+
+/// Simple compute pipeline.
+pub trait SimpleComputePipeline<B: Backend, T: ?Sized>:
+    std::fmt::Debug + Sized + Send + Sync + 'static
+{
+    /// This pipeline descriptor.
+    type Desc: SimpleComputePipelineDesc<B, T, Pipeline = Self> + 'static;
+
+    /// Make simple compute node builder.
+    fn builder() -> DescBuilder<B, T, SimpleComputeNodeDesc<Self::Desc>>
+    where
+        Self::Desc: Default,
+    {
+        Self::Desc::default().builder()
+    }
+
+    /// Prepare to record dispatch commands.
+    ///
+    /// Should return true if commands must be re-recorded.
+    fn prepare(
+        &mut self,
+        _factory: &Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _index: usize,
+        _aux: &T,
+    ) -> PrepareResult {
+        PrepareResult::DrawRecord
+    }
+
+    /// Get number of local workgroups to dispatch in each dimension.
+    /// Commands are re-recorded whenever returned value changes.
+    fn dispatch(&self, index: usize, aux: &T) -> [u32; 3];
+
+    /// Record additional commands before dispatch.
+    /// Pipeline and descriptor sets are already bound.
+    fn record(
+        &mut self,
+        _layout: &B::PipelineLayout,
+        _encoder: &mut Encoder<'_, B, Compute, PrimaryLevel>,
+        _index: usize,
+        _aux: &T,
+    ) {
+    }
+
+    /// Update descriptors after images were recreated by `Graph::resize`.
+    /// Called only if node uses at least one of recreated images.
+    /// Does nothing by default.
+    fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _sets: &[Escape<DescriptorSet<B>>],
+        _images: &[ImageId],
+    ) -> Result<(), rendy_core::hal::pso::CreationError> {
+        Ok(())
+    }
+
+    /// Free all resources and destroy pipeline instance.
+    fn dispose(self, factory: &mut Factory<B>, aux: &T);
+}
+
+/// Descriptor for simple compute node.
+#[derive(Debug)]
+pub struct SimpleComputeNodeDesc<P: std::fmt::Debug> {
+    inner: P,
+}
+
+/// Node that consist of simple compute pipeline.
+#[derive(Debug)]
+pub struct SimpleComputeNode<B: Backend, P> {
+    set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
+    sets: Vec<Escape<DescriptorSet<B>>>,
+    pipeline_layout: B::PipelineLayout,
+    compute_pipeline: B::ComputePipeline,
+    pipeline: P,
+
+    queue: QueueId,
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
+
+    command_pool: CommandPool<B, Compute, IndividualReset>,
+    command_cirque: CommandCirque<B, Compute>,
+    recorded: Vec<Option<[u32; 3]>>,
+}
+
+impl<B, T, P> NodeDesc<B, T> for SimpleComputeNodeDesc<P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleComputePipelineDesc<B, T> + 'static,
+{
+    type Node = SimpleComputeNode<B, P::Pipeline>;
+
+    fn buffers(&self) -> Vec<BufferAccess> {
+        self.inner.buffers()
+    }
+
+    fn images(&self) -> Vec<ImageAccess> {
+        self.inner.images()
+    }
+
+    fn build<'a>(
+        self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        family: &mut Family<B>,
+        queue: usize,
+        aux: &T,
+        buffers: Vec<NodeBuffer>,
+        images: Vec<NodeImage>,
+    ) -> Result<Self::Node, NodeBuildError> {
+        log::trace!("Load shader sets for");
+
+        let mut shader_set = self.inner.load_shader_set(factory, aux);
+
+        let layout = self.inner.layout();
+
+        let set_layouts = layout
+            .sets
+            .into_iter()
+            .map(|set| {
+                factory
+                    .create_descriptor_set_layout(set.bindings)
+                    .map(Handle::from)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                shader_set.dispose(factory);
+                NodeBuildError::OutOfMemory(e)
+            })?;
+
+        let sets = set_layouts
+            .iter()
+            .map(|layout| factory.create_descriptor_set(layout.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                shader_set.dispose(factory);
+                NodeBuildError::OutOfMemory(e)
+            })?;
+
+        let pipeline_layout = unsafe {
+            factory
+                .device()
+                .create_pipeline_layout(set_layouts.iter().map(|l| l.raw()), layout.push_constants)
+        }
+        .map_err(|e| {
+            shader_set.dispose(factory);
+            NodeBuildError::OutOfMemory(e)
+        })?;
+
+        let shader = match shader_set.raw_compute() {
+            Err(e) => {
+                unsafe {
+                    factory.device().destroy_pipeline_layout(pipeline_layout);
+                }
+                shader_set.dispose(factory);
+                log::warn!("Shader error {:?}", e);
+                return Err(NodeBuildError::Pipeline(
+                    rendy_core::hal::pso::CreationError::Other,
+                ));
+            }
+            Ok(s) => s,
+        };
+
+        let compute_pipeline = unsafe {
+            factory.device().create_compute_pipeline(
+                &rendy_core::hal::pso::ComputePipelineDesc {
+                    shader,
+                    layout: &pipeline_layout,
+                    flags: rendy_core::hal::pso::PipelineCreationFlags::empty(),
+                    parent: rendy_core::hal::pso::BasePipeline::None,
+                },
+                None,
+            )
+        };
+
+        shader_set.dispose(factory);
+
+        let compute_pipeline = match compute_pipeline {
+            Ok(compute_pipeline) => compute_pipeline,
+            Err(e) => {
+                unsafe {
+                    factory.device().destroy_pipeline_layout(pipeline_layout);
+                }
+                return Err(NodeBuildError::Pipeline(e));
+            }
+        };
+
+        let queue = family.queue(queue).id();
+
+        let command_pool = match factory
+            .create_command_pool(family)
+            .map(|pool| pool.with_capability())
+        {
+            Ok(pool) => pool.expect("Graph must specify family that supports `Compute`"),
+            Err(e) => {
+                unsafe {
+                    factory.device().destroy_compute_pipeline(compute_pipeline);
+                    factory.device().destroy_pipeline_layout(pipeline_layout);
+                }
+                return Err(NodeBuildError::OutOfMemory(e));
+            }
+        };
+
+        let pipeline = self.inner.build(
+            ctx,
+            factory,
+            queue,
+            aux,
+            buffers.clone(),
+            images.clone(),
+            &set_layouts,
+            &sets,
+        );
+
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                unsafe {
+                    factory.destroy_command_pool(command_pool.with_queue_type());
+                    factory.device().destroy_compute_pipeline(compute_pipeline);
+                    factory.device().destroy_pipeline_layout(pipeline_layout);
+                }
+                return Err(NodeBuildError::Pipeline(e));
+            }
+        };
+
+        Ok(SimpleComputeNode {
+            set_layouts,
+            sets,
+            pipeline_layout,
+            compute_pipeline,
+            pipeline,
+            queue,
+            buffers,
+            images,
+            command_pool,
+            command_cirque: CommandCirque::new(),
+            recorded: Vec::new(),
+        })
+    }
+}
+
+impl<'a, B, P> NodeSubmittable<'a, B> for SimpleComputeNode<B, P>
+where
+    B: Backend,
+{
+    type Submittable = Submit<B, NoSimultaneousUse>;
+    type Submittables = Option<Submit<B, NoSimultaneousUse>>;
+}
+
+impl<B, T, P> Node<B, T> for SimpleComputeNode<B, P>
+where
+    B: Backend,
+    T: ?Sized,
+    P: SimpleComputePipeline<B, T>,
+{
+    type Capability = Compute;
+
+    fn run<'a>(
+        &'a mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+        aux: &T,
+        frames: &'a Frames<B>,
+    ) -> Option<Submit<B, NoSimultaneousUse>> {
+        let SimpleComputeNode {
+            set_layouts,
+            sets,
+            pipeline_layout,
+            compute_pipeline,
+            pipeline,
+            queue,
+            buffers,
+            images,
+            command_pool,
+            command_cirque,
+            recorded,
+        } = self;
+
+        let submit = command_cirque.encode(frames, command_pool, |mut cbuf| {
+            let index = cbuf.index();
+            if recorded.len() <= index {
+                recorded.resize(index + 1, None);
+            }
+
+            let force_record = pipeline
+                .prepare(factory, *queue, set_layouts, index, aux)
+                .force_record();
+            let [x, y, z] = pipeline.dispatch(index, aux);
+
+            if force_record || recorded[index] != Some([x, y, z]) {
+                recorded[index] = Some([x, y, z]);
+                cbuf = CirqueRef::Initial(cbuf.or_reset(|cbuf| cbuf.reset()));
+            }
+
+            cbuf.or_init(|cbuf| {
+                let mut cbuf = cbuf.begin(MultiShot(NoSimultaneousUse), ());
+                let mut encoder = cbuf.encoder();
+
+                let (stages, barriers) = gfx_acquire_barriers(ctx, &*buffers, &*images);
+                if !barriers.is_empty() {
+                    unsafe {
+                        encoder.pipeline_barrier(
+                            stages,
+                            rendy_core::hal::memory::Dependencies::empty(),
+                            barriers,
+                        );
+                    }
+                }
+
+                encoder.bind_compute_pipeline(compute_pipeline);
+                if !sets.is_empty() {
+                    unsafe {
+                        encoder.bind_compute_descriptor_sets(
+                            pipeline_layout,
+                            0,
+                            sets.iter().map(|set| set.raw()),
+                            std::iter::empty(),
+                        );
+                    }
+                }
+
+                pipeline.record(pipeline_layout, &mut encoder, index, aux);
+
+                unsafe {
+                    encoder.dispatch(x, y, z);
+                }
+
+                let (stages, barriers) = gfx_release_barriers(ctx, &*buffers, &*images);
+                if !barriers.is_empty() {
+                    unsafe {
+                        encoder.pipeline_barrier(
+                            stages,
+                            rendy_core::hal::memory::Dependencies::empty(),
+                            barriers,
+                        );
+                    }
+                }
+
+                cbuf.finish()
+            })
+        });
+
+        Some(submit)
+    }
+
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        if !self.images.iter().any(|image| images.contains(&image.id)) {
+            return Ok(());
+        }
+
+        // Recorded barriers reference old images.
+        self.recorded.clear();
+
+        self.pipeline
+            .resize(ctx, factory, self.queue, &self.sets, images)
+            .map_err(NodeBuildError::Pipeline)
+    }
+
+    unsafe fn dispose(self, factory: &mut Factory<B>, aux: &T) {
+        let SimpleComputeNode {
+            set_layouts,
+            sets,
+            pipeline_layout,
+            compute_pipeline,
+            pipeline,
+            mut command_pool,
+            command_cirque,
+            ..
+        } = self;
+
+        pipeline.dispose(factory, aux);
+
+        let pool = &mut command_pool;
+        command_cirque.dispose(|buffer| {
+            buffer.either_with(
+                &mut *pool,
+                |pool, executable| pool.free_buffers(Some(executable)),
+                |pool, pending| {
+                    let executable = pending.mark_complete();
+                    pool.free_buffers(Some(executable))
+                },
+            );
+        });
+        factory.destroy_command_pool(command_pool.with_queue_type());
+
+        factory.device().destroy_compute_pipeline(compute_pipeline);
+        factory.device().destroy_pipeline_layout(pipeline_layout);
+        drop(sets);
+        drop(set_layouts);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            factory::{init_with_instance, Config},
+            graph::GraphBuilder,
+        },
+        rendy_core::{
+            hal::{
+                buffer,
+                format::Format,
+                image,
+                pso::{
+                    BufferDescriptorFormat, BufferDescriptorType, CreationError,
+                    DescriptorSetLayoutBinding, DescriptorType, PipelineStage, ShaderStageFlags,
+                },
+                window::Extent2D,
+            },
+            Instance,
+        },
+        rendy_fake::{FakeBackend, FakeCommand, FakeInstance},
+        rendy_shader::{ShaderSetBuilder, SpirvShader},
+        std::sync::{Arc, Mutex},
+    };
+
+    /// Number of work groups to dispatch is taken from auxiliary data.
+    type Aux = Mutex<[u32; 3]>;
+
+    /// Pipeline that writes a storage buffer and a storage image.
+    #[derive(Debug, Default)]
+    struct TestDesc {
+        /// Images passed to the pipeline when it is resized.
+        resized: Arc<Mutex<Vec<ImageId>>>,
+    }
+
+    /// Pipeline that reuses commands unless dispatch size changes.
+    #[derive(Debug)]
+    struct TestPipeline {
+        resized: Arc<Mutex<Vec<ImageId>>>,
+    }
+
+    impl SimpleComputePipelineDesc<FakeBackend, Aux> for TestDesc {
+        type Pipeline = TestPipeline;
+
+        fn buffers(&self) -> Vec<BufferAccess> {
+            vec![BufferAccess {
+                access: buffer::Access::SHADER_WRITE,
+                usage: buffer::Usage::STORAGE,
+                stages: PipelineStage::COMPUTE_SHADER,
+            }]
+        }
+
+        fn images(&self) -> Vec<ImageAccess> {
+            vec![ImageAccess {
+                access: image::Access::SHADER_WRITE,
+                usage: image::Usage::STORAGE,
+                layout: image::Layout::General,
+                stages: PipelineStage::COMPUTE_SHADER,
+                range: None,
+            }]
+        }
+
+        fn layout(&self) -> Layout {
+            Layout {
+                sets: vec![SetLayout {
+                    bindings: vec![DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: DescriptorType::Buffer {
+                            ty: BufferDescriptorType::Storage { read_only: false },
+                            format: BufferDescriptorFormat::Structured {
+                                dynamic_offset: false,
+                            },
+                        },
+                        count: 1,
+                        stage_flags: ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    }],
+                }],
+                push_constants: Vec::new(),
+            }
+        }
+
+        fn load_shader_set(
+            &self,
+            factory: &mut Factory<FakeBackend>,
+            _aux: &Aux,
+        ) -> rendy_shader::ShaderSet<FakeBackend> {
+            let shader = SpirvShader::new(vec![0x0723_0203], ShaderStageFlags::COMPUTE, "main");
+            ShaderSetBuilder::default()
+                .with_compute(&shader)
+                .unwrap()
+                .build(factory, Default::default())
+                .unwrap()
+        }
+
+        fn build<'a>(
+            self,
+            _ctx: &GraphContext<FakeBackend>,
+            _factory: &mut Factory<FakeBackend>,
+            _queue: QueueId,
+            _aux: &Aux,
+            buffers: Vec<NodeBuffer>,
+            images: Vec<NodeImage>,
+            set_layouts: &[Handle<DescriptorSetLayout<FakeBackend>>],
+            sets: &[Escape<DescriptorSet<FakeBackend>>],
+        ) -> Result<TestPipeline, CreationError> {
+            assert_eq!(buffers.len(), 1);
+            assert_eq!(images.len(), 1);
+            assert_eq!(set_layouts.len(), 1);
+            assert_eq!(sets.len(), 1);
+            Ok(TestPipeline {
+                resized: self.resized,
+            })
+        }
+    }
+
+    impl SimpleComputePipeline<FakeBackend, Aux> for TestPipeline {
+        type Desc = TestDesc;
+
+        fn prepare(
+            &mut self,
+            _factory: &Factory<FakeBackend>,
+            _queue: QueueId,
+            _set_layouts: &[Handle<DescriptorSetLayout<FakeBackend>>],
+            _index: usize,
+            _aux: &Aux,
+        ) -> PrepareResult {
+            PrepareResult::DrawReuse
+        }
+
+        fn dispatch(&self, _index: usize, aux: &Aux) -> [u32; 3] {
+            *aux.lock().unwrap()
+        }
+
+        fn resize(
+            &mut self,
+            _ctx: &GraphContext<FakeBackend>,
+            _factory: &mut Factory<FakeBackend>,
+            _queue: QueueId,
+            sets: &[Escape<DescriptorSet<FakeBackend>>],
+            images: &[ImageId],
+        ) -> Result<(), CreationError> {
+            assert_eq!(sets.len(), 1);
+            self.resized.lock().unwrap().extend_from_slice(images);
+            Ok(())
+        }
+
+        fn dispose(self, _factory: &mut Factory<FakeBackend>, _aux: &Aux) {}
+    }
+
+    #[test]
+    fn build_and_run() {
+        let config: Config = Default::default();
+        let (mut factory, mut families) =
+            init_with_instance(Instance::new(FakeInstance), &config).unwrap();
+        let aux = Mutex::new([4, 2, 1]);
+
+        let mut builder = GraphBuilder::new().with_frames_in_flight(1);
+        let buffer = builder.create_buffer(1024);
+        let image = builder.create_resizable_image(
+            image::Kind::D2(64, 64, 1, 1),
+            1,
+            Format::Rgba8Unorm,
+            None,
+        );
+        let desc = TestDesc::default();
+        let resized = desc.resized.clone();
+        builder.add_node(desc.builder().with_buffer(buffer).with_image(image));
+        let mut graph = builder.build(&mut factory, &mut families, &aux).unwrap();
+
+        let stats = factory.device().stats();
+        assert_eq!(stats.pipeline_layouts, 1);
+        assert_eq!(stats.compute_pipelines, 1);
+
+        // Pipeline and descriptor set are bound before dispatch.
+        graph.run(&mut factory, &mut families, &aux).unwrap();
+        assert_eq!(
+            factory.device().take_submitted(),
+            vec![
+                FakeCommand::BindComputePipeline,
+                FakeCommand::BindComputeDescriptorSets {
+                    first_set: 0,
+                    sets: 1
+                },
+                FakeCommand::Dispatch([4, 2, 1]),
+            ]
+        );
+        let recordings = factory.device().stats().recordings;
+
+        // Commands are reused while dispatch size is the same.
+        graph.run(&mut factory, &mut families, &aux).unwrap();
+        assert_eq!(factory.device().stats().recordings, recordings);
+        assert!(factory
+            .device()
+            .take_submitted()
+            .contains(&FakeCommand::Dispatch([4, 2, 1])));
+
+        // And re-recorded when it changes.
+        *aux.lock().unwrap() = [8, 8, 1];
+        graph.run(&mut factory, &mut families, &aux).unwrap();
+        assert_eq!(factory.device().stats().recordings, recordings + 1);
+        assert!(factory
+            .device()
+            .take_submitted()
+            .contains(&FakeCommand::Dispatch([8, 8, 1])));
+
+        // Pipeline is notified about resized image and commands are re-recorded.
+        let extent = Extent2D {
+            width: 128,
+            height: 32,
+        };
+        graph.resize(&mut factory, &mut families, extent).unwrap();
+        assert_eq!(*resized.lock().unwrap(), vec![image]);
+        graph.run(&mut factory, &mut families, &aux).unwrap();
+        assert_eq!(factory.device().stats().recordings, recordings + 2);
+
+        graph.dispose(&mut factory, &aux);
+        let stats = factory.device().stats();
+        assert_eq!(stats.pipeline_layouts, 0);
+        assert_eq!(stats.compute_pipelines, 0);
+    }
+}
@@ -1,6 +1,7 @@
 //! Defines node - building block for framegraph.
 //!
 
+pub mod compute;
 pub mod present;
 pub mod render;
 
@@ -73,7 +74,7 @@ pub struct NodeBuffer {
 }
 
 /// Image access node wants to perform.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct ImageAccess {
     /// Access flags.
     pub access: rendy_core::hal::image::Access,
@@ -90,6 +91,10 @@ pub struct ImageAccess {
 
     /// Pipeline stages at which image is accessd.
     pub stages: rendy_core::hal::pso::PipelineStage,
+
+    /// Subresource range of the image accessed.
+    /// `None` means the whole image.
+    pub range: Option<rendy_core::hal::image::SubresourceRange>,
 }
 
 /// Image pipeline barrier.
@@ -114,6 +119,8 @@ pub struct NodeImage {
     pub id: ImageId,
 
     /// Region of the image that is the transient resource.
+    /// Ranges accessed by other nodes are tracked separately,
+    /// so node may receive several `NodeImage`s with disjoint ranges of the same image.
     pub range: rendy_core::hal::image::SubresourceRange,
 
     /// Image state for node.
@@ -168,6 +175,22 @@ pub trait Node<B: Backend, T: ?Sized>:
         frames: &'a Frames<B>,
     ) -> <Self as NodeSubmittable<'a, B>>::Submittables;
 
+    /// Recreate resources that depend on extent of transient images.
+    /// Called by `Graph::resize` with ids of recreated images.
+    /// Does nothing by default.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    unsafe fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        Ok(())
+    }
+
     /// Dispose of the node.
     ///
     /// # Safety
@@ -236,6 +259,26 @@ pub trait DynNode<B: Backend, T: ?Sized>: std::fmt::Debug + Sync + Send {
         fence: Option<&mut Fence<B>>,
     );
 
+    /// Recreate resources that depend on extent of transient images or surface,
+    /// such as image views and framebuffers.
+    /// Called by `Graph::resize` with ids of recreated images.
+    /// Requested extent is available as `GraphContext::extent`.
+    /// Does nothing by default.
+    ///
+    /// # Safety
+    ///
+    /// Must be called after waiting for device idle.
+    unsafe fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _family: &mut Family<B>,
+        _queue: usize,
+        _images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        Ok(())
+    }
+
     /// Dispose of the node.
     ///
     /// # Safety
@@ -273,6 +316,17 @@ where
         )
     }
 
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _family: &mut Family<B>,
+        _queue: usize,
+        images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        Node::resize(&mut self.0, ctx, factory, images)
+    }
+
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         N::dispose(self.0, factory, aux);
     }
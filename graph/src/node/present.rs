@@ -12,7 +12,7 @@ use crate::{
         gfx_acquire_barriers, gfx_release_barriers, BufferAccess, DynNode, ImageAccess, NodeBuffer,
         NodeBuildError, NodeBuilder, NodeImage,
     },
-    wsi::{Surface, Target},
+    wsi::{Surface, SwapchainError, Target},
     BufferId, ImageId, NodeId,
 };
 
@@ -48,7 +48,7 @@ pub struct PresentNode<B: rendy_core::hal::Backend> {
     free_acquire: B::Semaphore,
     target: Target<B>,
     pool: CommandPool<B, rendy_core::hal::queue::QueueType>,
-    input_image: NodeImage,
+    input_images: Vec<NodeImage>,
     blit_filter: rendy_core::hal::image::Filter,
 }
 
@@ -95,14 +95,62 @@ where
     }
 }
 
+impl<B> PresentNode<B>
+where
+    B: rendy_core::hal::Backend,
+{
+    /// Recreate swapchain with extent of the input image
+    /// and record commands for new swapchain images.
+    ///
+    /// # Safety
+    ///
+    /// Swapchain and recorded commands must be not in use.
+    unsafe fn recreate(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &Factory<B>,
+    ) -> Result<(), SwapchainError> {
+        let extent = crate::graph::clamped_extent(
+            ctx.extent().unwrap_or_else(|| {
+                ctx.get_image(self.input_images[0].id)
+                    .expect("Context must contain node's image")
+                    .kind()
+                    .extent()
+                    .into()
+            }),
+            &self.target.surface().capabilities(factory.physical()),
+        );
+
+        self.target
+            .recreate(factory.physical(), factory.device(), extent)?;
+
+        for data in self.per_image.drain(..) {
+            data.dispose(factory, &mut self.pool);
+        }
+
+        self.per_image = create_per_image_data(
+            ctx,
+            &self.input_images,
+            &mut self.pool,
+            factory,
+            &self.target,
+            self.blit_filter,
+        );
+
+        Ok(())
+    }
+}
+
 fn create_per_image_data<B: rendy_core::hal::Backend>(
     ctx: &GraphContext<B>,
-    input_image: &NodeImage,
+    input_images: &[NodeImage],
     pool: &mut CommandPool<B, rendy_core::hal::queue::QueueType>,
     factory: &Factory<B>,
     target: &Target<B>,
     blit_filter: rendy_core::hal::image::Filter,
 ) -> Vec<ForImage<B>> {
+    // Images are sorted by range, so the first one contains the level and layer to present.
+    let input_image = &input_images[0];
     let input_image_res = ctx.get_image(input_image.id).expect("Image does not exist");
 
     let target_images = target.backbuffer();
@@ -114,7 +162,7 @@ fn create_per_image_data<B: rendy_core::hal::Backend>(
             let mut buf_recording = buf_initial.begin(MultiShot(SimultaneousUse), ());
             let mut encoder = buf_recording.encoder();
             let (mut stages, mut barriers) =
-                gfx_acquire_barriers(ctx, None, Some(input_image));
+                gfx_acquire_barriers(ctx, None, input_images);
             stages.start |= rendy_core::hal::pso::PipelineStage::TRANSFER;
             stages.end |= rendy_core::hal::pso::PipelineStage::TRANSFER;
             barriers.push(rendy_core::hal::memory::Barrier::Image {
@@ -212,7 +260,7 @@ fn create_per_image_data<B: rendy_core::hal::Backend>(
 
             {
                 let (mut stages, mut barriers) =
-                    gfx_release_barriers(ctx, None, Some(input_image));
+                    gfx_release_barriers(ctx, None, input_images);
                 stages.start |= rendy_core::hal::pso::PipelineStage::TRANSFER;
                 stages.end |= rendy_core::hal::pso::PipelineStage::BOTTOM_OF_PIPE;
                 barriers.push(rendy_core::hal::memory::Barrier::Image {
@@ -382,6 +430,7 @@ where
                 layout: rendy_core::hal::image::Layout::TransferSrcOptimal,
                 usage: rendy_core::hal::image::Usage::TRANSFER_SRC,
                 stages: rendy_core::hal::pso::PipelineStage::TRANSFER,
+                range: None,
             },
         )]
     }
@@ -401,11 +450,10 @@ where
         images: Vec<NodeImage>,
     ) -> Result<Box<dyn DynNode<B, T>>, NodeBuildError> {
         assert_eq!(buffers.len(), 0);
-        assert_eq!(images.len(), 1);
+        assert!(!images.is_empty());
 
-        let input_image = images.into_iter().next().unwrap();
         let extent = ctx
-            .get_image(input_image.id)
+            .get_image(images[0].id)
             .expect("Context must contain node's image")
             .kind()
             .extent()
@@ -434,21 +482,15 @@ where
             .create_command_pool(family)
             .map_err(NodeBuildError::OutOfMemory)?;
 
-        let per_image = create_per_image_data(
-            ctx,
-            &input_image,
-            &mut pool,
-            factory,
-            &target,
-            self.blit_filter,
-        );
+        let per_image =
+            create_per_image_data(ctx, &images, &mut pool, factory, &target, self.blit_filter);
 
         Ok(Box::new(PresentNode {
             free_acquire: factory.create_semaphore().unwrap(),
             pool,
             target,
             per_image,
-            input_image,
+            input_images: images,
             blit_filter: self.blit_filter,
         }))
     }
@@ -516,32 +558,23 @@ where
             // TODO: use retired swapchains once available in hal and remove that wait
             factory.wait_idle().unwrap();
 
-            let extent = ctx
-                .get_image(self.input_image.id)
-                .expect("Context must contain node's image")
-                .kind()
-                .extent()
-                .into();
-
-            self.target
-                .recreate(factory.physical(), factory.device(), extent)
+            self.recreate(ctx, factory)
                 .expect("Failed recreating swapchain");
-
-            for data in self.per_image.drain(..) {
-                data.dispose(factory, &mut self.pool);
-            }
-
-            self.per_image = create_per_image_data(
-                ctx,
-                &self.input_image,
-                &mut self.pool,
-                factory,
-                &self.target,
-                self.blit_filter,
-            );
         }
     }
 
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _family: &mut Family<B>,
+        _queue: usize,
+        _images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        self.recreate(ctx, factory)
+            .map_err(NodeBuildError::Swapchain)
+    }
+
     unsafe fn dispose(mut self: Box<Self>, factory: &mut Factory<B>, _aux: &T) {
         for data in self.per_image {
             data.dispose(factory, &mut self.pool);
@@ -88,6 +88,27 @@ pub trait RenderGroup<B: Backend, T: ?Sized>: std::fmt::Debug + Send + Sync {
         aux: &T,
     );
 
+    /// Recreate resources that depend on framebuffer extent
+    /// or on images recreated by `Graph::resize`.
+    /// Does nothing by default.
+    ///
+    /// Commands are re-recorded after resize.
+    /// Groups with pipelines that bake viewport or scissor
+    /// must recreate them here or set them dynamically when drawing.
+    /// `SimpleRenderGroup` creates pipelines with dynamic viewport and scissor
+    /// and sets both to the whole framebuffer before calling `SimpleGraphicsPipeline::draw`.
+    fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _framebuffer_width: u32,
+        _framebuffer_height: u32,
+        _images: &[ImageId],
+    ) -> Result<(), rendy_core::hal::pso::CreationError> {
+        Ok(())
+    }
+
     /// Free all resources and destroy group instance.
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T);
 }
@@ -8,6 +8,7 @@ use {
             render::PrepareResult, BufferAccess, DescBuilder, ImageAccess, NodeBuffer, NodeImage,
         },
         resource::{DescriptorSetLayout, Handle},
+        ImageId,
     },
     rendy_core::hal::{device::Device as _, Backend},
 };
@@ -185,6 +186,9 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
     }
 
     /// Record drawing commands to the command buffer provided.
+    ///
+    /// Pipeline is created with dynamic viewport and scissor,
+    /// both are set to the whole framebuffer before this call.
     fn draw(
         &mut self,
         layout: &B::PipelineLayout,
@@ -193,6 +197,19 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
         aux: &T,
     );
 
+    /// Update descriptors after images were recreated by `Graph::resize`.
+    /// Does nothing by default.
+    fn resize(
+        &mut self,
+        _ctx: &GraphContext<B>,
+        _factory: &mut Factory<B>,
+        _queue: QueueId,
+        _set_layouts: &[Handle<DescriptorSetLayout<B>>],
+        _images: &[ImageId],
+    ) -> Result<(), rendy_core::hal::pso::CreationError> {
+        Ok(())
+    }
+
     /// Free all resources and destroy pipeline instance.
     fn dispose(self, factory: &mut Factory<B>, aux: &T);
 }
@@ -200,6 +217,8 @@ pub trait SimpleGraphicsPipeline<B: Backend, T: ?Sized>:
 /// Render group that consist of simple graphics pipeline.
 #[derive(Debug)]
 pub struct SimpleRenderGroup<B: Backend, P> {
+    framebuffer_width: u32,
+    framebuffer_height: u32,
     set_layouts: Vec<Handle<DescriptorSetLayout<B>>>,
     pipeline_layout: B::PipelineLayout,
     graphics_pipeline: B::GraphicsPipeline,
@@ -287,13 +306,6 @@ where
             push_vertex_desc(elemets, stride, rate, &mut vertex_buffers, &mut attributes);
         }
 
-        let rect = rendy_core::hal::pso::Rect {
-            x: 0,
-            y: 0,
-            w: framebuffer_width as i16,
-            h: framebuffer_height as i16,
-        };
-
         let shaders = match shader_set.raw() {
             Err(e) => {
                 shader_set.dispose(factory);
@@ -317,12 +329,10 @@ where
                     },
                     depth_stencil: pipeline.depth_stencil,
                     multisampling: None,
+                    // Viewport and scissor are dynamic so that pipeline survives resize.
                     baked_states: rendy_core::hal::pso::BakedStates {
-                        viewport: Some(rendy_core::hal::pso::Viewport {
-                            rect,
-                            depth: 0.0..1.0,
-                        }),
-                        scissor: Some(rect),
+                        viewport: None,
+                        scissor: None,
                         blend_color: None,
                         depth_bounds: None,
                     },
@@ -351,6 +361,8 @@ where
         shader_set.dispose(factory);
 
         Ok(Box::new(SimpleRenderGroup::<B, _> {
+            framebuffer_width,
+            framebuffer_height,
             set_layouts,
             pipeline_layout,
             graphics_pipeline,
@@ -384,11 +396,43 @@ where
         _subpass: rendy_core::hal::pass::Subpass<'_, B>,
         aux: &T,
     ) {
+        let rect = rendy_core::hal::pso::Rect {
+            x: 0,
+            y: 0,
+            w: self.framebuffer_width as i16,
+            h: self.framebuffer_height as i16,
+        };
+
         encoder.bind_graphics_pipeline(&self.graphics_pipeline);
+        unsafe {
+            encoder.set_viewports(
+                0,
+                Some(&rendy_core::hal::pso::Viewport {
+                    rect,
+                    depth: 0.0..1.0,
+                }),
+            );
+            encoder.set_scissors(0, Some(&rect));
+        }
         self.pipeline
             .draw(&self.pipeline_layout, encoder, index, aux);
     }
 
+    fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        queue: QueueId,
+        framebuffer_width: u32,
+        framebuffer_height: u32,
+        images: &[ImageId],
+    ) -> Result<(), rendy_core::hal::pso::CreationError> {
+        self.framebuffer_width = framebuffer_width;
+        self.framebuffer_height = framebuffer_height;
+        self.pipeline
+            .resize(ctx, factory, queue, &self.set_layouts, images)
+    }
+
     fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         self.pipeline.dispose(factory, aux);
 
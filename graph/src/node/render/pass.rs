@@ -20,11 +20,15 @@ use {
             render::group::{RenderGroup, RenderGroupBuilder},
             BufferAccess, DynNode, ImageAccess, NodeBuffer, NodeBuildError, NodeBuilder, NodeImage,
         },
+        resource::Image,
         wsi::{Surface, Target},
         BufferId, ImageId, NodeId,
     },
     either::Either,
-    std::{cmp::min, collections::HashMap},
+    std::{
+        cmp::{max, min},
+        collections::HashMap,
+    },
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -315,6 +319,7 @@ where
             usage: rendy_core::hal::image::Usage::empty(),
             stages: rendy_core::hal::pso::PipelineStage::empty(),
             layout: Layout::Undefined,
+            range: None,
         };
         let mut attachments = HashMap::new();
         let mut images = HashMap::new();
@@ -323,7 +328,7 @@ where
             for &id in subpass.inputs.iter().filter_map(|e| e.as_ref().left()) {
                 let entry = attachments.entry(id).or_insert(ImageAccess {
                     layout: Layout::ShaderReadOnlyOptimal,
-                    ..empty
+                    ..empty.clone()
                 });
                 entry.access |= rendy_core::hal::image::Access::INPUT_ATTACHMENT_READ;
                 entry.usage |= rendy_core::hal::image::Usage::INPUT_ATTACHMENT;
@@ -333,7 +338,7 @@ where
             for &id in subpass.colors.iter().filter_map(|e| e.as_ref().left()) {
                 let entry = attachments.entry(id).or_insert(ImageAccess {
                     layout: Layout::ColorAttachmentOptimal,
-                    ..empty
+                    ..empty.clone()
                 });
                 entry.access |= rendy_core::hal::image::Access::COLOR_ATTACHMENT_READ
                     | rendy_core::hal::image::Access::COLOR_ATTACHMENT_WRITE;
@@ -344,7 +349,7 @@ where
             if let Some(id) = subpass.depth_stencil.and_then(Either::left) {
                 let entry = attachments.entry(id).or_insert(ImageAccess {
                     layout: Layout::DepthStencilAttachmentOptimal,
-                    ..empty
+                    ..empty.clone()
                 });
                 entry.access |= rendy_core::hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
                     | rendy_core::hal::image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
@@ -359,11 +364,18 @@ where
                         !attachments.contains_key(&id),
                         "Attachment image can't be used otherwise in render pass"
                     );
-                    let entry = images.entry(id).or_insert(empty);
+                    let entry = images.entry(id).or_insert_with(|| ImageAccess {
+                        range: access.range.clone(),
+                        ..empty.clone()
+                    });
                     entry.access |= access.access;
                     entry.usage |= access.usage;
                     entry.stages |= access.stages;
                     entry.layout = common_layout(entry.layout, access.layout);
+                    if entry.range != access.range {
+                        // Groups access different ranges of the image.
+                        entry.range = None;
+                    }
                 }
             }
         }
@@ -468,33 +480,14 @@ where
                     Either::Left(image_id) => {
                         log::debug!("Image {:?} attachment", image_id);
 
-                        let node_image = find_attachment_node_image(image_id);
+                        let range = attachment_range(&images, image_id);
                         let image = ctx.get_image(image_id).expect("Image does not exist");
                         let extent = image.kind().extent();
                         framebuffer_width = min(framebuffer_width, extent.width);
                         framebuffer_height = min(framebuffer_height, extent.height);
-                        framebuffer_layers = min(
-                            framebuffer_layers,
-                            node_image.range.layers.end - node_image.range.layers.start,
-                        );
-                        Ok(vec![unsafe {
-                            factory
-                                .device()
-                                .create_image_view(
-                                    image.raw(),
-                                    rendy_core::hal::image::ViewKind::D2,
-                                    image.format(),
-                                    rendy_core::hal::format::Swizzle::NO,
-                                    rendy_core::hal::image::SubresourceRange {
-                                        // NOTE: Framebuffer must always be created with only one mip level. If image contains multiple levels,
-                                        // only the first one is bound as an attachment.
-                                        // TODO: Allow customizing this behaviour to choose which level to bind.
-                                        levels: 0 .. 1,
-                                        ..node_image.range.clone()
-                                    }
-                                )
-                                .map_err(NodeBuildError::View)?
-                            }])
+                        framebuffer_layers =
+                            min(framebuffer_layers, range.layers.end - range.layers.start);
+                        Ok(vec![create_attachment_view(factory, image, range)?])
                     },
                     Either::Right(RenderPassSurface) => {
                         log::trace!("Surface attachment");
@@ -541,22 +534,7 @@ where
                             target.backbuffer()[0].layers(),
                         );
 
-                        let views = target.backbuffer().iter().map(|image| unsafe {
-                            factory
-                                .device()
-                                .create_image_view(
-                                    image.raw(),
-                                    rendy_core::hal::image::ViewKind::D2,
-                                    image.format(),
-                                    rendy_core::hal::format::Swizzle::NO,
-                                    rendy_core::hal::image::SubresourceRange {
-                                        aspects: image.format().surface_desc().aspects,
-                                        levels: 0 .. 1,
-                                        layers: 0 .. 1,
-                                    },
-                                )
-                                .map_err(NodeBuildError::View)
-                        }).collect::<Result<Vec<_>, NodeBuildError>>()?;
+                        let views = create_target_views(factory, &target)?;
 
                         node_target = Some(target);
                         Ok(views)
@@ -709,34 +687,17 @@ where
             result
         };
 
-        log::trace!(
-            "Create {} framebuffers",
-            views.len() - attachments.len() + 1
-        );
-
-        // Swapchain image views, if any, are last ones.
-        let mut framebuffers = (attachments.len() - 1..views.len())
-            .map(|i| unsafe {
-                log::trace!(
-                    "Create framebuffer for views {}..{} and {}",
-                    0,
-                    attachments.len() - 1,
-                    i,
-                );
-                factory
-                    .device()
-                    .create_framebuffer(
-                        &render_pass,
-                        views[..attachments.len() - 1].iter().chain(Some(&views[i])),
-                        rendy_core::hal::image::Extent {
-                            width: framebuffer_width,
-                            height: framebuffer_height,
-                            depth: framebuffer_layers as u32, // This is gfx-hal BUG as this parameter actually means framebuffer layers number,
-                        },
-                    )
-                    .map_err(NodeBuildError::OutOfMemory)
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+        let mut framebuffers = create_framebuffers(
+            factory,
+            &render_pass,
+            &views,
+            attachments.len(),
+            rendy_core::hal::image::Extent {
+                width: framebuffer_width,
+                height: framebuffer_height,
+                depth: framebuffer_layers as u32,
+            },
+        )?;
 
         log::trace!("Collect clears for render pass");
 
@@ -757,57 +718,8 @@ where
 
         let command_cirque = CommandCirque::new();
 
-        let acquire = if uses_pipeline_barriers::<B>(factory.device()) {
-            let (stages, barriers) = gfx_acquire_barriers(ctx, &buffers, &images);
-
-            if !barriers.is_empty() {
-                let initial = command_pool.allocate_buffers(1).pop().unwrap();
-                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
-                log::debug!("Acquire {:?} : {:#?}", stages, barriers);
-                unsafe {
-                    recording.encoder().pipeline_barrier(
-                        stages,
-                        rendy_core::hal::memory::Dependencies::empty(),
-                        barriers,
-                    );
-                }
-                let (acquire_submit, acquire_buffer) = recording.finish().submit();
-                Some(BarriersCommands {
-                    buffer: acquire_buffer,
-                    submit: acquire_submit,
-                })
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        let release = if uses_pipeline_barriers::<B>(factory.device()) {
-            let (stages, barriers) = gfx_release_barriers(ctx, &buffers, &images);
-
-            if !barriers.is_empty() {
-                let initial = command_pool.allocate_buffers(1).pop().unwrap();
-                let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
-                log::debug!("Release {:?} : {:#?}", stages, barriers);
-                unsafe {
-                    recording.encoder().pipeline_barrier(
-                        stages,
-                        rendy_core::hal::memory::Dependencies::empty(),
-                        barriers,
-                    );
-                }
-                let (release_submit, release_buffer) = recording.finish().submit();
-                Some(BarriersCommands {
-                    buffer: release_buffer,
-                    submit: release_submit,
-                })
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        let (acquire, release) =
+            record_barriers(ctx, factory, &mut command_pool, &buffers, &images);
 
         let subpasses = self
             .subpasses
@@ -838,12 +750,11 @@ where
                         let images: Vec<_> = group
                             .images()
                             .into_iter()
-                            .map(|(id, _)| {
-                                images
-                                    .iter()
-                                    .find(|i| i.id == id)
-                                    .expect("Transient image wasn't provided")
-                                    .clone()
+                            .flat_map(|(id, _)| {
+                                let node_images: Vec<_> =
+                                    images.iter().filter(|i| i.id == id).cloned().collect();
+                                assert!(!node_images.is_empty(), "Transient image wasn't provided");
+                                node_images
                             })
                             .collect();
 
@@ -892,6 +803,14 @@ where
                         acquire,
                         release,
 
+                        queue: QueueId {
+                            family: family.id(),
+                            index: queue,
+                        },
+                        attachments: attachments.clone(),
+                        buffers: buffers.clone(),
+                        images: images.clone(),
+
                         relevant: relevant::Relevant,
                     },
 
@@ -928,6 +847,14 @@ where
                         acquire,
                         release,
 
+                        queue: QueueId {
+                            family: family.id(),
+                            index: queue,
+                        },
+                        attachments: attachments.clone(),
+                        buffers: buffers.clone(),
+                        images: images.clone(),
+
                         relevant: relevant::Relevant,
                     },
                     framebuffer: {
@@ -1000,6 +927,11 @@ struct RenderPassNodeCommon<B: Backend, T: ?Sized> {
     acquire: Option<BarriersCommands<B>>,
     release: Option<BarriersCommands<B>>,
 
+    queue: QueueId,
+    attachments: Vec<Attachment>,
+    buffers: Vec<NodeBuffer>,
+    images: Vec<NodeImage>,
+
     relevant: relevant::Relevant,
 }
 
@@ -1021,6 +953,10 @@ where
             .field("command_cirque", &self.command_cirque)
             .field("acquire", &self.acquire)
             .field("release", &self.release)
+            .field("queue", &self.queue)
+            .field("attachments", &self.attachments)
+            .field("buffers", &self.buffers)
+            .field("images", &self.images)
             .field("relevant", &self.relevant)
             .finish()
     }
@@ -1031,15 +967,10 @@ where
     B: Backend,
     T: ?Sized,
 {
-    unsafe fn dispose(mut self, factory: &mut Factory<B>, aux: &T) {
-        self.relevant.dispose();
-        for subpass in self.subpasses {
-            for group in subpass.groups {
-                group.dispose(factory, aux)
-            }
-        }
+    /// Free all recorded command buffers.
+    unsafe fn free_commands(&mut self) {
         let pool = &mut self.command_pool;
-        self.command_cirque.dispose(|buffer| {
+        std::mem::replace(&mut self.command_cirque, CommandCirque::new()).dispose(|buffer| {
             buffer.either_with(
                 &mut *pool,
                 |pool, executable| pool.free_buffers(Some(executable)),
@@ -1059,6 +990,103 @@ where
             let executable = buffer.mark_complete();
             pool.free_buffers(Some(executable));
         }
+    }
+
+    /// Recreate image views and commands for attachments recreated with new extent.
+    /// Returns new framebuffers.
+    /// Views of the `target` images are the last ones, one framebuffer is created for each.
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        target: Option<&Target<B>>,
+        images: &[ImageId],
+    ) -> Result<Vec<B::Framebuffer>, NodeBuildError> {
+        self.free_commands();
+
+        for view in self.views.drain(..) {
+            factory.device().destroy_image_view(view);
+        }
+
+        let mut framebuffer_width = u32::max_value();
+        let mut framebuffer_height = u32::max_value();
+
+        for &attachment in &self.attachments {
+            match attachment {
+                Either::Left(image_id) => {
+                    let range = attachment_range(&self.images, image_id);
+                    let image = ctx.get_image(image_id).expect("Image does not exist");
+                    let extent = image.kind().extent();
+                    framebuffer_width = min(framebuffer_width, extent.width);
+                    framebuffer_height = min(framebuffer_height, extent.height);
+                    self.views
+                        .push(create_attachment_view(factory, image, range)?);
+                }
+                Either::Right(RenderPassSurface) => {
+                    let target =
+                        target.expect("Render pass with surface attachment must have target");
+                    framebuffer_width = min(framebuffer_width, target.extent().width);
+                    framebuffer_height = min(framebuffer_height, target.extent().height);
+                    self.views.extend(create_target_views(factory, target)?);
+                }
+            }
+        }
+
+        log::debug!(
+            "Resize render pass framebuffer to {}x{}",
+            framebuffer_width,
+            framebuffer_height
+        );
+
+        self.framebuffer_width = framebuffer_width;
+        self.framebuffer_height = framebuffer_height;
+
+        let (acquire, release) = record_barriers(
+            ctx,
+            factory,
+            &mut self.command_pool,
+            &self.buffers,
+            &self.images,
+        );
+        self.acquire = acquire;
+        self.release = release;
+
+        for subpass in &mut self.subpasses {
+            for group in &mut subpass.groups {
+                group
+                    .resize(
+                        ctx,
+                        factory,
+                        self.queue,
+                        framebuffer_width,
+                        framebuffer_height,
+                        images,
+                    )
+                    .map_err(NodeBuildError::Pipeline)?;
+            }
+        }
+
+        create_framebuffers(
+            factory,
+            &self.render_pass,
+            &self.views,
+            self.attachments.len(),
+            rendy_core::hal::image::Extent {
+                width: framebuffer_width,
+                height: framebuffer_height,
+                depth: self._framebuffer_layers as u32,
+            },
+        )
+    }
+
+    unsafe fn dispose(mut self, factory: &mut Factory<B>, aux: &T) {
+        for subpass in self.subpasses.drain(..) {
+            for group in subpass.groups {
+                group.dispose(factory, aux)
+            }
+        }
+        self.free_commands();
+        self.relevant.dispose();
         factory.destroy_command_pool(self.command_pool.with_queue_type());
 
         for view in self.views {
@@ -1268,6 +1296,56 @@ where
         }
     }
 
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _family: &mut Family<B>,
+        _queue: usize,
+        images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        let extent = crate::graph::clamped_extent(
+            ctx.extent().unwrap_or_else(|| self.target.extent()),
+            &self.target.surface().capabilities(factory.physical()),
+        );
+
+        self.target
+            .recreate(factory.physical(), factory.device(), extent)
+            .map_err(NodeBuildError::Swapchain)?;
+
+        let framebuffers = self
+            .common
+            .resize(ctx, factory, Some(&self.target), images)?;
+
+        for per_image in self.per_image.drain(framebuffers.len()..) {
+            factory.device().destroy_framebuffer(per_image.framebuffer);
+            factory.destroy_semaphore(per_image.acquire);
+            factory.destroy_semaphore(per_image.release);
+        }
+
+        for (index, framebuffer) in framebuffers.into_iter().enumerate() {
+            match self.per_image.get_mut(index) {
+                Some(per_image) => {
+                    let old = std::mem::replace(&mut per_image.framebuffer, framebuffer);
+                    factory.device().destroy_framebuffer(old);
+                    per_image.index = 0;
+                }
+                None => self.per_image.push(PerImage {
+                    framebuffer,
+                    acquire: factory
+                        .create_semaphore()
+                        .map_err(NodeBuildError::OutOfMemory)?,
+                    release: factory
+                        .create_semaphore()
+                        .map_err(NodeBuildError::OutOfMemory)?,
+                    index: 0,
+                }),
+            }
+        }
+
+        Ok(())
+    }
+
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         for per_image in self.per_image {
             factory.device().destroy_framebuffer(per_image.framebuffer);
@@ -1420,12 +1498,166 @@ where
         );
     }
 
+    unsafe fn resize(
+        &mut self,
+        ctx: &GraphContext<B>,
+        factory: &mut Factory<B>,
+        _family: &mut Family<B>,
+        _queue: usize,
+        images: &[ImageId],
+    ) -> Result<(), NodeBuildError> {
+        let mut framebuffers = self.common.resize(ctx, factory, None, images)?;
+        assert_eq!(framebuffers.len(), 1);
+        let old = std::mem::replace(&mut self.framebuffer, framebuffers.remove(0));
+        factory.device().destroy_framebuffer(old);
+        Ok(())
+    }
+
     unsafe fn dispose(self: Box<Self>, factory: &mut Factory<B>, aux: &T) {
         self.common.dispose(factory, aux);
         factory.device().destroy_framebuffer(self.framebuffer);
     }
 }
 
+/// Create view of the image for using as framebuffer attachment.
+fn create_attachment_view<B: Backend>(
+    factory: &Factory<B>,
+    image: &Image<B>,
+    range: rendy_core::hal::image::SubresourceRange,
+) -> Result<B::ImageView, NodeBuildError> {
+    unsafe {
+        factory.device().create_image_view(
+            image.raw(),
+            rendy_core::hal::image::ViewKind::D2,
+            image.format(),
+            rendy_core::hal::format::Swizzle::NO,
+            rendy_core::hal::image::SubresourceRange {
+                // NOTE: Framebuffer must always be created with only one mip level. If image contains multiple levels,
+                // only the first one is bound as an attachment.
+                // TODO: Allow customizing this behaviour to choose which level to bind.
+                levels: 0..1,
+                ..range
+            },
+        )
+    }
+    .map_err(NodeBuildError::View)
+}
+
+/// Get range covering all `NodeImage`s of the attachment image.
+fn attachment_range(images: &[NodeImage], id: ImageId) -> rendy_core::hal::image::SubresourceRange {
+    images
+        .iter()
+        .filter(|image| image.id == id)
+        .map(|image| image.range.clone())
+        .reduce(|acc, range| rendy_core::hal::image::SubresourceRange {
+            aspects: acc.aspects | range.aspects,
+            levels: min(acc.levels.start, range.levels.start)
+                ..max(acc.levels.end, range.levels.end),
+            layers: min(acc.layers.start, range.layers.start)
+                ..max(acc.layers.end, range.layers.end),
+        })
+        .expect("Attachment image wasn't provided")
+}
+
+/// Create views of the swapchain images for using as framebuffer attachments.
+fn create_target_views<B: Backend>(
+    factory: &Factory<B>,
+    target: &Target<B>,
+) -> Result<Vec<B::ImageView>, NodeBuildError> {
+    target
+        .backbuffer()
+        .iter()
+        .map(|image| unsafe {
+            factory
+                .device()
+                .create_image_view(
+                    image.raw(),
+                    rendy_core::hal::image::ViewKind::D2,
+                    image.format(),
+                    rendy_core::hal::format::Swizzle::NO,
+                    rendy_core::hal::image::SubresourceRange {
+                        aspects: image.format().surface_desc().aspects,
+                        levels: 0..1,
+                        layers: 0..1,
+                    },
+                )
+                .map_err(NodeBuildError::View)
+        })
+        .collect()
+}
+
+/// Create framebuffers for render pass.
+/// Swapchain image views, if any, are last ones and get framebuffer each.
+fn create_framebuffers<B: Backend>(
+    factory: &Factory<B>,
+    render_pass: &B::RenderPass,
+    views: &[B::ImageView],
+    attachments: usize,
+    extent: rendy_core::hal::image::Extent,
+) -> Result<Vec<B::Framebuffer>, NodeBuildError> {
+    log::trace!("Create {} framebuffers", views.len() - attachments + 1);
+
+    (attachments - 1..views.len())
+        .map(|i| unsafe {
+            log::trace!(
+                "Create framebuffer for views {}..{} and {}",
+                0,
+                attachments - 1,
+                i,
+            );
+            factory
+                .device()
+                .create_framebuffer(
+                    render_pass,
+                    views[..attachments - 1].iter().chain(Some(&views[i])),
+                    // Depth is gfx-hal BUG as this parameter actually means framebuffer layers number.
+                    extent,
+                )
+                .map_err(NodeBuildError::OutOfMemory)
+        })
+        .collect()
+}
+
+/// Record acquire and release barriers for resources of the node.
+fn record_barriers<B: Backend>(
+    ctx: &GraphContext<B>,
+    factory: &Factory<B>,
+    command_pool: &mut CommandPool<B, Graphics, IndividualReset>,
+    buffers: &[NodeBuffer],
+    images: &[NodeImage],
+) -> (Option<BarriersCommands<B>>, Option<BarriersCommands<B>>) {
+    if !uses_pipeline_barriers::<B>(factory.device()) {
+        return (None, None);
+    }
+
+    let mut record = |name: &str,
+                      (stages, barriers): (
+        std::ops::Range<rendy_core::hal::pso::PipelineStage>,
+        Vec<rendy_core::hal::memory::Barrier<'_, B>>,
+    )| {
+        if barriers.is_empty() {
+            return None;
+        }
+
+        let initial = command_pool.allocate_buffers(1).pop().unwrap();
+        let mut recording = initial.begin(MultiShot(SimultaneousUse), ());
+        log::debug!("{} {:?} : {:#?}", name, stages, barriers);
+        unsafe {
+            recording.encoder().pipeline_barrier(
+                stages,
+                rendy_core::hal::memory::Dependencies::empty(),
+                barriers,
+            );
+        }
+        let (submit, buffer) = recording.finish().submit();
+        Some(BarriersCommands { buffer, submit })
+    };
+
+    let acquire = record("Acquire", gfx_acquire_barriers(ctx, buffers, images));
+    let release = record("Release", gfx_release_barriers(ctx, buffers, images));
+    (acquire, release)
+}
+
 fn common_layout(acc: Layout, layout: Layout) -> Layout {
     match (acc, layout) {
         (Layout::Undefined, layout) => layout,
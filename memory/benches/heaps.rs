@@ -0,0 +1,102 @@
+//! Compares allocation throughput of `Heaps` shared between threads
+//! behind a single lock with `Heaps` using sharded allocators.
+//!
+//! Run with `cargo bench -p rendy-memory --bench heaps`.
+
+use {
+    gfx_hal::memory::Properties,
+    parking_lot::Mutex,
+    rendy_fake::{FakeBackend, FakeDevice},
+    rendy_memory::{Data, DynamicConfig, Heaps, HeapsConfig, MemoryBlock, ShardsConfig},
+    std::{
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+const MB: u64 = 1024 * 1024;
+const THREADS: usize = 8;
+const ITERATIONS: usize = 20_000;
+
+fn heaps(shards: Option<ShardsConfig>) -> Heaps<FakeBackend> {
+    let config = HeapsConfig {
+        linear: None,
+        dynamic: Some(DynamicConfig {
+            block_size_granularity: 256,
+            max_chunk_size: 4 * MB,
+            min_device_allocation: MB,
+        }),
+        tlsf: None,
+        shards,
+        pool: None,
+    };
+    unsafe {
+        Heaps::new(
+            vec![(Properties::DEVICE_LOCAL, 0, config)],
+            vec![4096 * MB],
+            64,
+            1,
+        )
+    }
+}
+
+/// Run `ITERATIONS` of allocations and frees on each of `THREADS` threads.
+fn run(
+    alloc: impl Fn(u64) -> MemoryBlock<FakeBackend> + Sync,
+    free: impl Fn(MemoryBlock<FakeBackend>) + Sync,
+) -> Duration {
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            scope.spawn(|| {
+                let mut blocks = Vec::with_capacity(64);
+                for i in 0..ITERATIONS {
+                    blocks.push(alloc(256 * (i as u64 % 16 + 1)));
+                    if blocks.len() == 64 {
+                        for block in blocks.drain(..) {
+                            free(block);
+                        }
+                    }
+                }
+                for block in blocks {
+                    free(block);
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn main() {
+    let device = FakeDevice::new();
+
+    let locked = Mutex::new(heaps(None));
+    let elapsed = run(
+        |size| {
+            locked
+                .lock()
+                .allocate(&device, !0, Data, size, 256)
+                .unwrap()
+        },
+        |block| locked.lock().free(&device, block),
+    );
+    println!("locked:  {:?}", elapsed);
+    locked.into_inner().dispose(&device);
+
+    let sharded = heaps(Some(ShardsConfig {
+        count: THREADS,
+        max_size: MB,
+    }));
+    let elapsed = run(
+        |size| sharded.allocate(&device, !0, Data, size, 256).unwrap(),
+        |block| sharded.free(&device, block),
+    );
+    println!("sharded: {:?}", elapsed);
+    sharded.dispose(&device);
+
+    let stats = device.stats();
+    println!(
+        "device allocations: {}, frees: {}",
+        stats.allocations, stats.frees
+    );
+}
@@ -96,7 +96,7 @@ where
                 ))
             } else {
                 self.unmap(device);
-                let segment = Segment { offset: mapping_range.start, size: Some(mapping_range.end) };
+                let segment = Segment { offset: mapping_range.start, size: Some(mapping_range.end - mapping_range.start) };
                 let ptr = device.map_memory(self.memory.raw(), segment)?;
                 let ptr = NonNull::new(ptr).expect("Memory mapping shouldn't return nullptr");
                 let mapping =
@@ -11,6 +11,7 @@ use {
         block::Block,
         mapping::*,
         memory::*,
+        usage::ResourceKind,
         util::*,
     },
     gfx_hal::{device::Device as _, Backend, memory::Segment},
@@ -20,6 +21,7 @@ use {
 /// Memory block allocated from `DynamicAllocator`
 #[derive(Debug)]
 pub struct DynamicBlock<B: Backend> {
+    class: usize,
     block_index: u32,
     chunk_index: u32,
     count: u32,
@@ -45,6 +47,21 @@ where
         self.range.end - self.range.start
     }
 
+    /// Get placement class, size of blocks
+    /// and index of the chunk this block was allocated from.
+    pub(crate) fn chunk(&self) -> (usize, u64, u32) {
+        (
+            self.class,
+            self.size() / self.count as u64,
+            self.chunk_index,
+        )
+    }
+
+    /// Get number of chunk blocks occupied by this block.
+    pub(crate) fn count(&self) -> u32 {
+        self.count
+    }
+
     fn dispose(self) {
         self.relevant.dispose();
     }
@@ -147,14 +164,42 @@ pub struct DynamicAllocator<B: Backend> {
     /// Minimum size of device allocation.
     min_device_allocation: u64,
 
+    /// Chunks of each placement class.
+    /// Blocks of different classes never share memory objects.
+    classes: [Class<B>; CLASSES],
+    non_coherent_atom_size: u64,
+
+    /// Granularity at which linear and non-linear resources
+    /// must not be placed next to each other.
+    buffer_image_granularity: u64,
+}
+
+/// Chunks of blocks of the same placement class.
+#[derive(Debug)]
+struct Class<B: Backend> {
     /// Chunk lists.
     sizes: HashMap<u64, SizeEntry<B>>,
 
     /// Ordered set of sizes that have allocated chunks.
     chunks: BTreeSet<u64>,
-    non_coherent_atom_size: u64,
 }
 
+impl<B> Default for Class<B>
+where
+    B: Backend,
+{
+    fn default() -> Self {
+        Class {
+            sizes: HashMap::new(),
+            chunks: BTreeSet::new(),
+        }
+    }
+}
+
+/// Number of placement classes.
+/// Blocks without resource kind, linear and non-linear resources.
+const CLASSES: usize = 3;
+
 unsafe impl<B> Send for DynamicAllocator<B> where B: Backend {}
 unsafe impl<B> Sync for DynamicAllocator<B> where B: Backend {}
 
@@ -247,9 +292,33 @@ where
             block_size_granularity,
             max_chunk_size: config.max_chunk_size,
             min_device_allocation: config.min_device_allocation,
-            sizes: HashMap::new(),
-            chunks: BTreeSet::new(),
+            classes: Default::default(),
             non_coherent_atom_size,
+            buffer_image_granularity: 1,
+        }
+    }
+
+    /// Place linear and non-linear resources into separate chunks
+    /// if `buffer_image_granularity` is larger than block size granularity.
+    /// Otherwise all blocks are aligned to the granularity anyway.
+    pub fn with_buffer_image_granularity(mut self, buffer_image_granularity: u64) -> Self {
+        assert!(
+            buffer_image_granularity.is_power_of_two(),
+            "Buffer-image granularity must be power of two"
+        );
+        self.buffer_image_granularity = buffer_image_granularity;
+        self
+    }
+
+    /// Get placement class for blocks of `resource` kind.
+    fn class(&self, resource: Option<ResourceKind>) -> usize {
+        if self.buffer_image_granularity <= self.block_size_granularity {
+            return 0;
+        }
+        match resource {
+            None => 0,
+            Some(ResourceKind::Linear) => 1,
+            Some(ResourceKind::NonLinear) => 2,
         }
     }
 
@@ -308,6 +377,7 @@ where
     fn alloc_chunk(
         &mut self,
         device: &B::Device,
+        class: usize,
         block_size: u64,
         total_blocks: u64,
     ) -> Result<(Chunk<B>, u64), gfx_hal::device::AllocationError> {
@@ -331,20 +401,21 @@ where
             return Ok((chunk, min_size));
         }
 
-        if let Some(&chunk_size) = self
+        if let Some(&chunk_size) = self.classes[class]
             .chunks
             .range(min_chunk_size..=max_chunk_size)
             .next_back()
         {
             // Allocate block for the chunk.
-            let (block, allocated) = self.alloc_from_entry(device, chunk_size, 1, block_size)?;
+            let (block, allocated) =
+                self.alloc_from_entry(device, class, chunk_size, 1, block_size)?;
             Ok((Chunk::from_block(block_size, block), allocated))
         } else {
-            let total_blocks = self.sizes[&block_size].total_blocks;
+            let total_blocks = self.classes[class].sizes[&block_size].total_blocks;
             let chunk_size =
                 (max_chunk_size.min(min_chunk_size.max(total_blocks * block_size)) / 2 + 1)
                     .next_power_of_two();
-            let (block, allocated) = self.alloc_block(device, chunk_size, block_size)?;
+            let (block, allocated) = self.alloc_block(device, class, chunk_size, block_size)?;
             Ok((Chunk::from_block(block_size, block), allocated))
         }
     }
@@ -352,6 +423,7 @@ where
     /// Allocate blocks from particular chunk.
     fn alloc_from_chunk(
         chunks: &mut slab::Slab<Chunk<B>>,
+        class: usize,
         chunk_index: u32,
         block_size: u64,
         count: u32,
@@ -373,6 +445,7 @@ where
         Some(DynamicBlock {
             range: block_range.clone(),
             memory: chunk.shared_memory(),
+            class,
             block_index,
             chunk_index,
             count,
@@ -388,6 +461,7 @@ where
     fn alloc_from_entry(
         &mut self,
         device: &B::Device,
+        class: usize,
         block_size: u64,
         count: u32,
         align: u64,
@@ -399,11 +473,12 @@ where
         );
 
         debug_assert!(count < MIN_BLOCKS_PER_CHUNK);
-        let size_entry = self.sizes.entry(block_size).or_default();
+        let size_entry = self.classes[class].sizes.entry(block_size).or_default();
 
         for chunk_index in (&size_entry.ready_chunks).iter() {
             if let Some(block) = Self::alloc_from_chunk(
                 &mut size_entry.chunks,
+                class,
                 chunk_index,
                 block_size,
                 count,
@@ -418,12 +493,13 @@ where
         }
 
         let total_blocks = size_entry.total_blocks;
-        let (chunk, allocated) = self.alloc_chunk(device, block_size, total_blocks)?;
-        let size_entry = self.sizes.entry(block_size).or_default();
+        let (chunk, allocated) = self.alloc_chunk(device, class, block_size, total_blocks)?;
+        let size_entry = self.classes[class].sizes.entry(block_size).or_default();
         let chunk_index = size_entry.chunks.insert(chunk) as u32;
 
         let block = Self::alloc_from_chunk(
             &mut size_entry.chunks,
+            class,
             chunk_index,
             block_size,
             count,
@@ -442,25 +518,28 @@ where
     fn alloc_block(
         &mut self,
         device: &B::Device,
+        class: usize,
         block_size: u64,
         align: u64,
     ) -> Result<(DynamicBlock<B>, u64), gfx_hal::device::AllocationError> {
         log::trace!("Allocate block of size {}", block_size);
 
         debug_assert_eq!(block_size % self.block_size_granularity, 0);
-        let size_entry = self.sizes.entry(block_size).or_default();
+        let class_entry = &mut self.classes[class];
+        let size_entry = class_entry.sizes.entry(block_size).or_default();
         size_entry.total_blocks += 1;
 
         let overhead = (MIN_BLOCKS_PER_CHUNK as u64 - 1) / size_entry.total_blocks;
 
         if overhead >= 1 {
-            if let Some(&size) = self
+            if let Some(&size) = class_entry
                 .chunks
                 .range(block_size / 4..block_size * overhead)
                 .next()
             {
                 return self.alloc_from_entry(
                     device,
+                    class,
                     size,
                     ((block_size - 1) / size + 1) as u32,
                     align,
@@ -469,10 +548,10 @@ where
         }
 
         if size_entry.total_blocks == MIN_BLOCKS_PER_CHUNK as u64 {
-            self.chunks.insert(block_size);
+            class_entry.chunks.insert(block_size);
         }
 
-        self.alloc_from_entry(device, block_size, 1, align)
+        self.alloc_from_entry(device, class, block_size, 1, align)
     }
 
     fn free_chunk(&mut self, device: &B::Device, chunk: Chunk<B>, block_size: u64) -> u64 {
@@ -501,7 +580,7 @@ where
         log::trace!("Free block: {:#?}", block);
 
         let block_size = block.size() / block.count as u64;
-        let ref mut size_entry = self
+        let ref mut size_entry = self.classes[block.class]
             .sizes
             .get_mut(&block_size)
             .expect("Unable to get size entry from which block was allocated");
@@ -516,19 +595,60 @@ where
             let chunk = size_entry.chunks.remove(chunk_index as usize);
             self.free_chunk(device, chunk, block_size)
         } else {
-            size_entry.ready_chunks.add(chunk_index);
+            if !chunk.retired {
+                size_entry.ready_chunks.add(chunk_index);
+            }
             0
         }
     }
 
+    /// Get number of used blocks, total number of blocks and size of the chunk.
+    pub(crate) fn chunk_usage(
+        &self,
+        class: usize,
+        block_size: u64,
+        chunk_index: u32,
+    ) -> (u32, u32, u64) {
+        let chunk = &self.classes[class].sizes[&block_size].chunks[chunk_index as usize];
+        let total = chunk.total_blocks(block_size);
+        (total - chunk.blocks.count_ones(), total, chunk.size())
+    }
+
+    /// Stop allocating blocks from the chunk.
+    /// Chunk is freed as usual when all its blocks are freed.
+    pub(crate) fn retire_chunk(&mut self, class: usize, block_size: u64, chunk_index: u32) {
+        let size_entry = self.classes[class]
+            .sizes
+            .get_mut(&block_size)
+            .expect("Unable to get size entry of the chunk");
+        size_entry.chunks[chunk_index as usize].retired = true;
+        size_entry.ready_chunks.remove(chunk_index);
+    }
+
+    /// Allocate blocks from the retired chunk again.
+    /// Does nothing if chunk was already freed.
+    pub(crate) fn restore_chunk(&mut self, class: usize, block_size: u64, chunk_index: u32) {
+        let size_entry = match self.classes[class].sizes.get_mut(&block_size) {
+            Some(size_entry) => size_entry,
+            None => return,
+        };
+        if let Some(chunk) = size_entry.chunks.get_mut(chunk_index as usize) {
+            chunk.retired = false;
+            if !chunk.is_exhausted() {
+                size_entry.ready_chunks.add(chunk_index);
+            }
+        }
+    }
+
     /// Perform full cleanup of the memory allocated.
     pub fn dispose(self) {
+        let sizes = IntoIterator::into_iter(self.classes).flat_map(|class| class.sizes);
         if !thread::panicking() {
-            for (index, size) in self.sizes {
+            for (index, size) in sizes {
                 assert_eq!(size.chunks.len(), 0, "SizeEntry({}) is still used", index);
             }
         } else {
-            for (index, size) in self.sizes {
+            for (index, size) in sizes {
                 if size.chunks.len() != 0 {
                     log::error!("Memory leak: SizeEntry({}) is still used", index);
                 }
@@ -552,6 +672,28 @@ where
         device: &B::Device,
         size: u64,
         align: u64,
+    ) -> Result<(DynamicBlock<B>, u64), gfx_hal::device::AllocationError> {
+        self.alloc_resource(device, size, align, None)
+    }
+
+    fn free(&mut self, device: &B::Device, block: DynamicBlock<B>) -> u64 {
+        self.free_block(device, block)
+    }
+}
+
+impl<B> DynamicAllocator<B>
+where
+    B: Backend,
+{
+    /// Allocate block of memory for resource of specified kind.
+    /// Blocks for linear and non-linear resources are placed
+    /// according to buffer-image granularity.
+    pub fn alloc_resource(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+        resource: Option<ResourceKind>,
     ) -> Result<(DynamicBlock<B>, u64), gfx_hal::device::AllocationError> {
         debug_assert!(size <= self.max_allocation());
         debug_assert!(align.is_power_of_two());
@@ -573,11 +715,8 @@ where
             self.memory_type.0
         );
 
-        self.alloc_block(device, aligned_size, align)
-    }
-
-    fn free(&mut self, device: &B::Device, block: DynamicBlock<B>) -> u64 {
-        self.free_block(device, block)
+        let class = self.class(resource);
+        self.alloc_block(device, class, aligned_size, align)
     }
 }
 
@@ -595,6 +734,9 @@ enum ChunkFlavor<B: Backend> {
 struct Chunk<B: Backend> {
     flavor: ChunkFlavor<B>,
     blocks: u64,
+
+    /// Retired chunks are not used for new allocations.
+    retired: bool,
 }
 
 impl<B> Chunk<B>
@@ -610,6 +752,7 @@ where
         Chunk {
             flavor: ChunkFlavor::Dedicated(Box::new(memory), mapping),
             blocks: (high_bit - 1) | high_bit,
+            retired: false,
         }
     }
 
@@ -621,6 +764,7 @@ where
         Chunk {
             flavor: ChunkFlavor::Dynamic(chunk_block),
             blocks: (high_bit - 1) | high_bit,
+            retired: false,
         }
     }
 
@@ -652,6 +796,11 @@ where
         start..end
     }
 
+    /// Get number of blocks in the chunk.
+    fn total_blocks(&self, block_size: u64) -> u32 {
+        (self.size() / block_size).min(MAX_BLOCKS_PER_CHUNK as u64) as u32
+    }
+
     /// Check if there are free blocks.
     fn is_unused(&self, block_size: u64) -> bool {
         let blocks = (self.size() / block_size).min(MAX_BLOCKS_PER_CHUNK as u64);
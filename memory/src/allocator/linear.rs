@@ -6,6 +6,7 @@ use {
         block::Block,
         mapping::*,
         memory::*,
+        usage::ResourceKind,
         util::*,
     },
     gfx_hal::{device::Device as _, Backend, memory::Segment},
@@ -136,6 +137,7 @@ pub struct LinearAllocator<B: Backend> {
     offset: u64,
     lines: VecDeque<Line<B>>,
     non_coherent_atom_size: u64,
+    buffer_image_granularity: u64,
 }
 
 #[derive(Debug)]
@@ -144,6 +146,9 @@ struct Line<B: Backend> {
     free: u64,
     memory: Arc<Memory<B>>,
     ptr: NonNull<u8>,
+
+    /// Kind of resource of the last block allocated from the line.
+    resource: Option<ResourceKind>,
 }
 
 unsafe impl<B> Send for Line<B> where B: Backend {}
@@ -195,9 +200,21 @@ where
             offset: 0,
             lines: VecDeque::new(),
             non_coherent_atom_size,
+            buffer_image_granularity: 1,
         }
     }
 
+    /// Pad blocks for linear and non-linear resources placed one after another
+    /// to `buffer_image_granularity`.
+    pub fn with_buffer_image_granularity(mut self, buffer_image_granularity: u64) -> Self {
+        assert!(
+            buffer_image_granularity.is_power_of_two(),
+            "Buffer-image granularity must be power of two"
+        );
+        self.buffer_image_granularity = buffer_image_granularity;
+        self
+    }
+
     /// Perform full cleanup of the memory allocated.
     pub fn dispose(mut self, device: &B::Device) {
         let _ = self.cleanup(device, 0);
@@ -251,6 +268,44 @@ where
         device: &B::Device,
         size: u64,
         align: u64,
+    ) -> Result<(LinearBlock<B>, u64), gfx_hal::device::AllocationError> {
+        self.alloc_resource(device, size, align, None)
+    }
+
+    fn free(&mut self, device: &B::Device, block: Self::Block) -> u64 {
+        let index = block.linear_index - self.offset;
+        assert!(
+            fits_usize(index),
+            "This can't exceed lines list length which fits into usize by definition"
+        );
+        let index = index as usize;
+        assert!(
+            index < self.lines.len(),
+            "Can't be allocated from not yet created line"
+        );
+        {
+            let ref mut line = self.lines[index];
+            line.free += block.size();
+        }
+        block.dispose();
+
+        self.cleanup(device, 1)
+    }
+}
+
+impl<B> LinearAllocator<B>
+where
+    B: Backend,
+{
+    /// Allocate block of memory for resource of specified kind.
+    /// Block is padded to buffer-image granularity
+    /// if previous block in the line is for resource of different kind.
+    pub fn alloc_resource(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+        resource: Option<ResourceKind>,
     ) -> Result<(LinearBlock<B>, u64), gfx_hal::device::AllocationError> {
         debug_assert!(self
             .memory_properties
@@ -269,12 +324,19 @@ where
         assert!(align <= self.linear_size);
 
         let count = self.lines.len() as u64;
+        let granularity = self.buffer_image_granularity;
         if let Some(line) = self.lines.back_mut() {
+            let align = if line.resource != resource {
+                align.max(granularity)
+            } else {
+                align
+            };
             let aligned_offset = aligned(line.used, align);
             let overhead = aligned_offset - line.used;
             if self.linear_size - size > aligned_offset {
                 line.used = aligned_offset + size;
                 line.free += overhead;
+                line.resource = resource;
 
                 let range = aligned_offset..aligned_offset + size;
 
@@ -321,6 +383,7 @@ where
             free: 0,
             ptr,
             memory: Arc::new(memory),
+            resource,
         };
 
         let block = LinearBlock {
@@ -334,24 +397,4 @@ where
         self.lines.push_back(line);
         Ok((block, self.linear_size))
     }
-
-    fn free(&mut self, device: &B::Device, block: Self::Block) -> u64 {
-        let index = block.linear_index - self.offset;
-        assert!(
-            fits_usize(index),
-            "This can't exceed lines list length which fits into usize by definition"
-        );
-        let index = index as usize;
-        assert!(
-            index < self.lines.len(),
-            "Can't be allocated from not yet created line"
-        );
-        {
-            let ref mut line = self.lines[index];
-            line.free += block.size();
-        }
-        block.dispose();
-
-        self.cleanup(device, 1)
-    }
 }
@@ -3,6 +3,9 @@
 mod dedicated;
 mod dynamic;
 mod linear;
+mod pool;
+mod ring;
+mod tlsf;
 
 use crate::block::Block;
 
@@ -10,6 +13,9 @@ pub use self::{
     dedicated::{DedicatedAllocator, DedicatedBlock},
     dynamic::{DynamicAllocator, DynamicBlock, DynamicConfig},
     linear::{LinearAllocator, LinearBlock, LinearConfig},
+    pool::{PoolAllocator, PoolBlock, PoolConfig},
+    ring::{RingAllocator, RingConfig},
+    tlsf::{TlsfAllocator, TlsfBlock, TlsfConfig},
 };
 
 /// Allocator kind.
@@ -25,6 +31,15 @@ pub enum Kind {
     /// Fast and low overhead.
     /// Suitable for one-time-use allocations.
     Linear,
+
+    /// Two-level segregated fit allocator.
+    /// Constant time allocation and low fragmentation.
+    Tlsf,
+
+    /// Allocates fixed-size slots.
+    /// Constant time allocation and no internal fragmentation.
+    /// Used only when requested explicitly with `Heaps::allocate_pooled`.
+    Pool,
 }
 
 /// Allocator trait implemented for various allocators.
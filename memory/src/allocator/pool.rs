@@ -0,0 +1,475 @@
+use std::{ops::Range, ptr::NonNull, thread};
+
+use {
+    crate::{
+        allocator::{Allocator, Kind},
+        block::Block,
+        mapping::*,
+        memory::*,
+        util::*,
+    },
+    gfx_hal::{device::Device as _, memory::Segment, Backend},
+    hibitset::{BitSet, BitSetLike as _},
+};
+
+/// Memory block allocated from `PoolAllocator`
+#[derive(Debug)]
+pub struct PoolBlock<B: Backend> {
+    slot_index: u32,
+    memory: *const Memory<B>,
+    ptr: Option<NonNull<u8>>,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+unsafe impl<B> Send for PoolBlock<B> where B: Backend {}
+unsafe impl<B> Sync for PoolBlock<B> where B: Backend {}
+
+impl<B> PoolBlock<B>
+where
+    B: Backend,
+{
+    fn shared_memory(&self) -> &Memory<B> {
+        // Memory won't be freed until last block created from it deallocated.
+        unsafe { &*self.memory }
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+impl<B> Block<B> for PoolBlock<B>
+where
+    B: Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.shared_memory().properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.shared_memory().raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &B::Device,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::device::MapError> {
+        debug_assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+
+        if !self.shared_memory().host_visible() {
+            //TODO: invalid access error
+            return Err(gfx_hal::device::MapError::MappingFailed);
+        }
+
+        let requested_range = relative_to_sub_range(self.range.clone(), range)
+            .ok_or(gfx_hal::device::MapError::OutOfBounds)?;
+
+        let mapping_range = if !self.shared_memory().host_coherent() {
+            align_range(
+                requested_range.clone(),
+                self.shared_memory().non_coherent_atom_size(),
+            )
+        } else {
+            requested_range.clone()
+        };
+
+        if let Some(ptr) = self.ptr {
+            let ptr = mapped_sub_range(ptr, self.range.clone(), mapping_range.clone()).unwrap();
+            let mapping = unsafe {
+                MappedRange::from_raw(self.shared_memory(), ptr, mapping_range, requested_range)
+            };
+            Ok(mapping)
+        } else {
+            Err(gfx_hal::device::MapError::MappingFailed)
+        }
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &B::Device) {}
+}
+
+/// Config for `PoolAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolConfig {
+    /// Size of memory objects slots are carved from.
+    /// Chunks hold at least one slot.
+    pub chunk_size: u64,
+}
+
+/// Allocator of fixed-size slots.
+///
+/// Carves slots out of large memory objects (chunks)
+/// and tracks free slots of all chunks in a bitmap.
+/// Both allocation and deallocation take constant time
+/// and every byte of the slot is available to the block.
+///
+/// Suitable for many allocations of the same size,
+/// like per-object constants or particle chunks.
+/// Empty chunks are returned to the device,
+/// except for the last one which is kept to avoid thrashing.
+#[derive(Debug)]
+pub struct PoolAllocator<B: Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of every block.
+    slot_size: u64,
+
+    /// Number of slots in each chunk.
+    slots_per_chunk: u32,
+
+    /// Bits of free slots. Slot `n` of chunk `c` is at index `c * slots_per_chunk + n`.
+    free_slots: BitSet,
+
+    /// List of chunks.
+    chunks: slab::Slab<PoolChunk<B>>,
+    non_coherent_atom_size: u64,
+}
+
+unsafe impl<B> Send for PoolAllocator<B> where B: Backend {}
+unsafe impl<B> Sync for PoolAllocator<B> where B: Backend {}
+
+#[derive(Debug)]
+struct PoolChunk<B: Backend> {
+    memory: Box<Memory<B>>,
+    ptr: Option<NonNull<u8>>,
+
+    /// Number of allocated slots.
+    used: u32,
+}
+
+impl<B> PoolAllocator<B>
+where
+    B: Backend,
+{
+    /// Get size of slots that blocks of `size` and `align` are allocated from
+    /// in memory with `memory_properties` specified.
+    pub fn slot_size(
+        memory_properties: gfx_hal::memory::Properties,
+        size: u64,
+        align: u64,
+        non_coherent_atom_size: u64,
+    ) -> u64 {
+        let slot_size = aligned(size, align);
+        if is_non_coherent_visible(memory_properties) {
+            align_size(slot_size, non_coherent_atom_size)
+        } else {
+            slot_size
+        }
+    }
+
+    /// Create new `PoolAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `PoolConfig` provided.
+    /// All blocks are allocated from slots of `slot_size` bytes.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        slot_size: u64,
+        config: PoolConfig,
+        non_coherent_atom_size: u64,
+    ) -> Self {
+        log::trace!(
+            "Create new 'pool' allocator: type: '{:?}', properties: '{:#?}', slot size: '{}', config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            slot_size,
+            config
+        );
+        assert_ne!(slot_size, 0, "Slot size must be greater than 0");
+        let slot_size = Self::slot_size(memory_properties, slot_size, 1, non_coherent_atom_size);
+        let slots_per_chunk = (config.chunk_size / slot_size).max(1);
+        assert!(
+            slots_per_chunk <= max_slots() as u64,
+            "Number of slots per chunk must fit in free slots bitmap"
+        );
+        if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
+            assert!(
+                fits_usize(slot_size * slots_per_chunk),
+                "Chunk size must fit usize for mapping"
+            );
+        }
+
+        PoolAllocator {
+            memory_type,
+            memory_properties,
+            slot_size,
+            slots_per_chunk: slots_per_chunk as u32,
+            free_slots: BitSet::new(),
+            chunks: slab::Slab::new(),
+            non_coherent_atom_size,
+        }
+    }
+
+    /// Size of blocks allocated from this pool.
+    pub fn block_size(&self) -> u64 {
+        self.slot_size
+    }
+
+    /// Size of chunks allocated from device.
+    pub fn chunk_size(&self) -> u64 {
+        self.slot_size * self.slots_per_chunk as u64
+    }
+
+    /// Allocate memory chunk from device and add its slots to the free list.
+    fn alloc_chunk(
+        &mut self,
+        device: &B::Device,
+    ) -> Result<usize, gfx_hal::device::AllocationError> {
+        let chunk_index = self.chunks.vacant_entry().key();
+        if (chunk_index + 1) * self.slots_per_chunk as usize > max_slots() {
+            return Err(gfx_hal::device::OutOfMemory::Host.into());
+        }
+
+        let chunk_size = self.chunk_size();
+        log::trace!(
+            "Allocate chunk of size: {} for slots of size {} from device",
+            chunk_size,
+            self.slot_size
+        );
+
+        let (memory, ptr) = unsafe {
+            // Valid memory type specified.
+            let raw = device.allocate_memory(self.memory_type, chunk_size)?;
+
+            let ptr = if self
+                .memory_properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+            {
+                let segment = Segment {
+                    offset: 0,
+                    size: Some(chunk_size),
+                };
+                match device.map_memory(&raw, segment) {
+                    Ok(ptr) => Some(NonNull::new_unchecked(ptr)),
+                    Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                        device.free_memory(raw);
+                        return Err(error.into());
+                    }
+                    Err(_) => panic!("Unexpected mapping failure"),
+                }
+            } else {
+                None
+            };
+            let memory = Memory::from_raw(
+                raw,
+                chunk_size,
+                self.memory_properties,
+                self.non_coherent_atom_size,
+            );
+            (memory, ptr)
+        };
+
+        self.chunks.insert(PoolChunk {
+            memory: Box::new(memory),
+            ptr,
+            used: 0,
+        });
+        for slot_index in self.chunk_slots(chunk_index) {
+            self.free_slots.add(slot_index);
+        }
+        Ok(chunk_index)
+    }
+
+    fn free_chunk(&mut self, device: &B::Device, chunk_index: usize) -> u64 {
+        for slot_index in self.chunk_slots(chunk_index) {
+            self.free_slots.remove(slot_index);
+        }
+        let chunk = self.chunks.remove(chunk_index);
+        log::trace!("Free chunk: {:#?}", chunk);
+        debug_assert_eq!(chunk.used, 0);
+        let size = chunk.memory.size();
+        unsafe {
+            if chunk.ptr.is_some() {
+                device.unmap_memory(chunk.memory.raw());
+            }
+            device.free_memory(chunk.memory.into_raw());
+        }
+        size
+    }
+
+    /// Get indices of all slots of the chunk.
+    fn chunk_slots(&self, chunk_index: usize) -> Range<u32> {
+        let start = chunk_index as u32 * self.slots_per_chunk;
+        start..start + self.slots_per_chunk
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(mut self, device: &B::Device) {
+        let used = self.chunks.iter().any(|(_, chunk)| chunk.used > 0);
+        if used {
+            if !thread::panicking() {
+                panic!("Pool of slots of size {} is still used", self.slot_size);
+            } else {
+                log::error!(
+                    "Memory leak: pool of slots of size {} is still used",
+                    self.slot_size
+                );
+            }
+        } else {
+            let chunks: Vec<_> = self.chunks.iter().map(|(index, _)| index).collect();
+            for chunk_index in chunks {
+                self.free_chunk(device, chunk_index);
+            }
+        }
+    }
+}
+
+impl<B> Allocator<B> for PoolAllocator<B>
+where
+    B: Backend,
+{
+    type Block = PoolBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Pool
+    }
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(PoolBlock<B>, u64), gfx_hal::device::AllocationError> {
+        assert!(size <= self.slot_size, "Block must fit into slot");
+        assert_eq!(
+            aligned(self.slot_size, align),
+            self.slot_size,
+            "Slot size must be multiple of alignment"
+        );
+
+        let (slot_index, allocated) = match (&self.free_slots).iter().next() {
+            Some(slot_index) => (slot_index, 0),
+            None => {
+                let chunk_index = self.alloc_chunk(device)?;
+                (self.chunk_slots(chunk_index).start, self.chunk_size())
+            }
+        };
+        self.free_slots.remove(slot_index);
+
+        let chunk_index = (slot_index / self.slots_per_chunk) as usize;
+        let offset = (slot_index % self.slots_per_chunk) as u64 * self.slot_size;
+        let range = offset..offset + self.slot_size;
+        let chunk_size = self.chunk_size();
+        let chunk = &mut self.chunks[chunk_index];
+        chunk.used += 1;
+
+        log::trace!(
+            "Allocate pool block: slot {}, size: {}, type: {}",
+            slot_index,
+            self.slot_size,
+            self.memory_type.0
+        );
+
+        Ok((
+            PoolBlock {
+                slot_index,
+                memory: &*chunk.memory,
+                ptr: chunk.ptr.map(|ptr| {
+                    mapped_sub_range(ptr, 0..chunk_size, range.clone())
+                        .expect("Slot must be sub-range of chunk")
+                }),
+                range,
+                relevant: relevant::Relevant,
+            },
+            allocated,
+        ))
+    }
+
+    fn free(&mut self, device: &B::Device, block: PoolBlock<B>) -> u64 {
+        let slot_index = block.slot_index;
+        block.dispose();
+
+        let chunk_index = (slot_index / self.slots_per_chunk) as usize;
+        let chunk = &mut self.chunks[chunk_index];
+        chunk.used -= 1;
+        self.free_slots.add(slot_index);
+
+        if chunk.used == 0 && self.chunks.len() > 1 {
+            self.free_chunk(device, chunk_index)
+        } else {
+            0
+        }
+    }
+}
+
+/// Maximum number of slots free slots bitmap can hold.
+fn max_slots() -> usize {
+    (usize::BITS as usize).pow(4)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{PoolAllocator, PoolConfig},
+        crate::{
+            allocator::Allocator as _,
+            block::Block as _,
+            replay::{FakeBackend, FakeDevice},
+        },
+        gfx_hal::{memory::Properties, MemoryTypeId},
+    };
+
+    #[test]
+    fn slots() {
+        let device = FakeDevice::new();
+        let mut pool: PoolAllocator<FakeBackend> = PoolAllocator::new(
+            MemoryTypeId(0),
+            Properties::DEVICE_LOCAL,
+            256,
+            PoolConfig { chunk_size: 1024 },
+            64,
+        );
+        assert_eq!(pool.chunk_size(), 1024);
+
+        let mut blocks = Vec::new();
+        for index in 0..6 {
+            let (block, allocated) = pool.alloc(&device, 200, 64).unwrap();
+            assert_eq!(allocated, if index % 4 == 0 { 1024 } else { 0 });
+            assert_eq!(block.range(), index % 4 * 256..index % 4 * 256 + 256);
+            blocks.push(block);
+        }
+        assert_eq!(device.stats().allocated, 2048);
+
+        // Freed slot is reused.
+        let block = blocks.remove(1);
+        let range = block.range();
+        assert_eq!(pool.free(&device, block), 0);
+        let (block, allocated) = pool.alloc(&device, 256, 256).unwrap();
+        assert_eq!((block.range(), allocated), (range, 0));
+        blocks.insert(1, block);
+
+        // Empty chunk is freed unless it is the last one.
+        let second: Vec<_> = blocks.drain(4..).collect();
+        let first = blocks;
+        let freed: u64 = first.into_iter().map(|b| pool.free(&device, b)).sum();
+        assert_eq!(freed, 1024);
+        let freed: u64 = second.into_iter().map(|b| pool.free(&device, b)).sum();
+        assert_eq!(freed, 0);
+        assert_eq!(device.stats().allocated, 1024);
+
+        pool.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+}
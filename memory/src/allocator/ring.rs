@@ -0,0 +1,506 @@
+use std::{
+    collections::VecDeque,
+    mem::{align_of, size_of_val},
+    ops::Range,
+    ptr::NonNull,
+};
+
+use {
+    crate::{
+        block::Block as _,
+        heaps::MemoryBlock,
+        mapping::{write::Write, *},
+        memory::*,
+        util::*,
+    },
+    gfx_hal::{device::Device as _, memory::Segment, Backend},
+};
+
+/// Config for `RingAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RingConfig {
+    /// Size of the ring.
+    /// Should fit all data written during frames in flight.
+    pub ring_size: u64,
+}
+
+/// Ring allocator for data that lives for one frame, like per-frame constants.
+///
+/// Allocates single persistently mapped memory object,
+/// or takes memory block allocated from `Heaps`,
+/// and returns its sub-ranges sequentially, wrapping around at the end.
+/// Unlike `LinearAllocator` ranges are not freed one by one.
+/// Instead each range is tagged with frame index
+/// and all ranges of the frame are reclaimed at once when frame is complete.
+///
+/// Buffer bound to the memory object at `offset` can be used
+/// to access allocated ranges from device.
+#[derive(Debug)]
+pub struct RingAllocator<B: Backend> {
+    memory_type: gfx_hal::MemoryTypeId,
+    memory: RingMemory<B>,
+    ring_size: u64,
+    non_coherent_atom_size: u64,
+
+    /// Total number of bytes ever allocated, including padding.
+    head: u64,
+
+    /// Total number of bytes ever reclaimed.
+    tail: u64,
+
+    /// Frame indices with `head` value at the end of each frame.
+    frames: VecDeque<(u64, u64)>,
+}
+
+/// Memory of the ring.
+#[derive(Debug)]
+enum RingMemory<B: Backend> {
+    /// Memory object allocated and mapped by the ring.
+    Owned(Memory<B>, NonNull<u8>),
+
+    /// Block allocated from `Heaps` and mapped as a whole.
+    Block(MemoryBlock<B>),
+}
+
+unsafe impl<B> Send for RingAllocator<B> where B: Backend {}
+unsafe impl<B> Sync for RingAllocator<B> where B: Backend {}
+
+impl<B> RingAllocator<B>
+where
+    B: Backend,
+{
+    /// Get properties required by the `RingAllocator`.
+    pub fn properties_required() -> gfx_hal::memory::Properties {
+        gfx_hal::memory::Properties::CPU_VISIBLE
+    }
+
+    /// Create new `RingAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `RingConfig` provided.
+    /// Memory for the ring is allocated and mapped immediately.
+    pub fn new(
+        device: &B::Device,
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: RingConfig,
+        non_coherent_atom_size: u64,
+    ) -> Result<Self, gfx_hal::device::AllocationError> {
+        log::trace!(
+            "Create new 'ring' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+        let ring_size = if is_non_coherent_visible(memory_properties) {
+            align_size(config.ring_size, non_coherent_atom_size)
+        } else {
+            config.ring_size
+        };
+        assert!(memory_properties.contains(Self::properties_required()));
+        assert!(
+            fits_usize(ring_size),
+            "Ring size must fit in both usize and u64"
+        );
+
+        let (memory, ptr) = unsafe {
+            let raw = device.allocate_memory(memory_type, ring_size)?;
+            let segment = Segment {
+                offset: 0,
+                size: Some(ring_size),
+            };
+            let ptr = match device.map_memory(&raw, segment) {
+                Ok(ptr) => NonNull::new_unchecked(ptr),
+                Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                    device.free_memory(raw);
+                    return Err(error.into());
+                }
+                Err(_) => panic!("Unexpected mapping failure"),
+            };
+
+            let memory =
+                Memory::from_raw(raw, ring_size, memory_properties, non_coherent_atom_size);
+            (memory, ptr)
+        };
+
+        Ok(RingAllocator {
+            memory_type,
+            memory: RingMemory::Owned(memory, ptr),
+            ring_size,
+            non_coherent_atom_size,
+            head: 0,
+            tail: 0,
+            frames: VecDeque::new(),
+        })
+    }
+
+    /// Create new `RingAllocator` on top of memory `block`
+    /// with `RingConfig` provided.
+    ///
+    /// Ring size must not exceed block size.
+    /// Block must be host visible and is mapped as a whole.
+    /// It is returned by `dispose` to be freed by the caller.
+    pub fn from_block(
+        device: &B::Device,
+        mut block: MemoryBlock<B>,
+        config: RingConfig,
+        non_coherent_atom_size: u64,
+    ) -> Result<Self, (gfx_hal::device::MapError, MemoryBlock<B>)> {
+        log::trace!(
+            "Create new 'ring' allocator from block: '{:?}', config: '{:#?}'",
+            block,
+            config
+        );
+        let ring_size = config.ring_size;
+        assert!(
+            ring_size <= block.size(),
+            "Ring size must not exceed block size"
+        );
+        assert!(
+            fits_usize(ring_size),
+            "Ring size must fit in both usize and u64"
+        );
+
+        let size = block.size();
+        if let Err(error) = block.map(device, 0..size).map(drop) {
+            return Err((error, block));
+        }
+
+        Ok(RingAllocator {
+            memory_type: gfx_hal::MemoryTypeId(block.memory_type() as usize),
+            memory: RingMemory::Block(block),
+            ring_size,
+            non_coherent_atom_size,
+            head: 0,
+            tail: 0,
+            frames: VecDeque::new(),
+        })
+    }
+
+    /// Get memory type of the ring.
+    pub fn memory_type(&self) -> gfx_hal::MemoryTypeId {
+        self.memory_type
+    }
+
+    /// Get memory object of the ring.
+    pub fn memory(&self) -> &B::Memory {
+        match &self.memory {
+            RingMemory::Owned(memory, _) => memory.raw(),
+            RingMemory::Block(block) => block.memory(),
+        }
+    }
+
+    /// Get offset of the ring in the memory object.
+    pub fn offset(&self) -> u64 {
+        match &self.memory {
+            RingMemory::Owned(_, _) => 0,
+            RingMemory::Block(block) => block.range().start,
+        }
+    }
+
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        match &self.memory {
+            RingMemory::Owned(memory, _) => memory.properties(),
+            RingMemory::Block(block) => block.properties(),
+        }
+    }
+
+    /// Get size of the ring.
+    pub fn size(&self) -> u64 {
+        self.ring_size
+    }
+
+    /// Get number of bytes allocated and not yet reclaimed.
+    pub fn used(&self) -> u64 {
+        self.head - self.tail
+    }
+
+    /// Allocate range of `size` bytes for `frame`.
+    ///
+    /// Frame indices must not decrease between calls.
+    /// Returns `None` if there is not enough free space in the ring
+    /// until older frames are reclaimed.
+    pub fn allocate(&mut self, frame: u64, size: u64, align: u64) -> Option<Range<u64>> {
+        assert_ne!(size, 0, "Allocation size must be greater than 0");
+        if let Some(&(last, _)) = self.frames.back() {
+            assert!(last <= frame, "Frame indices must not decrease");
+        }
+
+        let (size, align) = if is_non_coherent_visible(self.properties()) {
+            (
+                align_size(size, self.non_coherent_atom_size),
+                align_size(align, self.non_coherent_atom_size),
+            )
+        } else {
+            (size, align)
+        };
+
+        if size > self.ring_size {
+            return None;
+        }
+
+        let position = self.head % self.ring_size;
+        let mut start = aligned(position, align);
+        if start + size > self.ring_size {
+            // Skip the tail of the ring and start from the beginning.
+            start = self.ring_size;
+        }
+
+        let end = self.head - position + start + size;
+        if end - self.tail > self.ring_size {
+            return None;
+        }
+
+        self.head = end;
+        match self.frames.back_mut() {
+            Some((last, end)) if *last == frame => *end = self.head,
+            _ => self.frames.push_back((frame, self.head)),
+        }
+
+        let start = start % self.ring_size;
+        Some(start..start + size)
+    }
+
+    /// Map range allocated from this ring.
+    /// Range is relative to the ring `offset`.
+    ///
+    /// # Safety
+    ///
+    /// `range` must be allocated from this ring and not yet reclaimed.
+    /// `device` must be the same device ring was created with.
+    pub unsafe fn map(&mut self, device: &B::Device, range: Range<u64>) -> MappedRange<'_, B> {
+        assert!(
+            range.start < range.end && range.end <= self.ring_size,
+            "Range must be allocated from this ring"
+        );
+        match &mut self.memory {
+            RingMemory::Owned(memory, ptr) => {
+                let ptr = mapped_sub_range(*ptr, 0..self.ring_size, range.clone())
+                    .expect("Range must fit in ring mapping");
+                MappedRange::from_raw(memory, ptr, range.clone(), range)
+            }
+            RingMemory::Block(block) => block.map(device, range).expect("Block is mapped"),
+        }
+    }
+
+    /// Allocate range for `data` and write it into the ring.
+    /// Non-coherent memory is flushed.
+    /// Returns allocated range
+    /// or `None` if there is not enough free space in the ring.
+    /// Empty `data` is not written and gets empty range.
+    ///
+    /// # Safety
+    ///
+    /// `device` must be the same device ring was created with.
+    pub unsafe fn write<T>(
+        &mut self,
+        device: &B::Device,
+        frame: u64,
+        data: &[T],
+        align: u64,
+    ) -> Option<Range<u64>>
+    where
+        T: Copy,
+    {
+        let size = size_of_val(data) as u64;
+        if size == 0 {
+            let position = self.head % self.ring_size;
+            return Some(position..position);
+        }
+        let align = align.max(align_of::<T>() as u64);
+        let range = self.allocate(frame, size, align)?;
+        let mut mapped = self.map(device, range.clone());
+        mapped
+            .write(device, 0..size)
+            .expect("Range is mapped")
+            .write(data);
+        Some(range.start..range.start + size)
+    }
+
+    /// Reclaim ranges allocated for frames that are complete.
+    /// Frames with index less than `complete_upper_bound` are considered complete,
+    /// as returned by `Frames::complete_upper_bound`.
+    /// Returns number of bytes reclaimed.
+    pub fn reclaim(&mut self, complete_upper_bound: u64) -> u64 {
+        let tail = self.tail;
+        while let Some(&(frame, end)) = self.frames.front() {
+            if frame >= complete_upper_bound {
+                break;
+            }
+            self.frames.pop_front();
+            self.tail = end;
+        }
+        let reclaimed = self.tail - tail;
+        let position = self.head % self.ring_size;
+        if self.frames.is_empty() && position > 0 {
+            // Nothing is in use so ring can be restarted from the beginning.
+            self.head += self.ring_size - position;
+            self.tail = self.head;
+        }
+        reclaimed
+    }
+
+    /// Unmap and free memory of the ring.
+    /// Block the ring was created from is unmapped and returned instead.
+    pub fn dispose(self, device: &B::Device) -> Option<MemoryBlock<B>> {
+        if !self.frames.is_empty() {
+            log::error!(
+                "Frames are not reclaimed during allocator disposal. Frames: {:#?}",
+                self.frames
+            );
+        }
+        match self.memory {
+            RingMemory::Owned(memory, _) => {
+                unsafe {
+                    device.unmap_memory(memory.raw());
+                    device.free_memory(memory.into_raw());
+                }
+                None
+            }
+            RingMemory::Block(mut block) => {
+                block.unmap(device);
+                Some(block)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{RingAllocator, RingConfig},
+        crate::{
+            allocator::DynamicConfig,
+            block::Block as _,
+            heaps::{Heaps, HeapsConfig},
+            replay::{FakeBackend, FakeDevice},
+            usage::Dynamic,
+            Read as _, Write as _,
+        },
+        gfx_hal::{memory::Properties, MemoryTypeId},
+    };
+
+    fn ring(device: &FakeDevice, properties: Properties) -> RingAllocator<FakeBackend> {
+        RingAllocator::new(
+            device,
+            MemoryTypeId(0),
+            properties,
+            RingConfig { ring_size: 1024 },
+            64,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reclaim_by_frame() {
+        let device = FakeDevice::new();
+        let mut ring = ring(&device, Properties::CPU_VISIBLE | Properties::COHERENT);
+        assert_eq!(device.stats().allocated, 1024);
+
+        assert_eq!(ring.allocate(0, 300, 256), Some(0..300));
+        assert_eq!(ring.allocate(0, 100, 4), Some(300..400));
+        assert_eq!(ring.allocate(1, 500, 256), Some(512..1012));
+        // Doesn't fit into the tail, nor into the beginning of the ring.
+        assert_eq!(ring.allocate(2, 100, 4), None);
+
+        assert_eq!(ring.reclaim(1), 400);
+        assert_eq!(ring.allocate(2, 100, 4), Some(0..100));
+        assert_eq!(ring.allocate(2, 400, 4), None);
+        assert_eq!(ring.used(), 724);
+
+        assert_eq!(ring.reclaim(2), 612);
+        assert_eq!(ring.allocate(3, 200, 4), Some(100..300));
+        assert_eq!(ring.reclaim(4), 312);
+        assert_eq!(ring.used(), 0);
+
+        // Ring is restarted once empty.
+        assert_eq!(ring.allocate(4, 1024, 4), Some(0..1024));
+        ring.reclaim(5);
+
+        ring.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn write() {
+        let device = FakeDevice::new();
+        let mut ring = ring(&device, Properties::CPU_VISIBLE);
+
+        let range = unsafe { ring.write(&device, 0, &[1u32, 2, 3], 4) }.unwrap();
+        assert_eq!(range, 0..12);
+        let range = unsafe { ring.write(&device, 0, &[4u32, 5], 4) }.unwrap();
+        // Non-coherent allocations are aligned to atom size.
+        assert_eq!(range, 64..72);
+
+        let mut data = [0; 2];
+        unsafe {
+            let mut mapped = ring.map(&device, 64..128);
+            mapped.write::<u8>(&device, 0..2).unwrap().write(&[6, 7]);
+            mapped.read::<u8>(&device, 0..2).unwrap().read(&mut data);
+        }
+        assert_eq!(data, [6, 7]);
+
+        // Empty data takes no space.
+        let range = unsafe { ring.write::<u32>(&device, 0, &[], 4) }.unwrap();
+        assert!(range.start == range.end);
+        assert_eq!(ring.used(), 128);
+
+        ring.reclaim(1);
+        ring.dispose(&device);
+    }
+
+    #[test]
+    fn from_block() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 64 * 1024,
+                min_device_allocation: 16 * 1024,
+            }),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::CPU_VISIBLE | Properties::COHERENT, 0, config)],
+                vec![1024 * 1024],
+                64,
+                1,
+            )
+        };
+
+        // Ring doesn't start at the beginning of the memory object.
+        let first = heaps.allocate(&device, !0, Dynamic, 1024, 256).unwrap();
+        let block = heaps.allocate(&device, !0, Dynamic, 1024, 256).unwrap();
+        let start = block.range().start;
+        assert_ne!(start, 0);
+
+        let mut ring =
+            RingAllocator::from_block(&device, block, RingConfig { ring_size: 1000 }, 64).unwrap();
+        assert_eq!(ring.offset(), start);
+        assert_eq!(ring.size(), 1000);
+
+        let range = unsafe { ring.write(&device, 0, &[1u32, 2, 3], 4) }.unwrap();
+        assert_eq!(range, 0..12);
+        assert_eq!(ring.allocate(0, 1000, 4), None);
+
+        let mut data = [0u32; 3];
+        unsafe {
+            let mut mapped = ring.map(&device, range);
+            mapped.read::<u32>(&device, 0..12).unwrap().read(&mut data);
+        }
+        assert_eq!(data, [1, 2, 3]);
+
+        // Block is returned to be freed by the caller.
+        ring.reclaim(1);
+        let block = ring.dispose(&device).unwrap();
+        assert_eq!(block.range().start, start);
+        heaps.free(&device, block);
+        heaps.free(&device, first);
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+}
@@ -0,0 +1,803 @@
+use std::{ops::Range, ptr::NonNull, thread};
+
+use {
+    crate::{
+        allocator::{Allocator, Kind},
+        block::Block,
+        mapping::*,
+        memory::*,
+        usage::ResourceKind,
+        util::*,
+    },
+    gfx_hal::{device::Device as _, memory::Segment, Backend},
+};
+
+/// Memory block allocated from `TlsfAllocator`
+#[derive(Debug)]
+pub struct TlsfBlock<B: Backend> {
+    class: usize,
+    span: usize,
+    memory: *const Memory<B>,
+    ptr: Option<NonNull<u8>>,
+    range: Range<u64>,
+    relevant: relevant::Relevant,
+}
+
+unsafe impl<B> Send for TlsfBlock<B> where B: Backend {}
+unsafe impl<B> Sync for TlsfBlock<B> where B: Backend {}
+
+impl<B> TlsfBlock<B>
+where
+    B: Backend,
+{
+    fn shared_memory(&self) -> &Memory<B> {
+        // Memory won't be freed until last block allocated from it is deallocated.
+        unsafe { &*self.memory }
+    }
+
+    fn dispose(self) {
+        self.relevant.dispose();
+    }
+}
+
+impl<B> Block<B> for TlsfBlock<B>
+where
+    B: Backend,
+{
+    #[inline]
+    fn properties(&self) -> gfx_hal::memory::Properties {
+        self.shared_memory().properties()
+    }
+
+    #[inline]
+    fn memory(&self) -> &B::Memory {
+        self.shared_memory().raw()
+    }
+
+    #[inline]
+    fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    #[inline]
+    fn map<'a>(
+        &'a mut self,
+        _device: &B::Device,
+        range: Range<u64>,
+    ) -> Result<MappedRange<'a, B>, gfx_hal::device::MapError> {
+        debug_assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+
+        if !self.shared_memory().host_visible() {
+            //TODO: invalid access error
+            return Err(gfx_hal::device::MapError::MappingFailed);
+        }
+
+        let requested_range = relative_to_sub_range(self.range.clone(), range)
+            .ok_or(gfx_hal::device::MapError::OutOfBounds)?;
+
+        let mapping_range = if !self.shared_memory().host_coherent() {
+            align_range(
+                requested_range.clone(),
+                self.shared_memory().non_coherent_atom_size(),
+            )
+        } else {
+            requested_range.clone()
+        };
+
+        if let Some(ptr) = self.ptr {
+            let ptr = mapped_sub_range(ptr, self.range.clone(), mapping_range.clone()).unwrap();
+            let mapping = unsafe {
+                MappedRange::from_raw(self.shared_memory(), ptr, mapping_range, requested_range)
+            };
+            Ok(mapping)
+        } else {
+            Err(gfx_hal::device::MapError::MappingFailed)
+        }
+    }
+
+    #[inline]
+    fn unmap(&mut self, _device: &B::Device) {}
+}
+
+/// Config for `TlsfAllocator`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlsfConfig {
+    /// All requests are rounded up to multiple of this value.
+    /// Must be power of two.
+    pub block_size_granularity: u64,
+
+    /// Size of memory objects allocated from device.
+    /// Must be power of two.
+    pub arena_size: u64,
+}
+
+/// Two-level segregated fit allocator.
+///
+/// Sub-allocates blocks from big memory objects (arenas).
+/// Free blocks are kept in lists segregated by size classes:
+/// power of two ranges each split into `16` linear sub-ranges.
+/// Non-empty lists are tracked by bitmaps, so both allocation and deallocation
+/// are performed in constant time.
+/// Freed blocks are merged with free neighbours immediately,
+/// which keeps fragmentation low.
+///
+/// Arena is returned to the device as soon as all its blocks are freed.
+///
+/// Blocks for linear and non-linear resources are allocated from separate arenas
+/// if buffer-image granularity is larger than block size granularity.
+#[derive(Debug)]
+pub struct TlsfAllocator<B: Backend> {
+    /// Memory type that this allocator allocates.
+    memory_type: gfx_hal::MemoryTypeId,
+
+    /// Memory properties of the memory type.
+    memory_properties: gfx_hal::memory::Properties,
+
+    /// Size of memory objects allocated from device.
+    arena_size: u64,
+
+    /// Arenas allocated from device.
+    arenas: slab::Slab<Arena<B>>,
+
+    /// Spans of arenas and free lists of each placement class.
+    /// Arenas are never shared between classes.
+    classes: [Tlsf; CLASSES],
+
+    non_coherent_atom_size: u64,
+
+    /// Granularity at which linear and non-linear resources
+    /// must not be placed next to each other.
+    buffer_image_granularity: u64,
+}
+
+/// Number of placement classes.
+/// Blocks without resource kind, linear and non-linear resources.
+const CLASSES: usize = 3;
+
+unsafe impl<B> Send for TlsfAllocator<B> where B: Backend {}
+unsafe impl<B> Sync for TlsfAllocator<B> where B: Backend {}
+
+#[derive(Debug)]
+struct Arena<B: Backend> {
+    memory: Box<Memory<B>>,
+    ptr: Option<NonNull<u8>>,
+}
+
+impl<B> TlsfAllocator<B>
+where
+    B: Backend,
+{
+    /// Create new `TlsfAllocator`
+    /// for `memory_type` with `memory_properties` specified,
+    /// with `TlsfConfig` provided.
+    pub fn new(
+        memory_type: gfx_hal::MemoryTypeId,
+        memory_properties: gfx_hal::memory::Properties,
+        config: TlsfConfig,
+        non_coherent_atom_size: u64,
+    ) -> Self {
+        log::trace!(
+            "Create new 'tlsf' allocator: type: '{:?}', properties: '{:#?}' config: '{:#?}'",
+            memory_type,
+            memory_properties,
+            config
+        );
+
+        assert!(
+            config.block_size_granularity.is_power_of_two(),
+            "Allocation granularity must be power of two"
+        );
+
+        assert!(
+            config.arena_size.is_power_of_two(),
+            "Arena size must be power of two"
+        );
+
+        let block_size_granularity = if is_non_coherent_visible(memory_properties) {
+            non_coherent_atom_size
+                .max(config.block_size_granularity)
+                .next_power_of_two()
+        } else {
+            config.block_size_granularity
+        };
+
+        assert!(
+            block_size_granularity <= config.arena_size,
+            "Allocation granularity must not exceed arena size"
+        );
+
+        if memory_properties.contains(gfx_hal::memory::Properties::CPU_VISIBLE) {
+            debug_assert!(
+                fits_usize(config.arena_size),
+                "Arena size must fit usize for mapping"
+            );
+        }
+
+        TlsfAllocator {
+            memory_type,
+            memory_properties,
+            arena_size: config.arena_size,
+            arenas: slab::Slab::new(),
+            classes: [
+                Tlsf::new(block_size_granularity),
+                Tlsf::new(block_size_granularity),
+                Tlsf::new(block_size_granularity),
+            ],
+            non_coherent_atom_size,
+            buffer_image_granularity: 1,
+        }
+    }
+
+    /// Place linear and non-linear resources into separate arenas
+    /// if `buffer_image_granularity` is larger than block size granularity.
+    /// Otherwise all blocks are aligned to the granularity anyway.
+    pub fn with_buffer_image_granularity(mut self, buffer_image_granularity: u64) -> Self {
+        assert!(
+            buffer_image_granularity.is_power_of_two(),
+            "Buffer-image granularity must be power of two"
+        );
+        self.buffer_image_granularity = buffer_image_granularity;
+        self
+    }
+
+    /// Get placement class for blocks of `resource` kind.
+    fn class(&self, resource: Option<ResourceKind>) -> usize {
+        if self.buffer_image_granularity <= self.classes[0].granularity {
+            return 0;
+        }
+        match resource {
+            None => 0,
+            Some(ResourceKind::Linear) => 1,
+            Some(ResourceKind::NonLinear) => 2,
+        }
+    }
+
+    /// Maximum allocation size.
+    pub fn max_allocation(&self) -> u64 {
+        self.arena_size / 2
+    }
+
+    /// Allocate arena for blocks of the `class` from device.
+    fn alloc_arena(
+        &mut self,
+        device: &B::Device,
+        class: usize,
+    ) -> Result<usize, gfx_hal::device::AllocationError> {
+        log::trace!("Allocate arena of size: {} from device", self.arena_size);
+
+        let (memory, ptr) = unsafe {
+            // Valid memory type specified.
+            let raw = device.allocate_memory(self.memory_type, self.arena_size)?;
+
+            let ptr = if self
+                .memory_properties
+                .contains(gfx_hal::memory::Properties::CPU_VISIBLE)
+            {
+                log::trace!("Map new memory object");
+                let segment = Segment {
+                    offset: 0,
+                    size: Some(self.arena_size),
+                };
+                match device.map_memory(&raw, segment) {
+                    Ok(ptr) => Some(NonNull::new_unchecked(ptr)),
+                    Err(gfx_hal::device::MapError::OutOfMemory(error)) => {
+                        device.free_memory(raw);
+                        return Err(error.into());
+                    }
+                    Err(_) => panic!("Unexpected mapping failure"),
+                }
+            } else {
+                None
+            };
+            let memory = Memory::from_raw(
+                raw,
+                self.arena_size,
+                self.memory_properties,
+                self.non_coherent_atom_size,
+            );
+            (memory, ptr)
+        };
+
+        let index = self.arenas.insert(Arena {
+            memory: Box::new(memory),
+            ptr,
+        });
+        assert!(fits_u32(index), "Number of arenas must fit in u32 limit");
+        self.classes[class].add_arena(index as u32, self.arena_size);
+        Ok(index)
+    }
+
+    /// Free arena all blocks of which were freed.
+    fn free_arena(&mut self, device: &B::Device, index: u32) -> u64 {
+        let arena = self.arenas.remove(index as usize);
+        log::trace!("Free arena: {:#?}", arena);
+        let size = arena.memory.size();
+        unsafe {
+            if arena.ptr.is_some() {
+                log::trace!("Unmap memory: {:#?}", arena.memory);
+                device.unmap_memory(arena.memory.raw());
+            }
+            device.free_memory(arena.memory.into_raw());
+        }
+        size
+    }
+
+    /// Make block for span allocated in the `class`.
+    fn block(&self, class: usize, span: usize) -> TlsfBlock<B> {
+        let tlsf = &self.classes[class];
+        let range = tlsf.range(span);
+        let arena = &self.arenas[tlsf.arena(span) as usize];
+        TlsfBlock {
+            class,
+            span,
+            memory: &*arena.memory,
+            ptr: arena.ptr.map(|ptr| {
+                mapped_sub_range(ptr, 0..self.arena_size, range.clone())
+                    .expect("Block must be sub-range of arena")
+            }),
+            range,
+            relevant: relevant::Relevant,
+        }
+    }
+
+    /// Perform full cleanup of the memory allocated.
+    pub fn dispose(self) {
+        if !thread::panicking() {
+            assert_eq!(self.arenas.len(), 0, "Arenas are still used");
+        } else if !self.arenas.is_empty() {
+            log::error!("Memory leak: {} arenas are still used", self.arenas.len());
+        }
+    }
+}
+
+impl<B> Allocator<B> for TlsfAllocator<B>
+where
+    B: Backend,
+{
+    type Block = TlsfBlock<B>;
+
+    fn kind() -> Kind {
+        Kind::Tlsf
+    }
+
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+    ) -> Result<(TlsfBlock<B>, u64), gfx_hal::device::AllocationError> {
+        self.alloc_resource(device, size, align, None)
+    }
+
+    fn free(&mut self, device: &B::Device, block: TlsfBlock<B>) -> u64 {
+        log::trace!("Free block: {:#?}", block);
+        let (class, span) = (block.class, block.span);
+        block.dispose();
+        match self.classes[class].free(span) {
+            Some(arena) => self.free_arena(device, arena),
+            None => 0,
+        }
+    }
+}
+
+impl<B> TlsfAllocator<B>
+where
+    B: Backend,
+{
+    /// Allocate block of memory for resource of specified kind.
+    /// Blocks for linear and non-linear resources are placed
+    /// according to buffer-image granularity.
+    pub fn alloc_resource(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        align: u64,
+        resource: Option<ResourceKind>,
+    ) -> Result<(TlsfBlock<B>, u64), gfx_hal::device::AllocationError> {
+        debug_assert!(size <= self.max_allocation());
+        debug_assert!(align.is_power_of_two());
+        debug_assert!(align <= self.max_allocation());
+        let class = self.class(resource);
+        let aligned_size = aligned(size.max(1), self.classes[class].granularity);
+
+        log::trace!(
+            "Allocate tlsf block: size: {}, align: {}, aligned size: {}, type: {}",
+            size,
+            align,
+            aligned_size,
+            self.memory_type.0
+        );
+
+        if let Some(span) = self.classes[class].alloc(aligned_size, align) {
+            return Ok((self.block(class, span), 0));
+        }
+
+        self.alloc_arena(device, class)?;
+        let span = self.classes[class]
+            .alloc(aligned_size, align)
+            .expect("New arena should fit the block");
+        Ok((self.block(class, span), self.arena_size))
+    }
+}
+
+/// Number of bits of second level index.
+const SL_BITS: u32 = 4;
+
+/// Number of second level lists per first level.
+const SL_COUNT: usize = 1 << SL_BITS;
+
+/// Number of first level classes.
+/// First class holds sizes below `SL_COUNT` granules linearly,
+/// every next one holds sizes in next power of two range.
+const FL_COUNT: usize = (64 - SL_BITS + 1) as usize;
+
+/// Contiguous range of an arena, either allocated or free.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    arena: u32,
+    offset: u64,
+    size: u64,
+    free: bool,
+
+    /// Physical neighbours in the arena.
+    prev: Option<usize>,
+    next: Option<usize>,
+
+    /// Neighbours in the free list.
+    prev_free: Option<usize>,
+    next_free: Option<usize>,
+}
+
+/// Bookkeeping of the `TlsfAllocator`.
+/// Spans of all arenas and segregated free lists.
+#[derive(Debug)]
+struct Tlsf {
+    /// Sizes and offsets are multiples of this value.
+    granularity: u64,
+
+    spans: slab::Slab<Span>,
+
+    /// Bit per first level class with non-empty lists.
+    fl_bitmap: u64,
+
+    /// Bit per non-empty list in first level class.
+    sl_bitmaps: [u16; FL_COUNT],
+
+    /// Heads of the free lists.
+    heads: [[Option<usize>; SL_COUNT]; FL_COUNT],
+}
+
+impl Tlsf {
+    fn new(granularity: u64) -> Self {
+        debug_assert!(granularity.is_power_of_two());
+        Tlsf {
+            granularity,
+            spans: slab::Slab::new(),
+            fl_bitmap: 0,
+            sl_bitmaps: [0; FL_COUNT],
+            heads: [[None; SL_COUNT]; FL_COUNT],
+        }
+    }
+
+    fn range(&self, span: usize) -> Range<u64> {
+        let span = &self.spans[span];
+        span.offset..span.offset + span.size
+    }
+
+    fn arena(&self, span: usize) -> u32 {
+        self.spans[span].arena
+    }
+
+    /// Get list that holds free spans of specified size.
+    fn mapping(&self, size: u64) -> (usize, usize) {
+        let granules = size / self.granularity;
+        if granules < SL_COUNT as u64 {
+            (0, granules as usize)
+        } else {
+            let fl = 63 - granules.leading_zeros();
+            let sl = (granules >> (fl - SL_BITS)) as usize ^ SL_COUNT;
+            ((fl - SL_BITS + 1) as usize, sl)
+        }
+    }
+
+    /// Find non-empty list whose spans are all at least of specified size.
+    fn find(&self, size: u64) -> Option<(usize, usize)> {
+        let mut granules = size / self.granularity;
+        if granules >= SL_COUNT as u64 {
+            // Round up to the next list boundary.
+            let fl = 63 - granules.leading_zeros();
+            granules = granules.checked_add((1 << (fl - SL_BITS)) - 1)?;
+        }
+        let (fl, sl) = self.mapping(granules * self.granularity);
+
+        let sl_bitmap = self.sl_bitmaps[fl] as u64 & (!0u64 << sl);
+        if sl_bitmap != 0 {
+            return Some((fl, sl_bitmap.trailing_zeros() as usize));
+        }
+
+        let fl_bitmap = self.fl_bitmap & (!0u64).checked_shl(fl as u32 + 1).unwrap_or(0);
+        if fl_bitmap == 0 {
+            return None;
+        }
+        let fl = fl_bitmap.trailing_zeros() as usize;
+        Some((fl, self.sl_bitmaps[fl].trailing_zeros() as usize))
+    }
+
+    fn insert_free(&mut self, index: usize) {
+        let (fl, sl) = self.mapping(self.spans[index].size);
+        let head = self.heads[fl][sl];
+        {
+            let span = &mut self.spans[index];
+            span.free = true;
+            span.prev_free = None;
+            span.next_free = head;
+        }
+        if let Some(head) = head {
+            self.spans[head].prev_free = Some(index);
+        }
+        self.heads[fl][sl] = Some(index);
+        self.sl_bitmaps[fl] |= 1 << sl;
+        self.fl_bitmap |= 1 << fl;
+    }
+
+    fn remove_free(&mut self, index: usize) {
+        let span = self.spans[index];
+        debug_assert!(span.free);
+        if let Some(prev) = span.prev_free {
+            self.spans[prev].next_free = span.next_free;
+        } else {
+            let (fl, sl) = self.mapping(span.size);
+            debug_assert_eq!(self.heads[fl][sl], Some(index));
+            self.heads[fl][sl] = span.next_free;
+            if span.next_free.is_none() {
+                self.sl_bitmaps[fl] &= !(1 << sl);
+                if self.sl_bitmaps[fl] == 0 {
+                    self.fl_bitmap &= !(1 << fl);
+                }
+            }
+        }
+        if let Some(next) = span.next_free {
+            self.spans[next].prev_free = span.prev_free;
+        }
+        self.spans[index].free = false;
+    }
+
+    /// Add free span covering whole arena.
+    fn add_arena(&mut self, arena: u32, size: u64) {
+        debug_assert_eq!(size % self.granularity, 0);
+        let index = self.spans.insert(Span {
+            arena,
+            offset: 0,
+            size,
+            free: false,
+            prev: None,
+            next: None,
+            prev_free: None,
+            next_free: None,
+        });
+        self.insert_free(index);
+    }
+
+    /// Split span in two at `at` bytes from its start.
+    /// Returns index of the second span.
+    fn split(&mut self, index: usize, at: u64) -> usize {
+        let span = self.spans[index];
+        debug_assert!(at > 0 && at < span.size);
+        let tail = self.spans.insert(Span {
+            arena: span.arena,
+            offset: span.offset + at,
+            size: span.size - at,
+            free: false,
+            prev: Some(index),
+            next: span.next,
+            prev_free: None,
+            next_free: None,
+        });
+        if let Some(next) = span.next {
+            self.spans[next].prev = Some(tail);
+        }
+        let span = &mut self.spans[index];
+        span.size = at;
+        span.next = Some(tail);
+        tail
+    }
+
+    /// Merge span with the next one.
+    fn merge(&mut self, index: usize, next: usize) {
+        let next = self.spans.remove(next);
+        if let Some(after) = next.next {
+            self.spans[after].prev = Some(index);
+        }
+        let span = &mut self.spans[index];
+        span.size += next.size;
+        span.next = next.next;
+    }
+
+    /// Allocate span of `size` bytes aligned to `align`.
+    /// Both must be multiple of granularity.
+    fn alloc(&mut self, size: u64, align: u64) -> Option<usize> {
+        debug_assert_eq!(size % self.granularity, 0);
+
+        // Any span that large has aligned sub-range of `size` bytes.
+        let search = if align > self.granularity {
+            size.checked_add(align - self.granularity)?
+        } else {
+            size
+        };
+
+        let (fl, sl) = self.find(search)?;
+        let mut index = self.heads[fl][sl].expect("Bitmaps are in sync with lists");
+        self.remove_free(index);
+
+        let offset = self.spans[index].offset;
+        let padding = aligned(offset, align) - offset;
+        if padding > 0 {
+            let tail = self.split(index, padding);
+            self.insert_free(index);
+            index = tail;
+        }
+
+        if self.spans[index].size > size {
+            let tail = self.split(index, size);
+            self.insert_free(tail);
+        }
+
+        debug_assert!(self.spans[index].size >= size);
+        Some(index)
+    }
+
+    /// Free allocated span.
+    /// Returns arena index if all its spans are free.
+    fn free(&mut self, mut index: usize) -> Option<u32> {
+        debug_assert!(!self.spans[index].free);
+
+        if let Some(prev) = self.spans[index].prev {
+            if self.spans[prev].free {
+                self.remove_free(prev);
+                self.merge(prev, index);
+                index = prev;
+            }
+        }
+
+        if let Some(next) = self.spans[index].next {
+            if self.spans[next].free {
+                self.remove_free(next);
+                self.merge(index, next);
+            }
+        }
+
+        let span = self.spans[index];
+        if span.prev.is_none() && span.next.is_none() {
+            self.spans.remove(index);
+            Some(span.arena)
+        } else {
+            self.insert_free(index);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tlsf;
+    use rand::{Rng as _, SeedableRng as _};
+
+    const ARENA: u64 = 1 << 20;
+
+    #[test]
+    fn mapping_is_monotonic() {
+        let tlsf = Tlsf::new(256);
+        let mut last = (0, 0);
+        for granules in 1..4096 {
+            let class = tlsf.mapping(granules * 256);
+            assert!(class >= last);
+            last = class;
+        }
+    }
+
+    #[test]
+    fn found_spans_fit() {
+        let mut tlsf = Tlsf::new(256);
+        tlsf.add_arena(0, ARENA);
+        let first = tlsf.alloc(1000 * 256, 256).unwrap();
+        let second = tlsf.alloc(2072 * 256, 256).unwrap();
+        let rest = tlsf.alloc(1024 * 256, 256).unwrap();
+        assert_eq!(tlsf.fl_bitmap, 0);
+
+        // Free span of 1000 granules is in the list with spans up to 1023 granules.
+        assert_eq!(tlsf.free(first), None);
+        assert_eq!(tlsf.find(1000 * 256), None);
+        assert_eq!(tlsf.find(960 * 256), Some(tlsf.mapping(1000 * 256)));
+        assert!(tlsf.alloc(999 * 256, 256).is_none());
+        let third = tlsf.alloc(960 * 256, 256).unwrap();
+        assert_eq!(tlsf.range(third), 0..960 * 256);
+
+        assert_eq!(tlsf.free(second), None);
+        assert_eq!(tlsf.free(rest), None);
+        assert_eq!(tlsf.free(third), Some(0));
+        assert!(tlsf.spans.is_empty());
+    }
+
+    #[test]
+    fn alignment() {
+        let mut tlsf = Tlsf::new(256);
+        tlsf.add_arena(0, ARENA);
+        let small = tlsf.alloc(256, 256).unwrap();
+        let aligned = tlsf.alloc(4096, 65536).unwrap();
+        assert_eq!(tlsf.range(aligned), 65536..69632);
+
+        // Padding is reused.
+        let padding = tlsf.alloc(248 * 256, 256).unwrap();
+        assert_eq!(tlsf.range(padding), 256..63744);
+
+        assert_eq!(tlsf.free(aligned), None);
+        assert_eq!(tlsf.free(small), None);
+        assert_eq!(tlsf.free(padding), Some(0));
+    }
+
+    #[test]
+    fn arenas_are_released() {
+        let mut tlsf = Tlsf::new(256);
+        tlsf.add_arena(0, ARENA);
+        let first = tlsf.alloc(ARENA / 2, 256).unwrap();
+        let second = tlsf.alloc(ARENA / 2, 256).unwrap();
+        assert!(tlsf.alloc(256, 256).is_none());
+
+        tlsf.add_arena(1, ARENA);
+        let third = tlsf.alloc(256, 256).unwrap();
+        assert_eq!(tlsf.arena(third), 1);
+
+        assert_eq!(tlsf.free(first), None);
+        assert_eq!(tlsf.free(third), Some(1));
+        assert_eq!(tlsf.free(second), Some(0));
+        assert_eq!(tlsf.fl_bitmap, 0);
+    }
+
+    #[test]
+    fn random() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut tlsf = Tlsf::new(256);
+        let mut arenas = 0;
+        let mut live: Vec<usize> = Vec::new();
+
+        for _ in 0..2000 {
+            if live.is_empty() || rng.gen_range(0, 3) != 0 {
+                let size = rng.gen_range(1, 1024) * 256;
+                let align = 1 << rng.gen_range(8, 16);
+                let span = match tlsf.alloc(size, align) {
+                    Some(span) => span,
+                    None => {
+                        tlsf.add_arena(arenas, ARENA);
+                        arenas += 1;
+                        tlsf.alloc(size, align).unwrap()
+                    }
+                };
+                let range = tlsf.range(span);
+                assert_eq!(range.start % align, 0);
+                assert_eq!(range.end - range.start, size);
+                for &other in &live {
+                    let other_range = tlsf.range(other);
+                    assert!(
+                        tlsf.arena(other) != tlsf.arena(span)
+                            || other_range.end <= range.start
+                            || range.end <= other_range.start
+                    );
+                }
+                live.push(span);
+            } else {
+                let span = live.swap_remove(rng.gen_range(0, live.len()));
+                let _ = tlsf.free(span);
+            }
+        }
+
+        for span in live {
+            let _ = tlsf.free(span);
+        }
+        assert!(tlsf.spans.is_empty());
+        assert_eq!(tlsf.fl_bitmap, 0);
+    }
+}
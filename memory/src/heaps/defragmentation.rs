@@ -0,0 +1,360 @@
+use {
+    super::{BlockFlavor, Heaps, MemoryBlock},
+    crate::block::Block,
+    std::collections::HashMap,
+};
+
+/// Config for defragmentation pass.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DefragmentationConfig {
+    /// Chunks with smaller fraction of used blocks are considered sparse.
+    pub max_occupancy: f32,
+
+    /// Maximum number of bytes to move in one pass.
+    pub max_bytes: u64,
+}
+
+impl Default for DefragmentationConfig {
+    fn default() -> Self {
+        DefragmentationConfig {
+            max_occupancy: 0.25,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Moves proposed by defragmentation pass.
+#[derive(Clone, Debug)]
+pub struct DefragmentationPlan<K> {
+    /// Keys of the blocks that should be moved to newly allocated blocks.
+    pub moves: Vec<K>,
+
+    /// Total size of the blocks to move.
+    pub bytes: u64,
+
+    /// Number of chunks that will be released once all moved blocks are freed.
+    pub chunks: usize,
+
+    /// Total size of the chunks that will be released.
+    pub released: u64,
+
+    /// Chunks retired by the plan.
+    retired: Vec<ChunkKey>,
+}
+
+/// Chunk of the `DynamicAllocator` of particular memory type.
+/// Shared allocator if `shard` is `None`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ChunkKey {
+    memory_index: u32,
+    shard: Option<usize>,
+    class: usize,
+    block_size: u64,
+    chunk_index: u32,
+}
+
+#[derive(Debug)]
+struct SparseChunk<K> {
+    moves: Vec<K>,
+    blocks: u32,
+    bytes: u64,
+}
+
+impl<B> Heaps<B>
+where
+    B: gfx_hal::Backend,
+{
+    /// Find sparse chunks of dynamic allocators, including shards,
+    /// that can be released by moving relocatable blocks out of them.
+    ///
+    /// `blocks` are all blocks that can be moved, each with a key to identify it.
+    /// Only chunks that contain no other blocks are selected,
+    /// sparsest first, until `config.max_bytes` is reached.
+    ///
+    /// Selected chunks are retired so that blocks allocated to replace moved ones
+    /// are never placed into them.
+    /// Chunk is released when all its blocks are freed,
+    /// so all proposed moves should be carried out.
+    pub fn plan_defragmentation<'a, K>(
+        &mut self,
+        blocks: impl IntoIterator<Item = (K, &'a MemoryBlock<B>)>,
+        config: DefragmentationConfig,
+    ) -> DefragmentationPlan<K>
+    where
+        B: 'a,
+    {
+        let mut chunks: HashMap<ChunkKey, SparseChunk<K>> = HashMap::new();
+        for (key, block) in blocks {
+            let (shard, dynamic) = match &block.block {
+                BlockFlavor::Dynamic(dynamic) => (None, dynamic),
+                BlockFlavor::Shard(index, dynamic) => (Some(*index), dynamic),
+                _ => continue,
+            };
+            let (class, block_size, chunk_index) = dynamic.chunk();
+            let chunk = chunks
+                .entry(ChunkKey {
+                    memory_index: block.memory_index,
+                    shard,
+                    class,
+                    block_size,
+                    chunk_index,
+                })
+                .or_insert_with(|| SparseChunk {
+                    moves: Vec::new(),
+                    blocks: 0,
+                    bytes: 0,
+                });
+            chunk.moves.push(key);
+            chunk.blocks += dynamic.count();
+            chunk.bytes += block.size();
+        }
+
+        let mut sparse: Vec<_> = chunks
+            .into_iter()
+            .filter_map(|(key, chunk)| {
+                let dynamic = self.types[key.memory_index as usize].dynamic_mut(key.shard)?;
+                let (used, total, size) =
+                    dynamic.chunk_usage(key.class, key.block_size, key.chunk_index);
+                if used != chunk.blocks || used as f32 > total as f32 * config.max_occupancy {
+                    None
+                } else {
+                    Some((used as u64 * 10000 / total as u64, key, size, chunk))
+                }
+            })
+            .collect();
+        sparse.sort_by_key(|&(occupancy, key, _, _)| (occupancy, key));
+
+        let mut plan = DefragmentationPlan {
+            moves: Vec::new(),
+            bytes: 0,
+            chunks: 0,
+            released: 0,
+            retired: Vec::new(),
+        };
+
+        for (_, key, size, chunk) in sparse {
+            if plan.bytes + chunk.bytes > config.max_bytes {
+                continue;
+            }
+            log::trace!(
+                "Retire chunk {:?} with {} bytes used out of {}",
+                key,
+                chunk.bytes,
+                size
+            );
+            self.types[key.memory_index as usize]
+                .dynamic_mut(key.shard)
+                .expect("Chunk belongs to dynamic allocator")
+                .retire_chunk(key.class, key.block_size, key.chunk_index);
+            plan.moves.extend(chunk.moves);
+            plan.bytes += chunk.bytes;
+            plan.chunks += 1;
+            plan.released += size;
+            plan.retired.push(key);
+        }
+
+        plan
+    }
+
+    /// Allow allocating blocks from chunks retired by the `plan` again.
+    ///
+    /// Should be called if not all proposed moves were carried out,
+    /// otherwise chunks with blocks left in place stay unused until those blocks are freed.
+    pub fn cancel_defragmentation<K>(&mut self, plan: &DefragmentationPlan<K>) {
+        for key in &plan.retired {
+            log::trace!("Restore chunk {:?}", key);
+            if let Some(dynamic) = self.types[key.memory_index as usize].dynamic_mut(key.shard) {
+                dynamic.restore_chunk(key.class, key.block_size, key.chunk_index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::DefragmentationConfig,
+        crate::{
+            allocator::DynamicConfig,
+            heaps::{BlockFlavor, Heaps, HeapsConfig, MemoryBlock, ShardsConfig},
+            replay::{FakeBackend, FakeDevice},
+            usage::Data,
+        },
+        gfx_hal::memory::Properties,
+    };
+
+    const MB: u64 = 1024 * 1024;
+    const BLOCK: u64 = 64 * 1024;
+
+    fn chunk(block: &MemoryBlock<FakeBackend>) -> u32 {
+        match &block.block {
+            BlockFlavor::Dynamic(dynamic) | BlockFlavor::Shard(_, dynamic) => dynamic.chunk().2,
+            _ => panic!("Block must be allocated from dynamic allocator"),
+        }
+    }
+
+    /// Allocate chunks of 1, 2, 4, 8 and 16 blocks, each from separate device memory.
+    /// Only first two blocks of the last chunk are kept.
+    fn sparse(device: &FakeDevice) -> (Heaps<FakeBackend>, Vec<MemoryBlock<FakeBackend>>) {
+        sparse_with(device, None)
+    }
+
+    fn sparse_with(
+        device: &FakeDevice,
+        shards: Option<ShardsConfig>,
+    ) -> (Heaps<FakeBackend>, Vec<MemoryBlock<FakeBackend>>) {
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 4 * MB,
+                min_device_allocation: BLOCK,
+            }),
+            tlsf: None,
+            shards,
+            pool: None,
+        };
+        let heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        };
+
+        let mut blocks: Vec<_> = (0..31)
+            .map(|_| heaps.allocate(device, !0, Data, BLOCK, 256).unwrap())
+            .collect();
+        assert_eq!(chunk(&blocks[15]), 4);
+        for block in blocks.drain(17..) {
+            heaps.free(device, block);
+        }
+        (heaps, blocks)
+    }
+
+    fn dispose(
+        device: &FakeDevice,
+        heaps: Heaps<FakeBackend>,
+        blocks: Vec<MemoryBlock<FakeBackend>>,
+    ) {
+        for block in blocks {
+            heaps.free(device, block);
+        }
+        heaps.dispose(device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn chunk_usage() {
+        let device = FakeDevice::new();
+        let (mut heaps, blocks) = sparse(&device);
+
+        let dynamic = heaps.types[0].dynamic_mut(None).unwrap();
+        assert_eq!(dynamic.chunk_usage(0, BLOCK, 3), (8, 8, 8 * BLOCK));
+        assert_eq!(dynamic.chunk_usage(0, BLOCK, 4), (2, 16, 16 * BLOCK));
+
+        dispose(&device, heaps, blocks);
+    }
+
+    #[test]
+    fn plan() {
+        let device = FakeDevice::new();
+        let (mut heaps, blocks) = sparse(&device);
+        let config = DefragmentationConfig {
+            max_occupancy: 0.25,
+            max_bytes: MB,
+        };
+
+        // Chunk with blocks that can't be moved is not selected.
+        let plan = heaps.plan_defragmentation(Some((15, &blocks[15])), config);
+        assert!(plan.moves.is_empty());
+
+        // Chunk is not selected if moving its blocks exceeds the limit.
+        let plan = heaps.plan_defragmentation(
+            blocks.iter().enumerate(),
+            DefragmentationConfig {
+                max_bytes: BLOCK,
+                ..config
+            },
+        );
+        assert!(plan.moves.is_empty());
+
+        // Full chunks are left alone.
+        let plan = heaps.plan_defragmentation(blocks.iter().enumerate(), config);
+        assert_eq!(plan.moves, vec![15, 16]);
+        assert_eq!(plan.bytes, 2 * BLOCK);
+        assert_eq!(plan.chunks, 1);
+        assert_eq!(plan.released, 16 * BLOCK);
+
+        // Retired chunk is not used for new blocks.
+        let allocations = device.stats().allocations;
+        let block = heaps.allocate(&device, !0, Data, BLOCK, 256).unwrap();
+        assert_ne!(chunk(&block), 4);
+        assert_eq!(device.stats().allocations, allocations + 1);
+        heaps.free(&device, block);
+
+        // Retired chunk is released once moved blocks are freed.
+        let mut blocks = blocks;
+        let frees = device.stats().frees;
+        for block in blocks.drain(15..) {
+            heaps.free(&device, block);
+        }
+        assert_eq!(device.stats().frees, frees + 1);
+
+        dispose(&device, heaps, blocks);
+    }
+
+    #[test]
+    fn cancel() {
+        let device = FakeDevice::new();
+        let (mut heaps, blocks) = sparse(&device);
+
+        let plan =
+            heaps.plan_defragmentation(blocks.iter().enumerate(), DefragmentationConfig::default());
+        assert_eq!(plan.chunks, 1);
+        heaps.cancel_defragmentation(&plan);
+
+        // Chunk is used for new blocks again.
+        let allocations = device.stats().allocations;
+        let block = heaps.allocate(&device, !0, Data, BLOCK, 256).unwrap();
+        assert_eq!(chunk(&block), 4);
+        assert_eq!(device.stats().allocations, allocations);
+        heaps.free(&device, block);
+
+        dispose(&device, heaps, blocks);
+    }
+
+    #[test]
+    fn sharded() {
+        let device = FakeDevice::new();
+        let shards = ShardsConfig {
+            count: 1,
+            max_size: MB,
+        };
+        let (mut heaps, blocks) = sparse_with(&device, Some(shards));
+        match &blocks[0].block {
+            BlockFlavor::Shard(0, _) => {}
+            _ => panic!("Block must be allocated from shard"),
+        }
+
+        let plan =
+            heaps.plan_defragmentation(blocks.iter().enumerate(), DefragmentationConfig::default());
+        assert_eq!(plan.moves, vec![15, 16]);
+        assert_eq!(plan.chunks, 1);
+
+        // Retired chunk of the shard is not used for new blocks.
+        let block = heaps.allocate(&device, !0, Data, BLOCK, 256).unwrap();
+        assert_ne!(chunk(&block), 4);
+        heaps.free(&device, block);
+
+        // Until defragmentation is cancelled.
+        heaps.cancel_defragmentation(&plan);
+        let block = heaps.allocate(&device, !0, Data, BLOCK, 256).unwrap();
+        assert_eq!(chunk(&block), 4);
+        heaps.free(&device, block);
+
+        dispose(&device, heaps, blocks);
+    }
+}
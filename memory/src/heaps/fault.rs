@@ -0,0 +1,110 @@
+/// Policy to fail allocations from `Heaps` on purpose,
+/// so that handling of out-of-memory errors can be tested.
+///
+/// Failed allocations return `OutOfMemory::Device` error.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultPolicy {
+    /// Fail n-th allocation made after policy is set, counting from 1.
+    Nth(u64),
+
+    /// Fail allocations larger than specified number of bytes.
+    Above(u64),
+
+    /// Fail allocations randomly.
+    Random {
+        /// Seed for random sequence. Same seed yields same failures.
+        seed: u64,
+
+        /// Probability to fail each allocation.
+        probability: f32,
+    },
+}
+
+#[derive(Debug)]
+pub(super) struct FaultInjection {
+    policy: FaultPolicy,
+    allocations: u64,
+    state: u64,
+}
+
+impl FaultInjection {
+    pub(super) fn new(policy: FaultPolicy) -> Self {
+        let state = match policy {
+            FaultPolicy::Random { seed, .. } => seed,
+            _ => 0,
+        };
+
+        FaultInjection {
+            policy,
+            allocations: 0,
+            state,
+        }
+    }
+
+    /// Check if allocation should fail.
+    pub(super) fn fail(&mut self, size: u64) -> bool {
+        self.allocations += 1;
+        match self.policy {
+            FaultPolicy::Nth(n) => self.allocations == n,
+            FaultPolicy::Above(limit) => size > limit,
+            FaultPolicy::Random { probability, .. } => {
+                let value = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+                value < probability as f64
+            }
+        }
+    }
+
+    /// SplitMix64 step.
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FaultInjection, FaultPolicy};
+
+    fn failures(policy: FaultPolicy, sizes: impl IntoIterator<Item = u64>) -> Vec<bool> {
+        let mut faults = FaultInjection::new(policy);
+        sizes.into_iter().map(|size| faults.fail(size)).collect()
+    }
+
+    #[test]
+    fn nth() {
+        assert_eq!(
+            failures(FaultPolicy::Nth(3), vec![1; 5]),
+            vec![false, false, true, false, false]
+        );
+    }
+
+    #[test]
+    fn above() {
+        assert_eq!(
+            failures(FaultPolicy::Above(256), vec![1, 256, 257, 1024]),
+            vec![false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn random() {
+        let policy = FaultPolicy::Random {
+            seed: 42,
+            probability: 0.25,
+        };
+        let first = failures(policy, vec![1; 1000]);
+        assert_eq!(first, failures(policy, vec![1; 1000]));
+
+        let failed = first.iter().filter(|&&failed| failed).count();
+        assert!(failed > 200 && failed < 300, "{} failures", failed);
+
+        let never = FaultPolicy::Random {
+            seed: 42,
+            probability: 0.0,
+        };
+        assert!(failures(never, vec![1; 1000]).iter().all(|&failed| !failed));
+    }
+}
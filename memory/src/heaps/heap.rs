@@ -1,49 +1,324 @@
-use crate::utilization::*;
+use {
+    crate::utilization::*,
+    std::sync::atomic::{AtomicU64, Ordering},
+};
 
+/// Soft limit on the memory allocated from the heap.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HeapBudget {
+    /// Maximum number of bytes allocated from the heap.
+    pub size: u64,
+
+    /// Fractions of the budget size.
+    /// Budget callback is invoked whenever heap usage crosses one of them.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub thresholds: Vec<f32>,
+}
+
+impl HeapBudget {
+    /// Create budget without thresholds.
+    pub fn new(size: u64) -> Self {
+        HeapBudget {
+            size,
+            thresholds: Vec::new(),
+        }
+    }
+
+    /// Add threshold to the budget.
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+}
+
+/// Heap usage crossed one of the budget thresholds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BudgetEvent {
+    /// Index of the heap.
+    pub heap_index: usize,
+
+    /// Threshold crossed.
+    pub threshold: f32,
+
+    /// `true` if usage grew above the threshold,
+    /// `false` if it dropped below.
+    pub rising: bool,
+
+    /// Number of bytes allocated from the heap.
+    pub used: u64,
+
+    /// Budget size.
+    pub budget: u64,
+}
+
+/// Reason heap can't reserve memory for allocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Reservation {
+    /// Heap has not enough memory left.
+    Exhausted,
+
+    /// Allocation would exceed the budget.
+    OverBudget,
+}
+
+/// Heap usage is tracked with atomics
+/// so that allocations from different memory types don't contend.
 #[derive(Debug)]
 pub(super) struct MemoryHeap {
     size: u64,
-    used: u64,
-    effective: u64,
+    used: AtomicU64,
+    effective: AtomicU64,
+    budget: Option<HeapBudget>,
 }
 
 impl MemoryHeap {
     pub(super) fn new(size: u64) -> Self {
         MemoryHeap {
             size,
-            used: 0,
-            effective: 0,
+            used: AtomicU64::new(0),
+            effective: AtomicU64::new(0),
+            budget: None,
         }
     }
 
     pub(super) fn available(&self) -> u64 {
-        if self.used > self.size {
+        let used = self.used();
+        if used > self.size {
             log::warn!("Heap size exceeded");
             0
         } else {
-            self.size - self.used
+            self.size - used
+        }
+    }
+
+    pub(super) fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    pub(super) fn budget(&self) -> Option<&HeapBudget> {
+        self.budget.as_ref()
+    }
+
+    pub(super) fn set_budget(&mut self, budget: Option<HeapBudget>) {
+        self.budget = budget;
+    }
+
+    /// Number of bytes that can be allocated without exceeding the budget.
+    pub(super) fn headroom(&self) -> u64 {
+        match &self.budget {
+            Some(budget) => budget.size.saturating_sub(self.used()),
+            None => self.available(),
         }
     }
 
-    pub(super) fn allocated(&mut self, used: u64, effective: u64) {
-        self.used += used;
-        self.effective += effective;
-        debug_assert!(self.used >= self.effective);
+    #[cfg(test)]
+    pub(super) fn over_budget(&self) -> bool {
+        self.exceeds_budget(self.used())
+    }
+
+    /// Check if `used` bytes exceed the budget.
+    fn exceeds_budget(&self, used: u64) -> bool {
+        match &self.budget {
+            Some(budget) => used > budget.size,
+            None => false,
+        }
     }
 
-    pub(super) fn freed(&mut self, used: u64, effective: u64) {
-        self.used -= used;
-        self.effective -= effective;
-        debug_assert!(self.used >= self.effective);
+    /// Collect budget thresholds crossed when usage changed from `before` to `after`.
+    pub(super) fn crossed(&self, heap_index: usize, before: u64, after: u64) -> Vec<BudgetEvent> {
+        let budget = match &self.budget {
+            Some(budget) => budget,
+            None => return Vec::new(),
+        };
+
+        budget
+            .thresholds
+            .iter()
+            .filter_map(|&threshold| {
+                let level = (budget.size as f64 * threshold as f64) as u64;
+                let rising = if before <= level && after > level {
+                    true
+                } else if before > level && after <= level {
+                    false
+                } else {
+                    return None;
+                };
+
+                Some(BudgetEvent {
+                    heap_index,
+                    threshold,
+                    rising,
+                    used: after,
+                    budget: budget.size,
+                })
+            })
+            .collect()
+    }
+
+    /// Reserve `size` bytes for allocation.
+    ///
+    /// Check and update are done atomically,
+    /// so that concurrent allocations can't exceed the budget together.
+    /// Returns number of bytes used before.
+    pub(super) fn reserve(&self, size: u64) -> Result<u64, Reservation> {
+        let mut used = self.used();
+        loop {
+            if self.size.saturating_sub(used) < size {
+                return Err(Reservation::Exhausted);
+            }
+            if self.exceeds_budget(used + size) {
+                return Err(Reservation::OverBudget);
+            }
+            match self.used.compare_exchange_weak(
+                used,
+                used + size,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(used),
+                Err(actual) => used = actual,
+            }
+        }
+    }
+
+    /// Replace reserved bytes with memory actually allocated.
+    pub(super) fn commit(&self, reserved: u64, used: u64, effective: u64) {
+        self.effective.fetch_add(effective, Ordering::AcqRel);
+        if used >= reserved {
+            self.used.fetch_add(used - reserved, Ordering::AcqRel);
+        } else {
+            self.used.fetch_sub(reserved - used, Ordering::AcqRel);
+        }
+    }
+
+    /// Return reserved bytes when allocation fails.
+    pub(super) fn release(&self, reserved: u64) {
+        self.used.fetch_sub(reserved, Ordering::AcqRel);
+    }
+
+    /// Account allocated memory.
+    /// Returns number of bytes used before.
+    #[cfg(test)]
+    pub(super) fn allocated(&self, used: u64, effective: u64) -> u64 {
+        self.effective.fetch_add(effective, Ordering::AcqRel);
+        self.used.fetch_add(used, Ordering::AcqRel)
+    }
+
+    /// Account freed memory.
+    /// Returns number of bytes used before.
+    pub(super) fn freed(&self, used: u64, effective: u64) -> u64 {
+        self.effective.fetch_sub(effective, Ordering::AcqRel);
+        self.used.fetch_sub(used, Ordering::AcqRel)
     }
 
     pub(super) fn utilization(&self) -> MemoryHeapUtilization {
         MemoryHeapUtilization {
             utilization: MemoryUtilization {
-                used: self.used,
-                effective: self.effective,
+                used: self.used(),
+                effective: self.effective.load(Ordering::Acquire),
             },
             size: self.size,
+            budget: self.budget.as_ref().map(|budget| budget.size),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{BudgetEvent, HeapBudget, MemoryHeap, Reservation};
+
+    fn heap() -> MemoryHeap {
+        let mut heap = MemoryHeap::new(1024);
+        heap.set_budget(Some(
+            HeapBudget::new(512).with_threshold(0.5).with_threshold(1.0),
+        ));
+        heap
+    }
+
+    #[test]
+    fn headroom() {
+        let mut heap = heap();
+        heap.allocated(200, 200);
+        assert_eq!(heap.headroom(), 312);
+        assert!(!heap.over_budget());
+
+        heap.allocated(400, 400);
+        assert_eq!(heap.headroom(), 0);
+        assert!(heap.over_budget());
+
+        heap.set_budget(None);
+        assert_eq!(heap.headroom(), 424);
+        assert!(!heap.over_budget());
+    }
+
+    #[test]
+    fn thresholds() {
+        let heap = heap();
+        heap.allocated(256, 256);
+        assert!(heap.crossed(1, 0, 256).is_empty());
+
+        heap.allocated(300, 300);
+        let events = heap.crossed(1, 256, 556);
+        assert_eq!(
+            events,
+            vec![
+                BudgetEvent {
+                    heap_index: 1,
+                    threshold: 0.5,
+                    rising: true,
+                    used: 556,
+                    budget: 512,
+                },
+                BudgetEvent {
+                    heap_index: 1,
+                    threshold: 1.0,
+                    rising: true,
+                    used: 556,
+                    budget: 512,
+                },
+            ]
+        );
+
+        heap.freed(100, 100);
+        let events = heap.crossed(1, 556, 456);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].threshold, 1.0);
+        assert!(!events[0].rising);
+    }
+
+    #[test]
+    fn reserve() {
+        let heap = heap();
+        assert_eq!(heap.reserve(300), Ok(0));
+        assert_eq!(heap.reserve(300), Err(Reservation::OverBudget));
+        assert_eq!(heap.reserve(212), Ok(300));
+        assert_eq!(heap.headroom(), 0);
+
+        // Allocated memory may differ from reserved.
+        heap.commit(300, 0, 300);
+        heap.release(212);
+        assert_eq!(heap.utilization().utilization.used, 0);
+        assert_eq!(heap.utilization().utilization.effective, 300);
+
+        let mut heap = heap;
+        heap.set_budget(None);
+        assert_eq!(heap.reserve(2048), Err(Reservation::Exhausted));
+    }
+
+    #[test]
+    fn concurrent_reserve() {
+        use std::{sync::Arc, thread};
+
+        let heap = Arc::new(heap());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let heap = heap.clone();
+                thread::spawn(move || (0..100).filter(|_| heap.reserve(1).is_ok()).count())
+            })
+            .collect();
+        let reserved: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(reserved, 512);
+        assert_eq!(heap.used(), 512);
+    }
+}
@@ -1,19 +1,62 @@
 use {
-    super::{BlockFlavor, HeapsConfig},
-    crate::{allocator::*, usage::MemoryUsage, utilization::*},
+    super::{tags::LiveBlocksTable, BlockFlavor, HeapsConfig},
+    crate::{
+        allocator::*,
+        block::Block,
+        usage::{AllocationTag, MemoryUsage, ResourceKind},
+        util::aligned,
+        utilization::*,
+    },
     gfx_hal::memory::Properties,
+    parking_lot::Mutex,
+    std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
 };
 
+static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Index used to spread threads among shards.
+    static THREAD_INDEX: usize = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+}
+
 #[derive(Debug)]
-pub(super) struct MemoryType<B: gfx_hal::Backend> {
-    heap_index: usize,
-    properties: Properties,
+struct Allocators<B: gfx_hal::Backend> {
     dedicated: DedicatedAllocator,
     linear: Option<LinearAllocator<B>>,
     dynamic: Option<DynamicAllocator<B>>,
+    tlsf: Option<TlsfAllocator<B>>,
     // chunk: Option<ChunkAllocator>,
-    used: u64,
-    effective: u64,
+    /// Pool allocators by slot size and resource kind. Created on first use.
+    pools: HashMap<(u64, Option<ResourceKind>), PoolAllocator<B>>,
+    live: LiveBlocksTable,
+}
+
+/// Dynamic allocator serving small allocations for a subset of threads.
+#[derive(Debug)]
+struct Shard<B: gfx_hal::Backend> {
+    dynamic: DynamicAllocator<B>,
+    live: LiveBlocksTable,
+}
+
+#[derive(Debug)]
+pub(super) struct MemoryType<B: gfx_hal::Backend> {
+    memory_index: u32,
+    heap_index: usize,
+    properties: Properties,
+    non_coherent_atom_size: u64,
+    buffer_image_granularity: u64,
+    pool: Option<PoolConfig>,
+    allocators: Mutex<Allocators<B>>,
+    shards: Vec<Mutex<Shard<B>>>,
+    shard_max_size: u64,
+    linear_max_size: Option<u64>,
+    dynamic_max_size: Option<u64>,
+    tlsf_max_size: Option<u64>,
+    used: AtomicU64,
+    effective: AtomicU64,
 }
 
 impl<B> MemoryType<B>
@@ -26,23 +69,71 @@ where
         properties: Properties,
         config: HeapsConfig,
         non_coherent_atom_size: u64,
+        buffer_image_granularity: u64,
     ) -> Self {
+        let linear = if properties.contains(Properties::CPU_VISIBLE) {
+            config.linear.map(|config| {
+                LinearAllocator::new(memory_type, properties, config, non_coherent_atom_size)
+                    .with_buffer_image_granularity(buffer_image_granularity)
+            })
+        } else {
+            None
+        };
+        let dynamic = config.dynamic.map(|config| {
+            DynamicAllocator::new(memory_type, properties, config, non_coherent_atom_size)
+                .with_buffer_image_granularity(buffer_image_granularity)
+        });
+        let tlsf = config.tlsf.map(|config| {
+            TlsfAllocator::new(memory_type, properties, config, non_coherent_atom_size)
+                .with_buffer_image_granularity(buffer_image_granularity)
+        });
+
+        let (shards, shard_max_size) = match (config.dynamic, config.shards) {
+            (Some(dynamic_config), Some(shards_config)) if shards_config.count > 0 => {
+                let shards = (0..shards_config.count)
+                    .map(|_| {
+                        Mutex::new(Shard {
+                            dynamic: DynamicAllocator::new(
+                                memory_type,
+                                properties,
+                                dynamic_config,
+                                non_coherent_atom_size,
+                            )
+                            .with_buffer_image_granularity(buffer_image_granularity),
+                            live: LiveBlocksTable::default(),
+                        })
+                    })
+                    .collect();
+                let max_size = dynamic.as_ref().map_or(0, |dynamic| {
+                    shards_config.max_size.min(dynamic.max_allocation())
+                });
+                (shards, max_size)
+            }
+            _ => (Vec::new(), 0),
+        };
+
         MemoryType {
+            memory_index: memory_type.0 as u32,
             properties,
             heap_index,
-            dedicated: DedicatedAllocator::new(memory_type, properties, non_coherent_atom_size),
-            linear: if properties.contains(Properties::CPU_VISIBLE) {
-                config.linear.map(|config| {
-                    LinearAllocator::new(memory_type, properties, config, non_coherent_atom_size)
-                })
-            } else {
-                None
-            },
-            dynamic: config.dynamic.map(|config| {
-                DynamicAllocator::new(memory_type, properties, config, non_coherent_atom_size)
+            non_coherent_atom_size,
+            buffer_image_granularity,
+            pool: config.pool,
+            linear_max_size: linear.as_ref().map(LinearAllocator::max_allocation),
+            dynamic_max_size: dynamic.as_ref().map(DynamicAllocator::max_allocation),
+            tlsf_max_size: tlsf.as_ref().map(TlsfAllocator::max_allocation),
+            allocators: Mutex::new(Allocators {
+                dedicated: DedicatedAllocator::new(memory_type, properties, non_coherent_atom_size),
+                linear,
+                dynamic,
+                tlsf,
+                pools: HashMap::new(),
+                live: LiveBlocksTable::default(),
             }),
-            used: 0,
-            effective: 0,
+            shards,
+            shard_max_size,
+            used: AtomicU64::new(0),
+            effective: AtomicU64::new(0),
         }
     }
 
@@ -54,105 +145,292 @@ where
         self.heap_index
     }
 
+    /// Get shared dynamic allocator or allocator of the shard with specified index.
+    pub(super) fn dynamic_mut(&mut self, shard: Option<usize>) -> Option<&mut DynamicAllocator<B>> {
+        match shard {
+            None => self.allocators.get_mut().dynamic.as_mut(),
+            Some(index) => Some(&mut self.shards.get_mut(index)?.get_mut().dynamic),
+        }
+    }
+
+    /// Allocate block for the `usage`.
+    /// If `pooled` is set and pools are configured for this memory type
+    /// block is allocated from pool of slots of `size` rounded up to `align`.
     pub(super) fn alloc(
-        &mut self,
+        &self,
         device: &B::Device,
         usage: impl MemoryUsage,
         size: u64,
         align: u64,
+        pooled: bool,
     ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
-        let (block, allocated) = self.alloc_impl(device, usage, size, align)?;
-        self.effective += block.size();
-        self.used += allocated;
+        let tag = usage.tag();
+        let resource = usage.resource_kind();
+        let kind = match self.pool {
+            Some(_) if pooled => Kind::Pool,
+            _ => self.kind(&usage, size),
+        };
+        let (block, allocated) = match kind {
+            Kind::Dynamic if size <= self.shard_max_size => {
+                self.alloc_shard(device, tag, resource, size, align)?
+            }
+            Kind::Pool => {
+                let mut allocators = self.allocators.lock();
+                let (block, allocated) =
+                    self.alloc_pool(&mut allocators, device, resource, size, align)?;
+                allocators
+                    .live
+                    .allocated(tag, self.memory_index, block.size());
+                (block, allocated)
+            }
+            kind => {
+                let mut allocators = self.allocators.lock();
+                let (block, allocated) = allocators.alloc(device, kind, resource, size, align)?;
+                allocators
+                    .live
+                    .allocated(tag, self.memory_index, block.size());
+                (block, allocated)
+            }
+        };
+        self.effective.fetch_add(block.size(), Ordering::AcqRel);
+        self.used.fetch_add(allocated, Ordering::AcqRel);
         Ok((block, allocated))
     }
 
-    fn alloc_impl(
-        &mut self,
-        device: &B::Device,
-        usage: impl MemoryUsage,
-        size: u64,
-        align: u64,
-    ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
-        match (self.dynamic.as_mut(), self.linear.as_mut()) {
-            (Some(dynamic), Some(linear)) => {
-                if dynamic.max_allocation() >= size
+    /// Choose allocator for the `usage`.
+    fn kind(&self, usage: &impl MemoryUsage, size: u64) -> Kind {
+        let fits = |max_size: Option<u64>| match max_size {
+            Some(max_size) => max_size >= size,
+            None => false,
+        };
+
+        if self.tlsf_max_size.is_some() {
+            let fitness = usage.allocator_fitness(Kind::Tlsf);
+            if fits(self.tlsf_max_size)
+                && fitness > 0
+                && (self.dynamic_max_size.is_none()
+                    || fitness >= usage.allocator_fitness(Kind::Dynamic))
+                && (self.linear_max_size.is_none()
+                    || fitness >= usage.allocator_fitness(Kind::Linear))
+            {
+                return Kind::Tlsf;
+            }
+        }
+
+        match (self.dynamic_max_size, self.linear_max_size) {
+            (Some(_), Some(_)) => {
+                if fits(self.dynamic_max_size)
                     && usage.allocator_fitness(Kind::Dynamic)
                         > usage.allocator_fitness(Kind::Linear)
                 {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
-                } else if linear.max_allocation() >= size
-                    && usage.allocator_fitness(Kind::Linear) > 0
-                {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
+                    Kind::Dynamic
+                } else if fits(self.linear_max_size) && usage.allocator_fitness(Kind::Linear) > 0 {
+                    Kind::Linear
                 } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
+                    Kind::Dedicated
                 }
             }
-            (Some(dynamic), None) => {
-                if dynamic.max_allocation() >= size && usage.allocator_fitness(Kind::Dynamic) > 0 {
-                    dynamic
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dynamic(block), size))
+            (Some(_), None) => {
+                if fits(self.dynamic_max_size) && usage.allocator_fitness(Kind::Dynamic) > 0 {
+                    Kind::Dynamic
                 } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
+                    Kind::Dedicated
                 }
             }
-            (None, Some(linear)) => {
-                if linear.max_allocation() >= size && usage.allocator_fitness(Kind::Linear) > 0 {
-                    linear
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Linear(block), size))
+            (None, Some(_)) => {
+                if fits(self.linear_max_size) && usage.allocator_fitness(Kind::Linear) > 0 {
+                    Kind::Linear
                 } else {
-                    self.dedicated
-                        .alloc(device, size, align)
-                        .map(|(block, size)| (BlockFlavor::Dedicated(block), size))
+                    Kind::Dedicated
                 }
             }
-            (None, None) => self
-                .dedicated
-                .alloc(device, size, align)
-                .map(|(block, size)| (BlockFlavor::Dedicated(block), size)),
+            (None, None) => Kind::Dedicated,
         }
     }
 
-    pub(super) fn free(&mut self, device: &B::Device, block: BlockFlavor<B>) -> u64 {
-        match block {
-            BlockFlavor::Dedicated(block) => self.dedicated.free(device, block),
-            BlockFlavor::Linear(block) => self.linear.as_mut().unwrap().free(device, block),
-            BlockFlavor::Dynamic(block) => self.dynamic.as_mut().unwrap().free(device, block),
-        }
+    /// Allocate from the shard of current thread.
+    /// If it is busy any other free shard is used,
+    /// so that threads wait only if all shards are busy.
+    fn alloc_shard(
+        &self,
+        device: &B::Device,
+        tag: Option<AllocationTag>,
+        resource: Option<ResourceKind>,
+        size: u64,
+        align: u64,
+    ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
+        let count = self.shards.len();
+        let home = THREAD_INDEX.with(|index| *index) % count;
+        let (index, mut shard) = (0..count)
+            .map(|offset| (home + offset) % count)
+            .find_map(|index| Some((index, self.shards[index].try_lock()?)))
+            .unwrap_or_else(|| (home, self.shards[home].lock()));
+
+        let (block, allocated) = shard
+            .dynamic
+            .alloc_resource(device, size, align, resource)?;
+        shard.live.allocated(tag, self.memory_index, block.size());
+        Ok((BlockFlavor::Shard(index, block), allocated))
+    }
+
+    /// Allocate from pool of matching slot size, creating it if necessary.
+    /// Slots that are not multiple of buffer-image granularity
+    /// are pooled separately for each resource kind.
+    fn alloc_pool(
+        &self,
+        allocators: &mut Allocators<B>,
+        device: &B::Device,
+        resource: Option<ResourceKind>,
+        size: u64,
+        align: u64,
+    ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
+        let config = self.pool.expect("Pools must be configured");
+        let slot_size = PoolAllocator::<B>::slot_size(
+            self.properties,
+            size,
+            align,
+            self.non_coherent_atom_size,
+        );
+        let resource = if aligned(slot_size, self.buffer_image_granularity) == slot_size {
+            None
+        } else {
+            resource
+        };
+        let pool = allocators
+            .pools
+            .entry((slot_size, resource))
+            .or_insert_with(|| {
+                PoolAllocator::new(
+                    gfx_hal::MemoryTypeId(self.memory_index as usize),
+                    self.properties,
+                    slot_size,
+                    config,
+                    self.non_coherent_atom_size,
+                )
+            });
+        let (block, allocated) = pool.alloc(device, size, align)?;
+        Ok((BlockFlavor::Pool(resource, block), allocated))
+    }
+
+    pub(super) fn free(
+        &self,
+        device: &B::Device,
+        block: BlockFlavor<B>,
+        tag: Option<AllocationTag>,
+    ) -> u64 {
+        let size = block.size();
+        let freed = match block {
+            BlockFlavor::Shard(index, block) => {
+                let mut shard = self.shards[index].lock();
+                shard.live.freed(tag, self.memory_index, size);
+                shard.dynamic.free(device, block)
+            }
+            block => {
+                let mut allocators = self.allocators.lock();
+                allocators.live.freed(tag, self.memory_index, size);
+                allocators.free(device, block)
+            }
+        };
+        self.effective.fetch_sub(size, Ordering::AcqRel);
+        self.used.fetch_sub(freed, Ordering::AcqRel);
+        freed
     }
 
     pub(super) fn dispose(self, device: &B::Device) {
         log::trace!("Dispose memory allocators");
 
-        if let Some(linear) = self.linear {
+        let allocators = self.allocators.into_inner();
+        if let Some(linear) = allocators.linear {
             linear.dispose(device);
             log::trace!("Linear allocator disposed");
         }
-        if let Some(dynamic) = self.dynamic {
+        if let Some(dynamic) = allocators.dynamic {
             dynamic.dispose();
             log::trace!("Dynamic allocator disposed");
         }
+        if let Some(tlsf) = allocators.tlsf {
+            tlsf.dispose();
+            log::trace!("Tlsf allocator disposed");
+        }
+        for pool in allocators.pools.into_values() {
+            pool.dispose(device);
+        }
+        for shard in self.shards {
+            shard.into_inner().dynamic.dispose();
+        }
+    }
+
+    /// Add live blocks of this memory type to the `table`.
+    pub(super) fn live(&self, table: &mut LiveBlocksTable) {
+        table.merge(&self.allocators.lock().live);
+        for shard in &self.shards {
+            table.merge(&shard.lock().live);
+        }
     }
 
     pub(super) fn utilization(&self) -> MemoryTypeUtilization {
         MemoryTypeUtilization {
             utilization: MemoryUtilization {
-                used: self.used,
-                effective: self.effective,
+                used: self.used.load(Ordering::Acquire),
+                effective: self.effective.load(Ordering::Acquire),
             },
             properties: self.properties,
             heap_index: self.heap_index,
         }
     }
 }
+
+impl<B> Allocators<B>
+where
+    B: gfx_hal::Backend,
+{
+    fn alloc(
+        &mut self,
+        device: &B::Device,
+        kind: Kind,
+        resource: Option<ResourceKind>,
+        size: u64,
+        align: u64,
+    ) -> Result<(BlockFlavor<B>, u64), gfx_hal::device::AllocationError> {
+        match kind {
+            Kind::Tlsf => self
+                .tlsf
+                .as_mut()
+                .unwrap()
+                .alloc_resource(device, size, align, resource)
+                .map(|(block, size)| (BlockFlavor::Tlsf(block), size)),
+            Kind::Dynamic => self
+                .dynamic
+                .as_mut()
+                .unwrap()
+                .alloc_resource(device, size, align, resource)
+                .map(|(block, size)| (BlockFlavor::Dynamic(block), size)),
+            Kind::Linear => self
+                .linear
+                .as_mut()
+                .unwrap()
+                .alloc_resource(device, size, align, resource)
+                .map(|(block, size)| (BlockFlavor::Linear(block), size)),
+            Kind::Pool => unreachable!("Pool blocks are allocated with slot size"),
+            Kind::Dedicated => self
+                .dedicated
+                .alloc(device, size, align)
+                .map(|(block, size)| (BlockFlavor::Dedicated(block), size)),
+        }
+    }
+
+    fn free(&mut self, device: &B::Device, block: BlockFlavor<B>) -> u64 {
+        match block {
+            BlockFlavor::Dedicated(block) => self.dedicated.free(device, block),
+            BlockFlavor::Linear(block) => self.linear.as_mut().unwrap().free(device, block),
+            BlockFlavor::Dynamic(block) => self.dynamic.as_mut().unwrap().free(device, block),
+            BlockFlavor::Tlsf(block) => self.tlsf.as_mut().unwrap().free(device, block),
+            BlockFlavor::Pool(resource, block) => self
+                .pools
+                .get_mut(&(block.size(), resource))
+                .expect("Unable to get pool from which block was allocated")
+                .free(device, block),
+            BlockFlavor::Shard(..) => unreachable!("Shard blocks are freed into shards"),
+        }
+    }
+}
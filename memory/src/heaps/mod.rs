@@ -1,10 +1,41 @@
+mod defragmentation;
+mod fault;
 mod heap;
 mod memory_type;
+mod policy;
+mod tags;
+
+pub use self::{
+    defragmentation::{DefragmentationConfig, DefragmentationPlan},
+    fault::FaultPolicy,
+    heap::{BudgetEvent, HeapBudget},
+    policy::{
+        FitnessPolicy, MemoryFallback, MemoryTypeCandidate, MemoryTypePolicy, PreferProperties,
+    },
+};
 
 use {
-    self::{heap::MemoryHeap, memory_type::MemoryType},
-    crate::{allocator::*, block::Block, mapping::*, usage::MemoryUsage, util::*, utilization::*},
-    std::ops::Range,
+    self::{
+        fault::FaultInjection,
+        heap::{MemoryHeap, Reservation},
+        memory_type::MemoryType,
+        policy::record_fallback,
+        tags::LiveBlocksTable,
+    },
+    crate::{
+        allocator::*,
+        block::Block,
+        mapping::*,
+        trace::TraceRecorder,
+        usage::{AllocationTag, MemoryUsage, ResourceKind},
+        util::*,
+        utilization::*,
+    },
+    parking_lot::Mutex,
+    std::{
+        ops::Range,
+        sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
 /// Possible errors returned by `Heaps`.
@@ -15,6 +46,8 @@ pub enum HeapsError {
     AllocationError(gfx_hal::device::AllocationError),
     /// No memory types among required for resource with requested properties was found.
     NoSuitableMemory(u32, gfx_hal::memory::Properties),
+    /// Allocation would exceed budget of the heap with specified index.
+    BudgetExceeded(usize, u64),
 }
 
 impl std::fmt::Display for HeapsError {
@@ -26,6 +59,11 @@ impl std::fmt::Display for HeapsError {
                 "Memory type among ({}) with properties ({:?}) not found",
                 e, e2
             ),
+            HeapsError::BudgetExceeded(heap, budget) => write!(
+                f,
+                "Allocation exceeds budget ({} bytes) of heap {}",
+                budget, heap
+            ),
         }
     }
 }
@@ -52,13 +90,65 @@ pub struct HeapsConfig {
 
     /// Config for dynamic sub-allocator.
     pub dynamic: Option<DynamicConfig>,
+
+    /// Config for two-level segregated fit sub-allocator.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tlsf: Option<TlsfConfig>,
+
+    /// Config for sharding of small dynamic allocations.
+    /// Requires `dynamic` config.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub shards: Option<ShardsConfig>,
+
+    /// Config for pools of fixed-size slots.
+    /// Used for allocations made with `Heaps::allocate_pooled`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pool: Option<PoolConfig>,
+}
+
+/// Config for sharding of small allocations between threads.
+///
+/// Each shard is a separate dynamic sub-allocator with its own lock.
+/// Threads allocate from their own shard, or from any other one that is not busy,
+/// so that concurrent allocations from the same memory type don't wait for each other.
+/// More shards mean less contention, but more memory held in partially used chunks.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShardsConfig {
+    /// Number of shards.
+    pub count: usize,
+
+    /// Maximum size of allocations served by shards.
+    /// Larger allocations are served by shared sub-allocators.
+    pub max_size: u64,
+}
+
+/// Callback invoked when heap usage crosses budget threshold.
+struct BudgetCallback(Box<dyn FnMut(BudgetEvent) + Send>);
+
+impl std::fmt::Debug for BudgetCallback {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "BudgetCallback")
+    }
 }
 
 /// Heaps available on particular physical device.
+///
+/// Blocks can be allocated and freed concurrently from multiple threads.
+/// Each memory type is locked separately
+/// and small allocations are spread among shards if configured.
+/// Configuration methods require exclusive access.
 #[derive(Debug)]
 pub struct Heaps<B: gfx_hal::Backend> {
     types: Vec<MemoryType<B>>,
     heaps: Vec<MemoryHeap>,
+    budget_callback: Option<Mutex<BudgetCallback>>,
+    recording: AtomicBool,
+    recorder: Mutex<Option<TraceRecorder>>,
+    next_block_id: AtomicU64,
+    faults: Option<Mutex<FaultInjection>>,
+    policy: Box<dyn MemoryTypePolicy>,
+    fallbacks: Mutex<Vec<MemoryFallback>>,
 }
 
 impl<B> Heaps<B>
@@ -66,7 +156,16 @@ where
     B: gfx_hal::Backend,
 {
     /// This must be called with `gfx_hal::memory::Properties` fetched from physical device.
-    pub unsafe fn new<P, H>(types: P, heaps: H, non_coherent_atom_size: u64) -> Self
+    ///
+    /// `non_coherent_atom_size` and `buffer_image_granularity` are limits of the physical device.
+    /// Linear and non-linear resources are kept `buffer_image_granularity` apart
+    /// when allocated with `ResourceKind` attached to usage.
+    pub unsafe fn new<P, H>(
+        types: P,
+        heaps: H,
+        non_coherent_atom_size: u64,
+        buffer_image_granularity: u64,
+    ) -> Self
     where
         P: IntoIterator<Item = (gfx_hal::memory::Properties, u32, HeapsConfig)>,
         H: IntoIterator<Item = u64>,
@@ -97,10 +196,98 @@ where
                         properties,
                         config,
                         non_coherent_atom_size,
+                        buffer_image_granularity,
                     )
                 })
                 .collect(),
             heaps,
+            budget_callback: None,
+            recording: AtomicBool::new(false),
+            recorder: Mutex::new(None),
+            next_block_id: AtomicU64::new(0),
+            faults: None,
+            policy: Box::new(FitnessPolicy),
+            fallbacks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Set soft limit on the memory allocated from the heap.
+    ///
+    /// Allocations that would make heap usage exceed the budget
+    /// fall back to other suitable memory types
+    /// or fail with `HeapsError::BudgetExceeded`.
+    /// Memory already allocated is not affected.
+    ///
+    /// Requested size is reserved atomically before allocation,
+    /// so concurrent allocations can't exceed the budget together.
+    /// Allocators may still allocate whole chunks larger than requested size.
+    pub fn set_budget(&mut self, heap_index: usize, budget: Option<HeapBudget>) {
+        self.heaps[heap_index].set_budget(budget);
+    }
+
+    /// Get budget of the heap.
+    pub fn budget(&self, heap_index: usize) -> Option<&HeapBudget> {
+        self.heaps[heap_index].budget()
+    }
+
+    /// Set callback to invoke when heap usage crosses one of budget thresholds.
+    ///
+    /// Callback is invoked from within allocation and deallocation calls
+    /// and must not use these `Heaps`.
+    pub fn set_budget_callback(&mut self, callback: impl FnMut(BudgetEvent) + Send + 'static) {
+        self.budget_callback = Some(Mutex::new(BudgetCallback(Box::new(callback))));
+    }
+
+    /// Set policy to fail allocations on purpose.
+    /// Allocations are counted from this call.
+    ///
+    /// This is intended for testing of out-of-memory error handling.
+    pub fn set_fault_policy(&mut self, policy: Option<FaultPolicy>) {
+        self.faults = policy.map(|policy| Mutex::new(FaultInjection::new(policy)));
+    }
+
+    /// Start recording allocations and deallocations into trace.
+    /// Replaces recorder set before.
+    ///
+    /// If writing into recorder fails recording stops.
+    pub fn start_recording(&mut self, recorder: TraceRecorder) {
+        *self.recorder.get_mut() = Some(recorder);
+        *self.recording.get_mut() = true;
+    }
+
+    /// Stop recording allocations and deallocations.
+    /// Returns recorder if recording was active.
+    pub fn stop_recording(&mut self) -> Option<TraceRecorder> {
+        *self.recording.get_mut() = false;
+        let mut recorder = self.recorder.get_mut().take()?;
+        if let Err(err) = recorder.flush() {
+            log::error!("Failed to flush allocations trace: {}", err);
+        }
+        Some(recorder)
+    }
+
+    fn record(&self, record: impl FnOnce(&mut TraceRecorder) -> std::io::Result<()>) {
+        if !self.recording.load(Ordering::Acquire) {
+            return;
+        }
+        let mut recorder = self.recorder.lock();
+        if let Some(Err(err)) = recorder.as_mut().map(record) {
+            log::error!("Failed to record allocations trace: {}", err);
+            *recorder = None;
+            self.recording.store(false, Ordering::Release);
+        }
+    }
+
+    fn notify_budget(&self, heap_index: usize, before: u64, after: u64) {
+        if let Some(callback) = &self.budget_callback {
+            let events = self.heaps[heap_index].crossed(heap_index, before, after);
+            if !events.is_empty() {
+                let BudgetCallback(callback) = &mut *callback.lock();
+                for event in events {
+                    log::trace!("Budget threshold crossed: {:?}", event);
+                    callback(event);
+                }
+            }
         }
     }
 
@@ -109,50 +296,151 @@ where
     /// for intended `usage`,
     /// with `size`
     /// and `align` requirements.
+    ///
+    /// Block is accounted under the tag of the `usage`.
+    /// Use `Tagged` usage to attach one.
     pub fn allocate(
-        &mut self,
+        &self,
+        device: &B::Device,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        self.allocate_impl(device, mask, usage, size, align, false)
+    }
+
+    /// Allocate memory block from pool of fixed-size slots
+    /// from one of memory types specified by `mask`,
+    /// for intended `usage`.
+    ///
+    /// Slot size is `size` rounded up to `align`,
+    /// and all blocks of the same slot size share the pool.
+    /// Memory types without `HeapsConfig::pool` serve the block as `allocate` does.
+    pub fn allocate_pooled(
+        &self,
         device: &B::Device,
         mask: u32,
         usage: impl MemoryUsage,
         size: u64,
         align: u64,
+    ) -> Result<MemoryBlock<B>, HeapsError> {
+        self.allocate_impl(device, mask, usage, size, align, true)
+    }
+
+    fn allocate_impl(
+        &self,
+        device: &B::Device,
+        mask: u32,
+        usage: impl MemoryUsage,
+        size: u64,
+        align: u64,
+        pooled: bool,
     ) -> Result<MemoryBlock<B>, HeapsError> {
         debug_assert!(fits_u32(self.types.len()));
 
-        let (memory_index, _, _) = {
-            let suitable_types = self
-                .types
-                .iter()
-                .enumerate()
-                .filter(|(index, _)| (mask & (1u32 << index)) != 0)
-                .filter_map(|(index, mt)| {
-                    if mt.properties().contains(usage.properties_required()) {
-                        let fitness = usage.memory_fitness(mt.properties());
-                        Some((index, mt, fitness))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<smallvec::SmallVec<[_; 64]>>();
+        if let Some(faults) = &self.faults {
+            if faults.lock().fail(size) {
+                log::warn!("Injected failure of allocation of {} bytes", size);
+                return Err(gfx_hal::device::OutOfMemory::Device.into());
+            }
+        }
+
+        let mut candidates = self
+            .types
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| (mask & (1u32 << index)) != 0)
+            .filter(|(_, mt)| mt.properties().contains(usage.properties_required()))
+            .filter_map(|(index, mt)| {
+                let heap = &self.heaps[mt.heap_index()];
+                let candidate = MemoryTypeCandidate {
+                    memory_type: index as u32,
+                    heap_index: mt.heap_index(),
+                    properties: mt.properties(),
+                    available: heap.available(),
+                    headroom: heap.headroom(),
+                };
+                let rank = self.policy.rank(&usage, &candidate)?;
+                Some((rank, candidate))
+            })
+            .collect::<smallvec::SmallVec<[_; 64]>>();
+
+        // Highest rank first. Among equally ranked types later one is preferred.
+        candidates
+            .sort_by_key(|&(rank, candidate)| std::cmp::Reverse((rank, candidate.memory_type)));
 
-            if suitable_types.is_empty() {
+        let preferred = match candidates.first() {
+            Some((_, candidate)) => candidate.memory_type,
+            None => {
                 return Err(HeapsError::NoSuitableMemory(
                     mask,
                     usage.properties_required(),
-                ));
+                ))
             }
-
-            suitable_types
-                .into_iter()
-                .filter(|(_, mt, _)| self.heaps[mt.heap_index()].available() > size + align)
-                .max_by_key(|&(_, _, fitness)| fitness)
-                .ok_or_else(|| {
-                    log::error!("All suitable heaps are exhausted. {:#?}", self);
-                    gfx_hal::device::OutOfMemory::Device
-                })?
         };
 
-        self.allocate_from(device, memory_index as u32, usage, size, align)
+        // Prefer types of heaps with enough budget headroom.
+        // Otherwise allocation may still fit into already allocated memory.
+        let available = candidates
+            .iter()
+            .map(|&(_, candidate)| candidate)
+            .filter(|candidate| candidate.available > size + align);
+        let order = available
+            .clone()
+            .filter(|candidate| candidate.headroom > size + align)
+            .chain(available.filter(|candidate| candidate.headroom <= size + align));
+
+        let mut error = None;
+        for candidate in order {
+            match self.allocate_from(device, candidate.memory_type, &usage, size, align, pooled) {
+                Ok(block) => {
+                    if candidate.memory_type != preferred {
+                        log::debug!(
+                            "Allocate {} bytes from memory type {} instead of preferred {}",
+                            size,
+                            candidate.memory_type,
+                            preferred
+                        );
+                        record_fallback(
+                            &mut self.fallbacks.lock(),
+                            preferred,
+                            candidate.memory_type,
+                            size,
+                        );
+                    }
+                    return Ok(block);
+                }
+                Err(HeapsError::AllocationError(
+                    gfx_hal::device::AllocationError::OutOfMemory(oom),
+                )) => {
+                    log::trace!(
+                        "Memory type {} is out of memory. Trying next one",
+                        candidate.memory_type
+                    );
+                    error = Some(oom.into());
+                }
+                Err(err @ HeapsError::BudgetExceeded(..)) => {
+                    log::trace!("{}. Trying next memory type", err);
+                    error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        log::error!("All suitable heaps are exhausted. {:#?}", self);
+        Err(error.unwrap_or_else(|| gfx_hal::device::OutOfMemory::Device.into()))
+    }
+
+    /// Set policy to select memory types for allocations.
+    /// `FitnessPolicy` is used by default.
+    pub fn set_memory_type_policy(&mut self, policy: impl MemoryTypePolicy + 'static) {
+        self.policy = Box::new(policy);
+    }
+
+    /// Get allocations served from memory types other than preferred by the policy.
+    pub fn fallbacks(&self) -> Vec<MemoryFallback> {
+        self.fallbacks.lock().clone()
     }
 
     /// Allocate memory block
@@ -161,12 +449,13 @@ where
     /// with `size`
     /// and `align` requirements.
     fn allocate_from(
-        &mut self,
+        &self,
         device: &B::Device,
         memory_index: u32,
         usage: impl MemoryUsage,
         size: u64,
         align: u64,
+        pooled: bool,
     ) -> Result<MemoryBlock<B>, HeapsError> {
         log::trace!(
             "Allocate memory block: type '{}', usage '{:#?}', size: '{}', align: '{}'",
@@ -177,53 +466,100 @@ where
         );
         assert!(fits_usize(memory_index));
 
-        let ref mut memory_type = self.types[memory_index as usize];
-        let ref mut memory_heap = self.heaps[memory_type.heap_index()];
+        let memory_type = &self.types[memory_index as usize];
+        let heap_index = memory_type.heap_index();
+        let memory_heap = &self.heaps[heap_index];
 
-        if memory_heap.available() < size {
-            return Err(gfx_hal::device::OutOfMemory::Device.into());
-        }
+        let before = match memory_heap.reserve(size) {
+            Ok(before) => before,
+            Err(Reservation::Exhausted) => return Err(gfx_hal::device::OutOfMemory::Device.into()),
+            Err(Reservation::OverBudget) => {
+                let budget = memory_heap.budget().map_or(0, |budget| budget.size);
+                log::trace!(
+                    "Allocation of {} bytes exceeds budget ({} bytes) of heap {}",
+                    size,
+                    budget,
+                    heap_index
+                );
+                return Err(HeapsError::BudgetExceeded(heap_index, budget));
+            }
+        };
 
-        let (block, allocated) = memory_type.alloc(device, usage, size, align)?;
-        memory_heap.allocated(allocated, block.size());
+        let tag = usage.tag();
+        let (block, allocated) = match memory_type.alloc(device, &usage, size, align, pooled) {
+            Ok(allocated) => allocated,
+            Err(err) => {
+                memory_heap.release(size);
+                return Err(err.into());
+            }
+        };
+        memory_heap.commit(size, allocated, block.size());
 
-        Ok(MemoryBlock {
+        self.notify_budget(heap_index, before, before + allocated);
+
+        let block = MemoryBlock {
             block,
             memory_index,
-        })
+            tag,
+            id: self.next_block_id.fetch_add(1, Ordering::Relaxed),
+        };
+
+        self.record(|recorder| recorder.allocate(block.id, memory_index, &usage, size, align));
+
+        Ok(block)
     }
 
     /// Free memory block.
     ///
     /// Memory block must be allocated from this heap.
-    pub fn free(&mut self, device: &B::Device, block: MemoryBlock<B>) {
+    pub fn free(&self, device: &B::Device, block: MemoryBlock<B>) {
         // trace!("Free block '{:#?}'", block);
         let memory_index = block.memory_index;
         debug_assert!(fits_usize(memory_index));
         let size = block.size();
+        self.record(|recorder| recorder.free(block.id));
 
-        let ref mut memory_type = self.types[memory_index as usize];
-        let ref mut memory_heap = self.heaps[memory_type.heap_index()];
-        let freed = memory_type.free(device, block.block);
-        memory_heap.freed(freed, size);
+        let memory_type = &self.types[memory_index as usize];
+        let heap_index = memory_type.heap_index();
+        let freed = memory_type.free(device, block.block, block.tag);
+        let before = self.heaps[heap_index].freed(freed, size);
+        self.notify_budget(heap_index, before, before - freed);
     }
 
     /// Dispose of allocator.
     /// Cleanup allocators before dropping.
     /// Will panic if memory instances are left allocated.
     pub fn dispose(self, device: &B::Device) {
+        let live = self.live();
+        if !live.is_empty() {
+            log::error!("Memory blocks leaked:\n{}", live.report());
+        }
         for mt in self.types {
             mt.dispose(device)
         }
     }
 
+    fn live(&self) -> LiveBlocksTable {
+        let mut live = LiveBlocksTable::default();
+        for memory_type in &self.types {
+            memory_type.live(&mut live);
+        }
+        live
+    }
+
     /// Get memory utilization.
     pub fn utilization(&self) -> TotalMemoryUtilization {
         TotalMemoryUtilization {
             heaps: self.heaps.iter().map(MemoryHeap::utilization).collect(),
             types: self.types.iter().map(MemoryType::utilization).collect(),
+            tags: self.live().tags(),
         }
     }
+
+    /// Get report of live memory blocks by tag, memory type and size.
+    pub fn report(&self) -> MemoryReport {
+        self.live().report()
+    }
 }
 
 /// Memory block allocated from `Heaps`.
@@ -231,6 +567,10 @@ where
 pub struct MemoryBlock<B: gfx_hal::Backend> {
     block: BlockFlavor<B>,
     memory_index: u32,
+    tag: Option<AllocationTag>,
+    /// Identifies the block in allocation traces.
+    /// Unlike memory object address it doesn't change when block is moved.
+    id: u64,
 }
 
 impl<B> MemoryBlock<B>
@@ -241,6 +581,11 @@ where
     pub fn memory_type(&self) -> u32 {
         self.memory_index
     }
+
+    /// Get tag block was allocated under.
+    pub fn tag(&self) -> Option<AllocationTag> {
+        self.tag
+    }
 }
 
 #[derive(Debug)]
@@ -248,6 +593,9 @@ enum BlockFlavor<B: gfx_hal::Backend> {
     Dedicated(DedicatedBlock<B>),
     Linear(LinearBlock<B>),
     Dynamic(DynamicBlock<B>),
+    Tlsf(TlsfBlock<B>),
+    Shard(usize, DynamicBlock<B>),
+    Pool(Option<ResourceKind>, PoolBlock<B>),
     // Chunk(ChunkBlock<B>),
 }
 
@@ -258,6 +606,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Tlsf($block) => $expr,
+            Shard(_, $block) => $expr,
+            Pool(_, $block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -267,6 +618,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Tlsf($block) => $expr,
+            Shard(_, $block) => $expr,
+            Pool(_, $block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -276,6 +630,9 @@ macro_rules! any_block {
             Dedicated($block) => $expr,
             Linear($block) => $expr,
             Dynamic($block) => $expr,
+            Tlsf($block) => $expr,
+            Shard(_, $block) => $expr,
+            Pool(_, $block) => $expr,
             // Chunk($block) => $expr,
         }
     }};
@@ -292,6 +649,9 @@ where
             Dedicated(block) => block.size(),
             Linear(block) => block.size(),
             Dynamic(block) => block.size(),
+            Tlsf(block) => block.size(),
+            Shard(_, block) => block.size(),
+            Pool(_, block) => block.size(),
             // Chunk(block) => block.size(),
         }
     }
@@ -328,3 +688,338 @@ where
         any_block!(&mut self.block => block.unmap(device))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{FaultPolicy, HeapBudget, Heaps, HeapsConfig, HeapsError, ShardsConfig},
+        crate::{
+            allocator::{DynamicConfig, LinearConfig, PoolConfig, TlsfConfig},
+            block::Block as _,
+            replay::{FakeBackend, FakeDevice},
+            usage::{
+                Data, ForResource,
+                ResourceKind::{Linear, NonLinear},
+                Tagged, Upload,
+            },
+        },
+        gfx_hal::{device::OutOfMemory, memory::Properties},
+        std::{ptr, sync::Arc, thread},
+    };
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn concurrent() {
+        let device = Arc::new(FakeDevice::new());
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 4 * MB,
+                min_device_allocation: MB,
+            }),
+            tlsf: None,
+            shards: Some(ShardsConfig {
+                count: 4,
+                max_size: 64 * 1024,
+            }),
+            pool: None,
+        };
+        let heaps: Arc<Heaps<FakeBackend>> = Arc::new(unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        });
+
+        let threads: Vec<_> = (0..8u64)
+            .map(|index| {
+                let device = device.clone();
+                let heaps = heaps.clone();
+                thread::spawn(move || {
+                    let mut blocks = Vec::new();
+                    for i in 0..256 {
+                        // Mix small sharded allocations with large shared ones.
+                        let size = if i % 16 == 0 { MB } else { 256 * (i % 8 + 1) };
+                        let block = heaps
+                            .allocate(&*device, !0, Tagged::new(Data, "worker"), size, 256)
+                            .unwrap();
+                        blocks.push(block);
+                        if (i + index) % 3 == 0 {
+                            heaps.free(&*device, blocks.swap_remove(0));
+                        }
+                    }
+                    for block in blocks {
+                        heaps.free(&*device, block);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let heaps = Arc::try_unwrap(heaps).unwrap();
+        assert!(heaps.report().blocks.is_empty());
+        let utilization = heaps.utilization();
+        assert_eq!(utilization.heaps[0].utilization.effective, 0);
+        assert_eq!(
+            utilization.heaps[0].utilization.used,
+            utilization.types[0].utilization.used
+        );
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn pooled() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: None,
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let pooled = HeapsConfig {
+            pool: Some(PoolConfig { chunk_size: MB }),
+            ..config
+        };
+        let heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![
+                    (Properties::DEVICE_LOCAL, 0, pooled),
+                    (Properties::DEVICE_LOCAL, 0, config),
+                ],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        };
+
+        let blocks: Vec<_> = (0..100)
+            .map(|_| heaps.allocate_pooled(&device, 1, Data, 200, 256).unwrap())
+            .collect();
+        assert!(blocks.iter().all(|block| block.size() == 256));
+        assert!(blocks
+            .iter()
+            .all(|block| ptr::eq(block.memory(), blocks[0].memory())));
+        assert_eq!(device.stats().allocations, 1);
+        assert_eq!(
+            heaps.utilization().types[0].utilization.effective,
+            100 * 256
+        );
+
+        // Memory types without pools allocate as usual.
+        let block = heaps.allocate_pooled(&device, 2, Data, 200, 256).unwrap();
+        assert_eq!(block.memory_type(), 1);
+        assert_eq!(block.size(), 200);
+        heaps.free(&device, block);
+
+        for block in blocks {
+            heaps.free(&device, block);
+        }
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn buffer_image_granularity() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: Some(LinearConfig { linear_size: MB }),
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: 4 * MB,
+                min_device_allocation: 64 * 1024,
+            }),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let properties = Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE | Properties::COHERENT;
+        let heaps: Heaps<FakeBackend> =
+            unsafe { Heaps::new(vec![(properties, 0, config)], vec![1024 * MB], 64, 1024) };
+
+        // Dynamic allocator places linear and non-linear resources into separate chunks.
+        let buffer = heaps
+            .allocate(&device, !0, ForResource::new(Data, Linear), 256, 256)
+            .unwrap();
+        let image = heaps
+            .allocate(&device, !0, ForResource::new(Data, NonLinear), 256, 256)
+            .unwrap();
+        let other = heaps
+            .allocate(&device, !0, ForResource::new(Data, Linear), 256, 256)
+            .unwrap();
+        assert!(!ptr::eq(buffer.memory(), image.memory()));
+        assert!(ptr::eq(buffer.memory(), other.memory()));
+
+        // Linear allocator pads blocks following block of other kind.
+        let first = heaps
+            .allocate(&device, !0, ForResource::new(Upload, Linear), 100, 4)
+            .unwrap();
+        let second = heaps
+            .allocate(&device, !0, ForResource::new(Upload, NonLinear), 100, 4)
+            .unwrap();
+        let third = heaps
+            .allocate(&device, !0, ForResource::new(Upload, NonLinear), 100, 4)
+            .unwrap();
+        assert_eq!(first.range(), 0..100);
+        assert_eq!(second.range(), 1024..1124);
+        assert_eq!(third.range(), 1124..1224);
+
+        for block in [buffer, image, other, first, second, third] {
+            heaps.free(&device, block);
+        }
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn tlsf_buffer_image_granularity() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: None,
+            tlsf: Some(TlsfConfig {
+                block_size_granularity: 256,
+                arena_size: 4 * MB,
+            }),
+            shards: None,
+            pool: None,
+        };
+        let heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1024,
+            )
+        };
+
+        // Linear and non-linear resources are allocated from separate arenas
+        // without padding blocks to the granularity.
+        let buffer = heaps
+            .allocate(&device, !0, ForResource::new(Data, Linear), 256, 256)
+            .unwrap();
+        let image = heaps
+            .allocate(&device, !0, ForResource::new(Data, NonLinear), 256, 256)
+            .unwrap();
+        let other = heaps
+            .allocate(&device, !0, ForResource::new(Data, Linear), 256, 256)
+            .unwrap();
+        assert!(!ptr::eq(buffer.memory(), image.memory()));
+        assert!(ptr::eq(buffer.memory(), other.memory()));
+        assert_eq!(buffer.size(), 256);
+        assert_eq!(other.range(), 256..512);
+        assert_eq!(device.stats().allocations, 2);
+
+        for block in [buffer, image, other] {
+            heaps.free(&device, block);
+        }
+        assert_eq!(device.stats().allocated, 0);
+        heaps.dispose(&device);
+    }
+
+    #[test]
+    fn budget() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: MB,
+                min_device_allocation: MB,
+            }),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let mut heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        };
+
+        let block = heaps.allocate(&device, !0, Data, 1024, 256).unwrap();
+        let used = heaps.utilization().heaps[0].utilization.used;
+        let allocations = device.stats().allocations;
+
+        // Requests over budget are rejected without touching the device,
+        // even if they could be served from existing chunks.
+        heaps.set_budget(0, Some(HeapBudget::new(used)));
+        match heaps.allocate(&device, !0, Data, 1024, 256) {
+            Err(HeapsError::BudgetExceeded(0, budget)) => assert_eq!(budget, used),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(device.stats().allocations, allocations);
+        assert_eq!(device.stats().frees, 0);
+
+        heaps.set_budget(0, Some(HeapBudget::new(used + 1024)));
+        let other = heaps.allocate(&device, !0, Data, 1024, 256).unwrap();
+        assert_eq!(device.stats().allocations, allocations);
+
+        heaps.free(&device, block);
+        heaps.free(&device, other);
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn fault_policy() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(DynamicConfig {
+                block_size_granularity: 256,
+                max_chunk_size: MB,
+                min_device_allocation: MB,
+            }),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let mut heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        };
+
+        heaps.set_fault_policy(Some(FaultPolicy::Nth(2)));
+        let block = heaps.allocate(&device, !0, Data, 1024, 256).unwrap();
+        let allocations = device.stats().allocations;
+
+        // Failed allocation doesn't touch the device, even if it fits into existing chunk.
+        match heaps.allocate(&device, !0, Data, 1024, 256) {
+            Err(HeapsError::AllocationError(err)) => {
+                assert_eq!(err, OutOfMemory::Device.into())
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+        assert_eq!(device.stats().allocations, allocations);
+        let other = heaps.allocate(&device, !0, Data, 1024, 256).unwrap();
+
+        heaps.set_fault_policy(Some(FaultPolicy::Above(MB)));
+        assert!(heaps.allocate(&device, !0, Data, 2 * MB, 256).is_err());
+        assert_eq!(device.stats().allocations, allocations);
+
+        heaps.set_fault_policy(None);
+        let large = heaps.allocate(&device, !0, Data, 2 * MB, 256).unwrap();
+
+        heaps.free(&device, block);
+        heaps.free(&device, other);
+        heaps.free(&device, large);
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::usage::MemoryUsage;
+
+/// Memory type considered for allocation.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryTypeCandidate {
+    /// Index of the memory type.
+    pub memory_type: u32,
+
+    /// Index of the heap memory type belongs to.
+    pub heap_index: usize,
+
+    /// Properties of the memory type.
+    pub properties: gfx_hal::memory::Properties,
+
+    /// Bytes that can be allocated from the heap before it is exhausted.
+    pub available: u64,
+
+    /// Bytes that can be allocated from the heap before its budget is exceeded.
+    pub headroom: u64,
+}
+
+/// Policy to select memory type for allocations made from `Heaps`.
+///
+/// Memory types are tried in order of decreasing rank.
+/// If allocation from the highest ranked type fails because its heap is exhausted
+/// or over budget, next one is tried and the fallback is recorded.
+pub trait MemoryTypePolicy: std::fmt::Debug + Send + Sync {
+    /// Rank memory type for allocation with `usage`.
+    /// Returns `None` if memory type must not be used for the usage.
+    ///
+    /// This is called only for memory types that have all properties required by the `usage`.
+    fn rank(&self, usage: &dyn MemoryUsage, candidate: &MemoryTypeCandidate) -> Option<u32>;
+}
+
+/// Policy that ranks memory types by `MemoryUsage::memory_fitness`.
+/// This is the default policy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FitnessPolicy;
+
+impl MemoryTypePolicy for FitnessPolicy {
+    fn rank(&self, usage: &dyn MemoryUsage, candidate: &MemoryTypeCandidate) -> Option<u32> {
+        Some(usage.memory_fitness(candidate.properties))
+    }
+}
+
+/// Policy that prefers memory types with specific properties
+/// for usages that require specific properties.
+/// Memory types are ranked by `MemoryUsage::memory_fitness` otherwise.
+///
+/// For example devices with resizable BAR expose large heap of
+/// `DEVICE_LOCAL | CPU_VISIBLE` memory, which is best for data written by host
+/// and read by device. `PreferProperties::new(CPU_VISIBLE, DEVICE_LOCAL)`
+/// makes all host-visible allocations prefer it.
+#[derive(Clone, Copy, Debug)]
+pub struct PreferProperties {
+    required: gfx_hal::memory::Properties,
+    preferred: gfx_hal::memory::Properties,
+}
+
+impl PreferProperties {
+    /// Prefer memory types with `preferred` properties
+    /// for usages that require all of `required` properties.
+    pub fn new(
+        required: gfx_hal::memory::Properties,
+        preferred: gfx_hal::memory::Properties,
+    ) -> Self {
+        PreferProperties {
+            required,
+            preferred,
+        }
+    }
+}
+
+impl MemoryTypePolicy for PreferProperties {
+    fn rank(&self, usage: &dyn MemoryUsage, candidate: &MemoryTypeCandidate) -> Option<u32> {
+        let fitness = usage.memory_fitness(candidate.properties);
+        let preferred = usage.properties_required().contains(self.required)
+            && candidate.properties.contains(self.preferred);
+        Some((preferred as u32) << 16 | fitness)
+    }
+}
+
+/// Allocations served from memory type other than the highest ranked one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryFallback {
+    /// Highest ranked memory type.
+    pub preferred: u32,
+
+    /// Memory type allocations were served from.
+    pub selected: u32,
+
+    /// Number of allocations.
+    pub count: u64,
+
+    /// Total size of allocations.
+    pub bytes: u64,
+}
+
+/// Record fallback allocation of `size` bytes.
+pub(super) fn record_fallback(
+    fallbacks: &mut Vec<MemoryFallback>,
+    preferred: u32,
+    selected: u32,
+    size: u64,
+) {
+    match fallbacks
+        .iter_mut()
+        .find(|fallback| fallback.preferred == preferred && fallback.selected == selected)
+    {
+        Some(fallback) => {
+            fallback.count += 1;
+            fallback.bytes += size;
+        }
+        None => fallbacks.push(MemoryFallback {
+            preferred,
+            selected,
+            count: 1,
+            bytes: size,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{MemoryFallback, PreferProperties},
+        crate::{
+            block::Block,
+            heaps::{Heaps, HeapsConfig},
+            replay::{FakeBackend, FakeDevice},
+            usage::{Data, Upload},
+        },
+        gfx_hal::memory::Properties,
+    };
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn prefer_and_fallback() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: None,
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let host = Properties::CPU_VISIBLE | Properties::COHERENT;
+        let bar = Properties::DEVICE_LOCAL | host;
+        let mut heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![
+                    (Properties::DEVICE_LOCAL, 0, config),
+                    (host, 1, config),
+                    (bar, 2, config),
+                ],
+                vec![1024 * MB, 1024 * MB, MB],
+                64,
+                1,
+            )
+        };
+
+        let block = heaps.allocate(&device, !0, Upload, MB / 2, 4).unwrap();
+        assert_eq!(block.memory_type(), 1);
+        heaps.free(&device, block);
+
+        heaps.set_memory_type_policy(PreferProperties::new(
+            Properties::CPU_VISIBLE,
+            Properties::DEVICE_LOCAL,
+        ));
+        let first = heaps.allocate(&device, !0, Upload, MB / 2, 4).unwrap();
+        assert_eq!(first.memory_type(), 2);
+        assert!(first.properties().contains(bar));
+
+        // Heap of preferred type is exhausted.
+        let second = heaps.allocate(&device, !0, Upload, MB / 2, 4).unwrap();
+        assert_eq!(second.memory_type(), 1);
+
+        // Usages that don't require host access are not affected.
+        let data = heaps.allocate(&device, !0, Data, MB / 2, 4).unwrap();
+        assert_eq!(data.memory_type(), 0);
+
+        assert_eq!(
+            heaps.fallbacks(),
+            &[MemoryFallback {
+                preferred: 2,
+                selected: 1,
+                count: 1,
+                bytes: MB / 2,
+            }]
+        );
+
+        heaps.free(&device, first);
+        heaps.free(&device, second);
+        heaps.free(&device, data);
+        heaps.dispose(&device);
+    }
+}
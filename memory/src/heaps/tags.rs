@@ -0,0 +1,112 @@
+use {
+    crate::{usage::AllocationTag, utilization::*},
+    std::collections::BTreeMap,
+};
+
+/// Table of live blocks by tag, memory type and size.
+#[derive(Debug, Default)]
+pub(super) struct LiveBlocksTable {
+    blocks: BTreeMap<(Option<AllocationTag>, u32, u64), usize>,
+}
+
+impl LiveBlocksTable {
+    pub(super) fn allocated(&mut self, tag: Option<AllocationTag>, memory_type: u32, size: u64) {
+        *self.blocks.entry((tag, memory_type, size)).or_insert(0) += 1;
+    }
+
+    pub(super) fn freed(&mut self, tag: Option<AllocationTag>, memory_type: u32, size: u64) {
+        let key = (tag, memory_type, size);
+        let count = self
+            .blocks
+            .get_mut(&key)
+            .expect("Freed block must be allocated");
+        *count -= 1;
+        if *count == 0 {
+            self.blocks.remove(&key);
+        }
+    }
+
+    /// Add blocks from `other` table.
+    pub(super) fn merge(&mut self, other: &Self) {
+        for (&key, &count) in &other.blocks {
+            *self.blocks.entry(key).or_insert(0) += count;
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub(super) fn tags(&self) -> Vec<TagUtilization> {
+        let mut tags: Vec<TagUtilization> = Vec::new();
+        for (&(tag, _, size), &count) in &self.blocks {
+            match tags.last_mut() {
+                Some(last) if last.tag == tag => {
+                    last.count += count;
+                    last.bytes += size * count as u64;
+                }
+                _ => tags.push(TagUtilization {
+                    tag,
+                    count,
+                    bytes: size * count as u64,
+                }),
+            }
+        }
+        tags
+    }
+
+    pub(super) fn report(&self) -> MemoryReport {
+        MemoryReport {
+            tags: self.tags(),
+            blocks: self
+                .blocks
+                .iter()
+                .map(|(&(tag, memory_type, size), &count)| LiveBlocks {
+                    tag,
+                    memory_type,
+                    size,
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::LiveBlocksTable, crate::usage::AllocationTag};
+
+    #[test]
+    fn report() {
+        let textures = Some(AllocationTag::new("textures"));
+        let meshes = Some(AllocationTag::new("meshes"));
+
+        let mut table = LiveBlocksTable::default();
+        table.allocated(textures, 0, 1024);
+        table.allocated(textures, 0, 1024);
+        table.allocated(textures, 1, 256);
+        table.allocated(meshes, 0, 512);
+        table.allocated(None, 2, 64);
+        table.freed(meshes, 0, 512);
+
+        let report = table.report();
+        assert_eq!(report.tags.len(), 2);
+        assert_eq!(report.tags[0].tag, None);
+        assert_eq!(report.tags[0].bytes, 64);
+        assert_eq!(report.tags[1].tag, textures);
+        assert_eq!(report.tags[1].count, 3);
+        assert_eq!(report.tags[1].bytes, 2304);
+        assert_eq!(report.blocks.len(), 3);
+        assert_eq!(
+            report.to_string(),
+            "<untagged>: 1 blocks, 64 bytes\n    type 2: 1 x 64 bytes\n\
+             textures: 3 blocks, 2304 bytes\n    type 0: 2 x 1024 bytes\n    type 1: 1 x 256 bytes\n"
+        );
+
+        table.freed(textures, 0, 1024);
+        table.freed(textures, 0, 1024);
+        table.freed(textures, 1, 256);
+        table.freed(None, 2, 64);
+        assert!(table.is_empty());
+    }
+}
@@ -16,6 +16,9 @@ mod block;
 mod heaps;
 mod mapping;
 mod memory;
+#[cfg(any(test, feature = "replay"))]
+pub mod replay;
+mod trace;
 mod usage;
 mod util;
 mod utilization;
@@ -23,9 +26,14 @@ mod utilization;
 pub use crate::{
     allocator::*,
     block::Block,
-    heaps::{Heaps, HeapsConfig, HeapsError, MemoryBlock},
-    mapping::{write::Write, Coherent, MappedRange, MaybeCoherent, NonCoherent},
+    heaps::{
+        BudgetEvent, DefragmentationConfig, DefragmentationPlan, FaultPolicy, FitnessPolicy,
+        HeapBudget, Heaps, HeapsConfig, HeapsError, MemoryBlock, MemoryFallback,
+        MemoryTypeCandidate, MemoryTypePolicy, PreferProperties, ShardsConfig,
+    },
+    mapping::{read::Read, write::Write, Coherent, MappedRange, MaybeCoherent, NonCoherent},
     memory::Memory,
+    trace::{Trace, TraceOp, TraceRecorder},
     usage::*,
     utilization::*,
 };
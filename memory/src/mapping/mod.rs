@@ -1,4 +1,5 @@
 mod range;
+pub(crate) mod read;
 pub(crate) mod write;
 
 use {
@@ -8,7 +9,10 @@ use {
 };
 
 pub(crate) use self::range::*;
-use self::write::{Write, WriteCoherent, WriteFlush};
+use self::{
+    read::{Read, ReadGuard},
+    write::{Write, WriteCoherent, WriteFlush},
+};
 
 /// Non-coherent marker.
 #[derive(Clone, Copy, Debug)]
@@ -140,8 +144,9 @@ where
         self.requested_range.clone()
     }
 
-    /// Fetch readable slice of sub-range to be read.
-    /// Invalidating range if memory is not coherent.
+    /// Fetch reader of the sub-region.
+    /// Invalidates range if memory is not coherent,
+    /// expanding it to `non_coherent_atom_size` boundaries.
     /// `range.end - range.start` must be multiple of `size_of::()`.
     /// `mapping offset + range.start` must be multiple of `align_of::()`.
     ///
@@ -153,22 +158,22 @@ where
         &'b mut self,
         device: &B::Device,
         range: Range<u64>,
-    ) -> Result<&'b [T], gfx_hal::device::MapError>
+    ) -> Result<impl Read<T> + 'b, gfx_hal::device::MapError>
     where
         'a: 'b,
-        T: Copy,
+        T: Copy + 'b,
     {
-        debug_assert!(
+        assert!(
             range.start < range.end,
             "Memory mapping region must have valid size"
         );
-        debug_assert!(
+        assert!(
             fits_usize(range.end - range.start),
             "Range length must fit in usize"
         );
 
         let sub_range = relative_to_sub_range(self.requested_range.clone(), range)
-            .ok_or_else(|| gfx_hal::device::MapError::OutOfBounds)?;
+            .ok_or(gfx_hal::device::MapError::OutOfBounds)?;
 
         let ptr =
             mapped_sub_range(self.ptr, self.mapping_range.clone(), sub_range.clone()).unwrap();
@@ -177,16 +182,19 @@ where
 
         if !self.coherent.0 {
             let aligned_sub_range = align_range(sub_range, self.memory.non_coherent_atom_size());
-            let segment = Segment { offset: aligned_sub_range.start, size: Some(aligned_sub_range.end) };
             debug_assert!(is_sub_range(
                 self.mapping_range.clone(),
                 aligned_sub_range.clone()
             ));
+            let segment = Segment {
+                offset: aligned_sub_range.start,
+                size: Some(aligned_sub_range.end - aligned_sub_range.start),
+            };
             device.invalidate_mapped_memory_ranges(Some((self.memory.raw(), segment)))?;
         }
 
         let slice = mapped_slice::<T>(ptr, size);
-        Ok(slice)
+        Ok(ReadGuard { slice })
     }
 
     /// Fetch writer to the sub-region.
@@ -226,11 +234,14 @@ where
         let ref memory = self.memory;
         let flush = if !self.coherent.0 {
             let aligned_sub_range = align_range(sub_range, self.memory.non_coherent_atom_size());
-            let segment = Segment { offset: aligned_sub_range.start, size: Some(aligned_sub_range.end) };
             debug_assert!(is_sub_range(
                 self.mapping_range.clone(),
                 aligned_sub_range.clone()
             ));
+            let segment = Segment {
+                offset: aligned_sub_range.start,
+                size: Some(aligned_sub_range.end - aligned_sub_range.start),
+            };
 
             Some(move || {
                 device
@@ -333,4 +344,90 @@ where
 
         Ok(WriteCoherent { slice })
     }
+
+    /// Fetch reader of the sub-region.
+    ///
+    /// # Safety
+    ///
+    /// * Caller must ensure that device won't write to the memory region until the borrowing ends.
+    /// * `U` Must be plain-old-data type compatible with data in mapped region.
+    pub unsafe fn read<'b, U>(
+        &'b mut self,
+        range: Range<u64>,
+    ) -> Result<impl Read<U> + 'b, gfx_hal::device::MapError>
+    where
+        U: Copy + 'b,
+    {
+        assert!(
+            range.start < range.end,
+            "Memory mapping region must have valid size"
+        );
+        assert!(
+            fits_usize(range.end - range.start),
+            "Range length must fit in usize"
+        );
+
+        let sub_range = relative_to_sub_range(self.requested_range.clone(), range)
+            .ok_or(gfx_hal::device::MapError::OutOfBounds)?;
+
+        let ptr =
+            mapped_sub_range(self.ptr, self.mapping_range.clone(), sub_range.clone()).unwrap();
+
+        let size = (sub_range.end - sub_range.start) as usize;
+
+        let slice = mapped_slice::<U>(ptr, size);
+
+        Ok(ReadGuard { slice })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::MappedRange,
+        crate::{
+            memory::Memory,
+            replay::{FakeBackend, FakeDevice},
+            Read as _, Write as _,
+        },
+        gfx_hal::{device::Device as _, memory::Properties, memory::Segment, MemoryTypeId},
+        std::ptr::NonNull,
+    };
+
+    #[test]
+    fn read_non_coherent() {
+        let device = FakeDevice::new();
+        unsafe {
+            let raw = device.allocate_memory(MemoryTypeId(0), 256).unwrap();
+            let segment = Segment {
+                offset: 64,
+                size: Some(128),
+            };
+            let ptr = NonNull::new(device.map_memory(&raw, segment).unwrap()).unwrap();
+            let memory: Memory<FakeBackend> =
+                Memory::from_raw(raw, 256, Properties::CPU_VISIBLE, 64);
+
+            let mut mapped = MappedRange::from_raw(&memory, ptr, 64..192, 80..160);
+            mapped
+                .write::<u32>(&device, 8..24)
+                .unwrap()
+                .write(&[1, 2, 3, 4]);
+            assert_eq!(device.stats().flushed, 64);
+
+            let mut data = [0; 2];
+            {
+                let read = mapped.read::<u32>(&device, 12..20).unwrap();
+                assert_eq!(read.slice(), &[2, 3]);
+                read.read(&mut data);
+            }
+            assert_eq!(data, [2, 3]);
+            // Invalidated range is expanded to `non_coherent_atom_size` boundaries.
+            assert_eq!(device.stats().invalidated, 64);
+
+            assert!(mapped.read::<u32>(&device, 72..84).is_err());
+
+            device.unmap_memory(memory.raw());
+            device.free_memory(memory.into_raw());
+        }
+    }
 }
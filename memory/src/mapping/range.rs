@@ -60,7 +60,7 @@ pub(crate) unsafe fn mapped_slice_mut<'a, T>(ptr: NonNull<u8>, size: usize) -> &
         "Range offset must be multiple of element alignment"
     );
     assert!(usize::max_value() - size >= ptr.as_ptr() as usize);
-    from_raw_parts_mut(ptr.as_ptr() as *mut T, size)
+    from_raw_parts_mut(ptr.as_ptr() as *mut T, size / size_of::<T>())
 }
 
 /// # Safety
@@ -81,5 +81,5 @@ pub(crate) unsafe fn mapped_slice<'a, T>(ptr: NonNull<u8>, size: usize) -> &'a [
         "Range offset must be multiple of element alignment"
     );
     assert!(usize::max_value() - size >= ptr.as_ptr() as usize);
-    from_raw_parts(ptr.as_ptr() as *const T, size)
+    from_raw_parts(ptr.as_ptr() as *const T, size / size_of::<T>())
 }
@@ -0,0 +1,32 @@
+/// Trait for memory region suitable for host reads.
+pub trait Read<T: Copy> {
+    /// Get slice of `T` bound to mapped range.
+    fn slice(&self) -> &[T];
+
+    /// Read data from mapped memory sub-region.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `data.len()` is greater than this sub-region len.
+    fn read(&self, data: &mut [T]) {
+        let slice = self.slice();
+        assert!(data.len() <= slice.len());
+        data.copy_from_slice(&slice[..data.len()]);
+    }
+}
+
+/// Slice of mapped memory which is visible to the host.
+/// Non-coherent memory is invalidated before guard is created.
+#[derive(Debug)]
+pub(super) struct ReadGuard<'a, T> {
+    pub(super) slice: &'a [T],
+}
+
+impl<'a, T> Read<T> for ReadGuard<'a, T>
+where
+    T: Copy + 'a,
+{
+    fn slice(&self) -> &[T] {
+        self.slice
+    }
+}
@@ -0,0 +1,290 @@
+//! Offline replay of recorded allocation traces.
+//!
+//! Traces recorded with `TraceRecorder` can be replayed against any `Allocator`
+//! running on top of the fake device, without GPU.
+//! This allows to compare allocator configurations on real workloads.
+//!
+//! Requires `replay` feature.
+
+pub use rendy_fake::{FakeBackend, FakeDevice, FakeDeviceStats};
+
+use {
+    crate::{
+        allocator::Allocator,
+        block::Block,
+        trace::{Trace, TraceOp},
+    },
+    std::collections::HashMap,
+};
+
+/// Result of the trace replay.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    /// Number of blocks allocated.
+    pub allocations: usize,
+
+    /// Number of allocations that failed.
+    pub failures: usize,
+
+    /// Number of memory objects allocated from the device.
+    pub device_allocations: usize,
+
+    /// Number of memory objects freed.
+    pub device_frees: usize,
+
+    /// Highest total size of memory objects allocated from the device at once.
+    pub peak_device_bytes: u64,
+
+    /// Highest total size of allocated blocks at once.
+    pub peak_block_bytes: u64,
+
+    /// Fraction of device memory not occupied by blocks
+    /// at the moment of peak device memory usage.
+    pub fragmentation: f64,
+}
+
+/// Replay allocations of the trace made from `memory_type`.
+///
+/// Blocks that are not freed in the trace are freed at the end,
+/// so that `allocator` can be disposed.
+pub fn replay<A>(
+    device: &FakeDevice,
+    allocator: &mut A,
+    trace: &Trace,
+    memory_type: u32,
+) -> ReplayReport
+where
+    A: Allocator<FakeBackend>,
+{
+    let start = device.stats();
+    let mut blocks = HashMap::new();
+    let mut report = ReplayReport::default();
+    let mut block_bytes = 0;
+
+    for op in &trace.ops {
+        match *op {
+            TraceOp::Allocate {
+                block,
+                memory_type: ty,
+                size,
+                align,
+                ..
+            } if ty == memory_type => match allocator.alloc(device, size, align) {
+                Ok((allocated, _)) => {
+                    report.allocations += 1;
+                    block_bytes += allocated.size();
+                    report.peak_block_bytes = report.peak_block_bytes.max(block_bytes);
+                    blocks.insert(block, allocated);
+                }
+                Err(err) => {
+                    log::trace!("Failed to allocate {} bytes: {:?}", size, err);
+                    report.failures += 1;
+                }
+            },
+            TraceOp::Free { block } => {
+                if let Some(block) = blocks.remove(&block) {
+                    block_bytes -= block.size();
+                    allocator.free(device, block);
+                }
+            }
+            TraceOp::Allocate { .. } => {}
+        }
+
+        let device_bytes = device.stats().allocated.saturating_sub(start.allocated);
+        if device_bytes > report.peak_device_bytes {
+            report.peak_device_bytes = device_bytes;
+            report.fragmentation = 1.0 - block_bytes as f64 / device_bytes as f64;
+        }
+    }
+
+    for (_, block) in blocks {
+        allocator.free(device, block);
+    }
+
+    let stats = device.stats();
+    report.device_allocations = stats.allocations - start.allocations;
+    report.device_frees = stats.frees - start.frees;
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{replay, FakeBackend, FakeDevice},
+        crate::{
+            allocator::{
+                DedicatedAllocator, DynamicAllocator, DynamicConfig, LinearAllocator, LinearConfig,
+            },
+            heaps::{Heaps, HeapsConfig},
+            trace::{Trace, TraceOp, TraceRecorder},
+            usage::{Data, Upload},
+        },
+        gfx_hal::{memory::Properties, MemoryTypeId},
+        std::{
+            io,
+            sync::{Arc, Mutex},
+        },
+    };
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const MB: u64 = 1024 * 1024;
+
+    fn dynamic_config(max_chunk_size: u64) -> DynamicConfig {
+        DynamicConfig {
+            block_size_granularity: 256,
+            max_chunk_size,
+            min_device_allocation: MB,
+        }
+    }
+
+    fn record() -> Trace {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(dynamic_config(32 * MB)),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let upload = Properties::CPU_VISIBLE | Properties::COHERENT;
+        let mut heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config), (upload, 1, config)],
+                vec![1024 * MB, 256 * MB],
+                64,
+                1,
+            )
+        };
+
+        let buffer = Shared::default();
+        heaps.start_recording(TraceRecorder::new(buffer.clone()).unwrap());
+
+        let mut blocks = Vec::new();
+        for i in 0..64 {
+            blocks.push(
+                heaps
+                    .allocate(&device, !0, Data, 4096 * (i % 4 + 1), 256)
+                    .unwrap(),
+            );
+        }
+        let staging = heaps.allocate(&device, !0, Upload, 64 * 1024, 4).unwrap();
+        let (freed, kept): (Vec<_>, Vec<_>) = blocks
+            .into_iter()
+            .enumerate()
+            .partition(|(index, _)| index % 2 == 0);
+        for (_, block) in freed {
+            heaps.free(&device, block);
+        }
+        heaps.free(&device, staging);
+
+        heaps.stop_recording().unwrap();
+        for (_, block) in kept {
+            heaps.free(&device, block);
+        }
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        Trace::read(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let trace = record();
+        assert_eq!(trace.memory_types(), vec![0, 1]);
+        assert_eq!(trace.usages, vec!["Data", "Upload"]);
+        assert_eq!(trace.ops.len(), 65 + 33);
+
+        let device = FakeDevice::new();
+        let mut allocator = DynamicAllocator::new(
+            MemoryTypeId(0),
+            Properties::DEVICE_LOCAL,
+            dynamic_config(32 * MB),
+            64,
+        );
+        let report = replay(&device, &mut allocator, &trace, 0);
+        allocator.dispose();
+        assert_eq!(report.allocations, 64);
+        assert_eq!(report.failures, 0);
+        assert_eq!(report.device_allocations, report.device_frees);
+        assert_eq!(report.peak_block_bytes, 64 / 4 * (4 + 8 + 12 + 16) * 1024);
+        assert!(report.peak_device_bytes >= report.peak_block_bytes);
+        assert!(report.fragmentation >= 0.0 && report.fragmentation < 1.0);
+        assert_eq!(device.stats().allocated, 0);
+
+        let mut linear = LinearAllocator::new(
+            MemoryTypeId(1),
+            Properties::CPU_VISIBLE | Properties::COHERENT,
+            LinearConfig { linear_size: MB },
+            64,
+        );
+        let report = replay(&device, &mut linear, &trace, 1);
+        linear.dispose(&device);
+        assert_eq!(report.allocations, 1);
+        assert_eq!(report.peak_device_bytes, MB);
+        assert_eq!(device.stats().allocated, 0);
+    }
+
+    #[test]
+    fn record_dedicated() {
+        let device = FakeDevice::new();
+        let config = HeapsConfig {
+            linear: None,
+            dynamic: Some(dynamic_config(MB)),
+            tlsf: None,
+            shards: None,
+            pool: None,
+        };
+        let mut heaps: Heaps<FakeBackend> = unsafe {
+            Heaps::new(
+                vec![(Properties::DEVICE_LOCAL, 0, config)],
+                vec![1024 * MB],
+                64,
+                1,
+            )
+        };
+
+        let buffer = Shared::default();
+        heaps.start_recording(TraceRecorder::new(buffer.clone()).unwrap());
+        for _ in 0..4 {
+            // Too large for dynamic allocator, so memory is dedicated to the block.
+            let block = heaps.allocate(&device, !0, Data, 16 * MB, 256).unwrap();
+            heaps.free(&device, block);
+        }
+        heaps.stop_recording().unwrap();
+        heaps.dispose(&device);
+        assert_eq!(device.stats().allocated, 0);
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let trace = Trace::read(&bytes[..]).unwrap();
+        let frees = trace
+            .ops
+            .iter()
+            .filter(|op| match op {
+                TraceOp::Free { .. } => true,
+                TraceOp::Allocate { .. } => false,
+            })
+            .count();
+        assert_eq!(trace.ops.len(), 8);
+        assert_eq!(frees, 4);
+
+        let mut dedicated = DedicatedAllocator::new(MemoryTypeId(0), Properties::DEVICE_LOCAL, 64);
+        let report = replay(&device, &mut dedicated, &trace, 0);
+        assert_eq!(report.allocations, 4);
+        assert_eq!(report.peak_device_bytes, 16 * MB);
+        assert_eq!(report.fragmentation, 0.0);
+        assert_eq!(device.stats().allocated, 0);
+    }
+}
@@ -0,0 +1,318 @@
+//! Recording of allocations made through `Heaps`.
+//!
+//! Trace is a compact binary stream.
+//! It starts with magic bytes and version followed by records.
+//! Each record is a tag byte followed by fields encoded as LEB128 varints.
+
+use std::{collections::HashMap, io};
+
+const MAGIC: &[u8; 4] = b"RMTR";
+const VERSION: u8 = 1;
+
+const USAGE: u8 = 0;
+const ALLOCATE: u8 = 1;
+const FREE: u8 = 2;
+
+/// Operation recorded in the trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    /// Block was allocated.
+    Allocate {
+        /// Id of the block, unique within the trace.
+        block: u64,
+
+        /// Memory type block was allocated from.
+        memory_type: u32,
+
+        /// Index of the usage in `Trace::usages`.
+        usage: u32,
+
+        /// Requested size.
+        size: u64,
+
+        /// Requested alignment.
+        align: u64,
+    },
+
+    /// Block was freed.
+    Free {
+        /// Id of the block.
+        block: u64,
+    },
+}
+
+/// Allocations trace read from recorded stream.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// Memory usages allocations were made for, as formatted by `Debug`.
+    pub usages: Vec<String>,
+
+    /// Recorded operations in order.
+    pub ops: Vec<TraceOp>,
+}
+
+impl Trace {
+    /// Read trace recorded by `TraceRecorder`.
+    pub fn read(mut reader: impl io::Read) -> io::Result<Self> {
+        let mut header = [0; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid("Not a memory trace or unsupported version"));
+        }
+
+        let mut trace = Trace::default();
+        loop {
+            let mut tag = [0];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+
+            match tag[0] {
+                USAGE => {
+                    let len = read_varint(&mut reader)? as usize;
+                    let mut name = vec![0; len];
+                    reader.read_exact(&mut name)?;
+                    let name = String::from_utf8(name).map_err(|_| invalid("Invalid usage"))?;
+                    trace.usages.push(name);
+                }
+                ALLOCATE => {
+                    let block = read_varint(&mut reader)?;
+                    let memory_type = read_varint(&mut reader)? as u32;
+                    let usage = read_varint(&mut reader)? as u32;
+                    let size = read_varint(&mut reader)?;
+                    let align = read_varint(&mut reader)?;
+                    if usage as usize >= trace.usages.len() {
+                        return Err(invalid("Unknown usage"));
+                    }
+                    trace.ops.push(TraceOp::Allocate {
+                        block,
+                        memory_type,
+                        usage,
+                        size,
+                        align,
+                    });
+                }
+                FREE => {
+                    let block = read_varint(&mut reader)?;
+                    trace.ops.push(TraceOp::Free { block });
+                }
+                _ => return Err(invalid("Unknown record")),
+            }
+        }
+
+        Ok(trace)
+    }
+
+    /// Get memory types used in the trace.
+    pub fn memory_types(&self) -> Vec<u32> {
+        let mut types: Vec<_> = self
+            .ops
+            .iter()
+            .filter_map(|op| match *op {
+                TraceOp::Allocate { memory_type, .. } => Some(memory_type),
+                TraceOp::Free { .. } => None,
+            })
+            .collect();
+        types.sort();
+        types.dedup();
+        types
+    }
+}
+
+/// Records allocations made through `Heaps`.
+///
+/// Writes are not buffered by recorder,
+/// so writer like file should be wrapped into `std::io::BufWriter`.
+pub struct TraceRecorder {
+    writer: Box<dyn io::Write + Send>,
+    usages: HashMap<String, u32>,
+    blocks: HashMap<u64, u64>,
+    next_block: u64,
+}
+
+impl std::fmt::Debug for TraceRecorder {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("TraceRecorder")
+            .field("usages", &self.usages)
+            .field("blocks", &self.blocks.len())
+            .field("next_block", &self.next_block)
+            .finish()
+    }
+}
+
+impl TraceRecorder {
+    /// Start recording trace into `writer`.
+    pub fn new(writer: impl io::Write + Send + 'static) -> io::Result<Self> {
+        let mut writer = Box::new(writer);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(TraceRecorder {
+            writer,
+            usages: HashMap::new(),
+            blocks: HashMap::new(),
+            next_block: 0,
+        })
+    }
+
+    /// Record allocation of the block identified by `id` assigned by `Heaps`.
+    pub(crate) fn allocate(
+        &mut self,
+        id: u64,
+        memory_type: u32,
+        usage: &dyn std::fmt::Debug,
+        size: u64,
+        align: u64,
+    ) -> io::Result<()> {
+        let name = format!("{:?}", usage);
+        let usage = match self.usages.get(&name) {
+            Some(&usage) => usage,
+            None => {
+                let usage = self.usages.len() as u32;
+                self.writer.write_all(&[USAGE])?;
+                write_varint(&mut self.writer, name.len() as u64)?;
+                self.writer.write_all(name.as_bytes())?;
+                self.usages.insert(name, usage);
+                usage
+            }
+        };
+
+        let block = self.next_block;
+        self.next_block += 1;
+        self.blocks.insert(id, block);
+
+        self.writer.write_all(&[ALLOCATE])?;
+        write_varint(&mut self.writer, block)?;
+        write_varint(&mut self.writer, memory_type as u64)?;
+        write_varint(&mut self.writer, usage as u64)?;
+        write_varint(&mut self.writer, size)?;
+        write_varint(&mut self.writer, align)
+    }
+
+    /// Record deallocation of the block identified by `id` assigned by `Heaps`.
+    /// Blocks allocated before recording started are ignored.
+    pub(crate) fn free(&mut self, id: u64) -> io::Result<()> {
+        match self.blocks.remove(&id) {
+            Some(block) => {
+                self.writer.write_all(&[FREE])?;
+                write_varint(&mut self.writer, block)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Flush underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_varint(writer: &mut impl io::Write, mut value: u64) -> io::Result<()> {
+    let mut bytes = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&bytes[..len])
+}
+
+fn read_varint(reader: &mut impl io::Read) -> io::Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        if shift > 63 {
+            return Err(invalid("Varint overflow"));
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{Trace, TraceOp, TraceRecorder},
+        std::{
+            io,
+            sync::{Arc, Mutex},
+        },
+    };
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let buffer = Shared::default();
+        let mut recorder = TraceRecorder::new(buffer.clone()).unwrap();
+        recorder.allocate(10, 2, &"Data", 1000, 256).unwrap();
+        recorder.allocate(11, 2, &"Data", 1 << 40, 1).unwrap();
+        recorder.allocate(12, 0, &"Upload", 10, 4).unwrap();
+        recorder.free(10).unwrap();
+        recorder.free(13).unwrap();
+        recorder.free(12).unwrap();
+        drop(recorder);
+
+        let bytes = buffer.0.lock().unwrap().clone();
+        let trace = Trace::read(&bytes[..]).unwrap();
+        assert_eq!(trace.usages, vec!["\"Data\"", "\"Upload\""]);
+        assert_eq!(
+            trace.ops,
+            vec![
+                TraceOp::Allocate {
+                    block: 0,
+                    memory_type: 2,
+                    usage: 0,
+                    size: 1000,
+                    align: 256,
+                },
+                TraceOp::Allocate {
+                    block: 1,
+                    memory_type: 2,
+                    usage: 0,
+                    size: 1 << 40,
+                    align: 1,
+                },
+                TraceOp::Allocate {
+                    block: 2,
+                    memory_type: 0,
+                    usage: 1,
+                    size: 10,
+                    align: 4,
+                },
+                TraceOp::Free { block: 0 },
+                TraceOp::Free { block: 2 },
+            ]
+        );
+        assert_eq!(trace.memory_types(), vec![0, 2]);
+
+        assert!(Trace::read(&bytes[1..]).is_err());
+        assert!(Trace::read(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -17,6 +17,17 @@ pub trait MemoryUsage: std::fmt::Debug {
 
     /// Get comparable fitness value for memory allocator.
     fn allocator_fitness(&self, kind: Kind) -> u32;
+
+    /// Get tag to account allocations under.
+    fn tag(&self) -> Option<AllocationTag> {
+        None
+    }
+
+    /// Get kind of resource memory is allocated for.
+    /// Used to place linear and non-linear resources according to buffer-image granularity.
+    fn resource_kind(&self) -> Option<ResourceKind> {
+        None
+    }
 }
 
 impl<T> MemoryUsage for T
@@ -33,6 +44,131 @@ where
     fn allocator_fitness(&self, kind: Kind) -> u32 {
         (&**self).allocator_fitness(kind)
     }
+    fn tag(&self) -> Option<AllocationTag> {
+        (**self).tag()
+    }
+    fn resource_kind(&self) -> Option<ResourceKind> {
+        (**self).resource_kind()
+    }
+}
+
+/// Tag to account allocations under.
+/// Typically names subsystem that owns allocated memory.
+///
+/// User-defined categories can be converted into tags
+/// by implementing `From<Category> for AllocationTag`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AllocationTag(&'static str);
+
+impl AllocationTag {
+    /// Create tag with specified name.
+    pub const fn new(name: &'static str) -> Self {
+        AllocationTag(name)
+    }
+
+    /// Get name of the tag.
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl From<&'static str> for AllocationTag {
+    fn from(name: &'static str) -> Self {
+        AllocationTag(name)
+    }
+}
+
+impl std::fmt::Display for AllocationTag {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str(self.0)
+    }
+}
+
+/// Memory usage with allocation tag attached.
+#[derive(Clone, Copy, Debug)]
+pub struct Tagged<U> {
+    usage: U,
+    tag: AllocationTag,
+}
+
+impl<U> Tagged<U> {
+    /// Attach tag to memory usage.
+    pub fn new(usage: U, tag: impl Into<AllocationTag>) -> Self {
+        Tagged {
+            usage,
+            tag: tag.into(),
+        }
+    }
+}
+
+impl<U> MemoryUsage for Tagged<U>
+where
+    U: MemoryUsage,
+{
+    fn properties_required(&self) -> gfx_hal::memory::Properties {
+        self.usage.properties_required()
+    }
+    fn memory_fitness(&self, properties: gfx_hal::memory::Properties) -> u32 {
+        self.usage.memory_fitness(properties)
+    }
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        self.usage.allocator_fitness(kind)
+    }
+    fn tag(&self) -> Option<AllocationTag> {
+        Some(self.tag)
+    }
+    fn resource_kind(&self) -> Option<ResourceKind> {
+        self.usage.resource_kind()
+    }
+}
+
+/// Kind of resource memory is bound to.
+///
+/// Linear and non-linear resources bound to the same memory object
+/// must be separated by `bufferImageGranularity` limit of the physical device.
+/// Blocks allocated without resource kind are kept apart from both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    /// Buffers and images with linear tiling.
+    Linear,
+
+    /// Images with optimal tiling.
+    NonLinear,
+}
+
+/// Memory usage for resource of specific kind.
+#[derive(Clone, Copy, Debug)]
+pub struct ForResource<U> {
+    usage: U,
+    kind: ResourceKind,
+}
+
+impl<U> ForResource<U> {
+    /// Attach resource kind to memory usage.
+    pub fn new(usage: U, kind: ResourceKind) -> Self {
+        ForResource { usage, kind }
+    }
+}
+
+impl<U> MemoryUsage for ForResource<U>
+where
+    U: MemoryUsage,
+{
+    fn properties_required(&self) -> gfx_hal::memory::Properties {
+        self.usage.properties_required()
+    }
+    fn memory_fitness(&self, properties: gfx_hal::memory::Properties) -> u32 {
+        self.usage.memory_fitness(properties)
+    }
+    fn allocator_fitness(&self, kind: Kind) -> u32 {
+        self.usage.allocator_fitness(kind)
+    }
+    fn tag(&self) -> Option<AllocationTag> {
+        self.usage.tag()
+    }
+    fn resource_kind(&self) -> Option<ResourceKind> {
+        Some(self.kind)
+    }
 }
 
 /// Full speed GPU access.
@@ -60,6 +196,8 @@ impl MemoryUsage for Data {
             Kind::Dedicated => 1,
             Kind::Dynamic => 2,
             Kind::Linear => 0,
+            Kind::Tlsf => 3,
+            Kind::Pool => 0,
         }
     }
 }
@@ -91,6 +229,8 @@ impl MemoryUsage for Dynamic {
             Kind::Dedicated => 1,
             Kind::Dynamic => 2,
             Kind::Linear => 0,
+            Kind::Tlsf => 3,
+            Kind::Pool => 0,
         }
     }
 }
@@ -121,6 +261,8 @@ impl MemoryUsage for Upload {
             Kind::Dedicated => 0,
             Kind::Dynamic => 1,
             Kind::Linear => 2,
+            Kind::Tlsf => 1,
+            Kind::Pool => 0,
         }
     }
 }
@@ -151,6 +293,8 @@ impl MemoryUsage for Download {
             Kind::Dedicated => 0,
             Kind::Dynamic => 1,
             Kind::Linear => 2,
+            Kind::Tlsf => 1,
+            Kind::Pool => 0,
         }
     }
 }
@@ -1,4 +1,5 @@
 use {
+    crate::usage::AllocationTag,
     colorful::{core::color_string::CString, Color, Colorful as _},
     gfx_hal::memory::Properties,
 };
@@ -20,6 +21,20 @@ pub struct MemoryHeapUtilization {
 
     /// Memory heap size.
     pub size: u64,
+
+    /// Budget size, if heap has one.
+    pub budget: Option<u64>,
+}
+
+impl MemoryHeapUtilization {
+    /// Number of bytes that can be allocated from the heap
+    /// before budget is exceeded.
+    /// Heap size is used when heap has no budget.
+    pub fn headroom(&self) -> u64 {
+        self.budget
+            .unwrap_or(self.size)
+            .saturating_sub(self.utilization.used)
+    }
 }
 
 /// Memory utilization of one type.
@@ -43,6 +58,81 @@ pub struct TotalMemoryUtilization {
 
     /// Utilization by heaps.
     pub heaps: Vec<MemoryHeapUtilization>,
+
+    /// Live blocks by allocation tags.
+    pub tags: Vec<TagUtilization>,
+}
+
+/// Live blocks allocated under one tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagUtilization {
+    /// Allocation tag. `None` for untagged allocations.
+    pub tag: Option<AllocationTag>,
+
+    /// Number of live blocks.
+    pub count: usize,
+
+    /// Total size of live blocks.
+    pub bytes: u64,
+}
+
+/// Live blocks of the same size allocated under one tag from one memory type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveBlocks {
+    /// Allocation tag. `None` for untagged allocations.
+    pub tag: Option<AllocationTag>,
+
+    /// Memory type index.
+    pub memory_type: u32,
+
+    /// Size of each block.
+    pub size: u64,
+
+    /// Number of live blocks.
+    pub count: usize,
+}
+
+/// Report of the live memory blocks.
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    /// Live blocks by tag.
+    pub tags: Vec<TagUtilization>,
+
+    /// Live blocks by tag, memory type and size.
+    pub blocks: Vec<LiveBlocks>,
+}
+
+impl std::fmt::Display for MemoryReport {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for tag in &self.tags {
+            writeln!(
+                fmt,
+                "{}: {} blocks, {} bytes",
+                TagName(tag.tag),
+                tag.count,
+                tag.bytes
+            )?;
+            for blocks in self.blocks.iter().filter(|blocks| blocks.tag == tag.tag) {
+                writeln!(
+                    fmt,
+                    "    type {}: {} x {} bytes",
+                    blocks.memory_type, blocks.count, blocks.size
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct TagName(Option<AllocationTag>);
+
+impl std::fmt::Display for TagName {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(tag) => write!(fmt, "{}", tag),
+            None => write!(fmt, "<untagged>"),
+        }
+    }
 }
 
 impl std::fmt::Display for TotalMemoryUtilization {
@@ -79,6 +169,15 @@ impl std::fmt::Display for TotalMemoryUtilization {
                 line
             )?;
 
+            if let Some(budget) = heap.budget {
+                writeln!(
+                    fmt,
+                    "         budget: {:>6}, headroom: {:>6}",
+                    format!("{}MB", budget / MB),
+                    format!("{}MB", heap.headroom() / MB),
+                )?;
+            }
+
             for ty in self.types.iter().filter(|ty| ty.heap_index == index) {
                 let properties = ty.properties;
                 let MemoryUtilization { used, effective } = ty.utilization;
@@ -100,6 +199,16 @@ impl std::fmt::Display for TotalMemoryUtilization {
             }
         }
 
+        for tag in &self.tags {
+            writeln!(
+                fmt,
+                "{:>16}: {:>6} in {} blocks",
+                format!("{}", TagName(tag.tag)),
+                format!("{}MB", tag.bytes / MB),
+                tag.count,
+            )?;
+        }
+
         Ok(())
     }
 }
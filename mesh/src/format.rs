@@ -1,2 +1,5 @@
 #[cfg(feature = "obj")]
 pub mod obj;
+
+#[cfg(feature = "gltf")]
+pub mod gltf;
@@ -0,0 +1,934 @@
+//! Loading mesh data from glTF 2.0 format.
+//!
+//! Both `.gltf` documents with external or embedded buffers and binary `.glb` containers
+//! are supported. Only mesh data is loaded, materials are referenced by index.
+//!
+//! Document is read with `serde_json` directly instead of the `gltf` crate,
+//! which doesn't check accessor ranges against buffer data and panics on malformed input.
+//! Every offset and index read here is validated and reported as `GltfError::Invalid`.
+
+use {
+    crate::{
+        core::hal::pso::Primitive, mesh::MeshBuilder, Color, Joints, Normal, Position, Tangent,
+        TexCoord, Weights,
+    },
+    log::trace,
+    serde_json::Value,
+    std::borrow::Cow,
+};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+const BYTE: u64 = 5120;
+const UNSIGNED_BYTE: u64 = 5121;
+const SHORT: u64 = 5122;
+const UNSIGNED_SHORT: u64 = 5123;
+const UNSIGNED_INT: u64 = 5125;
+const FLOAT: u64 = 5126;
+
+/// glTF loading error.
+#[derive(Debug)]
+pub enum GltfError {
+    /// The passed bytes were improper UTF-8 data.
+    Utf8(std::str::Utf8Error),
+    /// Parsing of the JSON document failed.
+    Json(serde_json::Error),
+    /// Binary glTF container is malformed.
+    Glb(String),
+    /// Document violates glTF specification.
+    Invalid(String),
+    /// Data of the buffer with specified index was not provided.
+    MissingBuffer(usize),
+    /// Document uses features not supported by the loader.
+    Unsupported(String),
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Utf8(e) => Some(e),
+            GltfError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfError::Utf8(e) => write!(f, "{}", e),
+            GltfError::Json(e) => write!(f, "Error parsing glTF document: {}", e),
+            GltfError::Glb(e) => write!(f, "Malformed binary glTF: {}", e),
+            GltfError::Invalid(e) => write!(f, "Invalid glTF document: {}", e),
+            GltfError::MissingBuffer(index) => {
+                write!(f, "Data for glTF buffer {} is not provided", index)
+            }
+            GltfError::Unsupported(e) => write!(f, "Unsupported glTF feature: {}", e),
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> GltfError {
+    GltfError::Invalid(message.into())
+}
+
+/// Information about mesh primitive loaded from glTF.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GltfPrimitive {
+    /// Index of the mesh in the document.
+    pub mesh: usize,
+    /// Name of the mesh primitive belongs to.
+    pub mesh_name: Option<String>,
+    /// Names of nodes that instantiate the mesh.
+    pub node_names: Vec<String>,
+    /// Index of the material of the primitive in the document.
+    pub material: Option<usize>,
+}
+
+/// Get URIs of buffers declared by glTF document.
+///
+/// Data of buffers with external URI must be loaded by the caller
+/// and passed to `load_from_gltf`.
+/// Entries for buffers embedded as data URI or stored in GLB binary chunk are `None`.
+pub fn external_buffers(bytes: &[u8]) -> Result<Vec<Option<String>>, GltfError> {
+    let (document, _) = parse(bytes)?;
+    Ok(array(&document, "buffers")
+        .iter()
+        .map(|buffer| {
+            buffer
+                .get("uri")
+                .and_then(Value::as_str)
+                .filter(|uri| !uri.starts_with("data:"))
+                .map(String::from)
+        })
+        .collect())
+}
+
+/// Load mesh data from glTF document or binary glTF container.
+///
+/// `buffers` are indexed the same way as buffers declared in the document.
+/// Only data for buffers with external URI is required,
+/// see `external_buffers`. Other entries are ignored and may be empty.
+///
+/// Each mesh primitive is loaded as separate `MeshBuilder`
+/// with `Position` and, when present, `Normal`, `Tangent`, `TexCoord`, `Color`,
+/// `Joints` and `Weights` vertices.
+/// Index type and primitive mode are preserved, except for line loops and triangle fans
+/// which are converted to line strips and triangle lists.
+pub fn load_from_gltf(
+    bytes: &[u8],
+    buffers: &[&[u8]],
+) -> Result<Vec<(MeshBuilder<'static>, GltfPrimitive)>, GltfError> {
+    let (document, bin) = parse(bytes)?;
+
+    if let Some(required) = document
+        .get("extensionsRequired")
+        .and_then(Value::as_array)
+        .filter(|required| !required.is_empty())
+    {
+        return Err(GltfError::Unsupported(format!(
+            "Required extensions {:?}",
+            required
+        )));
+    }
+
+    let buffers = array(&document, "buffers")
+        .iter()
+        .enumerate()
+        .map(|(index, buffer)| load_buffer(index, buffer, buffers, bin))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let loader = Loader {
+        document: &document,
+        buffers,
+    };
+
+    trace!("Loading meshes");
+    let nodes = array(&document, "nodes");
+    let mut primitives = Vec::new();
+    for (index, mesh) in array(&document, "meshes").iter().enumerate() {
+        let mesh_name = mesh.get("name").and_then(Value::as_str).map(String::from);
+        let node_names: Vec<_> = nodes
+            .iter()
+            .filter(|node| node.get("mesh").and_then(Value::as_u64) == Some(index as u64))
+            .filter_map(|node| node.get("name").and_then(Value::as_str))
+            .map(String::from)
+            .collect();
+
+        for primitive in array(mesh, "primitives") {
+            let builder = loader.load_primitive(primitive)?;
+            primitives.push((
+                builder,
+                GltfPrimitive {
+                    mesh: index,
+                    mesh_name: mesh_name.clone(),
+                    node_names: node_names.clone(),
+                    material: optional_index(primitive, "material")?,
+                },
+            ));
+        }
+    }
+    trace!("Loaded {} primitives", primitives.len());
+    Ok(primitives)
+}
+
+/// Parse JSON document and binary chunk if `bytes` is binary glTF container.
+fn parse(bytes: &[u8]) -> Result<(Value, Option<&[u8]>), GltfError> {
+    if !bytes.starts_with(GLB_MAGIC) {
+        let string = std::str::from_utf8(bytes).map_err(GltfError::Utf8)?;
+        let document = serde_json::from_str(string).map_err(GltfError::Json)?;
+        return Ok((document, None));
+    }
+
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| GltfError::Glb("Unexpected end of data".into()))
+    };
+
+    let version = read_u32(4)?;
+    if version != 2 {
+        return Err(GltfError::Unsupported(format!("GLB version {}", version)));
+    }
+    let length = read_u32(8)? as usize;
+    if length > bytes.len() {
+        return Err(GltfError::Glb(format!(
+            "Length {} exceeds size of data {}",
+            length,
+            bytes.len()
+        )));
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset < length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let start = offset + 8;
+        let end = start
+            .checked_add(chunk_length)
+            .filter(|&end| end <= length)
+            .ok_or_else(|| GltfError::Glb("Chunk exceeds size of data".into()))?;
+        chunks.push((chunk_type, &bytes[start..end]));
+        offset = end;
+    }
+
+    let json = match chunks.first() {
+        Some(&(GLB_CHUNK_JSON, json)) => json,
+        _ => return Err(GltfError::Glb("First chunk must be JSON".into())),
+    };
+    let bin = match chunks.get(1) {
+        Some(&(GLB_CHUNK_BIN, bin)) => Some(bin),
+        _ => None,
+    };
+
+    let string = std::str::from_utf8(json).map_err(GltfError::Utf8)?;
+    let document = serde_json::from_str(string).map_err(GltfError::Json)?;
+    Ok((document, bin))
+}
+
+fn load_buffer<'a>(
+    index: usize,
+    buffer: &Value,
+    external: &[&'a [u8]],
+    bin: Option<&'a [u8]>,
+) -> Result<Cow<'a, [u8]>, GltfError> {
+    let data =
+        match buffer.get("uri").and_then(Value::as_str) {
+            Some(uri) if uri.starts_with("data:") => {
+                let data = uri
+                    .find(";base64,")
+                    .map(|start| &uri[start + 8..])
+                    .ok_or_else(|| invalid(format!("Buffer {} data URI is not base64", index)))?;
+                Cow::Owned(decode_base64(data).ok_or_else(|| {
+                    invalid(format!("Buffer {} data URI is not valid base64", index))
+                })?)
+            }
+            Some(_) => Cow::Borrowed(*external.get(index).ok_or(GltfError::MissingBuffer(index))?),
+            None if index == 0 => Cow::Borrowed(bin.ok_or(GltfError::MissingBuffer(index))?),
+            None => return Err(GltfError::MissingBuffer(index)),
+        };
+
+    let byte_length = required_index(buffer, "byteLength")?;
+    if data.len() < byte_length {
+        return Err(invalid(format!(
+            "Buffer {} has {} bytes, {} expected",
+            index,
+            data.len(),
+            byte_length
+        )));
+    }
+    Ok(data)
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let data = data.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut accumulator = 0u32;
+    let mut bits = 0;
+    for &c in data {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        accumulator = accumulator << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+fn optional_index(value: &Value, key: &str) -> Result<Option<usize>, GltfError> {
+    match value.get(key) {
+        None => Ok(None),
+        Some(index) => index
+            .as_u64()
+            .map(|index| Some(index as usize))
+            .ok_or_else(|| invalid(format!("'{}' must be non-negative integer", key))),
+    }
+}
+
+fn required_index(value: &Value, key: &str) -> Result<usize, GltfError> {
+    optional_index(value, key)?.ok_or_else(|| invalid(format!("'{}' is required", key)))
+}
+
+/// Components of accessor elements.
+struct AccessorData {
+    component_type: u64,
+    normalized: bool,
+    width: usize,
+    count: usize,
+    values: Vec<f64>,
+}
+
+impl AccessorData {
+    fn floats(&self) -> impl Iterator<Item = f32> + '_ {
+        let scale = match (self.normalized, self.component_type) {
+            (true, BYTE) => Some(127.0),
+            (true, UNSIGNED_BYTE) => Some(255.0),
+            (true, SHORT) => Some(32767.0),
+            (true, UNSIGNED_SHORT) => Some(65535.0),
+            _ => None,
+        };
+        self.values.iter().map(move |&value| match scale {
+            Some(scale) => (value / scale).max(-1.0) as f32,
+            None => value as f32,
+        })
+    }
+
+    fn elements<T>(&self, width: usize, element: impl Fn(&[f32]) -> T) -> Vec<T> {
+        let floats: Vec<f32> = self.floats().collect();
+        floats
+            .chunks_exact(self.width)
+            .map(|c| element(&c[..width]))
+            .collect()
+    }
+}
+
+struct Loader<'a> {
+    document: &'a Value,
+    buffers: Vec<Cow<'a, [u8]>>,
+}
+
+impl Loader<'_> {
+    fn load_primitive(&self, primitive: &Value) -> Result<MeshBuilder<'static>, GltfError> {
+        let attributes = primitive
+            .get("attributes")
+            .ok_or_else(|| invalid("Primitive has no attributes"))?;
+        let attribute = |name: &str, widths: &[usize], types: &[u64]| {
+            optional_index(attributes, name)?
+                .map(|index| {
+                    let data = self.accessor(index)?;
+                    if !widths.contains(&data.width) || !types.contains(&data.component_type) {
+                        return Err(invalid(format!(
+                            "Accessor {} has unexpected type for attribute {}",
+                            index, name
+                        )));
+                    }
+                    Ok(data)
+                })
+                .transpose()
+        };
+
+        let positions = attribute("POSITION", &[3], &[FLOAT])?
+            .ok_or_else(|| invalid("Primitive has no POSITION attribute"))?;
+        let count = positions.count;
+
+        // All attributes must have the same number of elements.
+        let attribute = |name: &str, widths: &[usize], types: &[u64]| {
+            attribute(name, widths, types)?
+                .map(|data| {
+                    if data.count != count {
+                        return Err(invalid(format!(
+                            "Attribute {} has {} elements, {} expected",
+                            name, data.count, count
+                        )));
+                    }
+                    Ok(data)
+                })
+                .transpose()
+        };
+
+        let normalized = [FLOAT, UNSIGNED_BYTE, UNSIGNED_SHORT];
+        let mut builder = MeshBuilder::new();
+        builder.add_vertices(positions.elements(3, |c| Position([c[0], c[1], c[2]])));
+
+        if let Some(data) = attribute("NORMAL", &[3], &[FLOAT])? {
+            builder.add_vertices(data.elements(3, |c| Normal([c[0], c[1], c[2]])));
+        }
+        if let Some(data) = attribute("TANGENT", &[4], &[FLOAT])? {
+            builder.add_vertices(data.elements(4, |c| Tangent([c[0], c[1], c[2], c[3]])));
+        }
+        if let Some(data) = attribute("TEXCOORD_0", &[2], &normalized)? {
+            builder.add_vertices(data.elements(2, |c| TexCoord([c[0], c[1]])));
+        }
+        if let Some(data) = attribute("COLOR_0", &[3, 4], &normalized)? {
+            builder.add_vertices(data.elements(data.width, |c| {
+                Color([c[0], c[1], c[2], c.get(3).copied().unwrap_or(1.0)])
+            }));
+        }
+        if let Some(data) = attribute("JOINTS_0", &[4], &[UNSIGNED_BYTE, UNSIGNED_SHORT])? {
+            builder.add_vertices(data.elements(4, |c| {
+                Joints([c[0] as u16, c[1] as u16, c[2] as u16, c[3] as u16])
+            }));
+        }
+        if let Some(data) = attribute("WEIGHTS_0", &[4], &normalized)? {
+            builder.add_vertices(data.elements(4, |c| Weights([c[0], c[1], c[2], c[3]])));
+        }
+
+        let indices = optional_index(primitive, "indices")?
+            .map(|index| {
+                let data = self.accessor(index)?;
+                if data.width != 1
+                    || ![UNSIGNED_BYTE, UNSIGNED_SHORT, UNSIGNED_INT].contains(&data.component_type)
+                {
+                    return Err(invalid(format!(
+                        "Accessor {} has unexpected type for indices",
+                        index
+                    )));
+                }
+                let wide = data.component_type == UNSIGNED_INT;
+                let indices = data.values.iter().map(|&i| i as u32).collect::<Vec<_>>();
+                if let Some(&i) = indices.iter().find(|&&i| i as usize >= count) {
+                    return Err(invalid(format!(
+                        "Index {} is out of bounds of {} vertices",
+                        i, count
+                    )));
+                }
+                Ok((indices, wide))
+            })
+            .transpose()?;
+
+        let mode = optional_index(primitive, "mode")?.unwrap_or(4);
+        let (prim, indices) = match mode {
+            0 => (Primitive::PointList, indices),
+            1 => (Primitive::LineList, indices),
+            2 => {
+                // Line loop is closed line strip.
+                let (mut indices, wide) = indices.unwrap_or_else(|| generated(count));
+                if let Some(&first) = indices.first() {
+                    indices.push(first);
+                }
+                (Primitive::LineStrip, Some((indices, wide)))
+            }
+            3 => (Primitive::LineStrip, indices),
+            4 => (Primitive::TriangleList, indices),
+            5 => (Primitive::TriangleStrip, indices),
+            6 => {
+                // Triangle fan is converted to list of triangles sharing first vertex.
+                let (indices, wide) = indices.unwrap_or_else(|| generated(count));
+                let list = (2..indices.len())
+                    .flat_map(|i| vec![indices[0], indices[i - 1], indices[i]])
+                    .collect();
+                (Primitive::TriangleList, Some((list, wide)))
+            }
+            mode => return Err(invalid(format!("Unknown primitive mode {}", mode))),
+        };
+
+        builder.set_prim_type(prim);
+        match indices {
+            None => {}
+            Some((indices, true)) => {
+                builder.set_indices(indices);
+            }
+            Some((indices, false)) => {
+                builder.set_indices(indices.into_iter().map(|i| i as u16).collect::<Vec<_>>());
+            }
+        }
+
+        Ok(builder)
+    }
+
+    fn get(&self, key: &str, index: usize) -> Result<&Value, GltfError> {
+        array(self.document, key)
+            .get(index)
+            .ok_or_else(|| invalid(format!("'{}' index {} is out of bounds", key, index)))
+    }
+
+    fn accessor(&self, index: usize) -> Result<AccessorData, GltfError> {
+        let accessor = self.get("accessors", index)?;
+        let component_type = accessor
+            .get("componentType")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid(format!("Accessor {} has no component type", index)))?;
+        let width = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some(ty) if ty.starts_with("MAT") => {
+                return Err(GltfError::Unsupported(format!("Accessor type {}", ty)))
+            }
+            _ => return Err(invalid(format!("Accessor {} has invalid type", index))),
+        };
+        let count = required_index(accessor, "count")?;
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        let mut values = match optional_index(accessor, "bufferView")? {
+            Some(view) => self.read(
+                view,
+                optional_index(accessor, "byteOffset")?.unwrap_or(0),
+                count,
+                component_type,
+                width,
+            )?,
+            None => vec![
+                0.0;
+                count
+                    .checked_mul(width)
+                    .ok_or_else(|| invalid(format!("Accessor {} is too large", index)))?
+            ],
+        };
+
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = required_index(sparse, "count")?;
+            let indices = sparse
+                .get("indices")
+                .ok_or_else(|| invalid("Sparse accessor has no indices"))?;
+            let sparse_values = sparse
+                .get("values")
+                .ok_or_else(|| invalid("Sparse accessor has no values"))?;
+            let indices = self.read(
+                required_index(indices, "bufferView")?,
+                optional_index(indices, "byteOffset")?.unwrap_or(0),
+                sparse_count,
+                indices
+                    .get("componentType")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("Sparse indices have no component type"))?,
+                1,
+            )?;
+            let sparse_values = self.read(
+                required_index(sparse_values, "bufferView")?,
+                optional_index(sparse_values, "byteOffset")?.unwrap_or(0),
+                sparse_count,
+                component_type,
+                width,
+            )?;
+            for (&target, sparse) in indices.iter().zip(sparse_values.chunks_exact(width)) {
+                let target = target as usize;
+                if target >= count {
+                    return Err(invalid(format!(
+                        "Sparse index {} is out of bounds of accessor {}",
+                        target, index
+                    )));
+                }
+                values[target * width..(target + 1) * width].copy_from_slice(sparse);
+            }
+        }
+
+        Ok(AccessorData {
+            component_type,
+            normalized,
+            width,
+            count,
+            values,
+        })
+    }
+
+    /// Read `count` elements of `width` components from buffer view.
+    fn read(
+        &self,
+        index: usize,
+        offset: usize,
+        count: usize,
+        component_type: u64,
+        width: usize,
+    ) -> Result<Vec<f64>, GltfError> {
+        let view = self.get("bufferViews", index)?;
+        let buffer = required_index(view, "buffer")?;
+        let buffer = self
+            .buffers
+            .get(buffer)
+            .ok_or_else(|| invalid(format!("Buffer index {} is out of bounds", buffer)))?;
+        let view_offset = optional_index(view, "byteOffset")?.unwrap_or(0);
+        let view_length = required_index(view, "byteLength")?;
+        let data = view_offset
+            .checked_add(view_length)
+            .and_then(|end| buffer.get(view_offset..end))
+            .ok_or_else(|| invalid(format!("Buffer view {} is out of bounds", index)))?;
+
+        let component_size = match component_type {
+            BYTE | UNSIGNED_BYTE => 1,
+            SHORT | UNSIGNED_SHORT => 2,
+            UNSIGNED_INT | FLOAT => 4,
+            _ => {
+                return Err(invalid(format!(
+                    "Unknown component type {}",
+                    component_type
+                )))
+            }
+        };
+        let element_size = component_size * width;
+        let stride = optional_index(view, "byteStride")?.unwrap_or(element_size);
+
+        // Offset past the last element read.
+        let end = match count.checked_sub(1) {
+            None => Some(0),
+            Some(last) => stride
+                .checked_mul(last)
+                .and_then(|last| last.checked_add(offset))
+                .and_then(|last| last.checked_add(element_size)),
+        };
+        if end.map_or(true, |end| end > data.len()) {
+            return Err(invalid(format!("Accessor exceeds buffer view {}", index)));
+        }
+
+        let mut values = Vec::with_capacity(count * width);
+        for element in 0..count {
+            let start = offset + stride * element;
+            for c in data[start..start + element_size].chunks_exact(component_size) {
+                values.push(match component_type {
+                    BYTE => f64::from(c[0] as i8),
+                    UNSIGNED_BYTE => f64::from(c[0]),
+                    SHORT => f64::from(i16::from_le_bytes([c[0], c[1]])),
+                    UNSIGNED_SHORT => f64::from(u16::from_le_bytes([c[0], c[1]])),
+                    UNSIGNED_INT => f64::from(u32::from_le_bytes([c[0], c[1], c[2], c[3]])),
+                    _ => f64::from(f32::from_le_bytes([c[0], c[1], c[2], c[3]])),
+                });
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Indices for non-indexed primitive of `count` vertices.
+fn generated(count: usize) -> (Vec<u32>, bool) {
+    ((0..count as u32).collect(), count > u16::MAX as usize + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::mesh::Indices};
+
+    fn floats(data: &[f32]) -> Vec<u8> {
+        data.iter().flat_map(|f| f.to_le_bytes().to_vec()).collect()
+    }
+
+    fn quad() -> Vec<f32> {
+        vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize((json.len() + 3) / 4 * 4, b' ');
+        let mut bin = bin.to_vec();
+        bin.resize((bin.len() + 3) / 4 * 4, 0);
+
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut glb = b"glTF".to_vec();
+        for &word in &[2, length as u32, json.len() as u32, GLB_CHUNK_JSON] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(&json);
+        for &word in &[bin.len() as u32, GLB_CHUNK_BIN] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(&bin);
+        glb
+    }
+
+    #[test]
+    fn load_glb() {
+        let mut bin = floats(&quad());
+        bin.extend(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+        bin.extend(floats(&[0.5; 16]));
+        for index in &[0u16, 1, 2, 0, 2, 3] {
+            bin.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 140 }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 128 },
+                { "buffer": 0, "byteOffset": 128, "byteLength": 12 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3" },
+                { "bufferView": 0, "byteOffset": 48, "componentType": 5121, "count": 4, "type": "VEC4" },
+                { "bufferView": 0, "byteOffset": 64, "componentType": 5126, "count": 4, "type": "VEC4" },
+                { "bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR" }
+            ],
+            "meshes": [{
+                "name": "Quad",
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2 },
+                    "indices": 3,
+                    "material": 1
+                }]
+            }],
+            "nodes": [
+                { "name": "Left", "mesh": 0 },
+                { "name": "Camera" },
+                { "name": "Right", "mesh": 0 }
+            ]
+        }"#;
+
+        let result = load_from_gltf(&glb(json, &bin), &[]).unwrap();
+        assert_eq!(result.len(), 1);
+        let (builder, primitive) = &result[0];
+        assert_eq!(
+            primitive,
+            &GltfPrimitive {
+                mesh: 0,
+                mesh_name: Some("Quad".into()),
+                node_names: vec!["Left".into(), "Right".into()],
+                material: Some(1),
+            }
+        );
+        assert_eq!(builder.prim_type(), Primitive::TriangleList);
+        assert_eq!(
+            builder.vertices::<Position>().unwrap()[2],
+            Position([1.0, 1.0, 0.0])
+        );
+        assert_eq!(
+            builder.vertices::<Joints>().unwrap()[1],
+            Joints([4, 5, 6, 7])
+        );
+        assert_eq!(builder.vertices::<Weights>().unwrap()[3], Weights([0.5; 4]));
+        assert!(builder.vertices::<Normal>().is_none());
+        match builder.indices() {
+            Indices::U16(indices) => assert_eq!(&*indices, &[0, 1, 2, 0, 2, 3]),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+    }
+
+    #[test]
+    fn load_external_buffer() {
+        // Interleaved positions and normalized texture coordinates, then indices.
+        let mut buffer = Vec::new();
+        for (i, position) in quad().chunks(3).enumerate() {
+            buffer.extend(floats(position));
+            buffer.extend_from_slice(&[(i % 2) as u8 * 255, 0, 0, 0]);
+        }
+        for index in &[0u32, 1, 2, 3] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        // Sparse replacement of the last position.
+        buffer.extend_from_slice(&3u16.to_le_bytes());
+        buffer.extend(floats(&[2.0, 2.0, 2.0]));
+
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "uri": "quad.bin", "byteLength": 94 }],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 64, "byteStride": 16 },
+                { "buffer": 0, "byteOffset": 64, "byteLength": 16 },
+                { "buffer": 0, "byteOffset": 80, "byteLength": 14 }
+            ],
+            "accessors": [
+                {
+                    "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                    "sparse": {
+                        "count": 1,
+                        "indices": { "bufferView": 2, "componentType": 5123 },
+                        "values": { "bufferView": 2, "byteOffset": 2 }
+                    }
+                },
+                {
+                    "bufferView": 0, "byteOffset": 12, "componentType": 5121,
+                    "normalized": true, "count": 4, "type": "VEC2"
+                },
+                { "bufferView": 1, "componentType": 5125, "count": 4, "type": "SCALAR" }
+            ],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "TEXCOORD_0": 1 },
+                    "indices": 2,
+                    "mode": 2
+                }]
+            }]
+        }"#;
+
+        assert_eq!(
+            external_buffers(json.as_bytes()).unwrap(),
+            vec![Some("quad.bin".to_string())]
+        );
+        match load_from_gltf(json.as_bytes(), &[]) {
+            Err(GltfError::MissingBuffer(0)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let result = load_from_gltf(json.as_bytes(), &[&buffer]).unwrap();
+        let (builder, primitive) = &result[0];
+        assert_eq!(primitive.node_names, Vec::<String>::new());
+        assert_eq!(primitive.material, None);
+        // Line loop is loaded as closed line strip.
+        assert_eq!(builder.prim_type(), Primitive::LineStrip);
+        match builder.indices() {
+            Indices::U32(indices) => assert_eq!(&*indices, &[0, 1, 2, 3, 0]),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+        assert_eq!(
+            builder.vertices::<Position>().unwrap(),
+            vec![
+                Position([0.0, 0.0, 0.0]),
+                Position([1.0, 0.0, 0.0]),
+                Position([1.0, 1.0, 0.0]),
+                Position([2.0, 2.0, 2.0]),
+            ]
+        );
+        assert_eq!(
+            builder.vertices::<TexCoord>().unwrap(),
+            vec![
+                TexCoord([0.0, 0.0]),
+                TexCoord([1.0, 0.0]),
+                TexCoord([0.0, 0.0]),
+                TexCoord([1.0, 0.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_data_uri() {
+        // Three positions followed by three `u16` indices.
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIA",
+                "byteLength": 42
+            }],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 36, "byteLength": 6 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+                { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }
+            ],
+            "meshes": [
+                { "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "mode": 5 }] },
+                { "primitives": [{ "attributes": { "POSITION": 0 }, "mode": 6 }] }
+            ]
+        }"#;
+
+        assert_eq!(external_buffers(json.as_bytes()).unwrap(), vec![None]);
+        let result = load_from_gltf(json.as_bytes(), &[]).unwrap();
+        assert_eq!(result.len(), 2);
+
+        let (strip, _) = &result[0];
+        assert_eq!(strip.prim_type(), Primitive::TriangleStrip);
+        assert_eq!(
+            strip.vertices::<Position>().unwrap(),
+            vec![
+                Position([0.0, 0.0, 0.0]),
+                Position([1.0, 0.0, 0.0]),
+                Position([0.0, 1.0, 0.0]),
+            ]
+        );
+        match strip.indices() {
+            Indices::U16(indices) => assert_eq!(&*indices, &[0, 1, 2]),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+
+        // Triangle fan is loaded as indexed triangle list.
+        let (fan, primitive) = &result[1];
+        assert_eq!(primitive.mesh, 1);
+        assert_eq!(fan.prim_type(), Primitive::TriangleList);
+        match fan.indices() {
+            Indices::U16(indices) => assert_eq!(&*indices, &[0, 1, 2]),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        // Three positions followed by three `u16` indices.
+        let uri = "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIA";
+        let load = |views: &str, accessors: &str| {
+            let json = format!(
+                r#"{{
+                    "asset": {{ "version": "2.0" }},
+                    "buffers": [{{ "uri": "{}", "byteLength": 42 }}],
+                    "bufferViews": [{}],
+                    "accessors": [{}],
+                    "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}]
+                }}"#,
+                uri, views, accessors
+            );
+            load_from_gltf(json.as_bytes(), &[])
+        };
+        let positions = r#"{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }"#;
+
+        // Index referencing vertex past the end.
+        match load(
+            r#"{ "buffer": 0, "byteLength": 36 }, { "buffer": 0, "byteOffset": 36, "byteLength": 6 }"#,
+            r#"{ "bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3" },
+               { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }"#,
+        ) {
+            Err(GltfError::Invalid(message)) => {
+                assert_eq!(message, "Index 2 is out of bounds of 2 vertices")
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        // Offsets and strides that overflow.
+        match load(
+            r#"{ "buffer": 0, "byteOffset": 18446744073709551615, "byteLength": 2 }"#,
+            positions,
+        ) {
+            Err(GltfError::Invalid(_)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+        match load(
+            r#"{ "buffer": 0, "byteLength": 36, "byteStride": 9223372036854775807 }"#,
+            positions,
+        ) {
+            Err(GltfError::Invalid(_)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn required_extensions() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "extensionsRequired": ["KHR_draco_mesh_compression"]
+        }"#;
+        match load_from_gltf(json.as_bytes(), &[]) {
+            Err(GltfError::Unsupported(_)) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
@@ -3,10 +3,11 @@
 use log::trace;
 use {
     crate::{mesh::MeshBuilder, Normal, Position, TexCoord},
-    wavefront_obj::obj,
+    std::collections::HashMap,
+    wavefront_obj::{mtl, obj},
 };
 
-/// Object loading error.Option
+/// Object loading error.
 #[derive(Debug, PartialEq)]
 pub enum ObjError {
     /// The passed bytes were improper UTF-8 data.
@@ -39,75 +40,99 @@ pub fn load_from_obj(
         .map_err(ObjError::Parse)
 }
 
+/// Load materials defined in mtl material library.
+///
+/// Material names returned by `load_from_obj` refer to materials of the library
+/// referenced by `mtllib` statement of the obj file.
+pub fn load_materials_from_mtl(bytes: &[u8]) -> Result<Vec<mtl::Material>, ObjError> {
+    let string = std::str::from_utf8(bytes).map_err(ObjError::Utf8)?;
+    let mtl_set = mtl::parse(string).map_err(ObjError::Parse)?;
+    Ok(mtl_set.materials)
+}
+
+/// Key of unique vertex.
+/// Bit patterns of position, normal and texture coordinates.
+type VertexKey = ([u32; 3], [u32; 3], [u32; 2]);
+
 fn load_from_data(
     obj_set: obj::ObjSet,
 ) -> Result<Vec<(MeshBuilder<'static>, Option<String>)>, wavefront_obj::ParseError> {
     // Takes a list of objects that contain geometries that contain shapes that contain
     // vertex/texture/normal indices into the main list of vertices, and converts to
-    // MeshBuilders with Position, Normal, TexCoord.
+    // MeshBuilders with Position, Normal, TexCoord and indices.
+    // Polygons are triangulated by the parser.
     trace!("Loading mesh");
     let mut objects = vec![];
 
     for object in obj_set.objects {
         for geometry in &object.geometry {
-            let mut builder = MeshBuilder::new();
-
+            let mut positions = Vec::new();
+            let mut normals = Vec::new();
+            let mut tex_coords = Vec::new();
             let mut indices = Vec::new();
 
+            // Obj files have separate indices for positions, normals and texture coordinates.
+            // Each unique combination of them becomes single vertex.
+            let mut unique = HashMap::<VertexKey, u32>::new();
+            let mut vertex = |(v, t, n): obj::VTNIndex| {
+                let position = object.vertices[v];
+                let position = Position([position.x as f32, position.y as f32, position.z as f32]);
+                let normal = n
+                    .map(|i| {
+                        let normal: obj::Normal = object.normals[i];
+                        Normal([normal.x as f32, normal.y as f32, normal.z as f32])
+                    })
+                    .unwrap_or(Normal([0.0, 0.0, 0.0]));
+                let tex_coord = t
+                    .map(|i| {
+                        let tvertex: obj::TVertex = object.tex_vertices[i];
+                        TexCoord([tvertex.u as f32, tvertex.v as f32])
+                    })
+                    .unwrap_or(TexCoord([0.0, 0.0]));
+
+                let key = (
+                    bits(&position.0),
+                    bits(&normal.0),
+                    [tex_coord.0[0].to_bits(), tex_coord.0[1].to_bits()],
+                );
+                let index = *unique.entry(key).or_insert_with(|| {
+                    positions.push(position);
+                    normals.push(normal);
+                    tex_coords.push(tex_coord);
+                    positions.len() as u32 - 1
+                });
+                indices.push(index);
+            };
+
             geometry.shapes.iter().for_each(|shape| {
                 if let obj::Primitive::Triangle(v1, v2, v3) = shape.primitive {
-                    indices.push(v1);
-                    indices.push(v2);
-                    indices.push(v3);
+                    vertex(v1);
+                    vertex(v2);
+                    vertex(v3);
                 }
             });
-            // We can't use the vertices directly because we have per face normals and not per vertex normals in most obj files
-            // TODO: Compress duplicates and return indices for indexbuffer.
-            let positions = indices
-                .iter()
-                .map(|index| {
-                    let vertex: obj::Normal = object.vertices[index.0];
-                    Position([vertex.x as f32, vertex.y as f32, vertex.z as f32])
-                })
-                .collect::<Vec<_>>();
-
-            trace!("Loading normals");
-            let normals = indices
-                .iter()
-                .map(|index| {
-                    index
-                        .2
-                        .map(|i| {
-                            let normal: obj::Normal = object.normals[i];
-                            Normal([normal.x as f32, normal.y as f32, normal.z as f32])
-                        })
-                        .unwrap_or(Normal([0.0, 0.0, 0.0]))
-                })
-                .collect::<Vec<_>>();
-
-            let tex_coords = indices
-                .iter()
-                .map(|index| {
-                    index
-                        .1
-                        .map(|i| {
-                            let tvertex: obj::TVertex = object.tex_vertices[i];
-                            TexCoord([tvertex.u as f32, tvertex.v as f32])
-                        })
-                        .unwrap_or(TexCoord([0.0, 0.0]))
-                })
-                .collect::<Vec<_>>();
 
-            // builder.set_indices(indices.iter().map(|i| i.0 as u16).collect::<Vec<u16>>());
-
-            debug_assert!(&normals.len() == &positions.len());
-            debug_assert!(&tex_coords.len() == &positions.len());
+            debug_assert!(normals.len() == positions.len());
+            debug_assert!(tex_coords.len() == positions.len());
+            trace!(
+                "Loaded {} unique vertices for {} indices",
+                positions.len(),
+                indices.len()
+            );
 
+            let mut builder = MeshBuilder::new();
+            // Geometry without triangles is left unindexed.
+            if !indices.is_empty() {
+                if positions.len() <= u16::MAX as usize + 1 {
+                    builder.set_indices(indices.into_iter().map(|i| i as u16).collect::<Vec<_>>());
+                } else {
+                    builder.set_indices(indices);
+                }
+            }
             builder.add_vertices(positions);
             builder.add_vertices(normals);
             builder.add_vertices(tex_coords);
 
-            // TODO: Add Material loading
             objects.push((builder, geometry.material_name.clone()))
         }
     }
@@ -115,9 +140,17 @@ fn load_from_data(
     Ok(objects)
 }
 
+fn bits(values: &[f32; 3]) -> [u32; 3] {
+    [
+        values[0].to_bits(),
+        values[1].to_bits(),
+        values[2].to_bits(),
+    ]
+}
+
 #[cfg(test)]
 mod test {
-    use super::*;
+    use {super::*, crate::mesh::Indices};
 
     #[test]
     fn test_load_from_obj() {
@@ -142,6 +175,151 @@ f 7/1/6 1/2/6 5/3/6\nf 5/3/6 1/2/6 3/4/6
         assert_eq!(result.len(), 1);
 
         // When compressed into unique vertices there should be 4 vertices per side of the quad
-        // assert!()
+        let (builder, _) = &result[0];
+        let positions = builder.vertices::<Position>().unwrap();
+        let normals = builder.vertices::<Normal>().unwrap();
+        let tex_coords = builder.vertices::<TexCoord>().unwrap();
+        assert_eq!(positions.len(), 24);
+        assert_eq!(normals.len(), 24);
+        assert_eq!(tex_coords.len(), 24);
+        let indices = match builder.indices() {
+            Indices::U16(indices) => indices.iter().map(|&i| i as usize).collect::<Vec<_>>(),
+            indices => panic!("Unexpected indices {:?}", indices),
+        };
+        assert_eq!(indices.len(), 36);
+
+        // Indexed vertices rebuild faces of the file, up to rotation of each triangle.
+        let text = std::str::from_utf8(quad).unwrap();
+        let values = |prefix: &str| {
+            text.lines()
+                .filter_map(|line| line.strip_prefix(prefix))
+                .map(|line| {
+                    line.split_whitespace()
+                        .map(|v| v.parse::<f32>().unwrap())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let (v, vt, vn) = (values("v "), values("vt "), values("vn "));
+        let corner = |p: &[f32], t: &[f32], n: &[f32]| {
+            let mut corner = p.to_vec();
+            corner.extend_from_slice(t);
+            corner.extend_from_slice(n);
+            corner.iter().map(|c| c.to_bits()).collect::<Vec<_>>()
+        };
+        let canonical = |mut triangle: Vec<Vec<u32>>| {
+            let first = (0..3).min_by_key(|&i| triangle[i].clone()).unwrap();
+            triangle.rotate_left(first);
+            triangle
+        };
+        let mut expected = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("f "))
+            .map(|line| {
+                canonical(
+                    line.split_whitespace()
+                        .map(|corner_indices| {
+                            let i: Vec<usize> = corner_indices
+                                .split('/')
+                                .map(|i| i.parse::<usize>().unwrap() - 1)
+                                .collect();
+                            corner(&v[i[0]], &vt[i[1]], &vn[i[2]])
+                        })
+                        .collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut loaded = indices
+            .chunks(3)
+            .map(|t| {
+                canonical(
+                    t.iter()
+                        .map(|&i| corner(&positions[i].0, &tex_coords[i].0, &normals[i].0))
+                        .collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        expected.sort();
+        loaded.sort();
+        assert_eq!(loaded, expected);
+    }
+
+    #[test]
+    fn test_load_polygons() {
+        let polygons = b"mtllib scene.mtl
+v 0.0 0.0 0.0\nv 1.0 0.0 0.0\nv 1.0 1.0 0.0\nv 0.0 1.0 0.0\nv 0.5 1.5 0.0
+usemtl quad
+f 1 2 3 4
+usemtl pentagon
+f 1 2 3 5 4
+";
+        let result = load_from_obj(polygons).unwrap();
+        assert_eq!(result.len(), 2);
+
+        let (quad, material) = &result[0];
+        assert_eq!(material.as_ref().map(String::as_str), Some("quad"));
+        assert_eq!(quad.vertices::<Position>().unwrap().len(), 4);
+        match quad.indices() {
+            Indices::U16(indices) => assert_eq!(indices.len(), 6),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+
+        let (pentagon, material) = &result[1];
+        assert_eq!(material.as_ref().map(String::as_str), Some("pentagon"));
+        assert_eq!(pentagon.vertices::<Position>().unwrap().len(), 5);
+        match pentagon.indices() {
+            Indices::U16(indices) => assert_eq!(indices.len(), 9),
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+    }
+
+    #[test]
+    fn test_load_without_triangles() {
+        let lines = b"v 0.0 0.0 0.0\nv 1.0 0.0 0.0\nl 1 2\n";
+        let result = load_from_obj(lines).unwrap();
+        assert_eq!(result.len(), 1);
+        match result[0].0.indices() {
+            Indices::None => {}
+            indices => panic!("Unexpected indices {:?}", indices),
+        }
+    }
+
+    #[test]
+    fn test_load_materials_from_mtl() {
+        // Statements of material must follow the order the parser expects.
+        let mtl = b"newmtl quad
+Ns 10.0
+Ka 0.0 0.0 0.0
+Kd 1.0 0.0 0.0
+Ks 0.0 0.0 0.0
+d 1.0
+illum 2
+
+newmtl pentagon
+Ns 10.0
+Ka 0.0 0.0 0.0
+Kd 0.0 1.0 0.0
+Ks 0.0 0.0 0.0
+d 1.0
+illum 2
+";
+        let materials = load_materials_from_mtl(mtl).unwrap();
+        let diffuse = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "quad");
+        assert_eq!(materials[1].name, "pentagon");
+        for (material, diffuse) in materials.iter().zip(&diffuse) {
+            let color = |c: mtl::Color| [c.r, c.g, c.b];
+            assert_eq!(material.specular_coefficient, 10.0);
+            assert_eq!(color(material.color_ambient), [0.0; 3]);
+            assert_eq!(color(material.color_diffuse), *diffuse);
+            assert_eq!(color(material.color_specular), [0.0; 3]);
+            assert_eq!(material.alpha, 1.0);
+            assert_eq!(
+                material.illumination,
+                mtl::Illumination::AmbientDiffuseSpecular
+            );
+            assert_eq!(material.uv_map, None);
+        }
     }
 }
@@ -0,0 +1,396 @@
+//!
+//! Generation of normals and tangents for triangle meshes.
+//!
+
+use {
+    crate::{core::hal::pso::Primitive, Normal, Position, Tangent, TexCoord},
+    std::collections::HashMap,
+};
+
+/// Error returned when vertex attributes can't be generated for the mesh
+/// or the mesh can't be optimized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GenerateError {
+    /// Attribute required for generation is missing.
+    MissingAttribute(&'static str),
+    /// Primitive type of the mesh is not a triangle list or strip.
+    UnsupportedPrimitive(Primitive),
+}
+
+impl std::fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GenerateError::MissingAttribute(name) => {
+                write!(
+                    f,
+                    "Mesh has no '{}' attribute required for generation",
+                    name
+                )
+            }
+            GenerateError::UnsupportedPrimitive(prim) => write!(
+                f,
+                "Only triangle meshes can be processed. Mesh primitive is {:?}",
+                prim
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+/// Collect triangles of the mesh as triplets of vertex indices.
+/// Triangle strips are unrolled preserving winding, degenerate triangles are skipped.
+pub(crate) fn triangles(
+    prim: Primitive,
+    indices: Option<Vec<u32>>,
+    vertex_count: usize,
+) -> Result<Vec<[u32; 3]>, GenerateError> {
+    let indices = indices.unwrap_or_else(|| (0..vertex_count as u32).collect());
+    match prim {
+        Primitive::TriangleList => Ok(indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect()),
+        Primitive::TriangleStrip => Ok(indices
+            .windows(3)
+            .enumerate()
+            .map(|(i, t)| {
+                if i % 2 == 0 {
+                    [t[0], t[1], t[2]]
+                } else {
+                    [t[1], t[0], t[2]]
+                }
+            })
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+            .collect()),
+        prim => Err(GenerateError::UnsupportedPrimitive(prim)),
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Normalize vector. Returns zero vector if length is zero.
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt();
+    if length > 0.0 {
+        scale(a, 1.0 / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+fn bits(a: [f32; 3]) -> [u32; 3] {
+    [a[0].to_bits(), a[1].to_bits(), a[2].to_bits()]
+}
+
+/// Angle between vectors from `p` to `a` and from `p` to `b`.
+fn angle(p: [f32; 3], a: [f32; 3], b: [f32; 3]) -> f32 {
+    let cos = dot(normalize(sub(a, p)), normalize(sub(b, p)));
+    cos.clamp(-1.0, 1.0).acos()
+}
+
+/// Generate normal for each corner of each triangle.
+///
+/// Normal of the corner is average of normals of triangles
+/// that share position of the corner and whose normals differ from normal
+/// of the corner's triangle by no more than `smooth_angle` radians.
+/// Triangle normals are weighted by the triangle's angle at the position,
+/// so result doesn't depend on triangulation of flat polygons.
+pub(crate) fn normals(
+    positions: &[Position],
+    triangles: &[[u32; 3]],
+    smooth_angle: f32,
+) -> Vec<[Normal; 3]> {
+    let position = |index: u32| positions[index as usize].0;
+
+    let face_normals: Vec<_> = triangles
+        .iter()
+        .map(|t| {
+            let [a, b, c] = [position(t[0]), position(t[1]), position(t[2])];
+            normalize(cross(sub(b, a), sub(c, a)))
+        })
+        .collect();
+
+    // Triangles that share each position with the triangle's angle at it.
+    // Vertices with different attributes may share the position.
+    let mut faces = HashMap::<[u32; 3], Vec<(usize, f32)>>::new();
+    for (face, t) in triangles.iter().enumerate() {
+        for c in 0..3 {
+            let p = position(t[c]);
+            let faces = faces.entry(bits(p)).or_default();
+            if faces.last().map(|&(last, _)| last) != Some(face) {
+                let weight = angle(p, position(t[(c + 1) % 3]), position(t[(c + 2) % 3]));
+                faces.push((face, weight));
+            }
+        }
+    }
+
+    let threshold = smooth_angle.cos();
+    triangles
+        .iter()
+        .enumerate()
+        .map(|(face, t)| {
+            let mut corners = [Normal([0.0; 3]); 3];
+            for (corner, &index) in corners.iter_mut().zip(t) {
+                let normal = faces[&bits(position(index))]
+                    .iter()
+                    .filter(|&&(other, _)| {
+                        other == face || dot(face_normals[face], face_normals[other]) >= threshold
+                    })
+                    .fold([0.0; 3], |sum, &(other, weight)| {
+                        add(sum, scale(face_normals[other], weight))
+                    });
+                *corner = Normal(normalize(normal));
+            }
+            corners
+        })
+        .collect()
+}
+
+/// Generate MikkTSpace compatible tangent for each corner of each triangle.
+///
+/// Tangents are derived from texture coordinates and projected onto the tangent plane
+/// defined by vertex normal.
+/// Contributions of triangles are weighted by corner angle and averaged
+/// across all corners with the same position, normal and texture coordinates
+/// that have the same orientation of texture space.
+/// The `w` component is the handedness of the bitangent,
+/// which is `cross(normal, tangent.xyz) * tangent.w`.
+pub(crate) fn tangents(
+    positions: &[Position],
+    normals: &[Normal],
+    tex_coords: &[TexCoord],
+    triangles: &[[u32; 3]],
+) -> Vec<[Tangent; 3]> {
+    #[derive(Clone, Copy, Hash, PartialEq, Eq)]
+    struct Key {
+        position: [u32; 3],
+        normal: [u32; 3],
+        tex_coord: [u32; 2],
+        preserving: bool,
+    }
+
+    let key = |index: u32, preserving: bool| {
+        let tex_coord = tex_coords[index as usize].0;
+        Key {
+            position: bits(positions[index as usize].0),
+            normal: bits(normals[index as usize].0),
+            tex_coord: [tex_coord[0].to_bits(), tex_coord[1].to_bits()],
+            preserving,
+        }
+    };
+
+    let mut sums = HashMap::<Key, [f32; 3]>::new();
+    let mut keys = Vec::with_capacity(triangles.len());
+
+    for t in triangles {
+        let [p0, p1, p2] = [0, 1, 2].map(|c| positions[t[c] as usize].0);
+        let [uv0, uv1, uv2] = [0, 1, 2].map(|c| tex_coords[t[c] as usize].0);
+        let d1 = sub(p1, p0);
+        let d2 = sub(p2, p0);
+        let (s1, t1) = (uv1[0] - uv0[0], uv1[1] - uv0[1]);
+        let (s2, t2) = (uv2[0] - uv0[0], uv2[1] - uv0[1]);
+        let signed_area = s1 * t2 - t1 * s2;
+        let preserving = signed_area > 0.0;
+        let corner_keys = [0, 1, 2].map(|c| key(t[c], preserving));
+        keys.push(corner_keys);
+
+        if signed_area == 0.0 {
+            // Texture space is degenerate, triangle doesn't contribute.
+            continue;
+        }
+
+        let sign = if preserving { 1.0 } else { -1.0 };
+        let os = scale(normalize(sub(scale(d1, t2), scale(d2, t1))), sign);
+
+        let corner_positions = [p0, p1, p2];
+        for c in 0..3 {
+            let n = normals[t[c] as usize].0;
+            let project = |v: [f32; 3]| normalize(sub(v, scale(n, dot(n, v))));
+            let p = corner_positions[c];
+            let e1 = project(sub(corner_positions[(c + 1) % 3], p));
+            let e2 = project(sub(corner_positions[(c + 2) % 3], p));
+            let angle = dot(e1, e2).clamp(-1.0, 1.0).acos();
+            let sum = sums.entry(corner_keys[c]).or_insert([0.0; 3]);
+            *sum = add(*sum, scale(project(os), angle));
+        }
+    }
+
+    keys.into_iter()
+        .map(|corner_keys| {
+            corner_keys.map(|key| {
+                let tangent = sums.get(&key).map_or([0.0; 3], |&sum| normalize(sum));
+                let tangent = if tangent == [0.0; 3] {
+                    // No usable texture space, pick any vector orthogonal to the normal.
+                    let n = [0, 1, 2].map(|i| f32::from_bits(key.normal[i]));
+                    let axis = if n[0].abs() < 0.9 {
+                        [1.0, 0.0, 0.0]
+                    } else {
+                        [0.0, 1.0, 0.0]
+                    };
+                    normalize(sub(axis, scale(n, dot(n, axis))))
+                } else {
+                    tangent
+                };
+                let w = if key.preserving { 1.0 } else { -1.0 };
+                Tangent([tangent[0], tangent[1], tangent[2], w])
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::mesh::{Indices, MeshBuilder},
+    };
+
+    fn cube() -> MeshBuilder<'static> {
+        let positions: Vec<_> = (0..8)
+            .map(|i| Position([(i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2 & 1) as f32]))
+            .collect();
+        let indices: Vec<u16> = vec![
+            0, 2, 1, 1, 2, 3, // -z
+            4, 5, 6, 5, 7, 6, // +z
+            0, 1, 4, 1, 5, 4, // -y
+            2, 6, 3, 3, 6, 7, // +y
+            0, 4, 2, 2, 4, 6, // -x
+            1, 3, 5, 3, 7, 5, // +x
+        ];
+        MeshBuilder::new()
+            .with_vertices(positions)
+            .with_indices(indices)
+    }
+
+    fn assert_near(left: [f32; 3], right: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (left[i] - right[i]).abs() < 1e-5),
+            "{:?} != {:?}",
+            left,
+            right
+        );
+    }
+
+    #[test]
+    fn flat_normals() {
+        let mut cube = cube();
+        cube.generate_normals(0.5).unwrap();
+
+        let positions = cube.vertices::<Position>().unwrap();
+        let normals = cube.vertices::<Normal>().unwrap();
+        assert_eq!(positions.len(), 24);
+        assert_eq!(normals.len(), 24);
+        let indices = match cube.indices() {
+            Indices::U16(indices) => indices.into_owned(),
+            indices => panic!("Unexpected indices {:?}", indices),
+        };
+        assert_eq!(indices.len(), 36);
+
+        let expected = [
+            [0.0, 0.0, -1.0],
+            [0.0, 0.0, 1.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+        ];
+        for (side, indices) in indices.chunks(6).enumerate() {
+            for &index in indices {
+                assert_near(normals[index as usize].0, expected[side]);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_normals() {
+        let mut cube = cube();
+        cube.generate_normals(std::f32::consts::PI).unwrap();
+
+        let positions = cube.vertices::<Position>().unwrap();
+        let normals = cube.vertices::<Normal>().unwrap();
+        assert_eq!(normals.len(), 8);
+        for (position, normal) in positions.iter().zip(&normals) {
+            let expected = normalize(sub(position.0, [0.5; 3]));
+            assert_near(normal.0, expected);
+        }
+    }
+
+    #[test]
+    fn mirrored_tangents() {
+        // Two quads sharing an edge at x = 1 with texture mirrored across the edge.
+        let positions: Vec<_> = (0..6)
+            .map(|i| Position([(i / 2) as f32, (i % 2) as f32, 0.0]))
+            .collect();
+        let tex_coords: Vec<_> = (0..6)
+            .map(|i| TexCoord([if i / 2 == 1 { 1.0 } else { 0.0 }, (i % 2) as f32]))
+            .collect();
+        let indices: Vec<u16> = vec![0, 2, 1, 1, 2, 3, 2, 4, 3, 3, 4, 5];
+
+        let mut mesh = MeshBuilder::new()
+            .with_vertices(positions)
+            .with_vertices(tex_coords)
+            .with_indices(indices);
+
+        assert_eq!(
+            mesh.generate_tangents().unwrap_err(),
+            GenerateError::MissingAttribute("normal")
+        );
+        mesh.generate_normals(0.1).unwrap();
+        assert_eq!(mesh.vertices::<Position>().unwrap().len(), 6);
+        mesh.generate_tangents().unwrap();
+
+        // Vertices on the shared edge are split.
+        let tangents = mesh.vertices::<Tangent>().unwrap();
+        assert_eq!(tangents.len(), 8);
+        assert_eq!(mesh.vertices::<TexCoord>().unwrap().len(), 8);
+
+        let indices = match mesh.indices() {
+            Indices::U16(indices) => indices.into_owned(),
+            indices => panic!("Unexpected indices {:?}", indices),
+        };
+        for &index in &indices[..6] {
+            let tangent = tangents[index as usize].0;
+            assert_near([tangent[0], tangent[1], tangent[2]], [1.0, 0.0, 0.0]);
+            assert_eq!(tangent[3], 1.0);
+        }
+        for &index in &indices[6..] {
+            let tangent = tangents[index as usize].0;
+            assert_near([tangent[0], tangent[1], tangent[2]], [-1.0, 0.0, 0.0]);
+            assert_eq!(tangent[3], -1.0);
+        }
+    }
+
+    #[test]
+    fn unsupported_primitive() {
+        let mut lines = MeshBuilder::new()
+            .with_vertices(vec![Position([0.0; 3]), Position([1.0; 3])])
+            .with_prim_type(Primitive::LineList);
+        assert_eq!(
+            lines.generate_normals(0.0).unwrap_err(),
+            GenerateError::UnsupportedPrimitive(Primitive::LineList)
+        );
+    }
+}
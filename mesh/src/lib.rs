@@ -23,7 +23,16 @@ use rendy_memory as memory;
 use rendy_resource as resource;
 
 mod format;
+mod generate;
 mod mesh;
+mod optimize;
+mod simplify;
 
-pub use crate::{format::*, mesh::*};
+pub use crate::{
+    format::*,
+    generate::GenerateError,
+    mesh::*,
+    optimize::{OptimizeStats, VERTEX_CACHE_SIZE},
+    simplify::Lod,
+};
 pub use rendy_core::types::vertex::*;
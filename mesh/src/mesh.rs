@@ -6,15 +6,15 @@ use crate::{
     command::{EncoderCommon, Graphics, QueueId, RenderPassEncoder, Supports},
     core::cast_cow,
     factory::{BufferState, Factory, UploadError},
+    generate::{self, GenerateError},
     memory::{Data, Upload, Write},
+    optimize::{self, OptimizeStats, VERTEX_CACHE_SIZE},
     resource::{Buffer, BufferInfo, Escape},
-    AsVertex, VertexFormat,
+    simplify::{self, Lod},
+    AsAttribute, AsVertex, Normal, Position, TexCoord, VertexFormat,
 };
-use rendy_core::hal::{
-    adapter::PhysicalDevice,
-    buffer::SubRange,
-};
-use std::{borrow::Cow, mem::size_of};
+use rendy_core::hal::{adapter::PhysicalDevice, buffer::SubRange};
+use std::{borrow::Cow, collections::HashMap, mem::size_of};
 
 /// Vertex buffer with it's format
 #[derive(Debug)]
@@ -210,6 +210,353 @@ impl<'a> MeshBuilder<'a> {
         self
     }
 
+    /// Get the primitive type of the mesh.
+    pub fn prim_type(&self) -> rendy_core::hal::pso::Primitive {
+        self.prim
+    }
+
+    /// Get indices of the `MeshBuilder`.
+    pub fn indices(&self) -> Indices<'_> {
+        match self.indices {
+            None => Indices::None,
+            Some(RawIndices {
+                ref indices,
+                index_type: rendy_core::hal::IndexType::U16,
+            }) => Indices::U16(read_cast(indices).into()),
+            Some(RawIndices {
+                ref indices,
+                index_type: rendy_core::hal::IndexType::U32,
+            }) => Indices::U32(read_cast(indices).into()),
+        }
+    }
+
+    /// Get copy of vertices with format of `V` added to the `MeshBuilder`.
+    /// Returns `None` if there are no such vertices.
+    pub fn vertices<V>(&self) -> Option<Vec<V>>
+    where
+        V: AsVertex,
+    {
+        let format = V::vertex();
+        self.vertices
+            .iter()
+            .find(|v| v.format == format)
+            .map(|v| read_cast(&v.vertices))
+    }
+
+    /// Generate normals for the triangles of the mesh.
+    ///
+    /// Normals are averaged across triangles sharing vertex position
+    /// whose normals differ by no more than `smooth_angle` radians.
+    /// Vertices on sharper edges are split.
+    /// Existing normals are replaced.
+    ///
+    /// If any vertex is split the mesh is converted to an indexed triangle list.
+    pub fn generate_normals(&mut self, smooth_angle: f32) -> Result<&mut Self, GenerateError> {
+        let positions = self
+            .attribute::<Position>()
+            .ok_or(GenerateError::MissingAttribute(Position::NAME))?;
+        let triangles = self.triangles(positions.len())?;
+        let normals = generate::normals(&positions, &triangles, smooth_angle);
+        self.set_corner_attribute(positions.len(), &triangles, normals);
+        Ok(self)
+    }
+
+    /// Generate MikkTSpace compatible tangents for the triangles of the mesh
+    /// from positions, normals and texture coordinates.
+    /// Existing tangents are replaced.
+    ///
+    /// Vertices shared by triangles with mirrored texture space are split,
+    /// in which case the mesh is converted to an indexed triangle list.
+    pub fn generate_tangents(&mut self) -> Result<&mut Self, GenerateError> {
+        let positions = self
+            .attribute::<Position>()
+            .ok_or(GenerateError::MissingAttribute(Position::NAME))?;
+        let normals = self
+            .attribute::<Normal>()
+            .ok_or(GenerateError::MissingAttribute(Normal::NAME))?;
+        let tex_coords = self
+            .attribute::<TexCoord>()
+            .ok_or(GenerateError::MissingAttribute(TexCoord::NAME))?;
+        let count = positions.len().min(normals.len()).min(tex_coords.len());
+        let triangles = self.triangles(count)?;
+        let tangents = generate::tangents(&positions, &normals, &tex_coords, &triangles);
+        self.set_corner_attribute(count, &triangles, tangents);
+        Ok(self)
+    }
+
+    /// Reorder triangles and vertices of the mesh for efficient rendering.
+    ///
+    /// Triangles are reordered for post-transform vertex cache locality
+    /// using Tipsify algorithm and, if the mesh has `Position` attribute,
+    /// clusters of triangles are sorted to reduce overdraw.
+    /// Then vertices in all vertex buffers are reordered
+    /// in order of first use for vertex fetch locality.
+    ///
+    /// The mesh is converted to an indexed triangle list.
+    /// Returns average cache miss ratio before and after optimization.
+    pub fn optimize(&mut self) -> Result<OptimizeStats, GenerateError> {
+        let vertex_count = self.vertex_count();
+        let triangles = self.triangles(vertex_count)?;
+        let acmr_before = optimize::acmr(&triangles, vertex_count, VERTEX_CACHE_SIZE);
+
+        let (triangles, clusters) = optimize::tipsify(&triangles, vertex_count, VERTEX_CACHE_SIZE);
+        let triangles = match self.attribute::<Position>() {
+            Some(positions) => optimize::sort_clusters(&triangles, &clusters, &positions),
+            None => triangles,
+        };
+
+        let remap = optimize::fetch_remap(&triangles, vertex_count);
+        let triangles: Vec<_> = triangles
+            .iter()
+            .map(|t| t.map(|index| remap[index as usize]))
+            .collect();
+        self.remap_vertices(&remap);
+        self.set_triangle_list(triangles.iter().flatten().copied().collect(), vertex_count);
+
+        Ok(OptimizeStats {
+            acmr_before,
+            acmr_after: optimize::acmr(&triangles, vertex_count, VERTEX_CACHE_SIZE),
+        })
+    }
+
+    /// Generate chain of simplified levels of detail of the mesh.
+    ///
+    /// For each ratio in `ratios` the level with at most that fraction
+    /// of the mesh's triangles is produced,
+    /// each level is simplified further from the previous one,
+    /// so ratios should be decreasing.
+    /// Level may have more triangles than requested if the mesh can't be simplified
+    /// further without breaking its topology.
+    ///
+    /// Edges are collapsed in order of increasing quadric error.
+    /// Vertices are never moved, so all vertex attributes stay valid.
+    /// Open borders are preserved and vertices on texture coordinate seams,
+    /// which share position with vertices with other texture coordinates, are never removed.
+    /// Vertices that share both position and texture coordinates,
+    /// such as vertices of hard edges, are removed together.
+    ///
+    /// Levels are indexed triangle lists with unused vertices removed.
+    pub fn simplify(&self, ratios: &[f32]) -> Result<Vec<Lod>, GenerateError> {
+        let positions = self
+            .attribute::<Position>()
+            .ok_or(GenerateError::MissingAttribute(Position::NAME))?;
+        let vertex_count = self.vertex_count();
+        let triangles = self.triangles(vertex_count)?;
+        let total = triangles.len();
+        let tex_coords = self.attribute::<TexCoord>();
+        let mut simplifier = simplify::Simplifier::new(
+            &positions[..vertex_count],
+            tex_coords.as_ref().map(|t| &t[..vertex_count]),
+            triangles,
+        );
+
+        Ok(ratios
+            .iter()
+            .map(|&ratio| {
+                simplifier.simplify((total as f32 * ratio).ceil() as usize);
+                let triangles = simplifier.triangles();
+
+                let mut mesh = self.clone().into_owned();
+                let remap = optimize::fetch_remap(&triangles, vertex_count);
+                let used = triangles
+                    .iter()
+                    .flatten()
+                    .map(|&index| remap[index as usize] + 1)
+                    .max()
+                    .unwrap_or(0) as usize;
+                mesh.remap_vertices(&remap);
+                for v in mesh.vertices.iter_mut() {
+                    v.vertices
+                        .to_mut()
+                        .truncate(used * v.format.stride as usize);
+                }
+                mesh.set_triangle_list(
+                    triangles
+                        .iter()
+                        .flatten()
+                        .map(|&index| remap[index as usize])
+                        .collect(),
+                    used,
+                );
+
+                Lod {
+                    mesh,
+                    error: simplifier.error(),
+                }
+            })
+            .collect())
+    }
+
+    /// Read attribute `A` from vertices that contain it, possibly interleaved.
+    fn attribute<A>(&self) -> Option<Vec<A>>
+    where
+        A: AsAttribute,
+    {
+        self.vertices.iter().find_map(|v| {
+            let offset = find_attribute::<A>(&v.format)?;
+            let stride = v.format.stride as usize;
+            Some(
+                v.vertices
+                    .chunks_exact(stride)
+                    .map(|vertex| unsafe {
+                        std::ptr::read_unaligned(
+                            vertex[offset..offset + size_of::<A>()].as_ptr() as *const A
+                        )
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    /// Triangles of the mesh with `vertex_count` vertices.
+    fn triangles(&self, vertex_count: usize) -> Result<Vec<[u32; 3]>, GenerateError> {
+        let indices = match self.indices() {
+            Indices::None => None,
+            Indices::U16(indices) => Some(indices.iter().map(|&i| u32::from(i)).collect()),
+            Indices::U32(indices) => Some(indices.into_owned()),
+        };
+        generate::triangles(self.prim, indices, vertex_count)
+    }
+
+    /// Set attribute value for each corner of `triangles`.
+    /// Vertices whose corners have different values are split
+    /// and mesh is converted to indexed triangle list.
+    fn set_corner_attribute<A>(
+        &mut self,
+        vertex_count: usize,
+        triangles: &[[u32; 3]],
+        corners: Vec<[A; 3]>,
+    ) where
+        A: AsAttribute,
+    {
+        let mut values: Vec<Option<A>> = vec![None; vertex_count];
+        let mut splits = HashMap::<u32, Vec<(A, u32)>>::new();
+        let mut copies = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+
+        for (t, corners) in triangles.iter().zip(corners) {
+            for (&index, value) in t.iter().zip(corners.iter().copied()) {
+                let index = match values[index as usize] {
+                    None => {
+                        values[index as usize] = Some(value);
+                        index
+                    }
+                    Some(existing) if existing == value => index,
+                    Some(_) => {
+                        let splits = splits.entry(index).or_default();
+                        match splits.iter().find(|&&(split, _)| split == value) {
+                            Some(&(_, copy)) => copy,
+                            None => {
+                                let copy = (vertex_count + copies.len()) as u32;
+                                copies.push((index, value));
+                                splits.push((value, copy));
+                                copy
+                            }
+                        }
+                    }
+                };
+                indices.push(index);
+            }
+        }
+
+        if !copies.is_empty() {
+            for v in self.vertices.iter_mut() {
+                let stride = v.format.stride as usize;
+                let vertices = v.vertices.to_mut();
+                vertices.resize(vertex_count * stride, 0);
+                for &(index, _) in &copies {
+                    let start = index as usize * stride;
+                    vertices.extend_from_within(start..start + stride);
+                }
+            }
+            values.extend(copies.iter().map(|&(_, value)| Some(value)));
+
+            self.set_triangle_list(indices, values.len());
+        }
+
+        self.write_attribute(&values);
+    }
+
+    /// Set indices of triangle list referencing `vertex_count` vertices.
+    /// `u32` indices are used if required or if mesh already has them.
+    fn set_triangle_list(&mut self, indices: Vec<u32>, vertex_count: usize) {
+        self.prim = rendy_core::hal::pso::Primitive::TriangleList;
+        let wide = matches!(
+            self.indices,
+            Some(RawIndices {
+                index_type: rendy_core::hal::IndexType::U32,
+                ..
+            })
+        );
+        if wide || vertex_count > u16::MAX as usize + 1 {
+            self.set_indices(Indices::U32(indices.into()));
+        } else {
+            let indices: Vec<_> = indices.into_iter().map(|i| i as u16).collect();
+            self.set_indices(Indices::U16(indices.into()));
+        }
+    }
+
+    /// Number of complete vertices in all vertex buffers.
+    fn vertex_count(&self) -> usize {
+        self.vertices
+            .iter()
+            .map(|v| v.vertices.len() / v.format.stride as usize)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Move vertex `i` to `remap[i]` in all vertex buffers.
+    fn remap_vertices(&mut self, remap: &[u32]) {
+        for v in self.vertices.iter_mut() {
+            let stride = v.format.stride as usize;
+            let mut vertices = v.vertices.to_vec();
+            for (old, &new) in remap.iter().enumerate() {
+                let new = new as usize * stride;
+                vertices[new..new + stride]
+                    .copy_from_slice(&v.vertices[old * stride..(old + 1) * stride]);
+            }
+            v.vertices = Cow::Owned(vertices);
+        }
+    }
+
+    /// Write values of attribute `A` into vertices that contain it
+    /// or add new vertices if there are none.
+    /// Values that are `None` are left intact.
+    fn write_attribute<A>(&mut self, values: &[Option<A>])
+    where
+        A: AsAttribute,
+    {
+        if !self
+            .vertices
+            .iter()
+            .any(|v| find_attribute::<A>(&v.format).is_some())
+        {
+            self.vertices.push(RawVertices {
+                vertices: Cow::Owned(Vec::new()),
+                format: A::vertex(),
+            });
+        }
+
+        for v in self.vertices.iter_mut() {
+            if let Some(offset) = find_attribute::<A>(&v.format) {
+                let stride = v.format.stride as usize;
+                let vertices = v.vertices.to_mut();
+                vertices.resize(values.len() * stride, 0);
+                for (vertex, value) in vertices.chunks_exact_mut(stride).zip(values) {
+                    if let Some(value) = value {
+                        unsafe {
+                            std::ptr::write_unaligned(
+                                vertex[offset..offset + size_of::<A>()].as_mut_ptr() as *mut A,
+                                *value,
+                            );
+                        }
+                    }
+                }
+                return;
+            }
+        }
+    }
+
     /// Builds and returns the new mesh.
     ///
     /// A mesh expects all vertex buffers to have the same number of elements.
@@ -247,10 +594,11 @@ impl<'a> MeshBuilder<'a> {
             )
             .map_err(UploadError::Create)?;
 
+        // Whole staging buffer is copied, so vertex buffer must be at least as large.
         let mut buffer = factory
             .create_buffer(
                 BufferInfo {
-                    size: buffer_size as _,
+                    size: aligned_size,
                     usage: rendy_core::hal::buffer::Usage::VERTEX
                         | rendy_core::hal::buffer::Usage::TRANSFER_DST,
                 },
@@ -345,6 +693,26 @@ impl<'a> MeshBuilder<'a> {
     }
 }
 
+/// Find offset of attribute `A` in vertex `format`.
+fn find_attribute<A>(format: &VertexFormat) -> Option<usize>
+where
+    A: AsAttribute,
+{
+    format
+        .attributes
+        .iter()
+        .find(|attribute| attribute.name() == A::NAME && attribute.element().format == A::FORMAT)
+        .map(|attribute| attribute.element().offset as usize)
+}
+
+/// Copy elements from raw bytes which may be not aligned for `T`.
+fn read_cast<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(size_of::<T>())
+        .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const T) })
+        .collect()
+}
+
 fn align_by(align: usize, value: usize) -> usize {
     ((value + align - 1) / align) * align
 }
@@ -578,3 +946,66 @@ macro_rules! impl_builder_from_vec {
 }
 
 impl_builder_from_vec!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            command::{Families, Submission},
+            factory::{init_with_instance, Config},
+            memory::FaultPolicy,
+        },
+        rendy_core::Instance,
+        rendy_fake::{FakeBackend, FakeInstance},
+    };
+
+    /// Complete submitted work and free dropped resources.
+    /// Staging buffers are dropped once uploads complete, and dropped resources
+    /// are freed once a fence submitted after the drop is waited for.
+    fn cleanup(factory: &mut Factory<FakeBackend>, families: &mut Families<FakeBackend>) {
+        factory.maintain(families);
+        for _ in 0..3 {
+            let mut fence = factory.create_fence(false).unwrap();
+            unsafe {
+                families
+                    .family_by_index_mut(0)
+                    .queue_mut(0)
+                    .submit(Some(Submission::new()), Some(&mut fence));
+            }
+            factory.wait_for_fence(&mut fence, !0).unwrap();
+            factory.destroy_fence(fence);
+            factory.cleanup(families);
+        }
+    }
+
+    #[test]
+    fn build_failure() {
+        let config: Config = Default::default();
+        let (mut factory, mut families) =
+            init_with_instance::<FakeBackend>(Instance::new(FakeInstance), &config).unwrap();
+        let queue = families.family_by_index(0).queue(0).id();
+        let builder = MeshBuilder::new()
+            .with_vertices(vec![Position([0.0; 3]); 3])
+            .with_indices(vec![0u16, 1, 2]);
+
+        // Vertex staging, vertex, index and index staging buffers.
+        for n in 1..=4 {
+            factory.set_memory_fault_policy(Some(FaultPolicy::Nth(n)));
+            match builder.build(queue, &factory) {
+                Err(UploadError::Create(_)) => {}
+                other => panic!("Allocation {} must fail, got {:?}", n, other),
+            }
+            factory.set_memory_fault_policy(None);
+            cleanup(&mut factory, &mut families);
+            assert_eq!(factory.device().stats().buffers, 0);
+        }
+
+        let mesh = builder.build(queue, &factory).unwrap();
+        assert_eq!(mesh.len(), 3);
+        drop(mesh);
+        cleanup(&mut factory, &mut families);
+        assert_eq!(factory.device().stats().buffers, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+}
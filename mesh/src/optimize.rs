@@ -0,0 +1,321 @@
+//!
+//! Reordering of triangles and vertices for efficient rendering.
+//!
+
+use crate::Position;
+
+/// Size of the post-transform vertex cache indices are optimized for.
+pub const VERTEX_CACHE_SIZE: usize = 16;
+
+/// Statistics of `MeshBuilder::optimize`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimizeStats {
+    /// Average cache miss ratio before optimization.
+    /// This is the number of vertex shader invocations per triangle
+    /// with FIFO cache of `VERTEX_CACHE_SIZE` entries.
+    pub acmr_before: f32,
+    /// Average cache miss ratio after optimization.
+    pub acmr_after: f32,
+}
+
+/// Average cache miss ratio of triangles with FIFO vertex cache of `cache_size` entries.
+pub(crate) fn acmr(triangles: &[[u32; 3]], vertex_count: usize, cache_size: usize) -> f32 {
+    if triangles.is_empty() {
+        return 0.0;
+    }
+
+    // Vertex is in cache if it was loaded less than `cache_size` misses ago.
+    let mut loaded = vec![0usize; vertex_count];
+    let mut misses = 0;
+    for &index in triangles.iter().flatten() {
+        let loaded = &mut loaded[index as usize];
+        if *loaded == 0 || misses - *loaded >= cache_size {
+            misses += 1;
+            *loaded = misses;
+        }
+    }
+    misses as f32 / triangles.len() as f32
+}
+
+/// Reorder triangles for vertex cache locality using Tipsify algorithm
+/// by Sander, Nehab and Barczak.
+///
+/// Returns reordered triangles and starting offsets of clusters.
+/// Cluster ends where the algorithm had to jump to unrelated part of the mesh,
+/// so clusters can be reordered without significant impact on cache efficiency.
+pub(crate) fn tipsify(
+    triangles: &[[u32; 3]],
+    vertex_count: usize,
+    cache_size: usize,
+) -> (Vec<[u32; 3]>, Vec<usize>) {
+    // Triangles adjacent to each vertex.
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for &index in triangles.iter().flatten() {
+        offsets[index as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut adjacency = vec![0usize; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    for (triangle, t) in triangles.iter().enumerate() {
+        for &index in t {
+            adjacency[fill[index as usize]] = triangle;
+            fill[index as usize] += 1;
+        }
+    }
+
+    let mut live: Vec<usize> = (0..vertex_count)
+        .map(|v| offsets[v + 1] - offsets[v])
+        .collect();
+    let mut timestamps = vec![0usize; vertex_count];
+    let mut emitted = vec![false; triangles.len()];
+    let mut dead_end = Vec::new();
+    let mut candidates = Vec::new();
+    let mut output = Vec::with_capacity(triangles.len());
+    let mut clusters = vec![0];
+    let mut time = cache_size + 1;
+    let mut cursor = 0;
+
+    let mut fanning = (0..vertex_count).find(|&v| live[v] > 0);
+    while let Some(vertex) = fanning {
+        candidates.clear();
+        for &triangle in &adjacency[offsets[vertex]..offsets[vertex + 1]] {
+            if emitted[triangle] {
+                continue;
+            }
+            emitted[triangle] = true;
+            output.push(triangles[triangle]);
+            for &index in &triangles[triangle] {
+                let index = index as usize;
+                dead_end.push(index);
+                candidates.push(index);
+                live[index] -= 1;
+                if time - timestamps[index] > cache_size {
+                    timestamps[index] = time;
+                    time += 1;
+                }
+            }
+        }
+
+        // Prefer vertex that will still be in cache after its remaining triangles are emitted.
+        let mut best = None;
+        let mut best_priority = 0;
+        for &candidate in &candidates {
+            if live[candidate] > 0 {
+                let age = time - timestamps[candidate];
+                let priority = if age + 2 * live[candidate] <= cache_size {
+                    age
+                } else {
+                    0
+                };
+                if best.is_none() || priority > best_priority {
+                    best = Some(candidate);
+                    best_priority = priority;
+                }
+            }
+        }
+
+        fanning = match best {
+            Some(best) => Some(best),
+            None => {
+                // Dead end. Try recently used vertices first, then scan the input.
+                let next = std::iter::from_fn(|| dead_end.pop())
+                    .find(|&v| live[v] > 0)
+                    .or_else(|| {
+                        while cursor < vertex_count && live[cursor] == 0 {
+                            cursor += 1;
+                        }
+                        Some(cursor).filter(|&v| v < vertex_count)
+                    });
+                if next.is_some() && output.len() > *clusters.last().unwrap() {
+                    clusters.push(output.len());
+                }
+                next
+            }
+        };
+    }
+
+    (output, clusters)
+}
+
+/// Reorder clusters of triangles to reduce overdraw.
+/// Clusters facing away from the mesh center are drawn first,
+/// as they are likely to occlude others.
+pub(crate) fn sort_clusters(
+    triangles: &[[u32; 3]],
+    clusters: &[usize],
+    positions: &[Position],
+) -> Vec<[u32; 3]> {
+    let position = |index: u32| positions[index as usize].0;
+    let mut mesh_center = [0.0f32; 3];
+    for &index in triangles.iter().flatten() {
+        let p = position(index);
+        (0..3).for_each(|i| mesh_center[i] += p[i]);
+    }
+    let count = (triangles.len() * 3).max(1) as f32;
+    (0..3).for_each(|i| mesh_center[i] /= count);
+
+    let mut sorted: Vec<(f32, &[[u32; 3]])> = clusters
+        .iter()
+        .zip(clusters.iter().skip(1).chain(Some(&triangles.len())))
+        .map(|(&start, &end)| {
+            let cluster = &triangles[start..end];
+            let mut center = [0.0f32; 3];
+            let mut normal = [0.0f32; 3];
+            for t in cluster {
+                let [a, b, c] = [position(t[0]), position(t[1]), position(t[2])];
+                let (e1, e2) = (
+                    [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+                    [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+                );
+                // Area weighted normal.
+                normal[0] += e1[1] * e2[2] - e1[2] * e2[1];
+                normal[1] += e1[2] * e2[0] - e1[0] * e2[2];
+                normal[2] += e1[0] * e2[1] - e1[1] * e2[0];
+                (0..3).for_each(|i| center[i] += a[i] + b[i] + c[i]);
+            }
+            let count = (cluster.len() * 3) as f32;
+            let facing: f32 = (0..3)
+                .map(|i| (center[i] / count - mesh_center[i]) * normal[i])
+                .sum();
+            (facing, cluster)
+        })
+        .collect();
+
+    sorted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    sorted
+        .into_iter()
+        .flat_map(|(_, cluster)| cluster.iter().copied())
+        .collect()
+}
+
+/// Remap vertices in order of first use by triangles.
+/// Returns new index for each old vertex.
+/// Vertices not used by any triangle are placed after used ones.
+pub(crate) fn fetch_remap(triangles: &[[u32; 3]], vertex_count: usize) -> Vec<u32> {
+    const UNUSED: u32 = !0;
+    let mut remap = vec![UNUSED; vertex_count];
+    let mut next = 0;
+    for &index in triangles.iter().flatten() {
+        if remap[index as usize] == UNUSED {
+            remap[index as usize] = next;
+            next += 1;
+        }
+    }
+    for index in remap.iter_mut().filter(|index| **index == UNUSED) {
+        *index = next;
+        next += 1;
+    }
+    remap
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            mesh::{Indices, MeshBuilder},
+            TexCoord,
+        },
+    };
+
+    /// Grid of `size` by `size` quads with triangles in scrambled order.
+    fn grid(size: u32) -> MeshBuilder<'static> {
+        let row = size + 1;
+        let positions: Vec<_> = (0..row * row)
+            .map(|i| Position([(i % row) as f32, (i / row) as f32, 0.0]))
+            .collect();
+        let tex_coords: Vec<_> = positions
+            .iter()
+            .map(|p| TexCoord([p.0[0], p.0[1]]))
+            .collect();
+
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * row + x;
+                triangles.push([i, i + 1, i + row]);
+                triangles.push([i + 1, i + row + 1, i + row]);
+            }
+        }
+        // Deterministic shuffle.
+        let mut state = 12345u32;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            triangles.swap(i, (state >> 8) as usize % (i + 1));
+        }
+        let indices: Vec<u16> = triangles.iter().flatten().map(|&i| i as u16).collect();
+
+        MeshBuilder::new()
+            .with_vertices(positions)
+            .with_vertices(tex_coords)
+            .with_indices(indices)
+    }
+
+    fn positions_of_triangles(mesh: &MeshBuilder<'_>) -> Vec<[[u32; 3]; 3]> {
+        let positions = mesh.vertices::<Position>().unwrap();
+        let indices = match mesh.indices() {
+            Indices::U16(indices) => indices.into_owned(),
+            indices => panic!("Unexpected indices {:?}", indices),
+        };
+        let mut triangles: Vec<_> = indices
+            .chunks(3)
+            .map(|t| {
+                let mut t = [0, 1, 2].map(|c| {
+                    let p = positions[t[c] as usize].0;
+                    [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]
+                });
+                // Rotate to canonical form preserving winding.
+                let min = (0..3).min_by_key(|&c| t[c]).unwrap();
+                t.rotate_left(min);
+                t
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn optimize_grid() {
+        let mut mesh = grid(32);
+        let before = positions_of_triangles(&mesh);
+
+        let stats = mesh.optimize().unwrap();
+        assert!(stats.acmr_before > 2.0, "{:?}", stats);
+        assert!(stats.acmr_after < 0.8, "{:?}", stats);
+
+        // Same triangles with the same winding.
+        assert_eq!(positions_of_triangles(&mesh), before);
+
+        // All vertex buffers are reordered consistently.
+        let positions = mesh.vertices::<Position>().unwrap();
+        let tex_coords = mesh.vertices::<TexCoord>().unwrap();
+        assert_eq!(positions.len(), 33 * 33);
+        for (p, t) in positions.iter().zip(&tex_coords) {
+            assert_eq!([p.0[0], p.0[1]], t.0);
+        }
+
+        // Vertices are in order of first use.
+        let indices = match mesh.indices() {
+            Indices::U16(indices) => indices.into_owned(),
+            indices => panic!("Unexpected indices {:?}", indices),
+        };
+        let mut next = 0;
+        for &index in &indices {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn acmr_of_strip() {
+        // Every triangle of a strip loads one new vertex, except the first.
+        let triangles: Vec<_> = (0..10).map(|i| [i, i + 1, i + 2]).collect();
+        assert_eq!(acmr(&triangles, 12, VERTEX_CACHE_SIZE), 1.2);
+        // Without cache every vertex of every triangle is loaded.
+        assert_eq!(acmr(&triangles, 12, 0), 3.0);
+    }
+}
@@ -0,0 +1,691 @@
+//!
+//! Mesh simplification by edge collapse guided by quadric error metrics.
+//!
+
+use {
+    crate::{mesh::MeshBuilder, Position, TexCoord},
+    std::collections::{HashMap, HashSet},
+};
+
+/// Level of detail produced by `MeshBuilder::simplify`.
+#[derive(Clone, Debug)]
+pub struct Lod {
+    /// Simplified mesh.
+    pub mesh: MeshBuilder<'static>,
+    /// Estimated largest distance between simplified and original surface,
+    /// in units of vertex positions.
+    pub error: f32,
+}
+
+/// Weight of planes that keep border edges in place, relative to triangle planes.
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// Minimal cosine of angle between normals of triangle before and after collapse.
+const MAX_FLIP_COS: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    /// Vertex can be collapsed to any neighbour.
+    Manifold,
+    /// Vertex on open border can be collapsed only along the border.
+    Border,
+    /// Vertex can't be collapsed.
+    /// These are vertices on texture coordinate seams,
+    /// sharing position with vertices with other texture coordinates,
+    /// and vertices with complex topology.
+    Locked,
+}
+
+/// Symmetric matrix of quadric error, sum of squared distances to set of planes.
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric {
+    a2: f64,
+    b2: f64,
+    c2: f64,
+    ab: f64,
+    ac: f64,
+    bc: f64,
+    ad: f64,
+    bd: f64,
+    cd: f64,
+    d2: f64,
+    weight: f64,
+}
+
+impl Quadric {
+    /// Quadric of plane with unit `normal` through `point`.
+    fn plane(normal: [f32; 3], point: [f32; 3], weight: f64) -> Self {
+        let [a, b, c] = normal.map(f64::from);
+        let d = -(a * f64::from(point[0]) + b * f64::from(point[1]) + c * f64::from(point[2]));
+        Quadric {
+            a2: a * a * weight,
+            b2: b * b * weight,
+            c2: c * c * weight,
+            ab: a * b * weight,
+            ac: a * c * weight,
+            bc: b * c * weight,
+            ad: a * d * weight,
+            bd: b * d * weight,
+            cd: c * d * weight,
+            d2: d * d * weight,
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.a2 += other.a2;
+        self.b2 += other.b2;
+        self.c2 += other.c2;
+        self.ab += other.ab;
+        self.ac += other.ac;
+        self.bc += other.bc;
+        self.ad += other.ad;
+        self.bd += other.bd;
+        self.cd += other.cd;
+        self.d2 += other.d2;
+        self.weight += other.weight;
+    }
+
+    /// Weighted mean of squared distances from `point` to the planes.
+    fn error(&self, point: [f32; 3]) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let [x, y, z] = point.map(f64::from);
+        let error = self.a2 * x * x
+            + self.b2 * y * y
+            + self.c2 * z * z
+            + 2.0 * (self.ab * x * y + self.ac * x * z + self.bc * y * z)
+            + 2.0 * (self.ad * x + self.bd * y + self.cd * z)
+            + self.d2;
+        error.max(0.0) / self.weight
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+/// Incremental simplifier of triangle mesh.
+///
+/// Uses half-edge collapses, so vertices are only removed and never moved
+/// and attributes of remaining vertices stay valid.
+///
+/// Vertices with the same position and texture coordinates,
+/// such as vertices of hard edges split to have different normals,
+/// are collapsed together, each into the copy of target vertex it shares triangle with.
+#[derive(Debug)]
+pub(crate) struct Simplifier {
+    positions: Vec<[f32; 3]>,
+    /// Representative vertex for each vertex among vertices with the same position.
+    welded: Vec<u32>,
+    /// Vertices with the same position, indexed by representative vertex.
+    copies: Vec<Vec<u32>>,
+    /// Kinds of vertices, the same for all copies.
+    kinds: Vec<Kind>,
+    /// Quadrics indexed by representative vertex.
+    quadrics: Vec<Quadric>,
+    triangles: Vec<[u32; 3]>,
+    removed: Vec<bool>,
+    live: usize,
+    max_error: f64,
+}
+
+impl Simplifier {
+    pub(crate) fn new(
+        positions: &[Position],
+        tex_coords: Option<&[TexCoord]>,
+        triangles: Vec<[u32; 3]>,
+    ) -> Self {
+        let positions: Vec<_> = positions.iter().map(|p| p.0).collect();
+
+        let mut representatives = HashMap::new();
+        let welded: Vec<u32> = positions
+            .iter()
+            .enumerate()
+            .map(|(v, p)| {
+                *representatives
+                    .entry(p.map(f32::to_bits))
+                    .or_insert(v as u32)
+            })
+            .collect();
+
+        let mut copies = vec![Vec::new(); positions.len()];
+        for (v, &w) in welded.iter().enumerate() {
+            copies[w as usize].push(v as u32);
+        }
+
+        // Kinds are found for representative vertices and then copied to other vertices.
+        let mut kinds = vec![Kind::Manifold; positions.len()];
+
+        // Vertices that share position with vertices with other texture coordinates
+        // are on texture seams.
+        if let Some(tex_coords) = tex_coords {
+            for (v, &w) in welded.iter().enumerate() {
+                if tex_coords[v] != tex_coords[w as usize] {
+                    kinds[w as usize] = Kind::Locked;
+                }
+            }
+        }
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        for t in &triangles {
+            let [a, b, c] = t.map(|v| positions[v as usize]);
+            let normal = cross(sub(b, a), sub(c, a));
+            let area = length(normal);
+            if area > 0.0 {
+                let quadric = Quadric::plane(normal.map(|n| n / area), a, f64::from(area));
+                for &v in t {
+                    quadrics[welded[v as usize] as usize].add(&quadric);
+                }
+            }
+        }
+
+        // Count directed edges in welded position space to find borders.
+        let mut edges = HashMap::<(u32, u32), usize>::new();
+        for t in &triangles {
+            for c in 0..3 {
+                let edge = (welded[t[c] as usize], welded[t[(c + 1) % 3] as usize]);
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+
+        let mut border_edges = vec![0usize; positions.len()];
+        for t in &triangles {
+            for c in 0..3 {
+                let (wa, wb) = (welded[t[c] as usize], welded[t[(c + 1) % 3] as usize]);
+                if edges[&(wa, wb)] > 1 || edges.get(&(wb, wa)).copied().unwrap_or(0) > 1 {
+                    // Non-manifold edge.
+                    kinds[wa as usize] = Kind::Locked;
+                    kinds[wb as usize] = Kind::Locked;
+                    continue;
+                }
+                if !edges.contains_key(&(wb, wa)) {
+                    border_edges[wa as usize] += 1;
+                    border_edges[wb as usize] += 1;
+
+                    // Plane perpendicular to the triangle keeps vertices on the border.
+                    let [pa, pb, pc] = [wa, wb, t[(c + 2) % 3]].map(|v| positions[v as usize]);
+                    let edge = sub(pb, pa);
+                    let normal = cross(sub(pb, pa), sub(pc, pa));
+                    let plane = cross(edge, normal);
+                    let plane_length = length(plane);
+                    if plane_length > 0.0 {
+                        let weight = f64::from(dot(edge, edge)) * BORDER_WEIGHT;
+                        let quadric = Quadric::plane(plane.map(|n| n / plane_length), pa, weight);
+                        quadrics[wa as usize].add(&quadric);
+                        quadrics[wb as usize].add(&quadric);
+                    }
+                }
+            }
+        }
+
+        for (v, &count) in border_edges.iter().enumerate() {
+            if kinds[v] == Kind::Manifold && count > 0 {
+                // Vertex with more than two border edges joins separate borders.
+                kinds[v] = if count == 2 {
+                    Kind::Border
+                } else {
+                    Kind::Locked
+                };
+            }
+        }
+        for (v, &w) in welded.iter().enumerate() {
+            kinds[v] = kinds[w as usize];
+        }
+
+        Simplifier {
+            positions,
+            welded,
+            copies,
+            kinds,
+            quadrics,
+            removed: vec![false; triangles.len()],
+            live: triangles.len(),
+            triangles,
+            max_error: 0.0,
+        }
+    }
+
+    /// Remaining triangles.
+    pub(crate) fn triangles(&self) -> Vec<[u32; 3]> {
+        self.triangles
+            .iter()
+            .zip(&self.removed)
+            .filter(|&(_, &removed)| !removed)
+            .map(|(&t, _)| t)
+            .collect()
+    }
+
+    /// Largest error of collapses made so far.
+    pub(crate) fn error(&self) -> f32 {
+        self.max_error.sqrt() as f32
+    }
+
+    /// Collapse edges until number of triangles is not greater than `target`
+    /// or no more edges can be collapsed.
+    pub(crate) fn simplify(&mut self, target: usize) {
+        while self.live > target {
+            if !self.pass(target) {
+                break;
+            }
+        }
+    }
+
+    /// Collapse cheapest independent edges.
+    /// Returns `false` if no edge could be collapsed.
+    fn pass(&mut self, target: usize) -> bool {
+        let mut adjacency = vec![Vec::new(); self.positions.len()];
+        for (index, t) in self.triangles.iter().enumerate() {
+            if !self.removed[index] {
+                for &v in t {
+                    adjacency[v as usize].push(index);
+                }
+            }
+        }
+
+        // Border edges of the current mesh.
+        let mut directed = HashSet::new();
+        for (index, t) in self.triangles.iter().enumerate() {
+            if !self.removed[index] {
+                for c in 0..3 {
+                    directed.insert((
+                        self.welded[t[c] as usize],
+                        self.welded[t[(c + 1) % 3] as usize],
+                    ));
+                }
+            }
+        }
+        let is_border = |a: u32, b: u32| {
+            let (wa, wb) = (self.welded[a as usize], self.welded[b as usize]);
+            !directed.contains(&(wa, wb)) || !directed.contains(&(wb, wa))
+        };
+
+        let mut candidates = Vec::new();
+        let mut seen = HashSet::new();
+        for (index, t) in self.triangles.iter().enumerate() {
+            if self.removed[index] {
+                continue;
+            }
+            for c in 0..3 {
+                let (a, b) = (t[c], t[(c + 1) % 3]);
+                let (wa, wb) = (self.welded[a as usize], self.welded[b as usize]);
+                if !seen.insert((wa.min(wb), wa.max(wb))) {
+                    continue;
+                }
+                let border = is_border(a, b);
+                let best = [(a, b), (b, a)]
+                    .iter()
+                    .filter(|&&(from, to)| self.can_collapse(from, to, border))
+                    .map(|&(from, to)| {
+                        let quadric = &self.quadrics[self.welded[from as usize] as usize];
+                        (quadric.error(self.positions[to as usize]), from, to)
+                    })
+                    .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal));
+                candidates.extend(best);
+            }
+        }
+        candidates.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Each collapse removes up to two triangles.
+        // Collapses much more expensive than needed to reach the target are deferred
+        // to next passes, where cheaper collapses may become available.
+        let goal = (self.live - target).div_ceil(2).min(candidates.len());
+        let limit = match goal {
+            0 => return false,
+            goal => candidates[goal - 1].0 * 1.5,
+        };
+
+        let mut touched = vec![false; self.positions.len()];
+        let mut collapsed = false;
+        for (error, from, to) in candidates {
+            if self.live <= target || (collapsed && error > limit) {
+                break;
+            }
+            let pairs = match self.pairs(&adjacency, from, to) {
+                Some(pairs) => pairs,
+                None => continue,
+            };
+            if pairs
+                .iter()
+                .any(|&(from, to)| touched[from as usize] || touched[to as usize])
+            {
+                continue;
+            }
+            if pairs.iter().any(|&(from, to)| {
+                !self.check_topology(&adjacency, from, to) || self.flips(&adjacency, from, to)
+            }) {
+                continue;
+            }
+
+            for &(from, to) in &pairs {
+                // Vertices of all changed triangles have stale adjacency.
+                for &index in adjacency[from as usize]
+                    .iter()
+                    .chain(&adjacency[to as usize])
+                {
+                    for &v in &self.triangles[index] {
+                        touched[v as usize] = true;
+                    }
+                }
+
+                for &index in &adjacency[from as usize] {
+                    let t = &mut self.triangles[index];
+                    if t.contains(&to) {
+                        self.removed[index] = true;
+                        self.live -= 1;
+                    } else {
+                        t.iter_mut().filter(|v| **v == from).for_each(|v| *v = to);
+                    }
+                }
+            }
+            let quadric = self.quadrics[self.welded[from as usize] as usize];
+            self.quadrics[self.welded[to as usize] as usize].add(&quadric);
+            self.max_error = self.max_error.max(error);
+            collapsed = true;
+        }
+        collapsed
+    }
+
+    fn can_collapse(&self, from: u32, to: u32, border: bool) -> bool {
+        match self.kinds[from as usize] {
+            Kind::Manifold => true,
+            // Border vertex may slide only along the border.
+            Kind::Border => border && self.kinds[to as usize] != Kind::Manifold,
+            Kind::Locked => false,
+        }
+    }
+
+    /// Pair each used copy of `from` with copy of `to` it shares triangle with.
+    /// Returns `None` if some copy has no such neighbour,
+    /// as collapsing it would mix attributes of different copies.
+    fn pairs(&self, adjacency: &[Vec<usize>], from: u32, to: u32) -> Option<Vec<(u32, u32)>> {
+        let targets = &self.copies[self.welded[to as usize] as usize];
+        self.copies[self.welded[from as usize] as usize]
+            .iter()
+            .filter(|&&copy| !adjacency[copy as usize].is_empty())
+            .map(|&copy| {
+                let target = targets.iter().copied().find(|target| {
+                    adjacency[copy as usize]
+                        .iter()
+                        .any(|&index| self.triangles[index].contains(target))
+                })?;
+                Some((copy, target))
+            })
+            .collect()
+    }
+
+    /// Check that collapse keeps mesh manifold.
+    /// Edge must be shared by as many triangles as there are common neighbours of its vertices.
+    fn check_topology(&self, adjacency: &[Vec<usize>], from: u32, to: u32) -> bool {
+        let neighbours = |v: u32| {
+            adjacency[v as usize]
+                .iter()
+                .flat_map(|&index| self.triangles[index].iter().copied())
+                .filter(|&n| n != v)
+                .collect::<HashSet<_>>()
+        };
+        let shared = adjacency[from as usize]
+            .iter()
+            .filter(|&&index| self.triangles[index].contains(&to))
+            .count();
+        let common = neighbours(from).intersection(&neighbours(to)).count();
+        shared > 0 && common == shared
+    }
+
+    /// Check if collapse flips or degenerates any triangle.
+    fn flips(&self, adjacency: &[Vec<usize>], from: u32, to: u32) -> bool {
+        let target = self.positions[to as usize];
+        adjacency[from as usize].iter().any(|&index| {
+            let t = self.triangles[index];
+            if t.contains(&to) {
+                return false;
+            }
+            let [a, b, c] = t.map(|v| self.positions[v as usize]);
+            let before = cross(sub(b, a), sub(c, a));
+            let [a, b, c] = t.map(|v| {
+                if v == from {
+                    target
+                } else {
+                    self.positions[v as usize]
+                }
+            });
+            let after = cross(sub(b, a), sub(c, a));
+            dot(before, after) <= MAX_FLIP_COS * length(before) * length(after)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{mesh::Indices, Normal},
+    };
+
+    /// Grid of `size` by `size` quads with height given by `height`.
+    /// Vertices with `x` equal to `seam` are duplicated to split texture coordinates.
+    fn grid(
+        size: u32,
+        seam: Option<u32>,
+        height: impl Fn(f32, f32) -> f32,
+    ) -> MeshBuilder<'static> {
+        let row = size + 1;
+        let mut positions = Vec::new();
+        let mut tex_coords = Vec::new();
+        for y in 0..row {
+            for x in 0..row {
+                let (fx, fy) = (x as f32, y as f32);
+                positions.push(Position([fx, fy, height(fx, fy)]));
+                tex_coords.push(TexCoord([fx, fy]));
+            }
+        }
+
+        let mut duplicates = HashMap::new();
+        if let Some(seam) = seam {
+            for y in 0..row {
+                let v = y * row + seam;
+                duplicates.insert(v, positions.len() as u32);
+                positions.push(positions[v as usize]);
+                tex_coords.push(TexCoord([-1.0, y as f32]));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * row + x;
+                let quad = [i, i + 1, i + row, i + row + 1];
+                // Right side of the seam uses duplicated vertices.
+                let quad = quad.map(|v| match seam {
+                    Some(seam) if x >= seam => duplicates.get(&v).copied().unwrap_or(v),
+                    _ => v,
+                });
+                indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[1], quad[3], quad[2]]);
+            }
+        }
+
+        MeshBuilder::new()
+            .with_vertices(positions)
+            .with_vertices(tex_coords)
+            .with_indices(indices)
+    }
+
+    /// Roof of two planes meeting at hard edge along `x` equal to `size / 2`.
+    /// Vertices of the edge are duplicated to split normals.
+    fn roof(size: u32) -> MeshBuilder<'static> {
+        let row = size + 1;
+        let ridge = size / 2;
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut tex_coords = Vec::new();
+        let mut vertex = |x: u32, y: u32, right: bool| {
+            let (fx, fy) = (x as f32, y as f32);
+            positions.push(Position([fx, fy, -(fx - ridge as f32).abs()]));
+            normals.push(Normal(if right {
+                [1.0, 0.0, 1.0]
+            } else {
+                [-1.0, 0.0, 1.0]
+            }));
+            tex_coords.push(TexCoord([fx, fy]));
+        };
+        for y in 0..row {
+            for x in 0..row {
+                vertex(x, y, x > ridge);
+            }
+        }
+        for y in 0..row {
+            vertex(ridge, y, true);
+        }
+
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * row + x;
+                let quad = [i, i + 1, i + row, i + row + 1].map(|v| {
+                    if x >= ridge && v % row == ridge {
+                        row * row + v / row
+                    } else {
+                        v
+                    }
+                });
+                indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[1], quad[3], quad[2]]);
+            }
+        }
+
+        MeshBuilder::new()
+            .with_vertices(positions)
+            .with_vertices(normals)
+            .with_vertices(tex_coords)
+            .with_indices(indices)
+    }
+
+    fn triangles(mesh: &MeshBuilder<'_>) -> Vec<u32> {
+        match mesh.indices() {
+            Indices::U16(indices) => indices.iter().map(|&i| u32::from(i)).collect(),
+            Indices::U32(indices) => indices.into_owned(),
+            Indices::None => panic!("Level must be indexed"),
+        }
+    }
+
+    fn triangle_count(mesh: &MeshBuilder<'_>) -> usize {
+        match mesh.indices() {
+            Indices::U16(indices) => indices.len() / 3,
+            Indices::U32(indices) => indices.len() / 3,
+            Indices::None => panic!("Level must be indexed"),
+        }
+    }
+
+    #[test]
+    fn flat_grid() {
+        let mesh = grid(16, None, |_, _| 0.0);
+        let lods = mesh.simplify(&[0.5, 0.1]).unwrap();
+        assert_eq!(lods.len(), 2);
+
+        for (lod, &target) in lods.iter().zip(&[256, 52]) {
+            assert!(triangle_count(&lod.mesh) <= target);
+            assert!(lod.error < 1e-4, "{}", lod.error);
+
+            let positions = lod.mesh.vertices::<Position>().unwrap();
+            let tex_coords = lod.mesh.vertices::<TexCoord>().unwrap();
+            // Unused vertices are removed and attributes follow positions.
+            assert!(positions.len() < 17 * 17);
+            for (p, t) in positions.iter().zip(&tex_coords) {
+                assert_eq!([p.0[0], p.0[1]], t.0);
+            }
+            // Border is preserved.
+            for corner in &[[0.0, 0.0], [16.0, 0.0], [0.0, 16.0], [16.0, 16.0]] {
+                assert!(positions.iter().any(|p| [p.0[0], p.0[1]] == *corner));
+            }
+        }
+    }
+
+    #[test]
+    fn seam_is_preserved() {
+        let mesh = grid(16, Some(8), |_, _| 0.0);
+        let lods = mesh.simplify(&[0.1]).unwrap();
+        let lod = &lods[0].mesh;
+        assert!(triangle_count(lod) < 512);
+
+        // Both sides of every seam vertex are kept.
+        let positions = lod.vertices::<Position>().unwrap();
+        let tex_coords = lod.vertices::<TexCoord>().unwrap();
+        let seam = positions
+            .iter()
+            .zip(&tex_coords)
+            .filter(|(p, _)| p.0[0] == 8.0)
+            .count();
+        assert_eq!(seam, 34);
+        assert_eq!(tex_coords.iter().filter(|t| t.0[0] == -1.0).count(), 17);
+    }
+
+    #[test]
+    fn hard_edge_is_collapsed() {
+        let mesh = roof(16);
+        let lods = mesh.simplify(&[0.1]).unwrap();
+        let lod = &lods[0].mesh;
+        assert!(triangle_count(lod) <= 52, "{}", triangle_count(lod));
+        assert!(lods[0].error < 1e-4, "{}", lods[0].error);
+
+        // Vertices of the edge are removed along the edge, both copies at once.
+        let positions = lod.vertices::<Position>().unwrap();
+        let normals = lod.vertices::<Normal>().unwrap();
+        let ridge = |right: bool| {
+            let mut ridge: Vec<_> = positions
+                .iter()
+                .zip(&normals)
+                .filter(|(p, n)| p.0[0] == 8.0 && (n.0[0] > 0.0) == right)
+                .map(|(p, _)| p.0[1] as u32)
+                .collect();
+            ridge.sort();
+            ridge
+        };
+        assert!(ridge(false).len() < 17);
+        assert_eq!(ridge(false), ridge(true));
+
+        // Triangles never mix normals of different sides.
+        for t in triangles(lod).chunks(3) {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|v| normals[v as usize]);
+            assert!(a == b && b == c);
+        }
+    }
+
+    #[test]
+    fn curved_grid() {
+        let mesh = grid(16, None, |x, y| ((x * 0.4).sin() + (y * 0.3).cos()) * 2.0);
+        let lods = mesh.simplify(&[0.5, 0.25, 0.05]).unwrap();
+        let counts: Vec<_> = lods.iter().map(|lod| triangle_count(&lod.mesh)).collect();
+        assert!(
+            counts[0] <= 256 && counts[1] <= 128 && counts[2] <= 26,
+            "{:?}",
+            counts
+        );
+        assert!(lods[0].error > 0.0);
+        assert!(lods[0].error <= lods[1].error && lods[1].error <= lods[2].error);
+    }
+
+    #[test]
+    fn missing_positions() {
+        let mesh = MeshBuilder::new().with_vertices(vec![TexCoord([0.0; 2]); 3]);
+        assert_eq!(
+            mesh.simplify(&[0.5]).unwrap_err(),
+            crate::GenerateError::MissingAttribute("position")
+        );
+    }
+}
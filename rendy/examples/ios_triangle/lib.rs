@@ -221,7 +221,7 @@ fn run(
             Event::EventsCleared => {
                 factory.maintain(&mut families);
                 if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &());
+                    graph.run(&mut factory, &mut families, &()).unwrap();
                     frame += 1;
                 }
             }
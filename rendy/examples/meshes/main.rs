@@ -10,6 +10,7 @@ use {
     rendy::{
         command::{DrawIndexedCommand, QueueId, RenderPassEncoder},
         factory::{Config, Factory},
+        frame::FramesRange,
         graph::{render::*, GraphBuilder, GraphContext, NodeBuffer, NodeImage},
         hal::{self, adapter::PhysicalDevice as _, device::Device as _},
         init::winit::{
@@ -18,12 +19,16 @@ use {
             window::WindowBuilder,
         },
         init::AnyWindowedRendy,
-        memory::Dynamic,
+        memory::{Block as _, Dynamic, RingAllocator, RingConfig, Write as _},
         mesh::{Mesh, Model, PosColorNorm},
-        resource::{Buffer, BufferInfo, DescriptorSet, DescriptorSetLayout, Escape, Handle},
+        resource::{DescriptorSet, DescriptorSetLayout, Escape, Handle},
         shader::{ShaderKind, SourceLanguage, SourceShaderInfo, SpirvShader},
     },
-    std::{cmp::min, mem::size_of, time},
+    std::{
+        cmp::min,
+        mem::{size_of, size_of_val},
+        time,
+    },
 };
 
 #[cfg(feature = "spirv-reflection")]
@@ -89,6 +94,8 @@ struct Scene<B: hal::Backend> {
     object_mesh: Option<Mesh<B>>,
     objects: Vec<nalgebra::Transform3<f32>>,
     lights: Vec<Light>,
+    /// Frames of the graph, updated before each run.
+    frames: Option<FramesRange>,
 }
 
 const MAX_LIGHTS: usize = 32;
@@ -101,20 +108,54 @@ fn iceil(value: u64, scale: u64) -> u64 {
     ((value - 1) / scale + 1) * scale
 }
 
-fn buffer_frame_size(align: u64) -> u64 {
-    iceil(UNIFORM_SIZE + MODELS_SIZE + INDIRECT_SIZE, align)
-}
-
-fn uniform_offset(index: usize, align: u64) -> u64 {
-    buffer_frame_size(align) * index as u64
+/// Create ring for per-frame data and buffer that spans the whole ring.
+/// Memory is allocated from the factory with size and alignment the buffer requires.
+unsafe fn create_ring<B: hal::Backend>(
+    factory: &Factory<B>,
+    size: u64,
+    usage: hal::buffer::Usage,
+) -> (RingAllocator<B>, B::Buffer) {
+    let mut buffer = factory.device().create_buffer(size, usage).unwrap();
+    let requirements = factory.device().get_buffer_requirements(&buffer);
+    let atom = factory.physical().limits().non_coherent_atom_size as u64;
+    let block = factory
+        .allocate_memory(
+            requirements.type_mask as u32,
+            Dynamic,
+            requirements.size,
+            requirements.alignment.max(atom),
+        )
+        .unwrap();
+    factory
+        .device()
+        .bind_buffer_memory(block.memory(), block.range().start, &mut buffer)
+        .unwrap();
+
+    let ring = RingAllocator::from_block(
+        factory.device().raw(),
+        block,
+        RingConfig { ring_size: size },
+        atom,
+    )
+    .map_err(|(error, _)| error)
+    .unwrap();
+    (ring, buffer)
 }
 
-fn models_offset(index: usize, align: u64) -> u64 {
-    uniform_offset(index, align) + UNIFORM_SIZE
-}
-
-fn indirect_offset(index: usize, align: u64) -> u64 {
-    models_offset(index, align) + MODELS_SIZE
+/// Write `data` into the ring at `offset`.
+unsafe fn write<B: hal::Backend, T: Copy>(
+    ring: &mut RingAllocator<B>,
+    device: &B::Device,
+    offset: u64,
+    data: &[T],
+) {
+    let size = size_of_val(data) as u64;
+    if size > 0 {
+        ring.map(device, offset..offset + size)
+            .write(device, 0..size)
+            .unwrap()
+            .write(data);
+    }
 }
 
 #[derive(Debug, Default)]
@@ -123,8 +164,14 @@ struct MeshRenderPipelineDesc;
 #[derive(Debug)]
 struct MeshRenderPipeline<B: hal::Backend> {
     align: u64,
-    buffer: Escape<Buffer<B>>,
-    sets: Vec<Escape<DescriptorSet<B>>>,
+    ring: RingAllocator<B>,
+    buffer: B::Buffer,
+    region_size: u64,
+    indirect_offset: u64,
+    models_offset: u64,
+    set: Escape<DescriptorSet<B>>,
+    /// Region of the ring used by commands recorded for each index.
+    recorded: Vec<Option<u64>>,
 }
 
 impl<B> SimpleGraphicsPipelineDesc<B, Scene<B>> for MeshRenderPipelineDesc
@@ -168,15 +215,27 @@ where
     }
 
     fn layout(&self) -> Layout {
+        // Uniform offset in the ring is set when descriptor set is bound.
+        let uniform = hal::pso::DescriptorType::Buffer {
+            ty: hal::pso::BufferDescriptorType::Uniform,
+            format: hal::pso::BufferDescriptorFormat::Structured {
+                dynamic_offset: true,
+            },
+        };
+
         #[cfg(feature = "spirv-reflection")]
-        return SHADER_REFLECTION.layout().unwrap();
+        return {
+            let mut layout = SHADER_REFLECTION.layout().unwrap();
+            layout.sets[0].bindings[0].ty = uniform;
+            layout
+        };
 
         #[cfg(not(feature = "spirv-reflection"))]
         return Layout {
             sets: vec![SetLayout {
                 bindings: vec![hal::pso::DescriptorSetLayoutBinding {
                     binding: 0,
-                    ty: hal::pso::DescriptorType::UniformBuffer,
+                    ty: uniform,
                     count: 1,
                     stage_flags: hal::pso::ShaderStageFlags::GRAPHICS,
                     immutable_samplers: false,
@@ -200,48 +259,56 @@ where
         assert!(images.is_empty());
         assert_eq!(set_layouts.len(), 1);
 
-        let frames = ctx.frames_in_flight as _;
-        let align = factory
-            .physical()
-            .limits()
-            .min_uniform_buffer_offset_alignment;
-
-        let buffer = factory
-            .create_buffer(
-                BufferInfo {
-                    size: buffer_frame_size(align) * frames as u64,
-                    usage: hal::buffer::Usage::UNIFORM
-                        | hal::buffer::Usage::INDIRECT
-                        | hal::buffer::Usage::VERTEX,
-                },
-                Dynamic,
+        let frames = ctx.frames_in_flight as u64;
+        let limits = factory.physical().limits();
+        let align = limits
+            .min_uniform_buffer_offset_alignment
+            .max(limits.non_coherent_atom_size as u64);
+
+        // Each frame writes one region of the same size,
+        // so regions used by command buffers repeat once ring wraps around.
+        let indirect_offset = iceil(UNIFORM_SIZE, align);
+        let models_offset = iceil(indirect_offset + INDIRECT_SIZE, align);
+        let region_size = iceil(models_offset + MODELS_SIZE, align);
+
+        // Frames in flight and the next frame are written before older ones are reclaimed.
+        let (ring, buffer) = unsafe {
+            create_ring(
+                factory,
+                region_size * (frames + 1),
+                hal::buffer::Usage::UNIFORM
+                    | hal::buffer::Usage::INDIRECT
+                    | hal::buffer::Usage::VERTEX,
             )
-            .unwrap();
+        };
 
-        let mut sets = Vec::new();
-        for index in 0..frames {
-            unsafe {
-                let set = factory
-                    .create_descriptor_set(set_layouts[0].clone())
-                    .unwrap();
-                factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
-                    set: set.raw(),
-                    binding: 0,
-                    array_offset: 0,
-                    descriptors: Some(hal::pso::Descriptor::Buffer(
-                        buffer.raw(),
-                        Some(uniform_offset(index, align))
-                            ..Some(uniform_offset(index, align) + UNIFORM_SIZE),
-                    )),
-                }));
-                sets.push(set);
-            }
+        let set = factory
+            .create_descriptor_set(set_layouts[0].clone())
+            .unwrap();
+        unsafe {
+            factory.write_descriptor_sets(Some(hal::pso::DescriptorSetWrite {
+                set: set.raw(),
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(hal::pso::Descriptor::Buffer(
+                    &buffer,
+                    hal::buffer::SubRange {
+                        offset: 0,
+                        size: Some(UNIFORM_SIZE),
+                    },
+                )),
+            }));
         }
 
         Ok(MeshRenderPipeline {
             align,
+            ring,
             buffer,
-            sets,
+            region_size,
+            indirect_offset,
+            models_offset,
+            set,
+            recorded: Vec::new(),
         })
     }
 }
@@ -260,60 +327,71 @@ where
         index: usize,
         scene: &Scene<B>,
     ) -> PrepareResult {
-        unsafe {
-            factory
-                .upload_visible_buffer(
-                    &mut self.buffer,
-                    uniform_offset(index, self.align),
-                    &[UniformArgs {
-                        pad: [0, 0, 0],
-                        proj: scene.camera.proj.to_homogeneous(),
-                        view: scene.camera.view.inverse().to_homogeneous(),
-                        lights_count: scene.lights.len() as i32,
-                        lights: {
-                            let mut array = [Light {
-                                pad: 0.0,
-                                pos: nalgebra::Vector3::new(0.0, 0.0, 0.0),
-                                intensity: 0.0,
-                            }; MAX_LIGHTS];
-                            let count = min(scene.lights.len(), 32);
-                            array[..count].copy_from_slice(&scene.lights[..count]);
-                            array
-                        },
-                    }],
-                )
-                .unwrap()
-        };
+        let frames = scene.frames.expect("Frames are set before graph runs");
+        self.ring.reclaim(frames.complete_upper_bound());
+
+        let region = self
+            .ring
+            .allocate(frames.next().index(), self.region_size, self.align)
+            .expect("Ring must fit frames in flight")
+            .start;
 
+        let device = factory.device().raw();
         unsafe {
-            factory
-                .upload_visible_buffer(
-                    &mut self.buffer,
-                    indirect_offset(index, self.align),
-                    &[DrawIndexedCommand {
-                        index_count: scene.object_mesh.as_ref().unwrap().len(),
-                        instance_count: scene.objects.len() as u32,
-                        first_index: 0,
-                        vertex_offset: 0,
-                        first_instance: 0,
-                    }],
-                )
-                .unwrap()
-        };
+            write(
+                &mut self.ring,
+                device,
+                region,
+                &[UniformArgs {
+                    pad: [0, 0, 0],
+                    proj: scene.camera.proj.to_homogeneous(),
+                    view: scene.camera.view.inverse().to_homogeneous(),
+                    lights_count: scene.lights.len() as i32,
+                    lights: {
+                        let mut array = [Light {
+                            pad: 0.0,
+                            pos: nalgebra::Vector3::new(0.0, 0.0, 0.0),
+                            intensity: 0.0,
+                        }; MAX_LIGHTS];
+                        let count = min(scene.lights.len(), 32);
+                        array[..count].copy_from_slice(&scene.lights[..count]);
+                        array
+                    },
+                }],
+            );
 
-        if !scene.objects.is_empty() {
-            unsafe {
-                factory
-                    .upload_visible_buffer(
-                        &mut self.buffer,
-                        models_offset(index, self.align),
-                        &scene.objects[..],
-                    )
-                    .unwrap()
-            };
+            write(
+                &mut self.ring,
+                device,
+                region + self.indirect_offset,
+                &[DrawIndexedCommand {
+                    index_count: scene.object_mesh.as_ref().unwrap().len(),
+                    instance_count: scene.objects.len() as u32,
+                    first_index: 0,
+                    vertex_offset: 0,
+                    first_instance: 0,
+                }],
+            );
+
+            write(
+                &mut self.ring,
+                device,
+                region + self.models_offset,
+                &scene.objects[..],
+            );
+        }
+
+        if self.recorded.len() <= index {
+            self.recorded.resize(index + 1, None);
         }
 
-        PrepareResult::DrawReuse
+        // Commands read everything from the ring, only the region matters.
+        if self.recorded[index] == Some(region) {
+            PrepareResult::DrawReuse
+        } else {
+            self.recorded[index] = Some(region);
+            PrepareResult::DrawRecord
+        }
     }
 
     fn draw(
@@ -323,12 +401,13 @@ where
         index: usize,
         scene: &Scene<B>,
     ) {
+        let region = self.recorded[index].expect("Region is written in prepare");
         unsafe {
             encoder.bind_graphics_descriptor_sets(
                 layout,
                 0,
-                Some(self.sets[index].raw()),
-                std::iter::empty(),
+                Some(self.set.raw()),
+                Some(region as u32),
             );
 
             #[cfg(feature = "spirv-reflection")]
@@ -348,18 +427,29 @@ where
 
             encoder.bind_vertex_buffers(
                 1,
-                std::iter::once((self.buffer.raw(), models_offset(index, self.align))),
+                std::iter::once((&self.buffer, region + self.models_offset)),
             );
             encoder.draw_indexed_indirect(
-                self.buffer.raw(),
-                indirect_offset(index, self.align),
+                &self.buffer,
+                region + self.indirect_offset,
                 1,
                 INDIRECT_SIZE as u32,
             );
         }
     }
 
-    fn dispose(self, _factory: &mut Factory<B>, _scene: &Scene<B>) {}
+    fn dispose(mut self, factory: &mut Factory<B>, _scene: &Scene<B>) {
+        // Graph waits for all frames before disposing nodes.
+        self.ring.reclaim(u64::max_value());
+        unsafe {
+            factory.device().destroy_buffer(self.buffer);
+        }
+        if let Some(block) = self.ring.dispose(factory.device().raw()) {
+            unsafe {
+                factory.free_memory(block);
+            }
+        }
+    }
 }
 
 fn main() {
@@ -444,6 +534,7 @@ fn main() {
                     intensity: 160.0,
                 },
             ],
+            frames: None,
         };
 
         log::info!("{:#?}", scene);
@@ -503,7 +594,8 @@ fn main() {
                 Event::EventsCleared => {
                     factory.maintain(&mut families);
                     if let Some(ref mut graph) = graph {
-                        graph.run(&mut factory, &mut families, &scene);
+                        scene.frames = Some(graph.frames());
+                        graph.run(&mut factory, &mut families, &scene).unwrap();
                         frame += 1;
                     }
 
@@ -239,7 +239,7 @@ fn run<B: hal::Backend>(
             Event::EventsCleared => {
                 factory.maintain(&mut families);
                 if let Some(ref mut graph) = graph {
-                    graph.run(&mut factory, &mut families, &());
+                    graph.run(&mut factory, &mut families, &()).unwrap();
                     frame += 1;
                 }
 
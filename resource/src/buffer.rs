@@ -5,7 +5,7 @@ pub use rendy_core::hal::buffer::*;
 use {
     crate::{
         core::{device_owned, Device, DeviceId},
-        memory::{Block, Heaps, MappedRange, MemoryBlock, MemoryUsage},
+        memory::{Block, ForResource, Heaps, MappedRange, MemoryBlock, MemoryUsage, ResourceKind},
         CreationError,
     },
     relevant::Relevant,
@@ -31,7 +31,7 @@ pub struct BufferInfo {
 pub struct Buffer<B: Backend> {
     device: DeviceId,
     raw: B::Buffer,
-    block: MemoryBlock<B>,
+    block: Option<MemoryBlock<B>>,
     info: BufferInfo,
     relevant: Relevant,
 }
@@ -55,7 +55,7 @@ where
     /// Otherwise usage of hal methods must be always valid.
     pub unsafe fn create(
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &Heaps<B>,
         info: BufferInfo,
         memory_usage: impl MemoryUsage,
     ) -> Result<Self, BufferCreationError> {
@@ -66,35 +66,99 @@ where
             .create_buffer(info.size, info.usage)
             .map_err(CreationError::Create)?;
         let reqs = device.get_buffer_requirements(&buf);
-        let block = heaps
-            .allocate(
-                device,
-                reqs.type_mask as u32,
-                memory_usage,
-                reqs.size,
-                reqs.alignment,
-            )
-            .map_err(CreationError::Allocate)?;
-
-        device
-            .bind_buffer_memory(block.memory(), block.range().start, &mut buf)
-            .map_err(CreationError::Bind)?;
+        let block = match heaps.allocate(
+            device,
+            reqs.type_mask as u32,
+            ForResource::new(memory_usage, ResourceKind::Linear),
+            reqs.size,
+            reqs.alignment,
+        ) {
+            Ok(block) => block,
+            Err(err) => {
+                device.destroy_buffer(buf);
+                return Err(CreationError::Allocate(err));
+            }
+        };
+
+        if let Err(err) = device.bind_buffer_memory(block.memory(), block.range().start, &mut buf) {
+            device.destroy_buffer(buf);
+            heaps.free(device, block);
+            return Err(CreationError::Bind(err));
+        }
 
         Ok(Buffer {
             device: device.id(),
             raw: buf,
-            block,
+            block: Some(block),
             info,
             relevant: Relevant,
         })
     }
 
+    /// Create buffer without memory bound to it.
+    /// Returns buffer and its memory requirements.
+    ///
+    /// Buffer doesn't own the memory it is bound to with [`bind`],
+    /// so the memory can be shared between resources that are never used simultaneously.
+    ///
+    /// # Safety
+    ///
+    /// Buffer must be bound with [`bind`] before it is used.
+    ///
+    /// [`bind`]: #method.bind
+    pub unsafe fn create_unbound(
+        device: &Device<B>,
+        info: BufferInfo,
+    ) -> Result<(Self, rendy_core::hal::memory::Requirements), BufferCreationError> {
+        log::trace!("{:#?}@unbound", info);
+        assert_ne!(info.size, 0);
+
+        let buf = device
+            .create_buffer(info.size, info.usage)
+            .map_err(CreationError::Create)?;
+        let reqs = device.get_buffer_requirements(&buf);
+
+        Ok((
+            Buffer {
+                device: device.id(),
+                raw: buf,
+                block: None,
+                info,
+                relevant: Relevant,
+            },
+            reqs,
+        ))
+    }
+
+    /// Bind buffer created with [`create_unbound`] to memory of the block at specified offset.
+    ///
+    /// # Safety
+    ///
+    /// Memory range starting at `offset` must satisfy buffer requirements.
+    /// Block must not be freed while buffer is in use.
+    ///
+    /// [`create_unbound`]: #method.create_unbound
+    pub unsafe fn bind(
+        &mut self,
+        device: &Device<B>,
+        block: &MemoryBlock<B>,
+        offset: u64,
+    ) -> Result<(), BufferCreationError> {
+        self.assert_device_owner(device);
+        assert!(self.block.is_none(), "Buffer owns memory block");
+        device
+            .bind_buffer_memory(block.memory(), block.range().start + offset, &mut self.raw)
+            .map_err(CreationError::Bind)
+    }
+
     /// Dispose of buffer resource.
     /// Deallocate memory block.
-    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut Heaps<B>) {
+    pub unsafe fn dispose(self, device: &Device<B>, heaps: &Heaps<B>) {
         self.assert_device_owner(device);
         device.destroy_buffer(self.raw);
-        heaps.free(device, self.block);
+        if let Some(block) = self.block {
+            heaps.free(device, block);
+        }
         self.relevant.dispose();
     }
 
@@ -109,13 +173,16 @@ where
     }
 
     /// Get reference to memory block occupied by buffer.
-    pub fn block(&self) -> &MemoryBlock<B> {
-        &self.block
+    /// Returns `None` for buffers created with [`create_unbound`].
+    ///
+    /// [`create_unbound`]: #method.create_unbound
+    pub fn block(&self) -> Option<&MemoryBlock<B>> {
+        self.block.as_ref()
     }
 
     /// Get mutable reference to memory block occupied by buffer.
-    pub unsafe fn block_mut(&mut self) -> &mut MemoryBlock<B> {
-        &mut self.block
+    pub unsafe fn block_mut(&mut self) -> Option<&mut MemoryBlock<B>> {
+        self.block.as_mut()
     }
 
     /// Get buffer info.
@@ -129,18 +196,25 @@ where
     /// [`map`]: #method.map
     /// [`InvalidAccess`]: https://docs.rs/gfx-hal/0.1/rendy_core::hal/mapping/enum.Error.html#InvalidAccess
     pub fn visible(&self) -> bool {
-        self.block
-            .properties()
-            .contains(rendy_core::hal::memory::Properties::CPU_VISIBLE)
+        match &self.block {
+            Some(block) => block
+                .properties()
+                .contains(rendy_core::hal::memory::Properties::CPU_VISIBLE),
+            None => false,
+        }
     }
 
     /// Map range of the buffer to the CPU accessible memory.
+    /// Buffers that don't own memory block can't be mapped.
     pub fn map<'a>(
         &'a mut self,
         device: &Device<B>,
         range: std::ops::Range<u64>,
     ) -> Result<MappedRange<'a, B>, rendy_core::hal::device::MapError> {
-        self.block.map(device, range)
+        match &mut self.block {
+            Some(block) => block.map(device, range),
+            None => Err(rendy_core::hal::device::MapError::MappingFailed),
+        }
     }
 
     /// Get buffer info.
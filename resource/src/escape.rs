@@ -174,6 +174,13 @@ impl<T> From<Escape<T>> for Handle<T> {
     }
 }
 
+impl<T> Handle<T> {
+    /// Get mutable reference to the value if there are no other handles to it.
+    pub fn get_mut(handle: &mut Self) -> Option<&mut T> {
+        Arc::get_mut(&mut handle.inner).map(|escape| &mut **escape)
+    }
+}
+
 impl<T> Deref for Handle<T> {
     type Target = T;
 
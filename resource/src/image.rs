@@ -6,7 +6,7 @@ use {
     crate::{
         core::{device_owned, Device, DeviceId},
         escape::Handle,
-        memory::{Block, Heaps, MemoryBlock, MemoryUsage},
+        memory::{Block, ForResource, Heaps, MemoryBlock, MemoryUsage, ResourceKind},
         CreationError,
     },
     relevant::Relevant,
@@ -71,7 +71,7 @@ where
     /// Otherwise usage of hal methods must be always valid.
     pub unsafe fn create(
         device: &Device<B>,
-        heaps: &mut Heaps<B>,
+        heaps: &Heaps<B>,
         info: ImageInfo,
         memory_usage: impl MemoryUsage,
     ) -> Result<Self, ImageCreationError> {
@@ -96,19 +96,29 @@ where
             )
             .map_err(CreationError::Create)?;
         let reqs = device.get_image_requirements(&img);
-        let block = heaps
-            .allocate(
-                device,
-                reqs.type_mask as u32,
-                memory_usage,
-                reqs.size,
-                reqs.alignment,
-            )
-            .map_err(CreationError::Allocate)?;
+        let resource = match info.tiling {
+            Tiling::Linear => ResourceKind::Linear,
+            Tiling::Optimal => ResourceKind::NonLinear,
+        };
+        let block = match heaps.allocate(
+            device,
+            reqs.type_mask as u32,
+            ForResource::new(memory_usage, resource),
+            reqs.size,
+            reqs.alignment,
+        ) {
+            Ok(block) => block,
+            Err(err) => {
+                device.destroy_image(img);
+                return Err(CreationError::Allocate(err));
+            }
+        };
 
-        device
-            .bind_image_memory(block.memory(), block.range().start, &mut img)
-            .map_err(CreationError::Bind)?;
+        if let Err(err) = device.bind_image_memory(block.memory(), block.range().start, &mut img) {
+            device.destroy_image(img);
+            heaps.free(device, block);
+            return Err(CreationError::Bind(err));
+        }
 
         Ok(Image {
             device: device.id(),
@@ -119,6 +129,76 @@ where
         })
     }
 
+    /// Create image without memory bound to it.
+    /// Returns image and its memory requirements.
+    ///
+    /// Image doesn't own the memory it is bound to with [`bind`],
+    /// so the memory can be shared between images that are never used simultaneously.
+    ///
+    /// # Safety
+    ///
+    /// Image must be bound with [`bind`] before it is used.
+    ///
+    /// [`bind`]: #method.bind
+    pub unsafe fn create_unbound(
+        device: &Device<B>,
+        info: ImageInfo,
+    ) -> Result<(Self, rendy_core::hal::memory::Requirements), ImageCreationError> {
+        assert!(
+            info.levels <= info.kind.num_levels(),
+            "Number of mip leves ({}) cannot be greater than {} for given kind {:?}",
+            info.levels,
+            info.kind.num_levels(),
+            info.kind,
+        );
+
+        log::trace!("{:#?}@unbound", info);
+
+        let img = device
+            .create_image(
+                info.kind,
+                info.levels,
+                info.format,
+                info.tiling,
+                info.usage,
+                info.view_caps,
+            )
+            .map_err(CreationError::Create)?;
+        let reqs = device.get_image_requirements(&img);
+
+        Ok((
+            Image {
+                device: device.id(),
+                raw: img,
+                block: None,
+                info,
+                relevant: Relevant,
+            },
+            reqs,
+        ))
+    }
+
+    /// Bind image created with [`create_unbound`] to memory of the block at specified offset.
+    ///
+    /// # Safety
+    ///
+    /// Memory range starting at `offset` must satisfy image requirements.
+    /// Block must not be freed while image is in use.
+    ///
+    /// [`create_unbound`]: #method.create_unbound
+    pub unsafe fn bind(
+        &mut self,
+        device: &Device<B>,
+        block: &MemoryBlock<B>,
+        offset: u64,
+    ) -> Result<(), ImageCreationError> {
+        self.assert_device_owner(device);
+        assert!(self.block.is_none(), "Image owns memory block");
+        device
+            .bind_image_memory(block.memory(), block.range().start + offset, &mut self.raw)
+            .map_err(CreationError::Bind)
+    }
+
     /// Create image handler for swapchain image.
     pub unsafe fn create_from_swapchain(device: DeviceId, info: ImageInfo, raw: B::Image) -> Self {
         Image {
@@ -131,7 +211,7 @@ where
     }
 
     /// Destroy image resource.
-    pub unsafe fn dispose(self, device: &Device<B>, heaps: &mut Heaps<B>) {
+    pub unsafe fn dispose(self, device: &Device<B>, heaps: &Heaps<B>) {
         self.assert_device_owner(device);
         device.destroy_image(self.raw);
         self.block.map(|block| heaps.free(device, block));
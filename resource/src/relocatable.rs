@@ -0,0 +1,125 @@
+//! This module provides wrapper for resources that can be moved to another memory block.
+
+use {
+    crate::escape::Handle,
+    std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
+
+#[derive(Debug)]
+struct Inner<T> {
+    current: parking_lot::Mutex<Handle<T>>,
+    version: AtomicU64,
+}
+
+/// Shared resource that can be relocated by defragmentation.
+///
+/// Relocation replaces resource with a copy bound to another memory block.
+/// Raw objects of the resource change, so users should fetch current resource with [`get`]
+/// and recreate views and descriptors whenever [`version`] changes.
+/// Replaced resource is kept alive for as long as there are handles to it.
+///
+/// [`get`]: #method.get
+/// [`version`]: #method.version
+#[derive(Debug)]
+pub struct Relocatable<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Relocatable<T> {
+    fn clone(&self) -> Self {
+        Relocatable {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Relocatable<T> {
+    /// Wrap resource handle.
+    pub fn new(handle: Handle<T>) -> Self {
+        Relocatable {
+            inner: Arc::new(Inner {
+                current: parking_lot::Mutex::new(handle),
+                version: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get handle to current resource.
+    pub fn get(&self) -> Handle<T> {
+        self.inner.current.lock().clone()
+    }
+
+    /// Get number of times resource was relocated.
+    pub fn version(&self) -> u64 {
+        self.inner.version.load(Ordering::Acquire)
+    }
+
+    /// Replace resource with its relocated copy.
+    /// Returns handle to the replaced resource.
+    pub fn relocate(&self, handle: Handle<T>) -> Handle<T> {
+        let mut current = self.inner.current.lock();
+        let old = std::mem::replace(&mut *current, handle);
+        self.inner.version.fetch_add(1, Ordering::AcqRel);
+        old
+    }
+
+    /// Create weak reference that doesn't keep resource alive.
+    pub fn downgrade(&self) -> WeakRelocatable<T> {
+        WeakRelocatable {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+}
+
+/// Weak reference to the [`Relocatable`] resource.
+///
+/// [`Relocatable`]: struct.Relocatable.html
+#[derive(Debug)]
+pub struct WeakRelocatable<T> {
+    inner: Weak<Inner<T>>,
+}
+
+impl<T> Clone for WeakRelocatable<T> {
+    fn clone(&self) -> Self {
+        WeakRelocatable {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> WeakRelocatable<T> {
+    /// Get the resource if it is still alive.
+    pub fn upgrade(&self) -> Option<Relocatable<T>> {
+        self.inner.upgrade().map(|inner| Relocatable { inner })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Relocatable;
+    use crate::escape::Terminal;
+
+    #[test]
+    fn relocate() {
+        let mut terminal = Terminal::new();
+        let relocatable = Relocatable::new(terminal.escape(1).into());
+        let weak = relocatable.downgrade();
+        assert_eq!(*relocatable.get(), 1);
+        assert_eq!(relocatable.version(), 0);
+
+        let old = relocatable.relocate(terminal.escape(2).into());
+        assert_eq!(*old, 1);
+        assert_eq!(*relocatable.get(), 2);
+        assert_eq!(weak.upgrade().unwrap().version(), 1);
+
+        drop(old);
+        assert_eq!(terminal.drain().collect::<Vec<_>>(), vec![1]);
+
+        drop(relocatable);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(terminal.drain().collect::<Vec<_>>(), vec![2]);
+    }
+}
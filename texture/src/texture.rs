@@ -701,3 +701,102 @@ fn buf_add_padding(buffer: &[u8], dst_slice: &mut [u8], stride: usize, padding:
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            factory::{init_with_instance, Config},
+            memory::FaultPolicy,
+            pixel::Rgba8Unorm,
+            resource::CreationError,
+        },
+        rendy_command::{Families, Submission},
+        rendy_core::{hal::device::OutOfMemory, Instance},
+        rendy_fake::{FakeBackend, FakeInstance},
+    };
+
+    /// Complete submitted work and free dropped resources.
+    /// Staging buffers are dropped once uploads complete, and dropped resources
+    /// are freed once a fence submitted after the drop is waited for.
+    /// Image is dropped only when its view is freed.
+    fn cleanup(factory: &mut Factory<FakeBackend>, families: &mut Families<FakeBackend>) {
+        factory.maintain(families);
+        for _ in 0..4 {
+            let mut fence = factory.create_fence(false).unwrap();
+            unsafe {
+                families
+                    .family_by_index_mut(0)
+                    .queue_mut(0)
+                    .submit(Some(Submission::new()), Some(&mut fence));
+            }
+            factory.wait_for_fence(&mut fence, !0).unwrap();
+            factory.destroy_fence(fence);
+            factory.cleanup(families);
+        }
+    }
+
+    fn build(
+        factory: &mut Factory<FakeBackend>,
+        families: &Families<FakeBackend>,
+    ) -> Result<Texture<FakeBackend>, BuildError> {
+        TextureBuilder::new()
+            .with_kind(image::Kind::D2(4, 4, 1, 1))
+            .with_view_kind(image::ViewKind::D2)
+            .with_data_width(4)
+            .with_data_height(4)
+            .with_data(vec![Rgba8Unorm { repr: [0; 4] }; 16])
+            .build(
+                ImageState {
+                    queue: families.family_by_index(0).queue(0).id(),
+                    stage: rendy_core::hal::pso::PipelineStage::FRAGMENT_SHADER,
+                    access: image::Access::SHADER_READ,
+                    layout: image::Layout::ShaderReadOnlyOptimal,
+                },
+                factory,
+            )
+    }
+
+    #[test]
+    fn build_failure() {
+        let config: Config = Default::default();
+        let (mut factory, mut families) =
+            init_with_instance::<FakeBackend>(Instance::new(FakeInstance), &config).unwrap();
+
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(1)));
+        match build(&mut factory, &families) {
+            Err(BuildError::Image(CreationError::Allocate(_))) => {}
+            other => panic!("Image allocation must fail, got {:?}", other),
+        }
+
+        factory.set_memory_fault_policy(Some(FaultPolicy::Nth(2)));
+        match build(&mut factory, &families) {
+            Err(BuildError::Upload(UploadError::Create(CreationError::Allocate(_)))) => {}
+            other => panic!("Staging buffer allocation must fail, got {:?}", other),
+        }
+        factory.set_memory_fault_policy(None);
+
+        factory.device().set_out_of_memory(true);
+        match build(&mut factory, &families) {
+            Err(BuildError::Image(CreationError::Create(err))) => {
+                assert_eq!(err, OutOfMemory::Device.into())
+            }
+            other => panic!("Image creation must fail, got {:?}", other),
+        }
+        factory.device().set_out_of_memory(false);
+
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.images, 0);
+        assert_eq!(stats.buffers, 0);
+
+        let texture = build(&mut factory, &families).unwrap();
+        drop(texture);
+        cleanup(&mut factory, &mut families);
+        let stats = factory.device().stats();
+        assert_eq!(stats.images, 0);
+        assert_eq!(stats.buffers, 0);
+        assert!(factory.memory_utilization().tags.is_empty());
+    }
+}